@@ -0,0 +1,119 @@
+use core::any::Any;
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use linux_raw_sys::general::S_IFCHR;
+
+use super::{FileLike, Kstat};
+use crate::random::fill_random;
+
+/// The kinds of [`DevFile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DevKind {
+    /// Reads nothing and discards writes.
+    Null,
+    /// Reads zeros and discards writes.
+    Zero,
+    /// Reads zeros, and writes fail with `ENOSPC`.
+    Full,
+    /// Reads random bytes and discards writes.
+    Random,
+    /// Same as `Random`.
+    Urandom,
+}
+
+/// The device numbers of the terminals, see `devices.txt`.
+pub const TTY_DEVICE: (u32, u32) = (5, 0);
+pub const CONSOLE_DEVICE: (u32, u32) = (5, 1);
+
+/// Returns the device number of the character device at `path`.
+///
+/// There is no device file system, so the device files are recognized by
+/// path. Everything else tells devices apart by their number.
+pub fn device_at(path: &str) -> Option<(u32, u32)> {
+    Some(match path {
+        "/dev/null" => (1, 3),
+        "/dev/zero" => (1, 5),
+        "/dev/full" => (1, 7),
+        "/dev/random" => (1, 8),
+        "/dev/urandom" => (1, 9),
+        "/dev/tty" => TTY_DEVICE,
+        "/dev/console" => CONSOLE_DEVICE,
+        _ => return None,
+    })
+}
+
+/// One of the memory devices under `/dev`, like `/dev/null`.
+pub struct DevFile {
+    kind: DevKind,
+}
+
+impl DevFile {
+    /// Opens the device numbered `rdev` if it is one of the memory devices.
+    pub fn open(rdev: (u32, u32)) -> Option<Self> {
+        let kind = match rdev {
+            (1, 3) => DevKind::Null,
+            (1, 5) => DevKind::Zero,
+            (1, 7) => DevKind::Full,
+            (1, 8) => DevKind::Random,
+            (1, 9) => DevKind::Urandom,
+            _ => return None,
+        };
+        Some(Self { kind })
+    }
+
+    /// Whether this is `/dev/zero`, mapping which gives anonymous memory.
+    pub fn is_zero(&self) -> bool {
+        self.kind == DevKind::Zero
+    }
+}
+
+impl FileLike for DevFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        match self.kind {
+            DevKind::Null => return Ok(0),
+            DevKind::Zero | DevKind::Full => buf.fill(0),
+            DevKind::Random | DevKind::Urandom => fill_random(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if self.kind == DevKind::Full {
+            return Err(LinuxError::ENOSPC);
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        // The numbers of the memory devices, see `devices.txt`.
+        let minor = match self.kind {
+            DevKind::Null => 3,
+            DevKind::Zero => 5,
+            DevKind::Full => 7,
+            DevKind::Random => 8,
+            DevKind::Urandom => 9,
+        };
+        Ok(Kstat {
+            mode: S_IFCHR | 0o666u32, // rw-rw-rw-
+            rdev: (1, minor),
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
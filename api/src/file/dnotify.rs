@@ -0,0 +1,119 @@
+use core::{
+    ffi::c_int,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axprocess::Process;
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{DN_MULTISHOT, POLL_MSG, POLLIN, POLLMSG, POLLRDNORM};
+
+use super::{Directory, FileLike, send_io_signal};
+
+/// A directory armed with `fcntl(F_NOTIFY)`.
+struct DirNotify {
+    dir: Weak<Directory>,
+    /// The file descriptor `F_NOTIFY` was issued on, reported in `si_fd`.
+    fd: c_int,
+    /// The process to signal.
+    owner: Weak<Process>,
+    /// The `DN_*` events to report.
+    mask: u32,
+}
+
+static DIR_NOTIFIES: Mutex<Vec<DirNotify>> = Mutex::new(Vec::new());
+
+/// Whether [`DIR_NOTIFIES`] may be non-empty, so that file changes need not
+/// take its lock while no directory is watched.
+static ANY_NOTIFY: AtomicBool = AtomicBool::new(false);
+
+/// Arms `dir`, open as `fd`, to signal the current process on the events in
+/// `mask`.
+///
+/// Successive calls add to the events already requested, while an empty
+/// `mask` disarms the directory.
+pub fn set_dir_notify(dir: &Arc<Directory>, fd: c_int, mask: u32) {
+    let mut notifies = DIR_NOTIFIES.lock();
+    notifies.retain(|it| it.dir.strong_count() > 0);
+    let pos = notifies
+        .iter()
+        .position(|it| Weak::as_ptr(&it.dir) == Arc::as_ptr(dir));
+    if mask == 0 {
+        if let Some(pos) = pos {
+            notifies.swap_remove(pos);
+        }
+        ANY_NOTIFY.store(!notifies.is_empty(), Ordering::Release);
+        return;
+    }
+
+    let owner = Arc::downgrade(current().task_ext().thread.process());
+    match pos {
+        Some(pos) => {
+            notifies[pos].fd = fd;
+            notifies[pos].owner = owner;
+            notifies[pos].mask |= mask;
+        }
+        None => notifies.push(DirNotify {
+            dir: Arc::downgrade(dir),
+            fd,
+            owner,
+            mask,
+        }),
+    }
+    ANY_NOTIFY.store(true, Ordering::Release);
+}
+
+/// Whether any directory is watched, so that a file may skip looking up its
+/// path for [`notify_dir_change`] otherwise.
+pub fn any_dir_notify() -> bool {
+    ANY_NOTIFY.load(Ordering::Acquire)
+}
+
+/// Reports `event`, one of the `DN_*` flags, on the entry at `path` to the
+/// processes watching its parent directory.
+///
+/// The signal is `SIGIO`, or the one chosen with `F_SETSIG` on the directory.
+/// Unless `DN_MULTISHOT` was requested, a directory is disarmed after its
+/// first notification.
+pub fn notify_dir_change(path: &str, event: u32) {
+    if !any_dir_notify() {
+        return;
+    }
+    let mut notifies = DIR_NOTIFIES.lock();
+    let parent = path
+        .trim_end_matches('/')
+        .rsplit_once('/')
+        .map_or("", |(parent, _)| parent);
+
+    let mut targets = Vec::new();
+    notifies.retain(|it| {
+        let Some(dir) = it.dir.upgrade() else {
+            return false;
+        };
+        if it.mask & event == 0 || dir.path().trim_end_matches('/') != parent {
+            return true;
+        }
+        targets.push((it.owner.clone(), dir, it.fd));
+        it.mask & DN_MULTISHOT != 0
+    });
+    ANY_NOTIFY.store(!notifies.is_empty(), Ordering::Release);
+    drop(notifies);
+
+    for (owner, dir, fd) in targets {
+        let Some(owner) = owner.upgrade() else {
+            continue;
+        };
+        let file: Arc<dyn FileLike> = dir;
+        send_io_signal(
+            &owner,
+            &file,
+            fd,
+            POLL_MSG as _,
+            POLLIN | POLLRDNORM | POLLMSG,
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use core::{any::Any, ffi::c_int};
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsync::Mutex;
+use linux_raw_sys::general::{
+    EPOLLERR, EPOLLET, EPOLLHUP, EPOLLIN, EPOLLONESHOT, EPOLLOUT, EPOLLRDNORM, EPOLLWRNORM,
+    epoll_event,
+};
+
+use super::{FileLike, Kstat};
+
+/// The deepest epoll instances may be nested in one another.
+const EP_MAX_NESTS: usize = 4;
+
+/// Held while an epoll instance is added to another, so that concurrent adds
+/// cannot together build a loop or a nesting deeper than [`EP_MAX_NESTS`]
+/// that each checked on its own.
+static EPOLL_NESTING: Mutex<()> = Mutex::new(());
+
+/// A file in the interest list of an [`Epoll`].
+struct EpollEntry {
+    /// Entries are dropped once the file is closed.
+    file: Weak<dyn FileLike>,
+    /// The watched events, which always include `EPOLLERR` and `EPOLLHUP`
+    /// unless the entry was disabled by `EPOLLONESHOT`.
+    events: u32,
+    data: u64,
+    /// The events that were ready when last checked, against which
+    /// `EPOLLET` finds the new ones.
+    last_ready: u32,
+}
+
+impl EpollEntry {
+    /// The watched events of the file that are ready.
+    fn ready(&self) -> u32 {
+        let Some(file) = self.file.upgrade() else {
+            return 0;
+        };
+        match file.poll() {
+            Ok(state) => {
+                let mut revents = 0;
+                if state.readable {
+                    revents |= EPOLLIN | EPOLLRDNORM;
+                }
+                if state.writable {
+                    revents |= EPOLLOUT | EPOLLWRNORM;
+                }
+                if file.hangup() {
+                    revents |= EPOLLHUP;
+                }
+                revents & self.events
+            }
+            Err(_) => EPOLLERR & self.events,
+        }
+    }
+}
+
+/// An epoll instance, created by `epoll_create1`.
+///
+/// Readiness is only known by polling the files, so with `EPOLLET` an event
+/// is reported when it was not ready at the previous check, rather than on
+/// every change of the file. An epoll instance can watch other ones, which
+/// are ready when any of their own files is.
+pub struct Epoll {
+    entries: Mutex<BTreeMap<c_int, EpollEntry>>,
+    /// The epoll instances this one was added to, some of which may have
+    /// removed it since.
+    watchers: Mutex<Vec<Weak<Epoll>>>,
+}
+
+impl Epoll {
+    /// Creates an epoll instance with an empty interest list.
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The epoll instances watched by this one.
+    fn nested(&self) -> Vec<Arc<Epoll>> {
+        self.entries
+            .lock()
+            .values()
+            .filter_map(|entry| entry.file.upgrade()?.into_any().downcast().ok())
+            .collect()
+    }
+
+    /// How deep epoll instances are nested under this one, failing with
+    /// `ELOOP` if `target` is one of them.
+    fn nesting_depth(&self, target: &Epoll) -> LinuxResult<usize> {
+        if core::ptr::eq(self, target) {
+            return Err(LinuxError::ELOOP);
+        }
+        let mut depth = 0;
+        for inner in self.nested() {
+            depth = depth.max(inner.nesting_depth(target)? + 1);
+        }
+        Ok(depth)
+    }
+
+    /// How deep this epoll instance is nested in others.
+    fn watched_depth(self: &Arc<Self>) -> usize {
+        let watchers = {
+            let mut watchers = self.watchers.lock();
+            watchers.retain(|it| it.strong_count() > 0);
+            watchers
+                .iter()
+                .filter_map(Weak::upgrade)
+                .collect::<Vec<_>>()
+        };
+        watchers
+            .iter()
+            .filter(|it| it.nested().iter().any(|inner| Arc::ptr_eq(inner, self)))
+            .map(|it| it.watched_depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Adds `file`, open as `fd`, to the interest list.
+    ///
+    /// Watching another epoll instance fails with `ELOOP` if it would watch
+    /// this one in turn, or if the chain of instances nested in one another
+    /// through this one would be deeper than [`EP_MAX_NESTS`], counting both
+    /// those watching this one and those watched by the other one.
+    pub fn add(
+        self: &Arc<Self>,
+        fd: c_int,
+        file: &Arc<dyn FileLike>,
+        event: epoll_event,
+    ) -> LinuxResult {
+        let mut nesting = None;
+        if let Ok(inner) = file.clone().into_any().downcast::<Epoll>() {
+            let guard = EPOLL_NESTING.lock();
+            if self.watched_depth() + inner.nesting_depth(self)? + 1 > EP_MAX_NESTS {
+                return Err(LinuxError::ELOOP);
+            }
+            nesting = Some((guard, inner));
+        }
+        let mut entries = self.entries.lock();
+        if entries
+            .get(&fd)
+            .and_then(|entry| entry.file.upgrade())
+            .is_some_and(|it| Arc::ptr_eq(&it, file))
+        {
+            return Err(LinuxError::EEXIST);
+        }
+        entries.insert(
+            fd,
+            EpollEntry {
+                file: Arc::downgrade(file),
+                events: event.events | EPOLLERR | EPOLLHUP,
+                data: event.data,
+                last_ready: 0,
+            },
+        );
+        drop(entries);
+        if let Some((_guard, inner)) = nesting {
+            let mut watchers = inner.watchers.lock();
+            if !watchers
+                .iter()
+                .any(|it| core::ptr::eq(it.as_ptr(), &**self))
+            {
+                watchers.push(Arc::downgrade(self));
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the events watched on `fd`.
+    pub fn modify(&self, fd: c_int, event: epoll_event) -> LinuxResult {
+        let mut entries = self.entries.lock();
+        let entry = entries.get_mut(&fd).ok_or(LinuxError::ENOENT)?;
+        entry.events = event.events | EPOLLERR | EPOLLHUP;
+        entry.data = event.data;
+        entry.last_ready = 0;
+        Ok(())
+    }
+
+    /// Removes `fd` from the interest list.
+    pub fn delete(&self, fd: c_int) -> LinuxResult {
+        self.entries
+            .lock()
+            .remove(&fd)
+            .map(|_| ())
+            .ok_or(LinuxError::ENOENT)
+    }
+
+    /// Collects up to `max` events of the files that are ready.
+    ///
+    /// A file watched with `EPOLLONESHOT` is disabled once reported, until
+    /// it is modified.
+    pub fn ready_events(&self, max: usize) -> Vec<epoll_event> {
+        let mut events = Vec::new();
+        let mut entries = self.entries.lock();
+        entries.retain(|_, entry| entry.file.strong_count() > 0);
+        for entry in entries.values_mut() {
+            if events.len() >= max {
+                break;
+            }
+            let ready = entry.ready();
+            let revents = if entry.events & EPOLLET != 0 {
+                ready & !entry.last_ready
+            } else {
+                ready
+            };
+            entry.last_ready = ready;
+            if revents == 0 {
+                continue;
+            }
+            events.push(epoll_event {
+                events: revents,
+                data: entry.data,
+            });
+            if entry.events & EPOLLONESHOT != 0 {
+                entry.events &= EPOLLONESHOT | EPOLLET;
+            }
+        }
+        events
+    }
+}
+
+impl FileLike for Epoll {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: 0o600u32, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    /// Readable when any of the watched files is ready, without consuming
+    /// `EPOLLONESHOT` events.
+    fn poll(&self) -> LinuxResult<PollState> {
+        let readable = self.entries.lock().values().any(|entry| entry.ready() != 0);
+        Ok(PollState {
+            readable,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
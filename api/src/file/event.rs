@@ -0,0 +1,59 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axtask::{TaskExtRef, WaitQueue, current};
+
+use crate::signal::has_interrupting_signal;
+
+/// Wakes up the tasks waiting for a change in the state of a file.
+pub struct WaitEvent {
+    /// Bumped on every change, so that a task never sleeps through one that
+    /// happened after it looked at the file.
+    seq: AtomicU64,
+    wq: WaitQueue,
+}
+
+impl WaitEvent {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    /// Returns the current sequence number, to be read before looking at
+    /// the state of the file and passed on to [`WaitEvent::wait`].
+    pub fn seq(&self) -> u64 {
+        self.seq.load(Ordering::Acquire)
+    }
+
+    /// Wakes up all the waiting tasks, for a change any of them may act on.
+    pub fn notify(&self) {
+        self.seq.fetch_add(1, Ordering::AcqRel);
+        self.wq.notify_all(false);
+    }
+
+    /// Wakes up one waiting task, for a change only one of them can take,
+    /// such as a single message.
+    ///
+    /// The task that takes it must call this again if something is left for
+    /// the others.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::AcqRel);
+        self.wq.notify_one(false);
+    }
+
+    /// Blocks until the first change after `seq` was read, for at most
+    /// `timeout`, or until the current thread gets a signal.
+    pub fn wait(&self, seq: u64, timeout: Option<Duration>) {
+        let curr = current();
+        let thr_data = curr.task_ext().thread_data();
+        let since = thr_data.interrupts();
+        if has_interrupting_signal() {
+            return;
+        }
+        thr_data.wait_interruptible(&self.wq, since, timeout, || self.seq() != seq);
+    }
+}
@@ -0,0 +1,133 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsync::Mutex;
+use linux_raw_sys::general::{O_NONBLOCK, O_RDWR};
+
+use super::{FileLike, Kstat, event::WaitEvent};
+use crate::signal::has_interrupting_signal;
+
+/// The largest value the counter can hold.
+const MAX_COUNT: u64 = u64::MAX - 1;
+
+/// A file holding a 64-bit counter, created by `eventfd`.
+///
+/// Writes add to the counter, blocking while it would overflow. Reads take
+/// the whole counter, or one in semaphore mode, blocking while it is zero.
+pub struct EventFd {
+    count: Mutex<u64>,
+    semaphore: bool,
+    nonblocking: AtomicBool,
+    /// Notified when the counter is increased.
+    readable: WaitEvent,
+    /// Notified when the counter is decreased.
+    writable: WaitEvent,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, semaphore: bool, nonblocking: bool) -> Self {
+        Self {
+            count: Mutex::new(initval),
+            semaphore,
+            nonblocking: AtomicBool::new(nonblocking),
+            readable: WaitEvent::new(),
+            writable: WaitEvent::new(),
+        }
+    }
+
+    /// Waits for `event` after `seq`, unless the file is non-blocking
+    /// (`EAGAIN`) or a signal is pending (`EINTR`).
+    fn wait(&self, event: &WaitEvent, seq: u64) -> LinuxResult {
+        if self.nonblocking.load(Ordering::Acquire) {
+            return Err(LinuxError::EAGAIN);
+        }
+        if has_interrupting_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        event.wait(seq, None);
+        Ok(())
+    }
+}
+
+impl FileLike for EventFd {
+    /// Read the counter as a `u64` and reset it to zero, or read 1 and
+    /// decrement it in semaphore mode.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let Some(buf) = buf.first_chunk_mut::<8>() else {
+            return Err(LinuxError::EINVAL);
+        };
+        loop {
+            let seq = self.readable.seq();
+            let mut count = self.count.lock();
+            if *count > 0 {
+                let value = if self.semaphore { 1 } else { *count };
+                *count -= value;
+                drop(count);
+                self.writable.notify();
+                *buf = value.to_ne_bytes();
+                return Ok(8);
+            }
+            drop(count);
+            self.wait(&self.readable, seq)?;
+        }
+    }
+
+    /// Add a `u64` to the counter, which cannot reach `u64::MAX`.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let Some(buf) = buf.first_chunk::<8>() else {
+            return Err(LinuxError::EINVAL);
+        };
+        let value = u64::from_ne_bytes(*buf);
+        if value > MAX_COUNT {
+            return Err(LinuxError::EINVAL);
+        }
+        loop {
+            let seq = self.writable.seq();
+            let mut count = self.count.lock();
+            if MAX_COUNT - *count >= value {
+                *count += value;
+                drop(count);
+                if value > 0 {
+                    self.readable.notify();
+                }
+                return Ok(8);
+            }
+            drop(count);
+            self.wait(&self.writable, seq)?;
+        }
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: 0o600u32, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let count = *self.count.lock();
+        Ok(PollState {
+            readable: count > 0,
+            writable: count < MAX_COUNT,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn status_flags(&self) -> u32 {
+        let nonblocking = self.nonblocking.load(Ordering::Acquire);
+        O_RDWR | if nonblocking { O_NONBLOCK } else { 0 }
+    }
+}
@@ -1,58 +1,246 @@
-use core::{any::Any, ffi::c_int};
+use core::{
+    any::Any,
+    ffi::c_int,
+    ops::Range,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-use alloc::{string::String, sync::Arc};
-use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::DirEntry;
-use axio::PollState;
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfs::{
+    api::FileType,
+    fops::{DirEntry, OpenOptions},
+};
+use axio::{PollState, SeekFrom};
+use axsignal::{SignalInfo, Signo};
 use axsync::{Mutex, MutexGuard};
-use linux_raw_sys::general::S_IFDIR;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{
+        DN_MODIFY, O_ACCMODE, O_APPEND, O_NONBLOCK, O_RDONLY, O_RDWR, RLIMIT_FSIZE, S_IFDIR,
+        SI_KERNEL,
+    },
+    ioctl::FIONREAD,
+};
+use memory_addr::PAGE_SIZE_4K;
 
-use super::{FileLike, Kstat, get_file_like};
+use super::{
+    FileLike, Kstat, any_dir_notify, file_perm, get_file_like, is_orphan, notify_dir_change,
+    orphan::{OpenPath, open_path},
+    touch_atime, touch_mtime,
+};
+use crate::{mount_flags_at, ptr::UserPtr, signal::send_signal_thread};
 
 /// File wrapper for `axfs::fops::File`.
 pub struct File {
     inner: Mutex<axfs::fops::File>,
-    path: String,
+    /// Where the file is, which follows renames, and keeps the file around
+    /// if it is unlinked, until it is closed.
+    open: Arc<OpenPath>,
+    /// The access mode, and `O_APPEND` and `O_NONBLOCK` if set, as reported
+    /// by `fcntl(F_GETFL)`.
+    flags: AtomicU32,
 }
 
 impl File {
     pub fn new(inner: axfs::fops::File, path: String) -> Self {
         Self {
             inner: Mutex::new(inner),
-            path,
+            open: open_path(&path),
+            flags: AtomicU32::new(O_RDWR),
+        }
+    }
+
+    /// Set the access mode and status flags given to `open`, of which
+    /// `O_APPEND` makes every write go to the end of the file.
+    pub fn status_flags(self, flags: u32) -> Self {
+        let flags = flags & (O_ACCMODE | O_APPEND | O_NONBLOCK);
+        self.flags.store(flags, Ordering::Release);
+        self
+    }
+
+    fn is_append(&self) -> bool {
+        self.flags.load(Ordering::Acquire) & O_APPEND != 0
+    }
+
+    /// Set or clear `O_APPEND`, as `fcntl(F_SETFL)` does.
+    pub fn set_append(&self, append: bool) {
+        self.set_flag(O_APPEND, append);
+    }
+
+    fn set_flag(&self, flag: u32, set: bool) {
+        if set {
+            self.flags.fetch_or(flag, Ordering::AcqRel);
+        } else {
+            self.flags.fetch_and(!flag, Ordering::AcqRel);
         }
     }
 
     /// Get the path of the file.
-    pub fn path(&self) -> &str {
-        &self.path
+    pub fn path(&self) -> String {
+        self.open.path()
     }
 
     /// Get the inner node of the file.
     pub fn inner(&self) -> MutexGuard<axfs::fops::File> {
         self.inner.lock()
     }
+
+    /// Read from `offset` without moving the file position.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        let read = self.inner().read_at(offset, buf)?;
+        self.touch_atime();
+        Ok(read)
+    }
+
+    /// Write at `offset` without moving the file position, within
+    /// `RLIMIT_FSIZE` like [`FileLike::write`].
+    ///
+    /// Like on Linux, a file opened with `O_APPEND` is appended to whatever
+    /// `offset` is.
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        let append = self.is_append();
+        let _append = append.then(|| self.open.lock_append());
+        let inner = self.inner();
+        let offset = if append {
+            inner.get_attr()?.size()
+        } else {
+            offset
+        };
+        let written = inner.write_at(offset, within_file_size(offset, buf)?)?;
+        drop(inner);
+        self.modified(offset, written);
+        Ok(written)
+    }
+
+    /// Record that `written` bytes were written at `offset`.
+    fn modified(&self, offset: u64, written: usize) {
+        if written > 0 {
+            let page = PAGE_SIZE_4K as u64;
+            let end = offset + written as u64;
+            self.open.mark_dirty(offset / page..end.div_ceil(page));
+            self.touch_mtime();
+        }
+    }
+
+    /// Update the access time after a read.
+    fn touch_atime(&self) {
+        let flags = self.open.with_path(mount_flags_at);
+        touch_atime(self.open.times(), flags);
+    }
+
+    /// Update the modification time after a write, and tell those watching
+    /// the directory.
+    fn touch_mtime(&self) {
+        touch_mtime(self.open.times());
+        if any_dir_notify() {
+            notify_dir_change(&self.path(), DN_MODIFY);
+        }
+    }
+
+    /// Count the pages in `pages` that were written since the file was last
+    /// flushed.
+    pub fn dirty_pages(&self, pages: Range<u64>) -> u64 {
+        self.open.dirty_pages(pages)
+    }
+
+    /// Overwrite `[start, end)` with zeros.
+    pub fn zero_range(&self, start: u64, end: u64) -> LinuxResult {
+        let zeros = vec![0u8; 4096];
+        let inner = self.inner();
+        let mut pos = start;
+        while pos < end {
+            let len = (end - pos).min(zeros.len() as u64) as usize;
+            match inner.write_at(pos, &zeros[..len])? {
+                0 => return Err(LinuxError::EIO),
+                written => pos += written as u64,
+            }
+        }
+        drop(inner);
+        self.modified(start, (end - start) as usize);
+        Ok(())
+    }
+
+    /// Truncate or extend the file to `size` bytes.
+    ///
+    /// Only extending it is subject to `RLIMIT_FSIZE`.
+    pub fn truncate(&self, size: u64) -> LinuxResult {
+        let inner = self.inner();
+        if size > inner.get_attr()?.size() {
+            check_file_size(size)?;
+        }
+        inner.truncate(size)?;
+        drop(inner);
+        self.touch_mtime();
+        Ok(())
+    }
+}
+
+/// Fail with `EFBIG` if a file may not grow to `size` bytes because of
+/// `RLIMIT_FSIZE`, raising `SIGXFSZ` as well.
+fn check_file_size(size: u64) -> LinuxResult {
+    let curr = current();
+    if size <= curr.task_ext().process_data().rlimits.read()[RLIMIT_FSIZE].current {
+        return Ok(());
+    }
+    let sig = SignalInfo::new(Signo::SIGXFSZ, SI_KERNEL as _);
+    send_signal_thread(&curr.task_ext().thread, sig)?;
+    Err(LinuxError::EFBIG)
+}
+
+/// Cut `buf` to what may be written at `offset` under `RLIMIT_FSIZE`, and
+/// only fail if that is nothing at all.
+fn within_file_size(offset: u64, buf: &[u8]) -> LinuxResult<&[u8]> {
+    let limit = current().task_ext().process_data().rlimits.read()[RLIMIT_FSIZE].current;
+    let room = usize::try_from(limit.saturating_sub(offset)).unwrap_or(usize::MAX);
+    if room == 0 && !buf.is_empty() {
+        check_file_size(offset + 1)?;
+    }
+    Ok(&buf[..buf.len().min(room)])
 }
 
 impl FileLike for File {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.inner().read(buf)?)
+        let read = self.inner().read(buf)?;
+        self.touch_atime();
+        Ok(read)
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner().write(buf)?)
+        // The end of the file and the write must not be apart, in case it is
+        // being appended to through another descriptor.
+        let append = self.is_append();
+        let _append = append.then(|| self.open.lock_append());
+        let mut inner = self.inner();
+        let offset = if append {
+            inner.get_attr()?.size()
+        } else {
+            inner.seek(SeekFrom::Current(0))?
+        };
+        // Written at the offset chosen here, whether the file was opened for
+        // appending or not, so that only `O_APPEND` as set now counts.
+        let written = inner.write_at(offset, within_file_size(offset, buf)?)?;
+        inner.seek(SeekFrom::Start(offset + written as u64))?;
+        drop(inner);
+        self.modified(offset, written);
+        Ok(written)
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
         let metadata = self.inner().get_attr()?;
         let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
+        let perm = file_perm(&self.path(), metadata.perm().bits() as u32);
+        let times = *self.open.times().lock();
 
         Ok(Kstat {
             mode: ((ty as u32) << 12) | perm,
             size: metadata.size(),
-            blocks: metadata.blocks(),
+            // Some file systems do not count blocks, but have no holes either.
+            blocks: metadata.blocks().max(metadata.size().div_ceil(512)),
             blksize: 512,
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.ctime,
             ..Default::default()
         })
     }
@@ -68,30 +256,64 @@ impl FileLike for File {
         })
     }
 
-    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+    /// Regular files never block, but the flag is kept for `F_GETFL`.
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.set_flag(O_NONBLOCK, nonblocking);
         Ok(())
     }
+
+    fn status_flags(&self) -> u32 {
+        self.flags.load(Ordering::Acquire)
+    }
+
+    fn flush(&self) -> LinuxResult {
+        match self.inner().flush() {
+            // Files opened read-only have nothing to write back.
+            Ok(()) | Err(AxError::PermissionDenied) => {}
+            Err(_) => return Err(LinuxError::EIO),
+        }
+        self.open.mark_clean();
+        Ok(())
+    }
+
+    /// Handles `FIONREAD`, which gives the number of bytes from the file
+    /// position to the end of the file.
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        if cmd != FIONREAD {
+            return Err(LinuxError::ENOTTY);
+        }
+        let mut inner = self.inner();
+        let size = inner.get_attr()?.size();
+        let pos = inner.seek(SeekFrom::Current(0))?;
+        drop(inner);
+        let left = size.saturating_sub(pos).min(c_int::MAX as u64);
+        *UserPtr::<c_int>::from(arg).get_as_mut()? = left as _;
+        Ok(0)
+    }
 }
 
 /// Directory wrapper for `axfs::fops::Directory`.
 pub struct Directory {
     inner: Mutex<axfs::fops::Directory>,
-    path: String,
-    last_dirent: Mutex<Option<DirEntry>>,
+    /// Where the directory is, which follows renames.
+    open: Arc<OpenPath>,
+    /// The entries being read and the index of the next one, or `None` if
+    /// reading has not started.
+    cursor: Mutex<Option<(Vec<DirEntry>, usize)>>,
 }
 
 impl Directory {
     pub fn new(inner: axfs::fops::Directory, path: String) -> Self {
         Self {
             inner: Mutex::new(inner),
-            path,
-            last_dirent: Mutex::new(None),
+            open: open_path(&path),
+            cursor: Mutex::new(None),
         }
     }
 
     /// Get the path of the directory.
-    pub fn path(&self) -> &str {
-        &self.path
+    pub fn path(&self) -> String {
+        self.open.path()
     }
 
     /// Get the inner node of the directory.
@@ -99,9 +321,75 @@ impl Directory {
         self.inner.lock()
     }
 
-    /// Get the last directory entry.
-    pub fn last_dirent(&self) -> MutexGuard<Option<DirEntry>> {
-        self.last_dirent.lock()
+    /// Read all the entries of the directory as they are now, starting with
+    /// `.` and `..`, which not every file system lists.
+    fn snapshot(&self) -> LinuxResult<Vec<DirEntry>> {
+        let mut opts = OpenOptions::new();
+        opts.read(true);
+        let path = self.path();
+        let mut dir = axfs::fops::Directory::open_dir(&path, &opts)?;
+        let mut entries = vec![
+            DirEntry::new(".", FileType::Dir),
+            DirEntry::new("..", FileType::Dir),
+        ];
+        loop {
+            let mut buf: [DirEntry; 16] = Default::default();
+            let cnt = dir.read_dir(&mut buf)?;
+            if cnt == 0 {
+                break;
+            }
+            entries.extend(buf.into_iter().take(cnt).filter(|entry| {
+                let name = String::from_utf8_lossy(entry.name_as_bytes());
+                name != "."
+                    && name != ".."
+                    && !is_orphan(&format!("{}/{name}", path.trim_end_matches('/')))
+            }));
+        }
+        Ok(entries)
+    }
+
+    /// Call `f` with each entry from the current position and the position
+    /// after it, until `f` returns false, and return how many were accepted.
+    ///
+    /// The entries are read all at once when reading starts, so that those
+    /// added or removed meanwhile cannot make others be skipped or repeated.
+    /// They may or may not be seen until the directory is rewound.
+    pub fn read_entries(&self, mut f: impl FnMut(&DirEntry, u64) -> bool) -> LinuxResult<usize> {
+        let mut cursor = self.cursor.lock();
+        let (entries, pos) = match &mut *cursor {
+            Some(cursor) => cursor,
+            cursor @ None => cursor.insert((self.snapshot()?, 0)),
+        };
+        let start = *pos;
+        while let Some(entry) = entries.get(*pos) {
+            if !f(entry, *pos as u64 + 1) {
+                break;
+            }
+            *pos += 1;
+        }
+        Ok(*pos - start)
+    }
+
+    /// Move to the entry at `pos`, as given by [`Self::read_entries`].
+    ///
+    /// Seeking to the start reads the entries again.
+    pub fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let mut cursor = self.cursor.lock();
+        let current = cursor.as_ref().map_or(0, |(_, pos)| *pos as u64);
+        let new = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(off) => current.checked_add_signed(off),
+            SeekFrom::End(_) => None,
+        }
+        .ok_or(LinuxError::EINVAL)?;
+        if new == 0 {
+            *cursor = None;
+        } else if let Some((_, pos)) = cursor.as_mut() {
+            *pos = new as usize;
+        } else {
+            *cursor = Some((self.snapshot()?, new as usize));
+        }
+        Ok(new)
     }
 }
 
@@ -115,8 +403,12 @@ impl FileLike for Directory {
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
+        let times = *self.open.times().lock();
         Ok(Kstat {
-            mode: S_IFDIR | 0o755u32, // rwxr-xr-x
+            mode: S_IFDIR | file_perm(&self.path(), 0o755), // rwxr-xr-x by default
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.ctime,
             ..Default::default()
         })
     }
@@ -136,6 +428,10 @@ impl FileLike for Directory {
         Ok(())
     }
 
+    fn status_flags(&self) -> u32 {
+        O_RDONLY
+    }
+
     fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
         get_file_like(fd)?
             .into_any()
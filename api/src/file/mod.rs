@@ -1,24 +1,64 @@
+mod dev;
+mod dnotify;
+mod epoll;
+mod event;
+mod eventfd;
 mod fs;
+mod mqueue;
 mod net;
+mod orphan;
+mod perms;
+mod pidfd;
 mod pipe;
+mod procfs;
+mod sigio;
+mod signalfd;
 mod stdio;
+mod timerfd;
+mod times;
+mod tty;
 
-use core::{any::Any, ffi::c_int};
+use core::{
+    any::Any,
+    ffi::c_int,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
+use axhal::time::TimeValue;
 use axio::PollState;
 use axns::{ResArc, def_resource};
+use axtask::{TaskExtRef, current};
 use flatten_objects::FlattenObjects;
-use linux_raw_sys::general::{stat, statx};
+use linux_raw_sys::general::{O_RDWR, RLIMIT_NOFILE, stat, statx};
 use spin::RwLock;
+use starry_core::task::{ProcessData, processes, try_processes};
 
 pub use self::{
+    dev::{CONSOLE_DEVICE, DevFile, TTY_DEVICE, device_at},
+    dnotify::{any_dir_notify, notify_dir_change, set_dir_notify},
+    epoll::Epoll,
+    eventfd::EventFd,
     fs::{Directory, File},
+    mqueue::{MessageQueue, MqAttr, MqNotification, MqQueue},
     net::Socket,
+    orphan::{is_orphan, move_open_paths, remove_file},
+    perms::{file_perm, forget_file_perm, move_file_perm, set_file_perm},
+    pidfd::PidFd,
     pipe::Pipe,
+    procfs::{ProcFile, memory_usage},
+    sigio::{io_signal, send_io_signal, set_io_signal},
+    signalfd::SignalFd,
+    timerfd::{TimerFd, notify_wall_time_set},
+    times::{
+        FileTimes, forget_file_times, move_file_times, shared_file_times, touch_atime, touch_mtime,
+    },
+    tty::{Terminal, Tty, console},
 };
 
+pub(crate) use self::event::WaitEvent;
+
 pub const AX_FILE_LIMIT: usize = 1024;
 
 #[derive(Debug, Clone, Copy)]
@@ -31,6 +71,11 @@ pub struct Kstat {
     size: u64,
     blocks: u64,
     blksize: u32,
+    atime: TimeValue,
+    mtime: TimeValue,
+    ctime: TimeValue,
+    /// The major and minor numbers of a device file.
+    rdev: (u32, u32),
 }
 
 impl Default for Kstat {
@@ -44,6 +89,10 @@ impl Default for Kstat {
             size: 0,
             blocks: 0,
             blksize: 4096,
+            atime: TimeValue::ZERO,
+            mtime: TimeValue::ZERO,
+            ctime: TimeValue::ZERO,
+            rdev: (0, 0),
         }
     }
 }
@@ -60,6 +109,17 @@ impl From<Kstat> for stat {
         stat.st_size = value.size as _;
         stat.st_blksize = value.blksize as _;
         stat.st_blocks = value.blocks as _;
+        stat.st_atime = value.atime.as_secs() as _;
+        stat.st_atime_nsec = value.atime.subsec_nanos() as _;
+        stat.st_mtime = value.mtime.as_secs() as _;
+        stat.st_mtime_nsec = value.mtime.subsec_nanos() as _;
+        stat.st_ctime = value.ctime.as_secs() as _;
+        stat.st_ctime_nsec = value.ctime.subsec_nanos() as _;
+        let (major, minor) = (value.rdev.0 as u64, value.rdev.1 as u64);
+        stat.st_rdev = ((major & 0xfffff000) << 32
+            | (major & 0xfff) << 8
+            | (minor & 0xffffff00) << 12
+            | (minor & 0xff)) as _;
 
         stat
     }
@@ -78,6 +138,14 @@ impl From<Kstat> for statx {
         statx.stx_ino = value.ino as _;
         statx.stx_size = value.size as _;
         statx.stx_blocks = value.blocks as _;
+        statx.stx_atime.tv_sec = value.atime.as_secs() as _;
+        statx.stx_atime.tv_nsec = value.atime.subsec_nanos() as _;
+        statx.stx_mtime.tv_sec = value.mtime.as_secs() as _;
+        statx.stx_mtime.tv_nsec = value.mtime.subsec_nanos() as _;
+        statx.stx_ctime.tv_sec = value.ctime.as_secs() as _;
+        statx.stx_ctime.tv_nsec = value.ctime.subsec_nanos() as _;
+        statx.stx_rdev_major = value.rdev.0;
+        statx.stx_rdev_minor = value.rdev.1;
 
         statx
     }
@@ -92,6 +160,34 @@ pub trait FileLike: Send + Sync {
     fn poll(&self) -> LinuxResult<PollState>;
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 
+    /// The access mode and status flags of the open file, as reported by
+    /// `fcntl(F_GETFL)`.
+    fn status_flags(&self) -> u32 {
+        O_RDWR
+    }
+
+    /// Whether the other end of the file is closed, which `epoll` reports as
+    /// `EPOLLHUP` whether it is watched for or not.
+    fn hangup(&self) -> bool {
+        false
+    }
+
+    /// Manipulates the underlying device parameters of special files.
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> LinuxResult<isize> {
+        Err(LinuxError::ENOTTY)
+    }
+
+    /// Writes back buffered data. Called every time a file descriptor
+    /// referring to the file is closed.
+    fn flush(&self) -> LinuxResult {
+        Ok(())
+    }
+
+    /// Called every time a file descriptor referring to the file is closed,
+    /// by the process it belongs to, whether explicitly, by being replaced
+    /// with `dup2`, on `execve` or when the process exits.
+    fn close(&self) {}
+
     fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>>
     where
         Self: Sized + 'static,
@@ -102,21 +198,31 @@ pub trait FileLike: Send + Sync {
             .map_err(|_| LinuxError::EINVAL)
     }
 
-    fn add_to_fd_table(self) -> LinuxResult<c_int>
+    /// Add the file to the file descriptor table, with `FD_CLOEXEC` set if
+    /// `cloexec` is true.
+    fn add_to_fd_table(self, cloexec: bool) -> LinuxResult<c_int>
     where
         Self: Sized + 'static,
     {
-        add_file_like(Arc::new(self))
+        add_file_like(Arc::new(self), cloexec)
     }
 }
 
+/// An entry of the file descriptor table.
+#[derive(Clone)]
+pub struct FileDescriptor {
+    pub file: Arc<dyn FileLike>,
+    /// Whether the descriptor is closed by `execve` (`FD_CLOEXEC`).
+    pub cloexec: bool,
+}
+
 def_resource! {
-    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>> = ResArc::new();
+    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<FileDescriptor, AX_FILE_LIMIT>>> = ResArc::new();
 }
 
 impl FD_TABLE {
     /// Return a copy of the inner table.
-    pub fn copy_inner(&self) -> RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> {
+    pub fn copy_inner(&self) -> RwLock<FlattenObjects<FileDescriptor, AX_FILE_LIMIT>> {
         let table = self.read();
         let mut new_table = FlattenObjects::new();
         for id in table.ids() {
@@ -125,50 +231,198 @@ impl FD_TABLE {
         RwLock::new(new_table)
     }
 
+    /// Close the descriptors that have `FD_CLOEXEC` set.
+    pub fn close_on_exec(&self) {
+        let mut table = self.write();
+        let ids = table
+            .ids()
+            .filter(|&id| table.get(id).unwrap().cloexec)
+            .collect::<Vec<_>>();
+        let files = ids
+            .into_iter()
+            .filter_map(|id| table.remove(id))
+            .collect::<Vec<_>>();
+        drop(table);
+        for fd in files {
+            fd.file.close();
+            let _ = fd.file.flush();
+        }
+    }
+
     pub fn clear(&self) {
         let mut table = self.write();
         let ids = table.ids().collect::<Vec<_>>();
-        for id in ids {
-            let _ = table.remove(id);
+        let files = ids
+            .into_iter()
+            .filter_map(|id| table.remove(id))
+            .collect::<Vec<_>>();
+        drop(table);
+        for fd in files {
+            fd.file.close();
+        }
+    }
+
+    /// Return the files in the table.
+    fn files(&self) -> Vec<Arc<dyn FileLike>> {
+        let table = self.read();
+        table
+            .ids()
+            .filter_map(|id| table.get(id))
+            .map(|fd| fd.file.clone())
+            .collect()
+    }
+}
+
+/// Write back the buffered data of the files open in every process, as
+/// `sync` does.
+///
+/// Also run when the kernel shuts down, so that nothing is lost then. Errors
+/// are ignored, so that one failing file does not keep the others from
+/// being written.
+pub fn sync_all() {
+    for process in processes() {
+        let Some(data) = process.data::<ProcessData>() else {
+            continue;
+        };
+        for file in FD_TABLE.deref_from(&data.ns).files() {
+            let _ = file.flush();
         }
     }
 }
 
+/// Write back what can be of the open files, like [`sync_all`], while the
+/// kernel panics.
+///
+/// The tables locked at the time, maybe by the panicking task, are skipped
+/// rather than waited for. Does nothing if the write-back panics itself.
+pub fn sync_on_panic() {
+    static SYNCING: AtomicBool = AtomicBool::new(false);
+    if SYNCING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    for process in try_processes().unwrap_or_default() {
+        let Some(data) = process.data::<ProcessData>() else {
+            continue;
+        };
+        let Some(table) = FD_TABLE.deref_from(&data.ns).try_read() else {
+            continue;
+        };
+        let files = table
+            .ids()
+            .filter_map(|id| table.get(id))
+            .map(|fd| fd.file.clone())
+            .collect::<Vec<_>>();
+        drop(table);
+        for file in files {
+            let _ = file.flush();
+        }
+    }
+    ax_println!("Emergency Sync complete");
+}
+
+/// Return the paths of the files and directories open in every process.
+pub fn open_file_paths() -> Vec<String> {
+    let mut paths = Vec::new();
+    for process in processes() {
+        let Some(data) = process.data::<ProcessData>() else {
+            continue;
+        };
+        for file in FD_TABLE.deref_from(&data.ns).files() {
+            let file = file.into_any();
+            if let Some(file) = file.downcast_ref::<File>() {
+                paths.push(file.path());
+            } else if let Some(dir) = file.downcast_ref::<Directory>() {
+                paths.push(dir.path());
+            }
+        }
+    }
+    paths
+}
+
 /// Get a file-like object by `fd`.
 pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
     FD_TABLE
         .read()
         .get(fd as usize)
-        .cloned()
+        .map(|fd| fd.file.clone())
         .ok_or(LinuxError::EBADF)
 }
 
-/// Add a file to the file descriptor table.
-pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)? as c_int)
+/// Return the open file limit of the current process: the soft
+/// `RLIMIT_NOFILE`, capped by the size of the table.
+///
+/// New file descriptors must be below it. Those already open stay valid if
+/// the limit is lowered.
+pub fn fd_limit() -> usize {
+    let limit = current().task_ext().process_data().rlimits.read()[RLIMIT_NOFILE].current;
+    limit.min(AX_FILE_LIMIT as u64) as usize
+}
+
+/// Add a file to the file descriptor table at the lowest free descriptor,
+/// with `FD_CLOEXEC` set if `cloexec` is true.
+///
+/// Fails with `EMFILE` if every descriptor below [`fd_limit`] is taken.
+pub fn add_file_like(f: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<c_int> {
+    let limit = fd_limit();
+    let mut table = FD_TABLE.write();
+    let fd = (0..limit)
+        .find(|&fd| !table.is_assigned(fd))
+        .ok_or(LinuxError::EMFILE)?;
+    table
+        .add_at(fd, FileDescriptor { file: f, cloexec })
+        .map_err(|_| LinuxError::EMFILE)?;
+    Ok(fd as c_int)
+}
+
+/// Get whether `fd` has `FD_CLOEXEC` set.
+pub fn get_cloexec(fd: c_int) -> LinuxResult<bool> {
+    FD_TABLE
+        .read()
+        .get(fd as usize)
+        .map(|fd| fd.cloexec)
+        .ok_or(LinuxError::EBADF)
+}
+
+/// Set or clear `FD_CLOEXEC` on `fd`.
+pub fn set_cloexec(fd: c_int, cloexec: bool) -> LinuxResult {
+    FD_TABLE
+        .write()
+        .get_mut(fd as usize)
+        .ok_or(LinuxError::EBADF)?
+        .cloexec = cloexec;
+    Ok(())
 }
 
 /// Close a file by `fd`.
+///
+/// Fails with `EBADF` if `fd` is not open, or with `EIO` if writing back
+/// buffered data fails.
 pub fn close_file_like(fd: c_int) -> LinuxResult {
     let f = FD_TABLE
         .write()
         .remove(fd as usize)
         .ok_or(LinuxError::EBADF)?;
-    debug!("close_file_like <= count: {}", Arc::strong_count(&f));
-    Ok(())
+    debug!("close_file_like <= count: {}", Arc::strong_count(&f.file));
+    f.file.close();
+    // The descriptor is free even if the flush fails.
+    f.file.flush()
 }
 
 #[ctor_bare::register_ctor]
 fn init_stdio() {
     let mut fd_table = flatten_objects::FlattenObjects::new();
+    let entry = |file: Arc<dyn FileLike>| FileDescriptor {
+        file,
+        cloexec: false,
+    };
     fd_table
-        .add_at(0, Arc::new(stdio::stdin()) as _)
+        .add_at(0, entry(Arc::new(stdio::stdin())))
         .unwrap_or_else(|_| panic!()); // stdin
     fd_table
-        .add_at(1, Arc::new(stdio::stdout()) as _)
+        .add_at(1, entry(Arc::new(stdio::stdout())))
         .unwrap_or_else(|_| panic!()); // stdout
     fd_table
-        .add_at(2, Arc::new(stdio::stdout()) as _)
+        .add_at(2, entry(Arc::new(stdio::stdout())))
         .unwrap_or_else(|_| panic!()); // stderr
     FD_TABLE.init_new(spin::RwLock::new(fd_table));
 }
@@ -0,0 +1,352 @@
+use core::{
+    any::Any,
+    ffi::c_long,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::vec_deque::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::TimeValue;
+use axio::PollState;
+use axprocess::{Pid, Process};
+use axsignal::{SignalInfo, Signo};
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY, S_IFREG, SI_MESGQ, sigval};
+
+use super::{FileLike, Kstat, event::WaitEvent};
+use crate::{
+    current_credentials,
+    signal::{has_interrupting_signal, send_signal_process},
+    time::wall_time,
+};
+
+/// `struct mq_attr`, the attributes of a message queue.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MqAttr {
+    /// `O_NONBLOCK` or 0.
+    pub mq_flags: c_long,
+    /// The maximum number of messages.
+    pub mq_maxmsg: c_long,
+    /// The maximum size of a message.
+    pub mq_msgsize: c_long,
+    /// The number of messages on the queue.
+    pub mq_curmsgs: c_long,
+    __reserved: [c_long; 4],
+}
+
+struct MqMessage {
+    prio: u32,
+    data: Vec<u8>,
+}
+
+/// The process to notify when a message arrives on an empty queue, as
+/// registered by `mq_notify`.
+pub struct MqNotification {
+    pub pid: Pid,
+    pub process: Weak<Process>,
+    /// The signal to send, or `None` for `SIGEV_NONE`.
+    pub signo: Option<Signo>,
+    /// The `sigev_value` passed on in `si_value`.
+    pub value: usize,
+}
+
+/// A POSIX message queue, shared by all the descriptors opened on it.
+///
+/// Messages are kept by decreasing priority, and in the order they were sent
+/// within a priority.
+pub struct MqQueue {
+    maxmsg: usize,
+    msgsize: usize,
+    /// The permission bits given at creation.
+    pub mode: u32,
+    /// The owner of the queue.
+    pub uid: u32,
+    pub gid: u32,
+    messages: Mutex<VecDeque<MqMessage>>,
+    /// Notified for one receiver when a message is sent to an empty queue. A
+    /// receiver that waited passes it on if messages are left.
+    readable: WaitEvent,
+    /// Notified for one sender when a message is received from a full queue.
+    /// A sender that waited passes it on if room is left.
+    writable: WaitEvent,
+    /// The number of tasks blocked receiving, which take precedence over the
+    /// notification.
+    receivers: AtomicUsize,
+    notification: Mutex<Option<MqNotification>>,
+}
+
+impl MqQueue {
+    /// Creates an empty queue of at most `maxmsg` messages of `msgsize`
+    /// bytes, owned by the current process.
+    pub fn new(maxmsg: usize, msgsize: usize, mode: u32) -> Self {
+        let cred = current_credentials();
+        Self {
+            maxmsg,
+            msgsize,
+            mode: mode & 0o777,
+            uid: cred.euid,
+            gid: cred.egid,
+            messages: Mutex::new(VecDeque::new()),
+            readable: WaitEvent::new(),
+            writable: WaitEvent::new(),
+            receivers: AtomicUsize::new(0),
+            notification: Mutex::new(None),
+        }
+    }
+
+    /// Registers `notification`, or fails with `EBUSY` if another process
+    /// already did.
+    pub fn set_notification(&self, notification: MqNotification) -> LinuxResult {
+        let mut current = self.notification.lock();
+        if let Some(registered) = current.as_ref() {
+            if registered.pid != notification.pid && registered.process.strong_count() > 0 {
+                return Err(LinuxError::EBUSY);
+            }
+        }
+        *current = Some(notification);
+        Ok(())
+    }
+
+    /// Removes the notification if it was registered by `pid`.
+    pub fn remove_notification(&self, pid: Pid) {
+        let mut current = self.notification.lock();
+        if current.as_ref().is_some_and(|it| it.pid == pid) {
+            *current = None;
+        }
+    }
+
+    /// Sends the registered notification, which is then removed.
+    fn notify_arrival(&self) {
+        let Some(notification) = self.notification.lock().take() else {
+            return;
+        };
+        let (Some(signo), Some(proc)) = (notification.signo, notification.process.upgrade()) else {
+            return;
+        };
+        let mut sig = SignalInfo::new(signo, SI_MESGQ);
+        // SAFETY: `_rt` is the active member for `SI_MESGQ`.
+        unsafe {
+            let rt = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._rt;
+            rt._pid = current().task_ext().thread.process().pid() as _;
+            rt._uid = current_credentials().uid;
+            rt._sigval = sigval {
+                sival_ptr: notification.value as _,
+            };
+        }
+        let _ = send_signal_process(&proc, sig);
+    }
+}
+
+/// A descriptor opened on a message queue by `mq_open`.
+pub struct MessageQueue {
+    queue: Arc<MqQueue>,
+    readable: bool,
+    writable: bool,
+    nonblocking: AtomicBool,
+}
+
+impl MessageQueue {
+    pub fn new(queue: Arc<MqQueue>, readable: bool, writable: bool, nonblocking: bool) -> Self {
+        Self {
+            queue,
+            readable,
+            writable,
+            nonblocking: AtomicBool::new(nonblocking),
+        }
+    }
+
+    pub fn queue(&self) -> &Arc<MqQueue> {
+        &self.queue
+    }
+
+    /// Returns the attributes of the queue, with the flags of this
+    /// descriptor.
+    pub fn attr(&self) -> MqAttr {
+        MqAttr {
+            mq_flags: if self.nonblocking() {
+                O_NONBLOCK as _
+            } else {
+                0
+            },
+            mq_maxmsg: self.queue.maxmsg as _,
+            mq_msgsize: self.queue.msgsize as _,
+            mq_curmsgs: self.queue.messages.lock().len() as _,
+            ..Default::default()
+        }
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Acquire)
+    }
+
+    /// Waits for `event` after `seq`, unless the descriptor is non-blocking
+    /// (`EAGAIN`), a signal is pending (`EINTR`), or the wall time reached
+    /// `deadline` (`ETIMEDOUT`).
+    fn wait(&self, event: &WaitEvent, seq: u64, deadline: Option<TimeValue>) -> LinuxResult {
+        if self.nonblocking() {
+            return Err(LinuxError::EAGAIN);
+        }
+        if has_interrupting_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        let timeout = match deadline {
+            Some(deadline) => Some(
+                deadline
+                    .checked_sub(wall_time())
+                    .filter(|it| !it.is_zero())
+                    .ok_or(LinuxError::ETIMEDOUT)?,
+            ),
+            None => None,
+        };
+        event.wait(seq, timeout);
+        Ok(())
+    }
+
+    /// Sends `data` with priority `prio`, blocking while the queue is full.
+    ///
+    /// If the queue was empty and no one is waiting to receive, the
+    /// registered notification is sent.
+    pub fn send(&self, data: &[u8], prio: u32, deadline: Option<TimeValue>) -> LinuxResult {
+        if !self.writable {
+            return Err(LinuxError::EBADF);
+        }
+        if data.len() > self.queue.msgsize {
+            return Err(LinuxError::EMSGSIZE);
+        }
+
+        let queue = &self.queue;
+        let mut waited = false;
+        loop {
+            let seq = queue.writable.seq();
+            let mut messages = queue.messages.lock();
+            if messages.len() < queue.maxmsg {
+                let was_empty = messages.is_empty();
+                let index = messages
+                    .iter()
+                    .position(|msg| msg.prio < prio)
+                    .unwrap_or(messages.len());
+                let data = data.to_vec();
+                messages.insert(index, MqMessage { prio, data });
+                let is_full = messages.len() == queue.maxmsg;
+                drop(messages);
+                if waited && !is_full {
+                    queue.writable.notify_one();
+                }
+                if was_empty {
+                    queue.readable.notify_one();
+                    if queue.receivers.load(Ordering::Acquire) == 0 {
+                        queue.notify_arrival();
+                    }
+                }
+                return Ok(());
+            }
+            drop(messages);
+            self.wait(&queue.writable, seq, deadline)?;
+            waited = true;
+        }
+    }
+
+    /// Receives the oldest message of the highest priority into `buf`,
+    /// blocking while the queue is empty.
+    ///
+    /// `buf` must have room for the largest message. Returns the size and
+    /// priority of the message.
+    pub fn receive(
+        &self,
+        buf: &mut [u8],
+        deadline: Option<TimeValue>,
+    ) -> LinuxResult<(usize, u32)> {
+        if !self.readable {
+            return Err(LinuxError::EBADF);
+        }
+        if buf.len() < self.queue.msgsize {
+            return Err(LinuxError::EMSGSIZE);
+        }
+
+        let queue = &self.queue;
+        let mut waited = false;
+        let message = loop {
+            let seq = queue.readable.seq();
+            let mut messages = queue.messages.lock();
+            let was_full = messages.len() == queue.maxmsg;
+            if let Some(message) = messages.pop_front() {
+                let is_empty = messages.is_empty();
+                drop(messages);
+                if was_full {
+                    queue.writable.notify_one();
+                }
+                if waited && !is_empty {
+                    queue.readable.notify_one();
+                }
+                break message;
+            }
+            drop(messages);
+            queue.receivers.fetch_add(1, Ordering::AcqRel);
+            let result = self.wait(&queue.readable, seq, deadline);
+            queue.receivers.fetch_sub(1, Ordering::AcqRel);
+            waited = true;
+            result?;
+        };
+        buf[..message.data.len()].copy_from_slice(&message.data);
+        Ok((message.data.len(), message.prio))
+    }
+}
+
+impl FileLike for MessageQueue {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: S_IFREG | self.queue.mode,
+            uid: self.queue.uid,
+            gid: self.queue.gid,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let len = self.queue.messages.lock().len();
+        Ok(PollState {
+            readable: len > 0,
+            writable: len < self.queue.maxmsg,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn status_flags(&self) -> u32 {
+        let mode = match (self.readable, self.writable) {
+            (true, true) => O_RDWR,
+            (false, true) => O_WRONLY,
+            _ => O_RDONLY,
+        };
+        let nonblocking = self.nonblocking.load(Ordering::Acquire);
+        mode | if nonblocking { O_NONBLOCK } else { 0 }
+    }
+
+    /// Removes the notification of the current process, which is tied to
+    /// its descriptors of the queue.
+    fn close(&self) {
+        let pid = current().task_ext().thread.process().pid();
+        self.queue.remove_notification(pid);
+    }
+}
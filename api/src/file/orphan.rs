@@ -0,0 +1,194 @@
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::AxResult;
+use axsync::{Mutex, MutexGuard};
+
+use super::{FileTimes, shared_file_times};
+
+/// The path of a file or directory that is open, shared by every [`File`]
+/// or [`Directory`] open on it.
+///
+/// [`File`]: super::File
+/// [`Directory`]: super::Directory
+pub struct OpenPath {
+    /// Where the file is now, which changes if it is renamed or unlinked.
+    path: Mutex<String>,
+    /// Whether the file was unlinked, and is to be removed once closed.
+    unlinked: AtomicBool,
+    /// Held while appending, so that appends through different descriptors
+    /// do not overwrite each other.
+    append: Mutex<()>,
+    /// The pages written since the file was last flushed, as sorted ranges
+    /// of page indices that neither overlap nor touch.
+    dirty: Mutex<Vec<Range<u64>>>,
+    /// The timestamps of the file.
+    times: Arc<Mutex<FileTimes>>,
+}
+
+impl OpenPath {
+    /// Get where the file is now.
+    pub fn path(&self) -> String {
+        self.path.lock().clone()
+    }
+
+    /// Call `f` with where the file is now.
+    pub fn with_path<R>(&self, f: impl FnOnce(&str) -> R) -> R {
+        f(&self.path.lock())
+    }
+
+    /// Get the timestamps of the file.
+    pub fn times(&self) -> &Mutex<FileTimes> {
+        &self.times
+    }
+
+    /// Lock the file for appending to it.
+    pub fn lock_append(&self) -> MutexGuard<()> {
+        self.append.lock()
+    }
+
+    /// Record that the pages in `pages` were written.
+    pub fn mark_dirty(&self, mut pages: Range<u64>) {
+        let mut dirty = self.dirty.lock();
+        dirty.retain(|it| {
+            if it.start > pages.end || pages.start > it.end {
+                return true;
+            }
+            pages.start = pages.start.min(it.start);
+            pages.end = pages.end.max(it.end);
+            false
+        });
+        let pos = dirty.partition_point(|it| it.start < pages.start);
+        dirty.insert(pos, pages);
+    }
+
+    /// Count the pages in `pages` that were written since the last flush.
+    pub fn dirty_pages(&self, pages: Range<u64>) -> u64 {
+        self.dirty
+            .lock()
+            .iter()
+            .map(|it| {
+                it.end
+                    .min(pages.end)
+                    .saturating_sub(it.start.max(pages.start))
+            })
+            .sum()
+    }
+
+    /// Record that the file was flushed.
+    pub fn mark_clean(&self) {
+        self.dirty.lock().clear();
+    }
+}
+
+impl Drop for OpenPath {
+    fn drop(&mut self) {
+        let path = self.path.lock();
+        let mut open = OPEN_PATHS.lock();
+        if open.get(&*path).is_some_and(|it| it.strong_count() == 0) {
+            open.remove(&*path);
+        }
+        drop(open);
+        if self.unlinked.load(Ordering::Acquire) {
+            let _ = axfs::api::remove_file(&path);
+        }
+    }
+}
+
+/// The files that are open, by path.
+static OPEN_PATHS: Mutex<BTreeMap<String, Weak<OpenPath>>> = Mutex::new(BTreeMap::new());
+
+/// Numbers the hidden names of unlinked files.
+static NEXT_ORPHAN: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers that a file at `path` is open, until the returned value and its
+/// clones are dropped.
+pub fn open_path(path: &str) -> Arc<OpenPath> {
+    let mut open = OPEN_PATHS.lock();
+    if let Some(it) = open.get(path).and_then(Weak::upgrade) {
+        return it;
+    }
+    let it = Arc::new(OpenPath {
+        path: Mutex::new(path.into()),
+        unlinked: AtomicBool::new(false),
+        append: Mutex::new(()),
+        dirty: Mutex::new(Vec::new()),
+        times: shared_file_times(path),
+    });
+    open.insert(path.into(), Arc::downgrade(&it));
+    it
+}
+
+/// Follow the rename of `old` to `new`, moving the files open below `old` as
+/// well if it is a directory.
+pub fn move_open_paths(old: &str, new: &str) {
+    let mut open = OPEN_PATHS.lock();
+    let moved = open
+        .keys()
+        .filter(|path| {
+            path.strip_prefix(old)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    // Dropping the last reference takes the lock, so the upgraded references
+    // are only dropped once it is released.
+    let mut alive = Vec::new();
+    for path in moved {
+        let Some(weak) = open.remove(&path) else {
+            continue;
+        };
+        let to = format!("{new}{}", &path[old.len()..]);
+        if let Some(it) = weak.upgrade() {
+            *it.path.lock() = to.clone();
+            alive.push(it);
+        }
+        open.insert(to, weak);
+    }
+    drop(open);
+}
+
+/// Remove the file at `path`.
+///
+/// If it is open, it is only renamed to a hidden name in the same directory,
+/// so that it can still be used through its descriptors, and it is removed
+/// once the last of them is closed.
+pub fn remove_file(path: &str) -> AxResult {
+    let mut open = OPEN_PATHS.lock();
+    // Dropping the last reference takes the lock, so it must be released
+    // before `it` is dropped.
+    let Some(it) = open.get(path).and_then(Weak::upgrade) else {
+        drop(open);
+        return axfs::api::remove_file(path);
+    };
+    let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let hidden = format!(
+        "{dir}/.unlinked-{}",
+        NEXT_ORPHAN.fetch_add(1, Ordering::Relaxed)
+    );
+    if let Err(err) = axfs::api::rename(path, &hidden) {
+        drop(open);
+        return Err(err);
+    }
+    open.remove(path);
+    open.insert(hidden.clone(), Arc::downgrade(&it));
+    *it.path.lock() = hidden;
+    it.unlinked.store(true, Ordering::Release);
+    drop(open);
+    Ok(())
+}
+
+/// Whether `path` is the hidden name of a file that was unlinked while open.
+pub fn is_orphan(path: &str) -> bool {
+    let it = OPEN_PATHS.lock().get(path).and_then(Weak::upgrade);
+    it.is_some_and(|it| it.unlinked.load(Ordering::Acquire))
+}
@@ -0,0 +1,33 @@
+use alloc::{collections::btree_map::BTreeMap, string::String};
+use axsync::Mutex;
+
+/// The permission bits files were created with, by path.
+///
+/// The file systems do not keep them, so files created before boot or
+/// without a mode report what the file system gives.
+static FILE_PERMS: Mutex<BTreeMap<String, u32>> = Mutex::new(BTreeMap::new());
+
+/// Records the permission bits of the file just created at `path`.
+pub fn set_file_perm(path: &str, perm: u32) {
+    FILE_PERMS.lock().insert(path.into(), perm & 0o7777);
+}
+
+/// Returns the permission bits of the file at `path`, or `default` if they
+/// were never recorded.
+pub fn file_perm(path: &str, default: u32) -> u32 {
+    FILE_PERMS.lock().get(path).copied().unwrap_or(default)
+}
+
+/// Moves the permission bits of the file at `old` to `new` after a rename.
+pub fn move_file_perm(old: &str, new: &str) {
+    let mut table = FILE_PERMS.lock();
+    match table.remove(old) {
+        Some(perm) => table.insert(new.into(), perm),
+        None => table.remove(new),
+    };
+}
+
+/// Drops the permission bits of the file at `path` once it is removed.
+pub fn forget_file_perm(path: &str) {
+    FILE_PERMS.lock().remove(path);
+}
@@ -0,0 +1,65 @@
+use core::any::Any;
+
+use alloc::sync::{Arc, Weak};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axprocess::Process;
+
+use super::{FileLike, Kstat};
+
+/// A file descriptor referring to a process.
+///
+/// It does not keep the process alive, and becomes readable once the process
+/// has exited.
+pub struct PidFd {
+    proc: Weak<Process>,
+}
+
+impl PidFd {
+    /// Create a new pidfd referring to `proc`.
+    pub fn new(proc: &Arc<Process>) -> Self {
+        Self {
+            proc: Arc::downgrade(proc),
+        }
+    }
+
+    /// Returns the process this refers to, or `ESRCH` if it has exited.
+    pub fn process(&self) -> LinuxResult<Arc<Process>> {
+        self.proc
+            .upgrade()
+            .filter(|proc| !proc.is_zombie())
+            .ok_or(LinuxError::ESRCH)
+    }
+}
+
+impl FileLike for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: 0o600u32, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.process().is_err(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
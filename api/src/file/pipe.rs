@@ -1,12 +1,25 @@
-use core::any::Any;
+use core::{
+    any::Any,
+    ffi::c_int,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use alloc::sync::Arc;
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
+use axsignal::{SignalInfo, Signo};
 use axsync::Mutex;
-use linux_raw_sys::general::S_IFIFO;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{O_NONBLOCK, O_RDONLY, O_WRONLY, S_IFIFO, SI_KERNEL},
+    ioctl::FIONREAD,
+};
 
-use super::{FileLike, Kstat};
+use super::{FileLike, Kstat, event::WaitEvent};
+use crate::{
+    ptr::UserPtr,
+    signal::{has_interrupting_signal, send_signal_thread},
+};
 
 #[derive(Copy, Clone, PartialEq)]
 enum RingBufferStatus {
@@ -15,19 +28,24 @@ enum RingBufferStatus {
     Normal,
 }
 
-const RING_BUFFER_SIZE: usize = 256;
+/// The capacity of a pipe.
+const RING_BUFFER_SIZE: usize = 65536;
+
+/// Writes of up to this size are atomic: they are not interleaved with the
+/// data of other writers.
+const PIPE_BUF: usize = 4096;
 
 struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Box<[u8]>,
     head: usize,
     tail: usize,
     status: RingBufferStatus,
 }
 
 impl PipeRingBuffer {
-    const fn new() -> Self {
+    fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; RING_BUFFER_SIZE].into_boxed_slice(),
             head: 0,
             tail: 0,
             status: RingBufferStatus::Empty,
@@ -53,6 +71,11 @@ impl PipeRingBuffer {
         c
     }
 
+    /// Get the byte `offset` bytes past the head, leaving it in the buffer.
+    fn peek_byte(&self, offset: usize) -> u8 {
+        self.arr[(self.head + offset) % RING_BUFFER_SIZE]
+    }
+
     /// Get the length of remaining data in the buffer
     const fn available_read(&self) -> usize {
         if matches!(self.status, RingBufferStatus::Empty) {
@@ -74,21 +97,55 @@ impl PipeRingBuffer {
     }
 }
 
+/// The state shared by the two ends of a pipe.
+///
+/// Taking bytes out of the buffer holds `reader`, and putting bytes in holds
+/// `writer`, both before `buffer`. Splicing keeps them across the transfer to
+/// or from the other file, so that no other reader takes the same bytes and
+/// no other writer takes the room meanwhile.
+struct PipeShared {
+    buffer: Mutex<PipeRingBuffer>,
+    reader: Mutex<()>,
+    writer: Mutex<()>,
+    /// Notified for one reader when data is written to an empty pipe, and
+    /// for all when the write end is closed. A reader that waited passes it
+    /// on if it leaves data behind.
+    readable: WaitEvent,
+    /// Notified for one writer when data is read from a pipe with less than
+    /// [`PIPE_BUF`] bytes of room, and for all when the read end is closed.
+    /// A writer that waited passes it on if it leaves room behind, or if the
+    /// room grew but is still too small for its write.
+    writable: WaitEvent,
+    read_closed: AtomicBool,
+    write_closed: AtomicBool,
+}
+
 pub struct Pipe {
     readable: bool,
-    buffer: Arc<Mutex<PipeRingBuffer>>,
+    shared: Arc<PipeShared>,
+    nonblocking: AtomicBool,
 }
 
 impl Pipe {
     pub fn new() -> (Pipe, Pipe) {
-        let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
+        let shared = Arc::new(PipeShared {
+            buffer: Mutex::new(PipeRingBuffer::new()),
+            reader: Mutex::new(()),
+            writer: Mutex::new(()),
+            readable: WaitEvent::new(),
+            writable: WaitEvent::new(),
+            read_closed: AtomicBool::new(false),
+            write_closed: AtomicBool::new(false),
+        });
         let read_end = Pipe {
             readable: true,
-            buffer: buffer.clone(),
+            shared: shared.clone(),
+            nonblocking: AtomicBool::new(false),
         };
         let write_end = Pipe {
             readable: false,
-            buffer,
+            shared,
+            nonblocking: AtomicBool::new(false),
         };
         (read_end, write_end)
     }
@@ -101,12 +158,259 @@ impl Pipe {
         !self.readable
     }
 
+    /// Whether the other end of the pipe is closed.
     pub fn closed(&self) -> bool {
-        Arc::strong_count(&self.buffer) == 1
+        let other_closed = if self.readable {
+            &self.shared.write_closed
+        } else {
+            &self.shared.read_closed
+        };
+        other_closed.load(Ordering::Acquire)
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Acquire)
+    }
+
+    /// Waits for `event` after `seq`, unless `nonblocking` or the pipe is
+    /// non-blocking (`EAGAIN`), or a signal is pending (`EINTR`).
+    fn wait(&self, event: &WaitEvent, seq: u64, nonblocking: bool) -> LinuxResult {
+        if nonblocking || self.nonblocking() {
+            return Err(LinuxError::EAGAIN);
+        }
+        if has_interrupting_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        event.wait(seq, None);
+        Ok(())
+    }
+
+    /// Pass up to `len` bytes from the front of the pipe to `f`, then remove
+    /// the number of bytes `f` returns.
+    ///
+    /// Blocks while the pipe is empty, like [`FileLike::read`], and fails
+    /// with `EAGAIN` instead if `nonblocking`. Returns 0 without calling `f`
+    /// once the pipe is empty and the write end is closed.
+    ///
+    /// `f` runs on a copy of the bytes without the buffer locked, since it may
+    /// block, but other readers wait until it is done.
+    pub fn drain_with(
+        &self,
+        len: usize,
+        nonblocking: bool,
+        f: impl FnOnce(&[u8]) -> LinuxResult<usize>,
+    ) -> LinuxResult<usize> {
+        if !self.readable() {
+            return Err(LinuxError::EBADF);
+        }
+        let mut waited = false;
+        loop {
+            let seq = self.shared.readable.seq();
+            let reader = self.shared.reader.lock();
+            let ring_buffer = self.shared.buffer.lock();
+            let size = ring_buffer.available_read().min(len);
+            if size == 0 {
+                if self.closed() {
+                    return Ok(0);
+                }
+                drop((ring_buffer, reader));
+                self.wait(&self.shared.readable, seq, nonblocking)?;
+                waited = true;
+                continue;
+            }
+            let data = (0..size)
+                .map(|i| ring_buffer.peek_byte(i))
+                .collect::<Vec<_>>();
+            drop(ring_buffer);
+
+            let taken = f(&data)?.min(size);
+            let mut ring_buffer = self.shared.buffer.lock();
+            let was_short = ring_buffer.available_write() < PIPE_BUF;
+            for _ in 0..taken {
+                ring_buffer.read_byte();
+            }
+            let left = ring_buffer.available_read();
+            drop((ring_buffer, reader));
+            if was_short && taken > 0 {
+                self.shared.writable.notify_one();
+            }
+            if waited && left > 0 {
+                self.shared.readable.notify_one();
+            }
+            return Ok(taken);
+        }
+    }
+
+    /// Let `f` fill a buffer of up to `len` bytes, then append the number of
+    /// bytes `f` returns to the pipe.
+    ///
+    /// Blocks while the pipe is full, like [`FileLike::write`], and fails
+    /// with `EAGAIN` instead if `nonblocking`. With the read end closed,
+    /// fails with `EPIPE` and raises `SIGPIPE`.
+    ///
+    /// `f` fills a separate buffer without the buffer locked, since it may
+    /// block, but other writers wait until it is done. So the room found
+    /// before is still there for all the bytes `f` took from their source.
+    pub fn fill_with(
+        &self,
+        len: usize,
+        nonblocking: bool,
+        f: impl FnOnce(&mut [u8]) -> LinuxResult<usize>,
+    ) -> LinuxResult<usize> {
+        if !self.writable() {
+            return Err(LinuxError::EBADF);
+        }
+        let mut waited = false;
+        loop {
+            let seq = self.shared.writable.seq();
+            let writer = self.shared.writer.lock();
+            let ring_buffer = self.shared.buffer.lock();
+            if self.closed() {
+                drop((ring_buffer, writer));
+                return Err(broken_pipe());
+            }
+            let size = ring_buffer.available_write().min(len);
+            if size == 0 {
+                drop((ring_buffer, writer));
+                self.wait(&self.shared.writable, seq, nonblocking)?;
+                waited = true;
+                continue;
+            }
+            drop(ring_buffer);
+
+            let mut data = vec![0; size];
+            let filled = f(&mut data)?.min(size);
+            let mut ring_buffer = self.shared.buffer.lock();
+            if self.closed() {
+                drop((ring_buffer, writer));
+                return Err(broken_pipe());
+            }
+            let was_empty = ring_buffer.available_read() == 0;
+            for &c in &data[..filled] {
+                ring_buffer.write_byte(c);
+            }
+            let room = ring_buffer.available_write();
+            drop((ring_buffer, writer));
+            if was_empty && filled > 0 {
+                self.shared.readable.notify_one();
+            }
+            if waited && room > 0 {
+                self.shared.writable.notify_one();
+            }
+            return Ok(filled);
+        }
+    }
+
+    /// Move up to `len` bytes from this read end to the write end `out`, or
+    /// copy them and leave them in this pipe if `keep` is true.
+    ///
+    /// Blocks while this pipe is empty or `out` is full, like
+    /// [`Pipe::drain_with`] and [`Pipe::fill_with`] respectively. The two
+    /// ends must belong to different pipes.
+    pub fn transfer_to(
+        &self,
+        out: &Pipe,
+        len: usize,
+        nonblocking: bool,
+        keep: bool,
+    ) -> LinuxResult<usize> {
+        if !self.readable() || !out.writable() {
+            return Err(LinuxError::EBADF);
+        }
+        if Arc::ptr_eq(&self.shared, &out.shared) {
+            return Err(LinuxError::EINVAL);
+        }
+        let (mut read_waited, mut write_waited) = (false, false);
+        loop {
+            let in_seq = self.shared.readable.seq();
+            let out_seq = out.shared.writable.seq();
+            let reader = self.shared.reader.lock();
+            let writer = out.shared.writer.lock();
+            // Lock in a fixed order, so that transfers in opposite directions
+            // cannot deadlock.
+            let (mut src, mut dst) = if Arc::as_ptr(&self.shared) < Arc::as_ptr(&out.shared) {
+                let src = self.shared.buffer.lock();
+                (src, out.shared.buffer.lock())
+            } else {
+                let dst = out.shared.buffer.lock();
+                (self.shared.buffer.lock(), dst)
+            };
+            if src.available_read() == 0 {
+                if self.closed() {
+                    return Ok(0);
+                }
+                drop((src, dst, reader, writer));
+                self.wait(&self.shared.readable, in_seq, nonblocking)?;
+                read_waited = true;
+                continue;
+            }
+            if out.closed() {
+                drop((src, dst, reader, writer));
+                return Err(broken_pipe());
+            }
+            let size = src.available_read().min(dst.available_write()).min(len);
+            if size == 0 {
+                drop((src, dst, reader, writer));
+                out.wait(&out.shared.writable, out_seq, nonblocking)?;
+                write_waited = true;
+                continue;
+            }
+
+            let was_empty = dst.available_read() == 0;
+            for i in 0..size {
+                dst.write_byte(src.peek_byte(i));
+            }
+            let was_short = src.available_write() < PIPE_BUF;
+            if !keep {
+                for _ in 0..size {
+                    src.read_byte();
+                }
+            }
+            let (left, room) = (src.available_read(), dst.available_write());
+            drop((src, dst, reader, writer));
+            if was_empty {
+                out.shared.readable.notify_one();
+            }
+            if was_short && !keep {
+                self.shared.writable.notify_one();
+            }
+            if read_waited && left > 0 {
+                self.shared.readable.notify_one();
+            }
+            if write_waited && room > 0 {
+                out.shared.writable.notify_one();
+            }
+            return Ok(size);
+        }
+    }
+}
+
+/// Raise `SIGPIPE` for a write to a pipe with no reader, and return `EPIPE`.
+fn broken_pipe() -> LinuxError {
+    let curr = current();
+    let sig = SignalInfo::new(Signo::SIGPIPE, SI_KERNEL as _);
+    let _ = send_signal_thread(&curr.task_ext().thread, sig);
+    LinuxError::EPIPE
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if self.readable {
+            self.shared.read_closed.store(true, Ordering::Release);
+            self.shared.writable.notify();
+        } else {
+            self.shared.write_closed.store(true, Ordering::Release);
+            self.shared.readable.notify();
+        }
     }
 }
 
 impl FileLike for Pipe {
+    /// Read from the pipe, blocking while it is empty.
+    ///
+    /// Returns 0 only once the pipe is empty and the write end is closed.
+    /// An empty pipe with the write end open fails with `EAGAIN` in
+    /// non-blocking mode, or with `EINTR` if a signal arrives while blocked.
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
         if !self.readable() {
             return Err(LinuxError::EPERM);
@@ -115,56 +419,123 @@ impl FileLike for Pipe {
             return Ok(0);
         }
 
+        let mut waited = false;
         loop {
-            let mut ring_buffer = self.buffer.lock();
+            let seq = self.shared.readable.seq();
+            let reader = self.shared.reader.lock();
+            let mut ring_buffer = self.shared.buffer.lock();
             let read_size = ring_buffer.available_read().min(buf.len());
             if read_size == 0 {
                 if self.closed() {
                     return Ok(0);
                 }
-                drop(ring_buffer);
+                drop((ring_buffer, reader));
+                if self.nonblocking() {
+                    return Err(LinuxError::EAGAIN);
+                }
+                if has_interrupting_signal() {
+                    return Err(LinuxError::EINTR);
+                }
                 // Data not ready, wait for write end
-                axtask::yield_now(); // TODO: use synconize primitive
+                self.shared.readable.wait(seq, None);
+                waited = true;
                 continue;
             }
+            // Writers may be waiting for room for a whole atomic write.
+            let was_short = ring_buffer.available_write() < PIPE_BUF;
             for c in buf.iter_mut().take(read_size) {
                 *c = ring_buffer.read_byte();
             }
+            let left = ring_buffer.available_read();
+            drop((ring_buffer, reader));
+            if was_short {
+                self.shared.writable.notify_one();
+            }
+            if waited && left > 0 {
+                self.shared.readable.notify_one();
+            }
             return Ok(read_size);
         }
     }
 
+    /// Write `buf` to the pipe, blocking while it is full.
+    ///
+    /// In non-blocking mode, or if a signal arrives while blocked, the write
+    /// ends early: the bytes written so far are returned, or `EAGAIN` or
+    /// `EINTR` respectively if there are none. Writing nothing always
+    /// returns 0 right away.
+    ///
+    /// Writes of up to [`PIPE_BUF`] bytes wait until they fit as a whole, so
+    /// that they are never split. Larger ones may be interleaved with the data
+    /// of other writers.
+    ///
+    /// With the read end closed, fails with `EPIPE` and raises `SIGPIPE`,
+    /// unless some bytes were already written.
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
         if !self.writable() {
             return Err(LinuxError::EPERM);
         }
-        if self.closed() {
-            return Err(LinuxError::EPIPE);
-        }
         if buf.is_empty() {
             return Ok(0);
         }
 
         let mut write_size = 0usize;
-        let total_len = buf.len();
+        // The room last seen before waiting.
+        let mut seen_room = None;
         loop {
-            let mut ring_buffer = self.buffer.lock();
-            let loop_write = ring_buffer.available_write();
-            if loop_write == 0 {
-                if self.closed() {
+            let seq = self.shared.writable.seq();
+            let writer = self.shared.writer.lock();
+            let mut ring_buffer = self.shared.buffer.lock();
+            if self.closed() {
+                drop((ring_buffer, writer));
+                if write_size > 0 {
                     return Ok(write_size);
                 }
-                drop(ring_buffer);
-                // Buffer is full, wait for read end to consume
-                axtask::yield_now(); // TODO: use synconize primitive
-                continue;
+                return Err(broken_pipe());
             }
-            for _ in 0..loop_write {
-                if write_size == total_len {
-                    return Ok(write_size);
+            let available = ring_buffer.available_write();
+            let loop_write = if buf.len() <= PIPE_BUF && available < buf.len() {
+                0
+            } else {
+                available.min(buf.len() - write_size)
+            };
+            if loop_write == 0 {
+                drop((ring_buffer, writer));
+                if seen_room.is_some_and(|seen| available > seen) {
+                    // Woken up for room too small for this write, which may
+                    // be enough for another one.
+                    self.shared.writable.notify_one();
                 }
-                ring_buffer.write_byte(buf[write_size]);
-                write_size += 1;
+                let err = if self.nonblocking() {
+                    LinuxError::EAGAIN
+                } else if has_interrupting_signal() {
+                    LinuxError::EINTR
+                } else {
+                    // Buffer is full, wait for read end to consume
+                    seen_room = Some(available);
+                    self.shared.writable.wait(seq, None);
+                    continue;
+                };
+                return match write_size {
+                    0 => Err(err),
+                    _ => Ok(write_size),
+                };
+            }
+            let was_empty = ring_buffer.available_read() == 0;
+            for &c in &buf[write_size..write_size + loop_write] {
+                ring_buffer.write_byte(c);
+            }
+            write_size += loop_write;
+            let room = ring_buffer.available_write();
+            drop((ring_buffer, writer));
+            if was_empty {
+                self.shared.readable.notify_one();
+            }
+            if write_size == buf.len() {
+                if seen_room.is_some() && room > 0 {
+                    self.shared.writable.notify_one();
+                }
+                return Ok(write_size);
             }
         }
     }
@@ -181,14 +552,39 @@ impl FileLike for Pipe {
     }
 
     fn poll(&self) -> LinuxResult<PollState> {
-        let buf = self.buffer.lock();
+        let buf = self.shared.buffer.lock();
+        // A closed other end makes reads return EOF and writes fail right away.
+        // Writing is only reported once a write of PIPE_BUF bytes would fit.
         Ok(PollState {
-            readable: self.readable() && buf.available_read() > 0,
-            writable: self.writable() && buf.available_write() > 0,
+            readable: self.readable() && (buf.available_read() > 0 || self.closed()),
+            writable: self.writable() && (buf.available_write() >= PIPE_BUF || self.closed()),
         })
     }
 
-    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+    /// The read end hangs up once the write end is closed.
+    fn hangup(&self) -> bool {
+        self.readable() && self.closed()
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
         Ok(())
     }
+
+    fn status_flags(&self) -> u32 {
+        let mode = if self.readable() { O_RDONLY } else { O_WRONLY };
+        let nonblocking = self.nonblocking.load(Ordering::Acquire);
+        mode | if nonblocking { O_NONBLOCK } else { 0 }
+    }
+
+    /// Handles `FIONREAD`, which gives the number of bytes in the pipe from
+    /// either end.
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        if cmd != FIONREAD {
+            return Err(LinuxError::ENOTTY);
+        }
+        let available = self.shared.buffer.lock().available_read();
+        *UserPtr::<c_int>::from(arg).get_as_mut()? = available as _;
+        Ok(0)
+    }
 }
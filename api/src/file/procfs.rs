@@ -0,0 +1,247 @@
+use core::{any::Any, fmt::Write};
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axio::{PollState, SeekFrom};
+use axprocess::Process;
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::S_IFREG;
+use memory_addr::PAGE_SIZE_4K;
+use starry_core::task::{ProcessData, get_process};
+
+use super::{FileLike, Kstat};
+use crate::{TASK_COMM_LEN, current_comm};
+
+/// Generates the content of a proc file.
+type Generator = Box<dyn Fn() -> String + Send + Sync>;
+/// Acts on the bytes written to a proc file.
+type Consumer = fn(&[u8]) -> LinuxResult<usize>;
+
+/// A file under `/proc`.
+///
+/// There is no proc file system, so the supported files are recognized by
+/// path. The content is generated whenever the file is read from the start,
+/// so it stays live while being read in pieces. Only the files with a
+/// consumer can be written.
+pub struct ProcFile {
+    generate: Generator,
+    consume: Option<Consumer>,
+    /// The content last generated, and the offset read up to in it.
+    state: Mutex<(Vec<u8>, usize)>,
+}
+
+impl ProcFile {
+    fn new(generate: Generator) -> Self {
+        Self {
+            generate,
+            consume: None,
+            state: Mutex::new((Vec::new(), 0)),
+        }
+    }
+
+    /// Opens the file at `path` if it is one of the supported proc files.
+    pub fn open(path: &str) -> Option<Self> {
+        let generate: Generator = match path {
+            "/proc/sysrq-trigger" => {
+                let mut file = Self::new(Box::new(String::new));
+                file.consume = Some(sysrq_trigger);
+                return Some(file);
+            }
+            "/proc/meminfo" => Box::new(meminfo),
+            "/proc/cpuinfo" => Box::new(cpuinfo),
+            _ => {
+                let (dir, name) = path.strip_prefix("/proc/")?.split_once('/')?;
+                let is_self = matches!(dir, "self" | "thread-self");
+                let process = if is_self {
+                    current().task_ext().thread.process().clone()
+                } else {
+                    get_process(dir.parse().ok()?).ok()?
+                };
+                match name {
+                    "comm" if is_self => Box::new(|| format!("{}\n", current_comm())),
+                    "maps" => Box::new(move || maps(&process)),
+                    "status" => Box::new(move || status(&process, is_self)),
+                    _ => return None,
+                }
+            }
+        };
+        Some(Self::new(generate))
+    }
+
+    /// Whether the file can be written.
+    pub fn writable(&self) -> bool {
+        self.consume.is_some()
+    }
+
+    /// Moves the read offset, which regenerates the content if it is moved
+    /// back to the start.
+    pub fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let mut state = self.state.lock();
+        let (data, offset) = &mut *state;
+        let new = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => (*offset as u64).checked_add_signed(delta),
+            SeekFrom::End(delta) => (data.len() as u64).checked_add_signed(delta),
+        };
+        let new = new.ok_or(LinuxError::EINVAL)?;
+        *offset = new as usize;
+        Ok(new)
+    }
+}
+
+/// The total and free memory in bytes, from the page allocator.
+pub fn memory_usage() -> (usize, usize) {
+    let allocator = axalloc::global_allocator();
+    let free = allocator.available_pages() * PAGE_SIZE_4K;
+    let total = allocator.used_pages() * PAGE_SIZE_4K + free;
+    (total, free)
+}
+
+/// Formats the memory usage like `/proc/meminfo`.
+fn meminfo() -> String {
+    let (total, free) = memory_usage();
+    let (total, free) = (total / 1024, free / 1024);
+    let mut out = String::new();
+    for (name, kb) in [
+        ("MemTotal", total),
+        ("MemFree", free),
+        ("MemAvailable", free),
+        ("Buffers", 0),
+        ("Cached", 0),
+        ("SwapTotal", 0),
+        ("SwapFree", 0),
+    ] {
+        writeln!(out, "{:<16}{:>8} kB", format!("{name}:"), kb).unwrap();
+    }
+    out
+}
+
+/// Formats one block per online CPU like `/proc/cpuinfo`.
+fn cpuinfo() -> String {
+    let mut out = String::new();
+    for cpu in 0..axconfig::plat::CPU_NUM {
+        writeln!(out, "processor\t: {cpu}").unwrap();
+        writeln!(out, "arch\t\t: {}\n", axconfig::ARCH).unwrap();
+    }
+    out
+}
+
+/// Formats the status of `process` like `/proc/<pid>/status`.
+///
+/// Only the name of the current thread can be looked up, so another process
+/// is named after the last component of its executable path.
+fn status(process: &Process, is_self: bool) -> String {
+    let Some(data) = process.data::<ProcessData>() else {
+        return String::new();
+    };
+    let name = if is_self {
+        current_comm()
+    } else {
+        let exe_path = data.exe_path.read();
+        let name = exe_path
+            .rsplit_once('/')
+            .map_or(exe_path.as_str(), |(_, name)| name);
+        name.chars().take(TASK_COMM_LEN - 1).collect()
+    };
+    let (size, rss) = {
+        let aspace = data.aspace.lock();
+        let maps = data.maps.lock();
+        (maps.total_size(), maps.resident_size(&aspace))
+    };
+    let ppid = process.parent().map_or(0, |it| it.pid());
+
+    let mut out = String::new();
+    writeln!(out, "Name:\t{name}").unwrap();
+    writeln!(out, "Tgid:\t{}", process.pid()).unwrap();
+    writeln!(out, "Pid:\t{}", process.pid()).unwrap();
+    writeln!(out, "PPid:\t{ppid}").unwrap();
+    writeln!(out, "VmSize:\t{:>8} kB", size / 1024).unwrap();
+    writeln!(out, "VmRSS:\t{:>8} kB", rss / 1024).unwrap();
+    writeln!(out, "Threads:\t{}", process.threads().len()).unwrap();
+    out
+}
+
+/// Formats the mappings of `process` like `/proc/<pid>/maps`, one per line.
+fn maps(process: &Process) -> String {
+    let Some(data) = process.data::<ProcessData>() else {
+        return String::new();
+    };
+    let mut out = String::new();
+    for map in data.maps.lock().iter() {
+        let perm = |flag, c| if map.flags.contains(flag) { c } else { '-' };
+        let line = format!(
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+            map.start,
+            map.end,
+            perm(MappingFlags::READ, 'r'),
+            perm(MappingFlags::WRITE, 'w'),
+            perm(MappingFlags::EXECUTE, 'x'),
+            if map.shared { 's' } else { 'p' },
+            map.offset,
+        );
+        if map.name.is_empty() {
+            writeln!(out, "{line}").unwrap();
+        } else {
+            writeln!(out, "{line:<72} {}", map.name).unwrap();
+        }
+    }
+    out
+}
+
+/// Run the SysRq commands written to `/proc/sysrq-trigger`.
+///
+/// Only `c`, which crashes the kernel, is supported. The others are ignored.
+fn sysrq_trigger(buf: &[u8]) -> LinuxResult<usize> {
+    if buf.contains(&b'c') {
+        ax_println!("sysrq: Trigger a crash");
+        panic!("sysrq triggered crash");
+    }
+    Ok(buf.len())
+}
+
+impl FileLike for ProcFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut state = self.state.lock();
+        let (data, offset) = &mut *state;
+        if *offset == 0 {
+            *data = (self.generate)().into_bytes();
+        }
+        let rest = data.get(*offset..).unwrap_or_default();
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        *offset += len;
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.consume.ok_or(LinuxError::EBADF)?(buf)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        let mode = match self.consume {
+            Some(_) => 0o200u32, // -w-------
+            None => 0o444u32,    // r--r--r--
+        };
+        Ok(Kstat {
+            mode: S_IFREG | mode,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: self.consume.is_some(),
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
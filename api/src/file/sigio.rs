@@ -0,0 +1,72 @@
+use core::ffi::c_int;
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Process;
+use axsignal::{SignalInfo, Signo};
+use axsync::Mutex;
+use linux_raw_sys::general::SI_KERNEL;
+
+use super::FileLike;
+use crate::signal::send_signal_process;
+
+/// The signals chosen with `fcntl(F_SETSIG)`, by open file.
+static IO_SIGNALS: Mutex<Vec<(Weak<dyn FileLike>, Signo)>> = Mutex::new(Vec::new());
+
+fn same_file(weak: &Weak<dyn FileLike>, file: &Arc<dyn FileLike>) -> bool {
+    Weak::as_ptr(weak) as *const () == Arc::as_ptr(file) as *const ()
+}
+
+/// Choose the signal sent when I/O is possible on `file`.
+///
+/// Zero restores the default, a plain `SIGIO` without extra information.
+pub fn set_io_signal(file: &Arc<dyn FileLike>, signo: u32) -> LinuxResult {
+    let signo = match signo {
+        0 => None,
+        _ => Some(
+            u8::try_from(signo)
+                .ok()
+                .and_then(Signo::from_repr)
+                .ok_or(LinuxError::EINVAL)?,
+        ),
+    };
+    let mut signals = IO_SIGNALS.lock();
+    signals.retain(|(it, _)| it.strong_count() > 0 && !same_file(it, file));
+    if let Some(signo) = signo {
+        signals.push((Arc::downgrade(file), signo));
+    }
+    Ok(())
+}
+
+/// Get the signal chosen for `file`, if any.
+pub fn io_signal(file: &Arc<dyn FileLike>) -> Option<Signo> {
+    IO_SIGNALS
+        .lock()
+        .iter()
+        .find(|(it, _)| same_file(it, file))
+        .map(|(_, signo)| *signo)
+}
+
+/// Signal `owner` that I/O is possible on `file`, open as `fd`.
+///
+/// With a signal chosen by `F_SETSIG`, the `siginfo_t` carries `fd` in
+/// `si_fd`, `band` in `si_band` and `code` in `si_code`.
+pub fn send_io_signal(owner: &Process, file: &Arc<dyn FileLike>, fd: c_int, code: i32, band: u32) {
+    let sig = match io_signal(file) {
+        Some(signo) => {
+            let mut sig = SignalInfo::new(signo, code as _);
+            // SAFETY: `_sigpoll` is the active member for I/O signals.
+            unsafe {
+                let sigpoll = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigpoll;
+                sigpoll._band = band as _;
+                sigpoll._fd = fd;
+            }
+            sig
+        }
+        None => SignalInfo::new(Signo::SIGIO, SI_KERNEL as _),
+    };
+    let _ = send_signal_process(owner, sig);
+}
@@ -0,0 +1,187 @@
+use core::{
+    any::Any,
+    mem,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsignal::{SignalInfo, SignalSet, Signo};
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{O_NONBLOCK, O_RDWR};
+
+use super::{FileLike, Kstat};
+use crate::signal::has_interrupting_signal;
+
+/// How long a blocking read waits for a signal before checking whether it
+/// was interrupted.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The `struct signalfd_siginfo` a signal is read as.
+#[repr(C)]
+struct SignalFdSiginfo {
+    ssi_signo: u32,
+    ssi_errno: i32,
+    ssi_code: i32,
+    ssi_pid: u32,
+    ssi_uid: u32,
+    ssi_fd: i32,
+    ssi_tid: u32,
+    ssi_band: u32,
+    ssi_overrun: u32,
+    ssi_trapno: u32,
+    ssi_status: i32,
+    ssi_int: i32,
+    ssi_ptr: u64,
+    ssi_utime: u64,
+    ssi_stime: u64,
+    ssi_addr: u64,
+    ssi_addr_lsb: u16,
+    _pad: [u8; 46],
+}
+
+const SIGINFO_SIZE: usize = size_of::<SignalFdSiginfo>();
+
+impl SignalFdSiginfo {
+    fn new(sig: &SignalInfo) -> Self {
+        // SAFETY: the record is plain data, the header is always valid, and
+        // the members read from the union only reinterpret plain integers.
+        unsafe {
+            let info = &sig.0.__bindgen_anon_1.__bindgen_anon_1;
+            let fields = &info._sifields;
+            let mut this: Self = mem::zeroed();
+            this.ssi_signo = info.si_signo as _;
+            this.ssi_errno = info.si_errno;
+            this.ssi_code = info.si_code;
+            this.ssi_pid = fields._rt._pid as _;
+            this.ssi_uid = fields._rt._uid as _;
+            this.ssi_int = fields._rt._sigval.sival_int;
+            this.ssi_ptr = fields._rt._sigval.sival_ptr as _;
+            match Signo::from_repr(info.si_signo as u8) {
+                Some(Signo::SIGCHLD) => this.ssi_status = fields._sigchld._status,
+                Some(Signo::SIGSEGV | Signo::SIGBUS | Signo::SIGILL | Signo::SIGFPE) => {
+                    this.ssi_addr = fields._sigfault._addr as _;
+                }
+                Some(Signo::SIGIO) => {
+                    this.ssi_band = fields._sigpoll._band as _;
+                    this.ssi_fd = fields._sigpoll._fd;
+                }
+                _ => {}
+            }
+            this
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8; SIGINFO_SIZE] {
+        // SAFETY: the struct is plain data without implicit padding.
+        unsafe { &*(self as *const Self as *const [u8; SIGINFO_SIZE]) }
+    }
+}
+
+/// A file signals are read from instead of being delivered, created by
+/// `signalfd4`.
+///
+/// Reading dequeues the pending signals of the reading thread and its process
+/// that are in the mask, so their handlers never run. The signals should be
+/// blocked, or they may be delivered before being read.
+pub struct SignalFd {
+    mask: Mutex<SignalSet>,
+    nonblocking: AtomicBool,
+}
+
+impl SignalFd {
+    pub fn new(mask: SignalSet, nonblocking: bool) -> Self {
+        Self {
+            mask: Mutex::new(mask),
+            nonblocking: AtomicBool::new(nonblocking),
+        }
+    }
+
+    /// Replaces the signals read from the file.
+    pub fn set_mask(&self, mask: SignalSet) {
+        *self.mask.lock() = mask;
+    }
+
+    /// Dequeues a pending signal in the mask, waiting up to `timeout`.
+    fn dequeue(&self, timeout: Duration) -> Option<SignalInfo> {
+        let mask = *self.mask.lock();
+        current()
+            .task_ext()
+            .thread_data()
+            .signal
+            .wait_timeout(mask, Some(timeout))
+    }
+}
+
+impl FileLike for SignalFd {
+    /// Read as many pending signals as fit in `buf`, as `signalfd_siginfo`
+    /// records, blocking until there is one.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.len() < SIGINFO_SIZE {
+            return Err(LinuxError::EINVAL);
+        }
+        let first = loop {
+            if let Some(sig) = self.dequeue(Duration::ZERO) {
+                break sig;
+            }
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            if has_interrupting_signal() {
+                return Err(LinuxError::EINTR);
+            }
+            if let Some(sig) = self.dequeue(POLL_INTERVAL) {
+                break sig;
+            }
+        };
+
+        let mut next = Some(first);
+        let mut read = 0;
+        for chunk in buf.chunks_exact_mut(SIGINFO_SIZE) {
+            let Some(sig) = next.take().or_else(|| self.dequeue(Duration::ZERO)) else {
+                break;
+            };
+            chunk.copy_from_slice(SignalFdSiginfo::new(&sig).as_bytes());
+            read += SIGINFO_SIZE;
+        }
+        Ok(read)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: 0o600u32, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let pending = current().task_ext().thread_data().signal.pending() & *self.mask.lock();
+        Ok(PollState {
+            readable: (1..=64u8)
+                .filter_map(Signo::from_repr)
+                .any(|signo| pending.has(signo)),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn status_flags(&self) -> u32 {
+        let nonblocking = self.nonblocking.load(Ordering::Acquire);
+        O_RDWR | if nonblocking { O_NONBLOCK } else { 0 }
+    }
+}
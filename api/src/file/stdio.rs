@@ -135,6 +135,10 @@ impl super::FileLike for Stdin {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        super::console().ioctl(cmd, arg)
+    }
 }
 
 impl super::FileLike for Stdout {
@@ -167,4 +171,8 @@ impl super::FileLike for Stdout {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        super::console().ioctl(cmd, arg)
+    }
 }
@@ -0,0 +1,239 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{TimeValue, monotonic_time};
+use axio::PollState;
+use axsync::Mutex;
+use linux_raw_sys::general::{O_NONBLOCK, O_RDWR};
+
+use super::{FileLike, Kstat, event::WaitEvent};
+use crate::{
+    signal::has_interrupting_signal,
+    time::{wall_time, wall_time_sets},
+};
+
+/// The expiration settings of a timerfd.
+struct TimerFdState {
+    /// The next expiration, or `None` if the timer is disarmed.
+    deadline: Option<TimeValue>,
+    /// The period of the timer, or zero for a one-shot timer.
+    interval: TimeValue,
+    /// The expirations not read yet.
+    ticks: u64,
+    /// With `TFD_TIMER_CANCEL_ON_SET`, the number of times `CLOCK_REALTIME`
+    /// was set when the timer was armed or last cancelled.
+    cancel_on_set: Option<u64>,
+}
+
+impl TimerFdState {
+    /// Whether `CLOCK_REALTIME` was set since the timer was armed with
+    /// `TFD_TIMER_CANCEL_ON_SET`.
+    fn cancelled(&self) -> bool {
+        self.cancel_on_set
+            .is_some_and(|sets| sets != wall_time_sets())
+    }
+}
+
+/// The events of the `CLOCK_REALTIME` timerfds, notified when that clock is
+/// set.
+static REALTIME_EVENTS: Mutex<Vec<Weak<WaitEvent>>> = Mutex::new(Vec::new());
+
+/// Wakes up the readers of the `CLOCK_REALTIME` timerfds after the clock was
+/// set, so that they fail with `ECANCELED` or wait for the new deadline.
+pub fn notify_wall_time_set() {
+    for event in REALTIME_EVENTS.lock().iter() {
+        if let Some(event) = event.upgrade() {
+            event.notify();
+        }
+    }
+}
+
+/// A timer delivering its expirations by reads, created by
+/// `timerfd_create`.
+///
+/// Expirations are counted when the file is looked at, so a periodic timer
+/// catches up on all the periods that went by since.
+pub struct TimerFd {
+    /// Whether the timer measures `CLOCK_REALTIME` rather than
+    /// `CLOCK_MONOTONIC`.
+    realtime: bool,
+    state: Mutex<TimerFdState>,
+    nonblocking: AtomicBool,
+    /// Notified when the timer is set, or when `CLOCK_REALTIME` is set if the
+    /// timer measures it.
+    changed: Arc<WaitEvent>,
+}
+
+impl TimerFd {
+    pub fn new(realtime: bool, nonblocking: bool) -> Self {
+        let changed = Arc::new(WaitEvent::new());
+        if realtime {
+            let mut events = REALTIME_EVENTS.lock();
+            events.retain(|it| it.strong_count() > 0);
+            events.push(Arc::downgrade(&changed));
+        }
+        Self {
+            realtime,
+            state: Mutex::new(TimerFdState {
+                deadline: None,
+                interval: TimeValue::ZERO,
+                ticks: 0,
+                cancel_on_set: None,
+            }),
+            nonblocking: AtomicBool::new(nonblocking),
+            changed,
+        }
+    }
+
+    /// Reads the clock the timer measures.
+    pub fn now(&self) -> TimeValue {
+        if self.realtime {
+            wall_time()
+        } else {
+            monotonic_time()
+        }
+    }
+
+    /// Counts the expirations up to now into `ticks`, re-arming a periodic
+    /// timer for the next period after now.
+    fn update(&self, state: &mut TimerFdState) {
+        let Some(deadline) = state.deadline else {
+            return;
+        };
+        let now = self.now();
+        if now < deadline {
+            return;
+        }
+        if state.interval.is_zero() {
+            state.ticks += 1;
+            state.deadline = None;
+            return;
+        }
+        let count = (now - deadline).as_nanos() / state.interval.as_nanos() + 1;
+        state.ticks += count as u64;
+        state.deadline =
+            Some(deadline + TimeValue::from_nanos((state.interval.as_nanos() * count) as u64));
+    }
+
+    fn setting(&self, state: &TimerFdState) -> (TimeValue, TimeValue) {
+        let left = state
+            .deadline
+            .map_or(TimeValue::ZERO, |it| it.saturating_sub(self.now()));
+        (left, state.interval)
+    }
+
+    /// Returns the time until the next expiration and the interval.
+    ///
+    /// The time is zero if the timer is disarmed.
+    pub fn get(&self) -> (TimeValue, TimeValue) {
+        let mut state = self.state.lock();
+        self.update(&mut state);
+        self.setting(&state)
+    }
+
+    /// Arms the timer to expire at `deadline` and then every `interval`, or
+    /// disarms it if `deadline` is `None`, dropping the expirations not read
+    /// yet.
+    ///
+    /// With `cancel_on_set`, reads of a `CLOCK_REALTIME` timer fail with
+    /// `ECANCELED` once that clock is set. Returns the previous setting like
+    /// [`TimerFd::get`].
+    pub fn set(
+        &self,
+        deadline: Option<TimeValue>,
+        interval: TimeValue,
+        cancel_on_set: bool,
+    ) -> (TimeValue, TimeValue) {
+        let mut state = self.state.lock();
+        self.update(&mut state);
+        let old = self.setting(&state);
+        *state = TimerFdState {
+            deadline,
+            interval,
+            ticks: 0,
+            cancel_on_set: (cancel_on_set && self.realtime).then(wall_time_sets),
+        };
+        drop(state);
+        self.changed.notify();
+        old
+    }
+}
+
+impl FileLike for TimerFd {
+    /// Read the number of expirations since the last read as a `u64`,
+    /// blocking until there is one.
+    ///
+    /// Fails with `ECANCELED` once after `CLOCK_REALTIME` is set, if the
+    /// timer was armed with `TFD_TIMER_CANCEL_ON_SET`.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let Some(buf) = buf.first_chunk_mut::<8>() else {
+            return Err(LinuxError::EINVAL);
+        };
+        loop {
+            let seq = self.changed.seq();
+            let mut state = self.state.lock();
+            if state.cancelled() {
+                state.cancel_on_set = Some(wall_time_sets());
+                state.ticks = 0;
+                return Err(LinuxError::ECANCELED);
+            }
+            self.update(&mut state);
+            if state.ticks > 0 {
+                *buf = state.ticks.to_ne_bytes();
+                state.ticks = 0;
+                return Ok(8);
+            }
+            let timeout = state.deadline.map(|it| it.saturating_sub(self.now()));
+            drop(state);
+
+            if self.nonblocking.load(Ordering::Acquire) {
+                return Err(LinuxError::EAGAIN);
+            }
+            if has_interrupting_signal() {
+                return Err(LinuxError::EINTR);
+            }
+            self.changed.wait(seq, timeout);
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: 0o600u32, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let mut state = self.state.lock();
+        self.update(&mut state);
+        Ok(PollState {
+            readable: state.ticks > 0 || state.cancelled(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn status_flags(&self) -> u32 {
+        let nonblocking = self.nonblocking.load(Ordering::Acquire);
+        O_RDWR | if nonblocking { O_NONBLOCK } else { 0 }
+    }
+}
@@ -0,0 +1,117 @@
+use alloc::{collections::btree_map::BTreeMap, string::String, sync::Arc};
+use axhal::time::TimeValue;
+use axsync::Mutex;
+use linux_raw_sys::general::{MS_NOATIME, MS_RELATIME};
+
+use crate::time::wall_time;
+
+/// The timestamps of a file.
+#[derive(Debug, Clone, Copy)]
+pub struct FileTimes {
+    /// Time of last access.
+    pub atime: TimeValue,
+    /// Time of last modification.
+    pub mtime: TimeValue,
+    /// Time of last status change.
+    pub ctime: TimeValue,
+}
+
+impl FileTimes {
+    fn new(now: TimeValue) -> Self {
+        Self {
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    /// The last time any of the timestamps was set.
+    fn latest(&self) -> TimeValue {
+        self.atime.max(self.mtime).max(self.ctime)
+    }
+}
+
+/// The timestamps of the files accessed so far, by path, shared with the
+/// files open on them.
+///
+/// The file systems do not keep timestamps, so a file seen for the first time
+/// gets the current time for all of them.
+static FILE_TIMES: Mutex<BTreeMap<String, Arc<Mutex<FileTimes>>>> = Mutex::new(BTreeMap::new());
+
+/// The most files whose timestamps are kept. Beyond that, those of the file
+/// untouched for the longest and not open are dropped, as if it was never
+/// seen.
+const MAX_FILE_TIMES: usize = 4096;
+
+/// Returns the timestamps of the file at `path`, to be kept by a file open on
+/// it, so that they follow renames and are not dropped while it is open.
+pub fn shared_file_times(path: &str) -> Arc<Mutex<FileTimes>> {
+    let mut table = FILE_TIMES.lock();
+    if let Some(times) = table.get(path) {
+        return times.clone();
+    }
+    if table.len() >= MAX_FILE_TIMES {
+        let oldest = table
+            .iter()
+            .filter(|(_, times)| Arc::strong_count(times) == 1)
+            .min_by_key(|(_, times)| times.lock().latest())
+            .map(|(path, _)| path.clone());
+        if let Some(oldest) = oldest {
+            table.remove(&oldest);
+        }
+    }
+    let times = Arc::new(Mutex::new(FileTimes::new(wall_time())));
+    table.insert(path.into(), times.clone());
+    times
+}
+
+/// How far the access time may lag behind under `MS_RELATIME`.
+const RELATIME_INTERVAL: TimeValue = TimeValue::from_secs(24 * 60 * 60);
+
+/// Updates the access time in `times` after a read, following the atime
+/// policy in `mount_flags` of the mount the file lives on.
+///
+/// - `MS_NOATIME` never updates it.
+/// - `MS_RELATIME` only updates it if it is not newer than the modification
+///   or status change time, or if it is more than a day old.
+/// - Otherwise every read updates it.
+pub fn touch_atime(times: &Mutex<FileTimes>, mount_flags: u32) {
+    if mount_flags & MS_NOATIME != 0 {
+        return;
+    }
+    let now = wall_time();
+    let mut times = times.lock();
+    if mount_flags & MS_RELATIME != 0
+        && times.atime > times.mtime
+        && times.atime > times.ctime
+        && now.saturating_sub(times.atime) < RELATIME_INTERVAL
+    {
+        return;
+    }
+    times.atime = now;
+}
+
+/// Updates the modification and status change time in `times` after a write.
+pub fn touch_mtime(times: &Mutex<FileTimes>) {
+    let now = wall_time();
+    let mut times = times.lock();
+    times.mtime = now;
+    times.ctime = now;
+}
+
+/// Moves the timestamps of the file at `old` to `new` after a rename, which
+/// changes the status change time.
+pub fn move_file_times(old: &str, new: &str) {
+    let now = wall_time();
+    let mut table = FILE_TIMES.lock();
+    let times = table
+        .remove(old)
+        .unwrap_or_else(|| Arc::new(Mutex::new(FileTimes::new(now))));
+    times.lock().ctime = now;
+    table.insert(new.into(), times);
+}
+
+/// Drops the timestamps of the file at `path` once it is removed.
+pub fn forget_file_times(path: &str) {
+    FILE_TIMES.lock().remove(path);
+}
@@ -0,0 +1,326 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axprocess::{Pid, Process};
+use axsignal::{SignalInfo, Signo};
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{
+        B38400, CREAD, CS8, ECHO, ECHOE, ECHOK, ICANON, ICRNL, IEXTEN, ISIG, ONLCR, OPOST, S_IFCHR,
+        SI_KERNEL, termios, winsize,
+    },
+    ioctl::{
+        TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGPGRP, TIOCGSID, TIOCGWINSZ, TIOCNOTTY, TIOCSCTTY,
+        TIOCSPGRP, TIOCSWINSZ,
+    },
+};
+use starry_core::task::{get_process_group, get_session};
+
+use super::{
+    CONSOLE_DEVICE, FileLike, Kstat, TTY_DEVICE,
+    stdio::{Stdin, Stdout, stdin, stdout},
+};
+use crate::{ptr::UserPtr, signal::send_signal_process_group};
+
+struct JobControl {
+    /// The session this is the controlling terminal of.
+    session: Option<Pid>,
+    /// The foreground process group of the session.
+    foreground: Option<Pid>,
+    termios: termios,
+}
+
+/// The job control state of a terminal device.
+///
+/// A terminal is the controlling terminal of at most one session, and a
+/// session has at most one controlling terminal. Since the console is the only
+/// terminal for now, a session has a controlling terminal if and only if it
+/// owns the console.
+pub struct Terminal {
+    job: Mutex<JobControl>,
+    /// Bumped on every hangup, revoking the handles opened before.
+    generation: AtomicUsize,
+}
+
+impl Terminal {
+    const fn new() -> Self {
+        // SAFETY: valid for termios
+        let mut termios: termios = unsafe { core::mem::zeroed() };
+        termios.c_iflag = ICRNL;
+        termios.c_oflag = OPOST | ONLCR;
+        termios.c_cflag = B38400 | CS8 | CREAD;
+        termios.c_lflag = ISIG | ICANON | ECHO | ECHOE | ECHOK | IEXTEN;
+        Self {
+            job: Mutex::new(JobControl {
+                session: None,
+                foreground: None,
+                termios,
+            }),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the session this is the controlling terminal of.
+    pub fn session(&self) -> Option<Pid> {
+        self.job
+            .lock()
+            .session
+            .filter(|sid| get_session(*sid).is_ok())
+    }
+
+    /// Returns the foreground process group of the session.
+    pub fn foreground(&self) -> Option<Pid> {
+        let job = self.job.lock();
+        job.session.and(job.foreground)
+    }
+
+    /// Checks whether this is the controlling terminal of `proc`.
+    pub fn is_controlling(&self, proc: &Process) -> bool {
+        self.session() == Some(proc.group().session().sid())
+    }
+
+    /// Makes this the controlling terminal of the session led by `proc`, with
+    /// the process group of `proc` in the foreground.
+    ///
+    /// This only succeeds if `proc` is a session leader and the terminal is
+    /// not the controlling terminal of another session.
+    pub fn acquire(&self, proc: &Process) -> bool {
+        let group = proc.group();
+        let sid = group.session().sid();
+        if sid != proc.pid() {
+            return false;
+        }
+        let mut job = self.job.lock();
+        match job.session {
+            Some(owner) if owner == sid => return true,
+            Some(owner) if get_session(owner).is_ok() => return false,
+            _ => {}
+        }
+        job.session = Some(sid);
+        job.foreground = Some(group.pgid());
+        true
+    }
+
+    /// Detaches this terminal from the session `sid`, if it is its
+    /// controlling terminal.
+    ///
+    /// Returns the foreground process group at the time.
+    pub fn release(&self, sid: Pid) -> Option<Pid> {
+        let mut job = self.job.lock();
+        if job.session != Some(sid) {
+            return None;
+        }
+        job.session = None;
+        job.foreground.take()
+    }
+
+    /// Hangs up the terminal.
+    ///
+    /// The terminal is detached from its session, its foreground process
+    /// group receives `SIGHUP` followed by `SIGCONT`, and the handles opened
+    /// so far are revoked.
+    pub fn hangup(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        let foreground = {
+            let mut job = self.job.lock();
+            job.session = None;
+            job.foreground.take()
+        };
+        if let Some(pgid) = foreground {
+            signal_hangup(pgid);
+        }
+    }
+
+    /// Detaches the terminal from the session `sid` when its leader exits,
+    /// sending `SIGHUP` followed by `SIGCONT` to the foreground process group.
+    pub fn disassociate(&self, sid: Pid) {
+        if let Some(pgid) = self.release(sid) {
+            signal_hangup(pgid);
+        }
+    }
+
+    fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Handles the terminal ioctls.
+    pub fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        let curr = current();
+        let proc = curr.task_ext().thread.process();
+        match cmd {
+            TCGETS => {
+                let termios = self.job.lock().termios;
+                *UserPtr::<termios>::from(arg).get_as_mut()? = termios;
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = *UserPtr::<termios>::from(arg).get_as_mut()?;
+                self.job.lock().termios = termios;
+            }
+            TIOCGWINSZ => {
+                *UserPtr::<winsize>::from(arg).get_as_mut()? = winsize {
+                    ws_row: 24,
+                    ws_col: 80,
+                    ws_xpixel: 0,
+                    ws_ypixel: 0,
+                };
+            }
+            TIOCSWINSZ => {}
+            TIOCGPGRP => {
+                if !self.is_controlling(proc) {
+                    return Err(LinuxError::ENOTTY);
+                }
+                *UserPtr::<Pid>::from(arg).get_as_mut()? = self.foreground().unwrap_or_default();
+            }
+            TIOCSPGRP => {
+                if !self.is_controlling(proc) {
+                    return Err(LinuxError::ENOTTY);
+                }
+                let pgid = *UserPtr::<Pid>::from(arg).get_as_mut()?;
+                let group = get_process_group(pgid)?;
+                if group.session().sid() != proc.group().session().sid() {
+                    return Err(LinuxError::EPERM);
+                }
+                self.job.lock().foreground = Some(pgid);
+            }
+            TIOCGSID => {
+                if !self.is_controlling(proc) {
+                    return Err(LinuxError::ENOTTY);
+                }
+                *UserPtr::<Pid>::from(arg).get_as_mut()? = proc.group().session().sid();
+            }
+            TIOCSCTTY => {
+                if !self.is_controlling(proc) && !self.acquire(proc) {
+                    return Err(LinuxError::EPERM);
+                }
+            }
+            TIOCNOTTY => {
+                if !self.is_controlling(proc) {
+                    return Err(LinuxError::ENOTTY);
+                }
+                // Only the session leader can detach the terminal from the
+                // session, since the association is not tracked per process.
+                if proc.group().session().sid() == proc.pid() {
+                    self.release(proc.pid());
+                }
+            }
+            _ => {
+                warn!("Unsupported terminal ioctl: {:#x}", cmd);
+                return Err(LinuxError::ENOTTY);
+            }
+        }
+        Ok(0)
+    }
+}
+
+fn signal_hangup(pgid: Pid) {
+    if let Ok(group) = get_process_group(pgid) {
+        send_signal_process_group(&group, SignalInfo::new(Signo::SIGHUP, SI_KERNEL as _));
+        send_signal_process_group(&group, SignalInfo::new(Signo::SIGCONT, SI_KERNEL as _));
+    }
+}
+
+/// Returns the job control state of the console.
+pub fn console() -> &'static Terminal {
+    static CONSOLE: Terminal = Terminal::new();
+    &CONSOLE
+}
+
+/// A handle to the console opened through `/dev/tty` or `/dev/console`.
+///
+/// Unlike the standard streams every process starts with, such a handle is
+/// revoked when the terminal is hung up.
+pub struct Tty {
+    stdin: Stdin,
+    stdout: Stdout,
+    generation: usize,
+    /// Which of `/dev/tty` and `/dev/console` this was opened as.
+    rdev: (u32, u32),
+}
+
+impl Tty {
+    fn new(rdev: (u32, u32)) -> Self {
+        Self {
+            stdin: stdin(),
+            stdout: stdout(),
+            generation: console().generation(),
+            rdev,
+        }
+    }
+
+    fn check_hangup(&self) -> LinuxResult {
+        if self.generation != console().generation() {
+            return Err(LinuxError::EIO);
+        }
+        Ok(())
+    }
+
+    /// Opens the controlling terminal of the current process, as `/dev/tty`
+    /// does.
+    ///
+    /// Fails with `ENXIO` if the process has no controlling terminal.
+    pub fn open_controlling() -> LinuxResult<Self> {
+        let curr = current();
+        if !console().is_controlling(curr.task_ext().thread.process()) {
+            return Err(LinuxError::ENXIO);
+        }
+        Ok(Self::new(TTY_DEVICE))
+    }
+
+    /// Opens the console.
+    ///
+    /// Unless `noctty` is set, this makes the console the controlling
+    /// terminal of the current process if it is a session leader without one.
+    pub fn open_console(noctty: bool) -> LinuxResult<Self> {
+        if !noctty {
+            let curr = current();
+            console().acquire(curr.task_ext().thread.process());
+        }
+        Ok(Self::new(CONSOLE_DEVICE))
+    }
+}
+
+impl FileLike for Tty {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.check_hangup()?;
+        self.stdin.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.check_hangup()?;
+        self.stdout.write(buf)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: S_IFCHR | 0o620u32, // rw--w----
+            rdev: self.rdev,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        self.check_hangup()?;
+        console().ioctl(cmd, arg)
+    }
+}
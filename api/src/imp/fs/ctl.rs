@@ -1,19 +1,34 @@
 use core::{
     ffi::{c_char, c_int, c_void},
     mem::offset_of,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use alloc::ffi::CString;
+use alloc::{
+    ffi::CString,
+    format,
+    string::{String, ToString},
+};
 use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::DirEntry;
-use linux_raw_sys::general::{
-    AT_FDCWD, AT_REMOVEDIR, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG, DT_SOCK, DT_UNKNOWN,
-    linux_dirent64,
+use axfs::{CURRENT_DIR_PATH, fops::OpenOptions};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{
+        AT_FDCWD, AT_REMOVEDIR, DN_CREATE, DN_DELETE, DN_RENAME, DT_BLK, DT_CHR, DT_DIR, DT_FIFO,
+        DT_LNK, DT_REG, DT_SOCK, DT_UNKNOWN, RENAME_EXCHANGE, RENAME_NOREPLACE, S_IFBLK, S_IFCHR,
+        S_IFIFO, S_IFMT, S_IFREG, S_IFSOCK, linux_dirent64,
+    },
+    ioctl::{FIOCLEX, FIONBIO, FIONCLEX},
 };
+use starry_core::task::{ProcessData, processes};
 
+use super::{check_searchable, check_writable, same_mount};
 use crate::{
-    file::{Directory, FileLike},
-    path::{HARDLINK_MANAGER, handle_file_path},
+    file::{
+        Directory, FileLike, forget_file_perm, forget_file_times, get_file_like, move_file_perm,
+        move_file_times, move_open_paths, notify_dir_change, set_cloexec, set_file_perm,
+    },
+    path::{FilePath, HARDLINK_MANAGER, current_root, handle_file_path, path_in_root},
     ptr::{UserConstPtr, UserPtr, nullable},
 };
 
@@ -25,36 +40,169 @@ use crate::{
 /// * `op` - The request code. It is of type unsigned long in glibc and BSD,
 ///   and of type int in musl and other UNIX systems.
 /// * `argp` - The argument to the request. It is a pointer to a memory location
-pub fn sys_ioctl(_fd: i32, _op: usize, _argp: UserPtr<c_void>) -> LinuxResult<isize> {
-    warn!("Unimplemented syscall: SYS_IOCTL");
+///
+/// The requests that apply to every file descriptor are handled here, and
+/// the rest by the file.
+pub fn sys_ioctl(fd: i32, op: usize, argp: UserPtr<c_void>) -> LinuxResult<isize> {
+    debug!("sys_ioctl <= fd: {}, op: {:#x}", fd, op);
+    let file = get_file_like(fd)?;
+    match op as u32 {
+        FIOCLEX => set_cloexec(fd, true)?,
+        FIONCLEX => set_cloexec(fd, false)?,
+        FIONBIO => {
+            let nonblocking = *UserPtr::<c_int>::from(argp.address().as_usize()).get_as_mut()?;
+            file.set_nonblocking(nonblocking != 0)?;
+        }
+        op => return file.ioctl(op, argp.address().as_usize()),
+    }
     Ok(0)
 }
 
+/// Change the working directory of the calling process, which its children
+/// inherit and its threads share.
+///
+/// Fails with `ENOENT` if `path` does not exist, with `ENOTDIR` if it is not
+/// a directory, or with `EACCES` if the caller may not search it.
 pub fn sys_chdir(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!("sys_chdir <= {:?}", path);
 
-    axfs::api::set_current_dir(path)?;
+    let path = handle_file_path(AT_FDCWD, path)?;
+    check_searchable(path.as_str())?;
+    axfs::api::set_current_dir(path.as_str())?;
+    Ok(0)
+}
+
+/// Like [`sys_chdir`], with the directory open as `fd`.
+pub fn sys_fchdir(fd: c_int) -> LinuxResult<isize> {
+    debug!("sys_fchdir <= {}", fd);
+    let dir = Directory::from_fd(fd)?;
+    let path = dir.path();
+    check_searchable(&path)?;
+    axfs::api::set_current_dir(&path)?;
     Ok(0)
 }
 
+/// Appended to working directories that were removed, so that nothing can be
+/// found from them.
+const REMOVED_CWD: &str = " (deleted)";
+
+/// Replace the working directory of every process for which `f` gives a new
+/// one.
+fn update_cwds(mut f: impl FnMut(&str) -> Option<String>) {
+    for process in processes() {
+        let Some(data) = process.data::<ProcessData>() else {
+            continue;
+        };
+        let mut cwd = CURRENT_DIR_PATH.deref_from(&data.ns).lock();
+        if let Some(new) = f(&cwd) {
+            *cwd = new;
+        }
+    }
+}
+
+/// Move the working directories in the directory `old` to `new` after it is
+/// renamed.
+fn move_cwds(old: &str, new: &str) {
+    update_cwds(|cwd| {
+        let rest = cwd.strip_prefix(old)?;
+        rest.starts_with('/').then(|| format!("{new}{rest}"))
+    });
+}
+
+/// Mark the working directories at `path` as removed.
+fn remove_cwds(path: &str) {
+    update_cwds(|cwd| (cwd.trim_end_matches('/') == path).then(|| format!("{path}{REMOVED_CWD}/")));
+}
+
+/// Create the directory `path` with the permissions in `mode` that are not
+/// in the umask.
 pub fn sys_mkdirat(dirfd: i32, path: UserConstPtr<c_char>, mode: u32) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!(
-        "sys_mkdirat <= dirfd: {}, path: {}, mode: {}",
+        "sys_mkdirat <= dirfd: {}, path: {}, mode: {:#o}",
         dirfd, path, mode
     );
 
-    if mode != 0 {
-        warn!("directory mode not supported.");
-    }
-
     let path = handle_file_path(dirfd, path)?;
+    check_writable(path.as_str())?;
     axfs::api::create_dir(path.as_str())?;
+    set_file_perm(path.as_str(), mode & 0o7777 & !current_umask());
+    notify_dir_change(path.as_str(), DN_CREATE);
+
+    Ok(0)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_mkdir(path: UserConstPtr<c_char>, mode: u32) -> LinuxResult<isize> {
+    sys_mkdirat(AT_FDCWD, path, mode)
+}
+
+/// Create the file `path`, which can only be a regular file, with the
+/// permissions in `mode` that are not in the umask.
+///
+/// Device files need privileges, and the file systems cannot hold FIFOs or
+/// sockets, so those fail with `EPERM`.
+pub fn sys_mknodat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    mode: u32,
+    dev: u64,
+) -> LinuxResult<isize> {
+    let path = path.get_as_path()?;
+    debug!(
+        "sys_mknodat <= dirfd: {}, path: {}, mode: {:#o}, dev: {:#x}",
+        dirfd, path, mode, dev
+    );
+
+    match mode & S_IFMT {
+        0 | S_IFREG => {}
+        S_IFCHR | S_IFBLK | S_IFIFO | S_IFSOCK => return Err(LinuxError::EPERM),
+        _ => return Err(LinuxError::EINVAL),
+    }
+    let path = handle_file_path(dirfd, path)?;
+    check_writable(path.as_str())?;
+    let opts = OpenOptions::new().set_write(true).set_create_new(true);
+    axfs::fops::File::open(path.as_str(), &opts)?;
+    set_file_perm(path.as_str(), mode & 0o7777 & !current_umask());
+    notify_dir_change(path.as_str(), DN_CREATE);
+
+    Ok(0)
+}
 
+#[cfg(target_arch = "x86_64")]
+pub fn sys_mknod(path: UserConstPtr<c_char>, mode: u32, dev: u64) -> LinuxResult<isize> {
+    sys_mknodat(AT_FDCWD, path, mode, dev)
+}
+
+/// Change the root directory of the calling process to `path`, which absolute
+/// paths then start from and `..` cannot leave.
+///
+/// The working directory stays where it is, even if outside the new root.
+/// Fails with `ENOTDIR` if `path` is not a directory.
+pub fn sys_chroot(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
+    let path = path.get_as_path()?;
+    debug!("sys_chroot <= {:?}", path);
+
+    let path = handle_file_path(AT_FDCWD, path)?;
+    if !axfs::api::metadata(path.as_str())?.is_dir() {
+        return Err(LinuxError::ENOTDIR);
+    }
+    *current().task_ext().process_data().root.write() = path.to_string();
     Ok(0)
 }
 
+/// Set the file mode creation mask of the calling process to `mask`,
+/// returning the previous one.
+pub fn sys_umask(mask: u32) -> LinuxResult<isize> {
+    Ok(current().task_ext().process_data().replace_umask(mask) as _)
+}
+
+/// The file mode creation mask of the calling process.
+pub(crate) fn current_umask() -> u32 {
+    current().task_ext().process_data().umask()
+}
+
 #[allow(dead_code)]
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -72,9 +220,13 @@ pub enum FileType {
 impl From<axfs::api::FileType> for FileType {
     fn from(ft: axfs::api::FileType) -> Self {
         match ft {
-            ft if ft.is_dir() => FileType::Dir,
-            ft if ft.is_file() => FileType::Reg,
-            _ => FileType::Unknown,
+            axfs::api::FileType::Fifo => FileType::Fifo,
+            axfs::api::FileType::CharDevice => FileType::Chr,
+            axfs::api::FileType::Dir => FileType::Dir,
+            axfs::api::FileType::BlockDevice => FileType::Blk,
+            axfs::api::FileType::File => FileType::Reg,
+            axfs::api::FileType::SymLink => FileType::Lnk,
+            axfs::api::FileType::Socket => FileType::Socket,
         }
     }
 }
@@ -94,7 +246,7 @@ impl<'a> DirBuffer<'a> {
         self.buf.len().saturating_sub(self.offset)
     }
 
-    fn write_entry(&mut self, d_type: FileType, name: &[u8]) -> bool {
+    fn write_entry(&mut self, d_type: FileType, name: &[u8], d_off: u64) -> bool {
         const NAME_OFFSET: usize = offset_of!(linux_dirent64, d_name);
 
         let len = NAME_OFFSET + name.len() + 1;
@@ -109,7 +261,7 @@ impl<'a> DirBuffer<'a> {
             entry_ptr.cast::<linux_dirent64>().write(linux_dirent64 {
                 // FIXME: real inode number
                 d_ino: 1,
-                d_off: 0,
+                d_off: d_off as _,
                 d_reclen: len as _,
                 d_type: d_type as _,
                 d_name: Default::default(),
@@ -125,6 +277,9 @@ impl<'a> DirBuffer<'a> {
     }
 }
 
+/// Read entries of the directory `fd` into `buf`.
+///
+/// Each entry's `d_off` is the position after it, which `lseek` accepts.
 pub fn sys_getdents64(fd: i32, buf: UserPtr<u8>, len: usize) -> LinuxResult<isize> {
     let buf = buf.get_as_mut_slice(len)?;
     debug!(
@@ -135,33 +290,14 @@ pub fn sys_getdents64(fd: i32, buf: UserPtr<u8>, len: usize) -> LinuxResult<isiz
     );
 
     let mut buffer = DirBuffer::new(buf);
-
     let dir = Directory::from_fd(fd)?;
+    let mut full = false;
+    dir.read_entries(|ent, next| {
+        full = !buffer.write_entry(ent.entry_type().into(), ent.name_as_bytes(), next);
+        !full
+    })?;
 
-    let mut last_dirent = dir.last_dirent();
-    if let Some(ent) = last_dirent.take() {
-        if !buffer.write_entry(ent.entry_type().into(), ent.name_as_bytes()) {
-            *last_dirent = Some(ent);
-            return Err(LinuxError::EINVAL);
-        }
-    }
-
-    let mut inner = dir.inner();
-    loop {
-        let mut dirents = [DirEntry::default()];
-        let cnt = inner.read_dir(&mut dirents)?;
-        if cnt == 0 {
-            break;
-        }
-
-        let [ent] = dirents;
-        if !buffer.write_entry(ent.entry_type().into(), ent.name_as_bytes()) {
-            *last_dirent = Some(ent);
-            break;
-        }
-    }
-
-    if last_dirent.is_some() && buffer.offset == 0 {
+    if full && buffer.offset == 0 {
         return Err(LinuxError::EINVAL);
     }
     Ok(buffer.offset as _)
@@ -179,8 +315,8 @@ pub fn sys_linkat(
     new_path: UserConstPtr<c_char>,
     flags: i32,
 ) -> LinuxResult<isize> {
-    let old_path = old_path.get_as_str()?;
-    let new_path = new_path.get_as_str()?;
+    let old_path = old_path.get_as_path()?;
+    let new_path = new_path.get_as_path()?;
     debug!(
         "sys_linkat <= old_dirfd: {}, old_path: {}, new_dirfd: {}, new_path: {}, flags: {}",
         old_dirfd, old_path, new_dirfd, new_path, flags
@@ -194,8 +330,10 @@ pub fn sys_linkat(
     let old_path = handle_file_path(old_dirfd, old_path)?;
     // handle new path
     let new_path = handle_file_path(new_dirfd, new_path)?;
+    check_writable(new_path.as_str())?;
 
     HARDLINK_MANAGER.create_link(&new_path, &old_path)?;
+    notify_dir_change(new_path.as_str(), DN_CREATE);
 
     Ok(0)
 }
@@ -213,16 +351,18 @@ pub fn sys_link(
 /// flags: can be 0 or AT_REMOVEDIR
 /// return 0 when success, else return -1
 pub fn sys_unlinkat(dirfd: c_int, path: UserConstPtr<c_char>, flags: u32) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!(
         "sys_unlinkat <= dirfd: {}, path: {}, flags: {}",
         dirfd, path, flags
     );
 
     let path = handle_file_path(dirfd, path)?;
+    check_writable(path.as_str())?;
 
     if flags == AT_REMOVEDIR {
         axfs::api::remove_dir(path.as_str())?;
+        remove_cwds(path.as_str().trim_end_matches('/'));
     } else {
         let metadata = axfs::api::metadata(path.as_str())?;
         if metadata.is_dir() {
@@ -234,6 +374,11 @@ pub fn sys_unlinkat(dirfd: c_int, path: UserConstPtr<c_char>, flags: u32) -> Lin
                 .ok_or(LinuxError::ENOENT)?;
         }
     }
+    if !path.exists() {
+        forget_file_times(path.as_str());
+        forget_file_perm(path.as_str());
+    }
+    notify_dir_change(path.as_str(), DN_DELETE);
     Ok(0)
 }
 
@@ -241,6 +386,215 @@ pub fn sys_unlink(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
     sys_unlinkat(AT_FDCWD, path, 0)
 }
 
+/// Rename `old_path` to `new_path`, replacing it if it exists unless
+/// `RENAME_NOREPLACE` is given, or swap the two with `RENAME_EXCHANGE`.
+///
+/// A directory can only replace an empty directory, and a file only a file.
+/// A directory cannot be moved into itself, nor out of the file system it is
+/// on.
+pub fn sys_renameat2(
+    old_dirfd: c_int,
+    old_path: UserConstPtr<c_char>,
+    new_dirfd: c_int,
+    new_path: UserConstPtr<c_char>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let old_path = old_path.get_as_path()?;
+    let new_path = new_path.get_as_path()?;
+    debug!(
+        "sys_renameat2 <= old_dirfd: {}, old_path: {}, new_dirfd: {}, new_path: {}, flags: {}",
+        old_dirfd, old_path, new_dirfd, new_path, flags
+    );
+    if flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE) != 0
+        || flags & RENAME_NOREPLACE != 0 && flags & RENAME_EXCHANGE != 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let old_path = handle_file_path(old_dirfd, old_path)?;
+    let new_path = handle_file_path(new_dirfd, new_path)?;
+    check_writable(old_path.as_str())?;
+    check_writable(new_path.as_str())?;
+
+    let old = old_path.as_str().trim_end_matches('/');
+    let new = new_path.as_str().trim_end_matches('/');
+    let is_dir = axfs::api::metadata(old)?.is_dir();
+    if !same_mount(old, new) {
+        return Err(LinuxError::EXDEV);
+    }
+    if flags & RENAME_EXCHANGE != 0 {
+        exchange(old, new, is_dir)?;
+        return Ok(0);
+    }
+    let target = axfs::api::metadata(new);
+    if target.is_ok() && flags & RENAME_NOREPLACE != 0 {
+        return Err(LinuxError::EEXIST);
+    }
+    if old == new {
+        return Ok(0);
+    }
+    if is_dir && is_inside(new, old) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    // The target is only removed for good once the rename succeeded, so that
+    // it can be put back otherwise.
+    let replaced = match target.map(|it| it.is_dir()) {
+        Ok(false) if is_dir => return Err(LinuxError::ENOTDIR),
+        Ok(true) if !is_dir => return Err(LinuxError::EISDIR),
+        // Fails with `ENOTEMPTY` unless it is empty, so that it can simply be
+        // made again.
+        Ok(true) => {
+            axfs::api::remove_dir(new)?;
+            Some(Replaced::Dir)
+        }
+        Ok(false) => {
+            let aside = temp_name(new, "replaced");
+            axfs::api::rename(new, &aside)?;
+            move_open_paths(new, &aside);
+            Some(Replaced::File(aside))
+        }
+        Err(_) => None,
+    };
+
+    if let Err(err) = axfs::api::rename(old, new) {
+        match replaced {
+            Some(Replaced::Dir) => {
+                let _ = axfs::api::create_dir(new);
+            }
+            Some(Replaced::File(aside)) => {
+                if axfs::api::rename(&aside, new).is_ok() {
+                    move_open_paths(&aside, new);
+                }
+            }
+            None => {}
+        }
+        return Err(err.into());
+    }
+    match &replaced {
+        Some(Replaced::Dir) => remove_cwds(new),
+        Some(Replaced::File(aside)) => {
+            if HARDLINK_MANAGER
+                .remove_link(&FilePath::new(aside)?)
+                .is_none()
+            {
+                debug!("failed to remove the replaced file {aside}");
+            }
+        }
+        None => {}
+    }
+    if replaced.is_some() {
+        forget_file_times(new);
+    }
+    if is_dir {
+        move_cwds(old, new);
+    }
+    move_file_times(old, new);
+    move_file_perm(old, new);
+    move_open_paths(old, new);
+    notify_dir_change(old, DN_RENAME);
+    notify_dir_change(new, DN_RENAME);
+    Ok(0)
+}
+
+/// Whether `path` lies below the directory `dir`.
+fn is_inside(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir).is_some_and(|it| it.starts_with('/'))
+}
+
+/// The target of a rename, while it is set aside.
+enum Replaced {
+    /// An empty directory, which was removed.
+    Dir,
+    /// A file, renamed to the given temporary name.
+    File(String),
+}
+
+/// Numbers the temporary names of files being renamed.
+static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary name next to `path` for a file being renamed, `kind` telling
+/// why.
+fn temp_name(path: &str, kind: &str) -> String {
+    let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    format!(
+        "{dir}/.{kind}-{}",
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Swap the files at `old` and `new`, which must both exist and may be of
+/// different types, for `RENAME_EXCHANGE`.
+///
+/// Unlike on Linux, this is not atomic: it takes three renames through a
+/// temporary name next to `new`, so other processes may briefly see only one
+/// of the two in between. If a rename fails, those done before it are undone,
+/// as far as that succeeds.
+fn exchange(old: &str, new: &str, old_is_dir: bool) -> LinuxResult {
+    let new_is_dir = axfs::api::metadata(new)?.is_dir();
+    if old == new {
+        return Ok(());
+    }
+    if is_inside(new, old) || is_inside(old, new) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let tmp = temp_name(new, "exchange");
+    axfs::api::rename(old, &tmp)?;
+    if let Err(err) = axfs::api::rename(new, old) {
+        let _ = axfs::api::rename(&tmp, old);
+        return Err(err.into());
+    }
+    if let Err(err) = axfs::api::rename(&tmp, new) {
+        let _ = axfs::api::rename(old, new);
+        let _ = axfs::api::rename(&tmp, old);
+        return Err(err.into());
+    }
+
+    for (from, to, is_dir) in [
+        (old, tmp.as_str(), old_is_dir),
+        (new, old, new_is_dir),
+        (tmp.as_str(), new, old_is_dir),
+    ] {
+        if is_dir {
+            move_cwds(from, to);
+        }
+        move_file_perm(from, to);
+        move_file_times(from, to);
+        move_open_paths(from, to);
+    }
+    notify_dir_change(old, DN_RENAME);
+    notify_dir_change(new, DN_RENAME);
+    Ok(())
+}
+
+/// Like [`sys_renameat2`] without flags.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub fn sys_renameat(
+    old_dirfd: c_int,
+    old_path: UserConstPtr<c_char>,
+    new_dirfd: c_int,
+    new_path: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    sys_renameat2(old_dirfd, old_path, new_dirfd, new_path, 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_rename(
+    old_path: UserConstPtr<c_char>,
+    new_path: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    sys_renameat2(AT_FDCWD, old_path, AT_FDCWD, new_path, 0)
+}
+
+/// Copy the working directory of the calling process to `buf`, returning its
+/// length including the trailing NUL.
+///
+/// It is given from the root directory. Outside of it, it starts with
+/// `(unreachable)` like on Linux.
+///
+/// Fails with `ENOENT` if it was removed, or with `ERANGE` if it does not fit
+/// in `size` bytes.
 pub fn sys_getcwd(buf: UserPtr<u8>, size: usize) -> LinuxResult<isize> {
     let buf = nullable!(buf.get_as_mut_slice(size))?;
 
@@ -248,12 +602,23 @@ pub fn sys_getcwd(buf: UserPtr<u8>, size: usize) -> LinuxResult<isize> {
         return Ok(0);
     };
 
-    let cwd = CString::new(axfs::api::current_dir()?).map_err(|_| LinuxError::EINVAL)?;
+    // The file system keeps a trailing slash, which Linux does not report.
+    let cwd = axfs::api::current_dir()?;
+    let cwd = match cwd.trim_end_matches('/') {
+        "" => "/",
+        cwd if cwd.ends_with(REMOVED_CWD) => return Err(LinuxError::ENOENT),
+        cwd => cwd,
+    };
+    let cwd = match path_in_root(&current_root(), cwd) {
+        Some(cwd) => CString::new(cwd),
+        None => CString::new(format!("(unreachable){cwd}")),
+    }
+    .map_err(|_| LinuxError::EINVAL)?;
     let cwd = cwd.as_bytes_with_nul();
 
     if cwd.len() <= buf.len() {
         buf[..cwd.len()].copy_from_slice(cwd);
-        Ok(buf.as_ptr() as _)
+        Ok(cwd.len() as _)
     } else {
         Err(LinuxError::ERANGE)
     }
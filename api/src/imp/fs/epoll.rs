@@ -0,0 +1,106 @@
+use core::{ffi::c_int, time::Duration};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axsignal::SignalSet;
+use linux_raw_sys::general::{
+    EPOLL_CLOEXEC, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLL_CTL_MOD, epoll_event,
+};
+
+use super::poll::{wait_ready, with_sigmask};
+use crate::{
+    file::{Directory, Epoll, File, FileLike, add_file_like, get_file_like},
+    ptr::{UserConstPtr, UserPtr},
+};
+
+/// Create an epoll instance.
+pub fn sys_epoll_create1(flags: u32) -> LinuxResult<isize> {
+    debug!("sys_epoll_create1 <= flags: {:#x}", flags);
+    if flags & !EPOLL_CLOEXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let fd = add_file_like(Arc::new(Epoll::new()), flags & EPOLL_CLOEXEC != 0)?;
+    Ok(fd as _)
+}
+
+/// Create an epoll instance. `size` is ignored but must be positive.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_epoll_create(size: i32) -> LinuxResult<isize> {
+    if size <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    sys_epoll_create1(0)
+}
+
+/// Add, modify or remove `fd` in the interest list of the epoll instance
+/// `epfd`.
+///
+/// `fd` can be another epoll instance, as long as no loop is formed.
+pub fn sys_epoll_ctl(
+    epfd: c_int,
+    op: u32,
+    fd: c_int,
+    event: UserConstPtr<epoll_event>,
+) -> LinuxResult<isize> {
+    debug!("sys_epoll_ctl <= epfd: {}, op: {}, fd: {}", epfd, op, fd);
+    let epoll = Epoll::from_fd(epfd)?;
+    let file = get_file_like(fd)?;
+    if fd == epfd {
+        return Err(LinuxError::EINVAL);
+    }
+    // Regular files and directories are always ready.
+    let any = file.clone().into_any();
+    if any.is::<File>() || any.is::<Directory>() {
+        return Err(LinuxError::EPERM);
+    }
+
+    match op {
+        EPOLL_CTL_ADD => epoll.add(fd, &file, *event.get_as_ref()?)?,
+        EPOLL_CTL_MOD => epoll.modify(fd, *event.get_as_ref()?)?,
+        EPOLL_CTL_DEL => epoll.delete(fd)?,
+        _ => return Err(LinuxError::EINVAL),
+    }
+    Ok(0)
+}
+
+/// Wait for up to `maxevents` events on the epoll instance `epfd`, for at
+/// most `timeout` milliseconds or forever if it is negative, with the signal
+/// mask replaced by `sigmask` if given.
+pub fn sys_epoll_pwait(
+    tf: &mut TrapFrame,
+    epfd: c_int,
+    events: UserPtr<epoll_event>,
+    maxevents: i32,
+    timeout: i32,
+    sigmask: UserConstPtr<SignalSet>,
+    sigsetsize: usize,
+) -> LinuxResult<isize> {
+    let epoll = Epoll::from_fd(epfd)?;
+    if maxevents <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let events = events.get_as_mut_slice(maxevents as usize)?;
+    let timeout = (timeout >= 0).then(|| Duration::from_millis(timeout as u64));
+
+    with_sigmask(tf, sigmask, sigsetsize, || {
+        let ready = wait_ready(timeout, true, || {
+            let ready = epoll.ready_events(events.len());
+            events[..ready.len()].copy_from_slice(&ready);
+            ready.len()
+        })?;
+        Ok(ready as _)
+    })
+}
+
+/// Like [`sys_epoll_pwait`] without changing the signal mask.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_epoll_wait(
+    tf: &mut TrapFrame,
+    epfd: c_int,
+    events: UserPtr<epoll_event>,
+    maxevents: i32,
+    timeout: i32,
+) -> LinuxResult<isize> {
+    sys_epoll_pwait(tf, epfd, events, maxevents, timeout, 0usize.into(), 0)
+}
@@ -0,0 +1,21 @@
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{O_CLOEXEC, O_NONBLOCK};
+
+use crate::file::{EventFd, add_file_like};
+
+/// Read one from the counter at a time.
+const EFD_SEMAPHORE: u32 = 1;
+
+/// Create an eventfd whose counter starts at `initval`.
+pub fn sys_eventfd2(initval: u32, flags: u32) -> LinuxResult<isize> {
+    debug!("sys_eventfd2 <= initval: {}, flags: {:#x}", initval, flags);
+    if flags & !(EFD_SEMAPHORE | O_CLOEXEC | O_NONBLOCK) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let semaphore = flags & EFD_SEMAPHORE != 0;
+    let nonblocking = flags & O_NONBLOCK != 0;
+    let eventfd = EventFd::new(initval as _, semaphore, nonblocking);
+    let fd = add_file_like(Arc::new(eventfd), flags & O_CLOEXEC != 0)?;
+    Ok(fd as _)
+}
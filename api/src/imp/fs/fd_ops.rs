@@ -1,24 +1,32 @@
-use core::{
-    ffi::{c_char, c_int},
-    panic,
-};
+use core::ffi::{c_char, c_int};
 
 use alloc::string::ToString;
 use axerrno::{AxError, LinuxError, LinuxResult};
 use axfs::fops::OpenOptions;
 use linux_raw_sys::general::{
-    __kernel_mode_t, AT_FDCWD, F_DUPFD, F_DUPFD_CLOEXEC, F_SETFL, O_APPEND, O_CREAT, O_DIRECTORY,
-    O_NONBLOCK, O_PATH, O_RDONLY, O_TRUNC, O_WRONLY,
+    __kernel_mode_t, AT_FDCWD, DN_CREATE, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD, F_GETFL, F_GETSIG,
+    F_NOTIFY, F_SETFD, F_SETFL, F_SETSIG, FD_CLOEXEC, O_APPEND, O_CLOEXEC, O_CREAT, O_DIRECTORY,
+    O_LARGEFILE, O_NOCTTY, O_NONBLOCK, O_PATH, O_RDONLY, O_TRUNC, O_WRONLY,
 };
 
+use super::{check_writable, current_umask};
 use crate::{
-    file::{Directory, FD_TABLE, File, FileLike, add_file_like, close_file_like, get_file_like},
+    file::{
+        CONSOLE_DEVICE, DevFile, Directory, FD_TABLE, File, FileDescriptor, FileLike, ProcFile,
+        TTY_DEVICE, Tty, add_file_like, close_file_like, device_at, fd_limit, get_cloexec,
+        get_file_like, io_signal, notify_dir_change, set_cloexec, set_dir_notify, set_file_perm,
+        set_io_signal,
+    },
     path::handle_file_path,
     ptr::UserConstPtr,
 };
 
 const O_EXEC: u32 = O_PATH;
 
+/// The largest file size representable in a 32-bit `off_t`, the limit for
+/// files opened without `O_LARGEFILE`.
+const MAX_NON_LFS: u64 = i32::MAX as u64;
+
 /// Convert open flags to [`OpenOptions`].
 fn flags_to_options(flags: c_int, _mode: __kernel_mode_t) -> OpenOptions {
     let flags = flags as u32;
@@ -62,38 +70,68 @@ pub fn sys_openat(
     flags: i32,
     mode: __kernel_mode_t,
 ) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     let opts = flags_to_options(flags, mode);
     debug!("sys_openat <= {} {} {:?}", dirfd, path, opts);
 
-    let dir = if path.starts_with('/') || dirfd == AT_FDCWD {
-        None
-    } else {
-        Some(Directory::from_fd(dirfd)?)
-    };
     let real_path = handle_file_path(dirfd, path)?;
+    let cloexec = flags as u32 & O_CLOEXEC != 0;
+
+    let rdev = device_at(real_path.as_str());
+    match rdev {
+        Some(TTY_DEVICE) => return Ok(Tty::open_controlling()?.add_to_fd_table(cloexec)? as _),
+        Some(CONSOLE_DEVICE) => {
+            let noctty = flags as u32 & O_NOCTTY != 0;
+            return Ok(Tty::open_console(noctty)?.add_to_fd_table(cloexec)? as _);
+        }
+        _ => {}
+    }
+
+    if let Some(dev) = rdev.and_then(DevFile::open) {
+        return Ok(dev.add_to_fd_table(cloexec)? as _);
+    }
+
+    if let Some(file) = ProcFile::open(real_path.as_str()) {
+        if flags as u32 & 0b11 != O_RDONLY && !file.writable() {
+            return Err(LinuxError::EACCES);
+        }
+        return Ok(file.add_to_fd_table(cloexec)? as _);
+    }
+
+    let created = flags as u32 & O_CREAT != 0 && !real_path.exists();
+    if flags as u32 & 0b11 != O_RDONLY || flags as u32 & O_TRUNC != 0 || created {
+        check_writable(real_path.as_str())?;
+    }
 
     if !opts.has_directory() {
-        match dir.as_ref().map_or_else(
-            || axfs::fops::File::open(path, &opts),
-            |dir| dir.inner().open_file_at(path, &opts),
-        ) {
+        match axfs::fops::File::open(real_path.as_str(), &opts) {
             Err(AxError::IsADirectory) => {}
             r => {
-                let fd = File::new(r?, real_path.to_string()).add_to_fd_table()?;
+                let file = r?;
+                // All offsets are 64-bit, so `O_LARGEFILE` changes nothing but
+                // this check. The C library normally sets it on every open.
+                if flags as u32 & O_LARGEFILE == 0 && file.get_attr()?.size() > MAX_NON_LFS {
+                    return Err(LinuxError::EOVERFLOW);
+                }
+                if created {
+                    set_file_perm(real_path.as_str(), mode as u32 & 0o7777 & !current_umask());
+                }
+                let fd = File::new(file, real_path.to_string())
+                    .status_flags(flags as u32)
+                    .add_to_fd_table(cloexec)?;
+                if created {
+                    notify_dir_change(real_path.as_str(), DN_CREATE);
+                }
                 return Ok(fd as _);
             }
         }
     }
 
     let fd = Directory::new(
-        dir.map_or_else(
-            || axfs::fops::Directory::open_dir(path, &opts),
-            |dir| dir.inner().open_dir_at(path, &opts),
-        )?,
+        axfs::fops::Directory::open_dir(real_path.as_str(), &opts)?,
         real_path.to_string(),
     )
-    .add_to_fd_table()?;
+    .add_to_fd_table(cloexec)?;
     Ok(fd as _)
 }
 
@@ -115,54 +153,133 @@ pub fn sys_close(fd: c_int) -> LinuxResult<isize> {
     Ok(0)
 }
 
-fn dup_fd(old_fd: c_int) -> LinuxResult<isize> {
+fn dup_fd(old_fd: c_int, cloexec: bool) -> LinuxResult<isize> {
     let f = get_file_like(old_fd)?;
-    let new_fd = add_file_like(f)?;
+    let new_fd = add_file_like(f, cloexec)?;
     Ok(new_fd as _)
 }
 
 pub fn sys_dup(old_fd: c_int) -> LinuxResult<isize> {
     debug!("sys_dup <= {}", old_fd);
-    dup_fd(old_fd)
+    dup_fd(old_fd, false)
 }
 
+/// Duplicate `old_fd` onto the lowest free file descriptor that is at least
+/// `min_fd`, as `F_DUPFD` does.
+///
+/// Fails with `EINVAL` if `min_fd` is not below the open file limit, or with
+/// `EMFILE` if every descriptor from `min_fd` up to the limit is taken.
+fn dup_fd_from(old_fd: c_int, min_fd: usize, cloexec: bool) -> LinuxResult<isize> {
+    let limit = fd_limit();
+    if min_fd >= limit {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut fd_table = FD_TABLE.write();
+    let file = fd_table
+        .get(old_fd as _)
+        .map(|fd| fd.file.clone())
+        .ok_or(LinuxError::EBADF)?;
+    let new_fd = (min_fd..limit)
+        .find(|&fd| !fd_table.is_assigned(fd))
+        .ok_or(LinuxError::EMFILE)?;
+    fd_table
+        .add_at(new_fd, FileDescriptor { file, cloexec })
+        .map_err(|_| LinuxError::EMFILE)?;
+    Ok(new_fd as _)
+}
+
+/// Make `new_fd` refer to the file of `old_fd`, closing `new_fd` first if it
+/// is open.
+///
+/// `new_fd` must be below the open file limit. Duplicating a valid file
+/// descriptor onto itself does nothing, otherwise `new_fd` has `FD_CLOEXEC`
+/// cleared.
 pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> LinuxResult<isize> {
     debug!("sys_dup2 <= old_fd: {}, new_fd: {}", old_fd, new_fd);
+    dup_to(old_fd, new_fd, false)
+}
+
+fn dup_to(old_fd: c_int, new_fd: c_int, cloexec: bool) -> LinuxResult<isize> {
+    if new_fd < 0 || new_fd as usize >= fd_limit() {
+        return Err(LinuxError::EBADF);
+    }
+
     let mut fd_table = FD_TABLE.write();
-    let f = fd_table
+    let file = fd_table
         .get(old_fd as _)
-        .cloned()
+        .map(|fd| fd.file.clone())
         .ok_or(LinuxError::EBADF)?;
 
-    if old_fd != new_fd {
-        fd_table.remove(new_fd as _);
-        fd_table
-            .add_at(new_fd as _, f)
-            .unwrap_or_else(|_| panic!("new_fd should be valid"));
+    if old_fd == new_fd {
+        return Ok(new_fd as _);
     }
+    let replaced = fd_table.remove(new_fd as _);
+    fd_table
+        .add_at(new_fd as _, FileDescriptor { file, cloexec })
+        .map_err(|_| LinuxError::EBADF)?;
+    drop(fd_table);
 
+    // The file that was open as `new_fd` is closed like by `close`, but
+    // errors writing it back are not reported.
+    if let Some(replaced) = replaced {
+        replaced.file.close();
+        let _ = replaced.file.flush();
+    }
     Ok(new_fd as _)
 }
 
+/// Like [`sys_dup2`], but fails with `EINVAL` if `old_fd` and `new_fd` are
+/// the same, and sets `FD_CLOEXEC` on `new_fd` with `O_CLOEXEC`.
+pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> LinuxResult<isize> {
+    debug!(
+        "sys_dup3 <= old_fd: {}, new_fd: {}, flags: {:#x}",
+        old_fd, new_fd, flags
+    );
+    if old_fd == new_fd || flags as u32 & !O_CLOEXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    dup_to(old_fd, new_fd, flags as u32 & O_CLOEXEC != 0)
+}
+
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> LinuxResult<isize> {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
 
     match cmd as u32 {
-        F_DUPFD => dup_fd(fd),
-        F_DUPFD_CLOEXEC => {
-            warn!("sys_fcntl: treat F_DUPFD_CLOEXEC as F_DUPFD");
-            dup_fd(fd)
+        F_DUPFD => dup_fd_from(fd, arg, false),
+        F_DUPFD_CLOEXEC => dup_fd_from(fd, arg, true),
+        F_GETFD => Ok(if get_cloexec(fd)? { FD_CLOEXEC as _ } else { 0 }),
+        F_SETFD => {
+            set_cloexec(fd, arg & FD_CLOEXEC as usize != 0)?;
+            Ok(0)
         }
+        F_NOTIFY => {
+            let dir = get_file_like(fd)?
+                .into_any()
+                .downcast::<Directory>()
+                .map_err(|_| LinuxError::ENOTDIR)?;
+            set_dir_notify(&dir, fd, arg as u32);
+            Ok(0)
+        }
+        F_SETSIG => {
+            set_io_signal(&get_file_like(fd)?, arg as u32)?;
+            Ok(0)
+        }
+        F_GETSIG => Ok(io_signal(&get_file_like(fd)?).map_or(0, |signo| signo as isize)),
+        F_GETFL => Ok(get_file_like(fd)?.status_flags() as _),
         F_SETFL => {
-            if fd == 0 || fd == 1 || fd == 2 {
-                return Ok(0);
+            // Only `O_NONBLOCK` and `O_APPEND` can be changed, the access mode
+            // and the other flags are ignored.
+            let file = get_file_like(fd)?;
+            file.set_nonblocking(arg as u32 & O_NONBLOCK != 0)?;
+            if let Ok(file) = file.into_any().downcast::<File>() {
+                file.set_append(arg as u32 & O_APPEND != 0);
             }
-            get_file_like(fd)?.set_nonblocking(arg & (O_NONBLOCK as usize) > 0)?;
             Ok(0)
         }
         _ => {
             warn!("unsupported fcntl parameters: cmd: {}", cmd);
-            Ok(0)
+            Err(LinuxError::EINVAL)
         }
     }
 }
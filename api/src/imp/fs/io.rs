@@ -1,11 +1,16 @@
-use core::ffi::c_int;
+use core::ffi::{c_char, c_int};
 
+use alloc::{string::ToString, sync::Arc};
 use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::OpenOptions;
 use axio::SeekFrom;
-use linux_raw_sys::general::{__kernel_off_t, iovec};
+use linux_raw_sys::general::{__kernel_off_t, AT_FDCWD, iovec};
+use memory_addr::PAGE_SIZE_4K;
 
+use super::check_writable;
 use crate::{
-    file::{File, FileLike, get_file_like},
+    file::{Directory, File, FileLike, Pipe, ProcFile, Socket, get_file_like, sync_all},
+    path::handle_file_path,
     ptr::{UserConstPtr, UserPtr},
 };
 
@@ -23,8 +28,11 @@ pub fn sys_read(fd: i32, buf: UserPtr<u8>, len: usize) -> LinuxResult<isize> {
     Ok(get_file_like(fd)?.read(buf)? as _)
 }
 
+/// The most buffers a vectored read or write may take.
+const IOV_MAX: usize = 1024;
+
 pub fn sys_readv(fd: i32, iov: UserPtr<iovec>, iocnt: usize) -> LinuxResult<isize> {
-    if !(0..=1024).contains(&iocnt) {
+    if !(0..=IOV_MAX).contains(&iocnt) {
         return Err(LinuxError::EINVAL);
     }
 
@@ -69,7 +77,7 @@ pub fn sys_write(fd: i32, buf: UserConstPtr<u8>, len: usize) -> LinuxResult<isiz
 }
 
 pub fn sys_writev(fd: i32, iov: UserConstPtr<iovec>, iocnt: usize) -> LinuxResult<isize> {
-    if !(0..=1024).contains(&iocnt) {
+    if !(0..=IOV_MAX).contains(&iocnt) {
         return Err(LinuxError::EINVAL);
     }
 
@@ -88,7 +96,13 @@ pub fn sys_writev(fd: i32, iov: UserConstPtr<iovec>, iocnt: usize) -> LinuxResul
             buf.len()
         );
 
-        let written = get_file_like(fd)?.write(buf)?;
+        // Once some bytes are written, an error (e.g. `EINTR`) ends the
+        // transfer with a partial count instead.
+        let written = match get_file_like(fd)?.write(buf) {
+            Ok(written) => written,
+            Err(_) if ret > 0 => break,
+            Err(err) => return Err(err),
+        };
         ret += written as isize;
 
         if written < buf.len() {
@@ -99,6 +113,130 @@ pub fn sys_writev(fd: i32, iov: UserConstPtr<iovec>, iocnt: usize) -> LinuxResul
     Ok(ret)
 }
 
+/// Get the file `fd` to read or write at `offset`.
+///
+/// Fails with `EINVAL` if `offset` is negative, with `EISDIR` for a directory,
+/// or with `ESPIPE` for anything else that is not a regular file.
+fn positional_file(fd: c_int, offset: __kernel_off_t) -> LinuxResult<(Arc<File>, u64)> {
+    if offset < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let f = get_file_like(fd)?.into_any();
+    if f.is::<Directory>() {
+        return Err(LinuxError::EISDIR);
+    }
+    let file = f.downcast::<File>().map_err(|_| LinuxError::ESPIPE)?;
+    Ok((file, offset as u64))
+}
+
+/// Read from the file `fd` at `offset`, without moving its position.
+pub fn sys_pread64(
+    fd: c_int,
+    buf: UserPtr<u8>,
+    len: usize,
+    offset: __kernel_off_t,
+) -> LinuxResult<isize> {
+    let buf = buf.get_as_mut_slice(len)?;
+    debug!(
+        "sys_pread64 <= fd: {}, len: {}, offset: {}",
+        fd,
+        buf.len(),
+        offset
+    );
+    let (file, offset) = positional_file(fd, offset)?;
+    Ok(file.read_at(offset, buf)? as _)
+}
+
+/// Write to the file `fd` at `offset`, without moving its position.
+pub fn sys_pwrite64(
+    fd: c_int,
+    buf: UserConstPtr<u8>,
+    len: usize,
+    offset: __kernel_off_t,
+) -> LinuxResult<isize> {
+    let buf = buf.get_as_slice(len)?;
+    debug!(
+        "sys_pwrite64 <= fd: {}, len: {}, offset: {}",
+        fd,
+        buf.len(),
+        offset
+    );
+    let (file, offset) = positional_file(fd, offset)?;
+    Ok(file.write_at(offset, buf)? as _)
+}
+
+/// Like [`sys_readv`], reading from `offset` on like [`sys_pread64`].
+pub fn sys_preadv(
+    fd: c_int,
+    iov: UserPtr<iovec>,
+    iocnt: usize,
+    offset: __kernel_off_t,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_preadv <= fd: {}, iocnt: {}, offset: {}",
+        fd, iocnt, offset
+    );
+    if iocnt > IOV_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let (file, offset) = positional_file(fd, offset)?;
+
+    let mut ret = 0;
+    for iov in iov.get_as_mut_slice(iocnt)? {
+        if iov.iov_len == 0 {
+            continue;
+        }
+        let buf = UserPtr::<u8>::from(iov.iov_base as usize);
+        let buf = buf.get_as_mut_slice(iov.iov_len as _)?;
+        let read = match file.read_at(offset + ret as u64, buf) {
+            Ok(read) => read,
+            Err(_) if ret > 0 => break,
+            Err(err) => return Err(err),
+        };
+        ret += read;
+        if read < buf.len() {
+            break;
+        }
+    }
+    Ok(ret as _)
+}
+
+/// Like [`sys_writev`], writing from `offset` on like [`sys_pwrite64`].
+pub fn sys_pwritev(
+    fd: c_int,
+    iov: UserConstPtr<iovec>,
+    iocnt: usize,
+    offset: __kernel_off_t,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_pwritev <= fd: {}, iocnt: {}, offset: {}",
+        fd, iocnt, offset
+    );
+    if iocnt > IOV_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let (file, offset) = positional_file(fd, offset)?;
+
+    let mut ret = 0;
+    for iov in iov.get_as_slice(iocnt)? {
+        if iov.iov_len == 0 {
+            continue;
+        }
+        let buf = UserConstPtr::<u8>::from(iov.iov_base as usize);
+        let buf = buf.get_as_slice(iov.iov_len as _)?;
+        let written = match file.write_at(offset + ret as u64, buf) {
+            Ok(written) => written,
+            Err(_) if ret > 0 => break,
+            Err(err) => return Err(err),
+        };
+        ret += written;
+        if written < buf.len() {
+            break;
+        }
+    }
+    Ok(ret as _)
+}
+
 pub fn sys_lseek(fd: c_int, offset: __kernel_off_t, whence: c_int) -> LinuxResult<isize> {
     debug!("sys_lseek <= {} {} {}", fd, offset, whence);
     let pos = match whence {
@@ -107,6 +245,183 @@ pub fn sys_lseek(fd: c_int, offset: __kernel_off_t, whence: c_int) -> LinuxResul
         2 => SeekFrom::End(offset as _),
         _ => return Err(LinuxError::EINVAL),
     };
+    if let Ok(file) = ProcFile::from_fd(fd) {
+        return Ok(file.seek(pos)? as _);
+    }
+    if let Ok(dir) = Directory::from_fd(fd) {
+        return Ok(dir.seek(pos)? as _);
+    }
     let off = File::from_fd(fd)?.inner().seek(pos)?;
     Ok(off as _)
 }
+
+/// Truncate or extend the file at `path` to `length` bytes.
+///
+/// Growing the file past `RLIMIT_FSIZE` fails with `EFBIG` and raises
+/// `SIGXFSZ`.
+pub fn sys_truncate(path: UserConstPtr<c_char>, length: __kernel_off_t) -> LinuxResult<isize> {
+    let path = handle_file_path(AT_FDCWD, path.get_as_path()?)?;
+    debug!("sys_truncate <= path: {}, length: {}", path, length);
+    if length < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    check_writable(path.as_str())?;
+
+    let mut opts = OpenOptions::new();
+    opts.write(true);
+    let file = axfs::fops::File::open(path.as_str(), &opts)?;
+    File::new(file, path.to_string()).truncate(length as _)?;
+    Ok(0)
+}
+
+/// Truncate or extend the file `fd` to `length` bytes, like
+/// [`sys_truncate`].
+pub fn sys_ftruncate(fd: c_int, length: __kernel_off_t) -> LinuxResult<isize> {
+    debug!("sys_ftruncate <= fd: {}, length: {}", fd, length);
+    if length < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    File::from_fd(fd)?.truncate(length as _)?;
+    Ok(0)
+}
+
+// The `fallocate` modes, see `linux/falloc.h`.
+const FALLOC_FL_KEEP_SIZE: u32 = 0x01;
+const FALLOC_FL_PUNCH_HOLE: u32 = 0x02;
+const FALLOC_FL_ZERO_RANGE: u32 = 0x10;
+
+/// Manipulate the space of the file `fd` in `[offset, offset + len)`.
+///
+/// Mode 0 extends the file to cover the range, `FALLOC_FL_ZERO_RANGE`
+/// zeroes it too, and `FALLOC_FL_PUNCH_HOLE` zeroes it within the file.
+/// With `FALLOC_FL_KEEP_SIZE` the size does not change, which
+/// `FALLOC_FL_PUNCH_HOLE` requires. The file systems allocate everything
+/// they store, so there is nothing else to reserve or free.
+///
+/// Fails with `EBADF` unless `fd` is open for writing, and with
+/// `EOPNOTSUPP` for other modes.
+pub fn sys_fallocate(
+    fd: c_int,
+    mode: u32,
+    offset: __kernel_off_t,
+    len: __kernel_off_t,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_fallocate <= fd: {}, mode: {:#x}, offset: {}, len: {}",
+        fd, mode, offset, len
+    );
+    if len <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let keep_size = mode & FALLOC_FL_KEEP_SIZE != 0;
+    match mode & !FALLOC_FL_KEEP_SIZE {
+        0 | FALLOC_FL_ZERO_RANGE => {}
+        FALLOC_FL_PUNCH_HOLE if keep_size => {}
+        _ => return Err(LinuxError::EOPNOTSUPP),
+    }
+    let (file, start) = positional_file(fd, offset)?;
+    let end = start.checked_add(len as u64).ok_or(LinuxError::EFBIG)?;
+
+    // Writing nothing tells if the file is open for writing.
+    file.inner()
+        .write_at(0, &[])
+        .map_err(|_| LinuxError::EBADF)?;
+
+    let size = file.inner().get_attr()?.size();
+    if mode & (FALLOC_FL_PUNCH_HOLE | FALLOC_FL_ZERO_RANGE) != 0 {
+        file.zero_range(start, end.min(size))?;
+    }
+    if !keep_size && end > size {
+        file.truncate(end)?;
+    }
+    Ok(0)
+}
+
+/// A range of a file for [`sys_cachestat`], `struct cachestat_range`.
+#[repr(C)]
+pub struct CachestatRange {
+    off: u64,
+    /// The length, or 0 for up to the end of the file.
+    len: u64,
+}
+
+/// The page cache state of a range of a file, `struct cachestat`.
+#[repr(C)]
+#[derive(Default)]
+pub struct Cachestat {
+    nr_cache: u64,
+    nr_dirty: u64,
+    nr_writeback: u64,
+    nr_evicted: u64,
+    nr_recently_evicted: u64,
+}
+
+/// Report how many pages of the file `fd` in `range` are in the page cache.
+///
+/// There is no page cache apart from the file systems themselves, so every
+/// page of the range within the file counts as cached, and none as under
+/// writeback or evicted. The pages written since the file was last flushed,
+/// by `fsync` for example, count as dirty while the file stays open. Files
+/// other than regular ones have no pages.
+pub fn sys_cachestat(
+    fd: c_int,
+    range: UserConstPtr<CachestatRange>,
+    cstat: UserPtr<Cachestat>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    debug!("sys_cachestat <= fd: {}, flags: {:#x}", fd, flags);
+    let range = range.get_as_ref()?;
+    let cstat = cstat.get_as_mut()?;
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let file = get_file_like(fd)?;
+
+    let mut stat = Cachestat::default();
+    if let Ok(file) = file.into_any().downcast::<File>() {
+        let size = file.inner().get_attr()?.size();
+        let first = range.off / PAGE_SIZE_4K as u64;
+        let end = match range.off.checked_add(range.len) {
+            Some(end) if range.len != 0 => end.min(size),
+            _ => size,
+        };
+        let last = end.div_ceil(PAGE_SIZE_4K as u64);
+        stat.nr_cache = last.saturating_sub(first);
+        stat.nr_dirty = file.dirty_pages(first..last);
+    }
+    *cstat = stat;
+    Ok(0)
+}
+
+/// Write back the buffered data of the file `fd`.
+///
+/// Fails with `EINVAL` for pipes and sockets, which have nothing to write
+/// back, or with `EIO` if writing it back fails.
+pub fn sys_fsync(fd: c_int) -> LinuxResult<isize> {
+    debug!("sys_fsync <= fd: {}", fd);
+    let f = get_file_like(fd)?;
+    let any = f.clone().into_any();
+    if any.is::<Pipe>() || any.is::<Socket>() {
+        return Err(LinuxError::EINVAL);
+    }
+    f.flush()?;
+    Ok(0)
+}
+
+/// Like [`sys_fsync`], as there is no metadata to leave out.
+pub fn sys_fdatasync(fd: c_int) -> LinuxResult<isize> {
+    sys_fsync(fd)
+}
+
+/// Write back the buffered data of every open file, see [`sync_all`].
+pub fn sys_sync() -> LinuxResult<isize> {
+    sync_all();
+    Ok(0)
+}
+
+/// Like [`sys_sync`], as there is only one file system to write back.
+pub fn sys_syncfs(fd: c_int) -> LinuxResult<isize> {
+    get_file_like(fd)?;
+    sync_all();
+    Ok(0)
+}
@@ -1,13 +1,19 @@
 mod ctl;
+mod epoll;
+mod eventfd;
 mod fd_ops;
 mod io;
 mod mount;
 mod pipe;
+mod poll;
 mod stat;
 
 pub use self::ctl::*;
+pub use self::epoll::*;
+pub use self::eventfd::*;
 pub use self::fd_ops::*;
 pub use self::io::*;
 pub use self::mount::*;
 pub use self::pipe::*;
+pub use self::poll::*;
 pub use self::stat::*;
@@ -1,12 +1,25 @@
-use core::ffi::{c_char, c_void};
+use core::{
+    ffi::{c_char, c_void},
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-use alloc::vec::Vec;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 use axerrno::{LinuxError, LinuxResult};
+use axfs::CURRENT_DIR_PATH;
 use axsync::Mutex;
-use linux_raw_sys::general::AT_FDCWD;
+use linux_raw_sys::general::{
+    AT_FDCWD, MS_BIND, MS_NOATIME, MS_NODEV, MS_NOEXEC, MS_NOSUID, MS_RDONLY, MS_REC, MS_RELATIME,
+    MS_REMOUNT, MSDOS_SUPER_MAGIC, statfs,
+};
 
 use crate::{
-    path::{FilePath, handle_file_path},
+    file::open_file_paths,
+    path::{FilePath, handle_file_path, handle_mount_point},
     ptr::UserConstPtr,
 };
 
@@ -17,8 +30,27 @@ pub fn sys_mount(
     flags: i32,
     _data: UserConstPtr<c_void>,
 ) -> LinuxResult<isize> {
-    let source = source.get_as_str()?;
-    let target = target.get_as_str()?;
+    let target = target.get_as_path()?;
+    if flags as u32 & MS_REMOUNT != 0 {
+        info!("sys_mount <= remount target: {}, flags: {}", target, flags);
+        remount(&handle_mount_point(target)?, flags as u32)?;
+        return Ok(0);
+    }
+
+    if flags as u32 & MS_BIND != 0 {
+        let source = handle_file_path(AT_FDCWD, source.get_as_path()?)?;
+        let target = handle_mount_point(target)?;
+        info!(
+            "sys_mount <= bind source: {}, target: {}, flags: {}",
+            source, target, flags
+        );
+        bind(&source, &target, flags as u32 & MS_REC != 0)?;
+        return Ok(0);
+    }
+
+    // TODO: only the mount table is updated, the file system itself is not
+    // mounted
+    let source = source.get_as_path()?;
     let fs_type = fs_type.get_as_str()?;
     info!(
         "sys_mount <= source: {}, target: {}, fs_type: {}, flags: {}",
@@ -26,7 +58,7 @@ pub fn sys_mount(
     );
 
     let device_path = handle_file_path(AT_FDCWD, source)?;
-    let mount_path = handle_file_path(AT_FDCWD, target)?;
+    let mount_path = handle_mount_point(target)?;
     info!(
         "mount {:?} to {:?} with fs_type={:?}",
         device_path, mount_path, fs_type
@@ -34,7 +66,7 @@ pub fn sys_mount(
 
     if fs_type != "vfat" {
         debug!("fs_type can only be vfat.");
-        return Err(LinuxError::EPERM);
+        return Err(LinuxError::ENODEV);
     }
 
     if !mount_path.exists() {
@@ -43,11 +75,11 @@ pub fn sys_mount(
     }
 
     if check_mounted(&mount_path) {
-        debug!("mount path includes mounted fs");
+        debug!("mount path is already a mount point");
         return Err(LinuxError::EPERM);
     }
 
-    if !mount_fat_fs(&device_path, &mount_path) {
+    if !mount_fs(&device_path, &mount_path, fs_type, flags as u32) {
         debug!("mount error");
         return Err(LinuxError::EPERM);
     }
@@ -55,13 +87,13 @@ pub fn sys_mount(
 }
 
 pub fn sys_umount2(target: UserConstPtr<c_char>, flags: i32) -> LinuxResult<isize> {
-    let target = target.get_as_str()?;
+    let target = target.get_as_path()?;
     info!("sys_umount2 <= target: {}, flags: {}", target, flags);
 
-    let mount_path = handle_file_path(AT_FDCWD, target)?;
-    if flags != 0 {
+    let mount_path = handle_mount_point(target)?;
+    if flags & !(MNT_FORCE | MNT_DETACH | UMOUNT_NOFOLLOW) != 0 {
         debug!("flags unimplemented");
-        return Err(LinuxError::EPERM);
+        return Err(LinuxError::EINVAL);
     }
 
     if !mount_path.exists() {
@@ -69,7 +101,21 @@ pub fn sys_umount2(target: UserConstPtr<c_char>, flags: i32) -> LinuxResult<isiz
         return Err(LinuxError::EPERM);
     }
 
-    if !umount_fat_fs(&mount_path) {
+    if !check_mounted(&mount_path) {
+        debug!("mount path is not a mount point");
+        return Err(LinuxError::EINVAL);
+    }
+
+    // Nothing is cut off from a lazily detached file system: what is open on
+    // it stays usable, and it is gone once that is closed. There is no I/O to
+    // abort either, so `MNT_FORCE` changes nothing.
+    let detach = flags & MNT_DETACH != 0;
+    if !detach && mount_busy(&mount_path) {
+        debug!("mount path is busy");
+        return Err(LinuxError::EBUSY);
+    }
+
+    if !umount_fs(&mount_path, detach) {
         debug!("umount error");
         return Err(LinuxError::EPERM);
     }
@@ -82,21 +128,47 @@ struct MountedFs {
     //pub inner: Arc<Mutex<FATFileSystem>>,
     pub device: FilePath,
     pub mnt_dir: FilePath,
+    /// The file system type passed to `mount`.
+    pub fs_type: String,
+    /// The `MS_*` mount options, see [`MOUNT_OPTIONS`].
+    pub flags: u32,
+    /// Whether this is a bind mount, so that paths below `mnt_dir` resolve
+    /// to the same places below `device`.
+    pub bind: bool,
 }
 
+/// The mount flags kept in the mount table.
+const MOUNT_OPTIONS: u32 = MS_RDONLY | MS_NOSUID | MS_NODEV | MS_NOEXEC | MS_NOATIME | MS_RELATIME;
+
 impl MountedFs {
-    pub fn new(device: &FilePath, mnt_dir: &FilePath) -> Self {
+    pub fn new(device: &FilePath, mnt_dir: &FilePath, fs_type: &str, flags: u32) -> Self {
         Self {
             device: device.clone(),
             mnt_dir: mnt_dir.clone(),
+            fs_type: fs_type.to_string(),
+            flags: flags & MOUNT_OPTIONS,
+            bind: false,
+        }
+    }
+
+    fn bind(source: &FilePath, mnt_dir: &FilePath, fs_type: &str, flags: u32) -> Self {
+        Self {
+            bind: true,
+            ..Self::new(source, mnt_dir, fs_type, flags)
         }
     }
 
+    /// Whether `path` lies on this file system.
+    fn contains(&self, path: &str) -> bool {
+        is_below(path, self.mnt_dir.as_str().trim_end_matches('/'))
+    }
+
     #[allow(unused)]
     pub fn device(&self) -> FilePath {
         self.device.clone()
     }
 
+    #[allow(unused)]
     pub fn mnt_dir(&self) -> FilePath {
         self.mnt_dir.clone()
     }
@@ -106,14 +178,19 @@ impl MountedFs {
 /// Note that the startup file system is not in the vec, but in mod.rs
 static MOUNTED: Mutex<Vec<MountedFs>> = Mutex::new(Vec::new());
 
-/// Mount a fatfs device
-pub fn mount_fat_fs(device_path: &FilePath, mount_path: &FilePath) -> bool {
+/// The mount options of the startup file system.
+static ROOT_FLAGS: AtomicU32 = AtomicU32::new(0);
+
+/// Mount a device with the file system `fs_type`
+pub fn mount_fs(device_path: &FilePath, mount_path: &FilePath, fs_type: &str, flags: u32) -> bool {
     // device_path needs symlink lookup, but mount_path does not
     // only opened files will be added to the symlink table for now, so do not convert now
     // debug!("mounting {} to {}", device_path.path(), mount_path.path());
     // if let Some(true_device_path) = real_path(device_path) {
     if mount_path.exists() {
-        MOUNTED.lock().push(MountedFs::new(device_path, mount_path));
+        MOUNTED
+            .lock()
+            .push(MountedFs::new(device_path, mount_path, fs_type, flags));
         info!(
             "mounted {} to {}",
             device_path.as_str(),
@@ -129,16 +206,210 @@ pub fn mount_fat_fs(device_path: &FilePath, mount_path: &FilePath) -> bool {
     false
 }
 
-/// unmount a fatfs device
-pub fn umount_fat_fs(mount_path: &FilePath) -> bool {
+/// Bind the directory `source` at `target`, with the options of the file
+/// system it lives on, so that the files below `source` are found below
+/// `target` too.
+///
+/// With `recursive`, the file systems mounted below `source` are bound at the
+/// same places below `target` too. Without it they still are, since every
+/// mount shares the files of the startup file system, but `target` reports
+/// the options of the file system holding `source` all the way down.
+fn bind(source: &FilePath, target: &FilePath, recursive: bool) -> LinuxResult {
+    if !source.exists() || !target.exists() {
+        return Err(LinuxError::ENOENT);
+    }
+    if check_mounted(target) {
+        return Err(LinuxError::EBUSY);
+    }
+    let src = source.as_str().trim_end_matches('/');
+    let dst = target.as_str().trim_end_matches('/');
+
+    let mut mounted = MOUNTED.lock();
+    let top = match mount_at(&mounted, src) {
+        Some(m) => MountedFs::bind(source, target, &m.fs_type, m.flags),
+        None => MountedFs::bind(source, target, "", ROOT_FLAGS.load(Ordering::Acquire)),
+    };
+    let mut binds = vec![top];
+    if recursive {
+        for m in mounted.iter() {
+            let rest = m.mnt_dir.as_str().trim_end_matches('/').strip_prefix(src);
+            if let Some(rest) = rest.filter(|rest| rest.starts_with('/')) {
+                let mnt_dir = FilePath::new(format!("{dst}{rest}"))?;
+                binds.push(MountedFs::new(&m.device, &mnt_dir, &m.fs_type, m.flags));
+            }
+        }
+    }
+    mounted.extend(binds);
+    Ok(())
+}
+
+/// unmount a device, and with `detach` the file systems mounted below it too
+pub fn umount_fs(mount_path: &FilePath, detach: bool) -> bool {
+    let mnt_dir = mount_path.as_str().trim_end_matches('/');
     let mut mounted = MOUNTED.lock();
     let length_before_deletion = mounted.len();
-    mounted.retain(|m| m.mnt_dir() != *mount_path);
+    mounted.retain(|m| {
+        let dir = m.mnt_dir.as_str().trim_end_matches('/');
+        dir != mnt_dir && !(detach && is_below(dir, mnt_dir))
+    });
     length_before_deletion > mounted.len()
 }
 
-/// check if a path is mounted
+/// Whether `path` is `dir` or lies below it.
+fn is_below(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Whether a file or working directory is open on the file system mounted
+/// at `mount_path`, or another one is mounted below it.
+///
+/// What is open through a bind mount is known by its path below the source,
+/// so anything open below the source keeps the bind mount busy too.
+fn mount_busy(mount_path: &FilePath) -> bool {
+    let mnt_dir = mount_path.as_str().trim_end_matches('/');
+    let mut dirs = vec![String::from(mnt_dir)];
+    let mounted = MOUNTED.lock();
+    for m in mounted.iter() {
+        let dir = m.mnt_dir.as_str().trim_end_matches('/');
+        if dir != mnt_dir && is_below(dir, mnt_dir) {
+            return true;
+        }
+        if dir == mnt_dir && m.bind {
+            dirs.push(m.device.as_str().trim_end_matches('/').into());
+        }
+    }
+    drop(mounted);
+    let busy = |path: &str| dirs.iter().any(|dir| is_below(path, dir));
+
+    if open_file_paths().iter().any(|path| busy(path)) {
+        return true;
+    }
+    processes().iter().any(|process| {
+        process.data::<ProcessData>().is_some_and(|data| {
+            let cwd = CURRENT_DIR_PATH.deref_from(&data.ns).lock();
+            busy(cwd.trim_end_matches('/'))
+        })
+    })
+}
+
+/// Change the mount options of the file system mounted at `mount_path`.
+///
+/// The file systems do not track errors, so unlike on Linux switching back
+/// to read-write never fails because of pending errors.
+fn remount(mount_path: &FilePath, flags: u32) -> LinuxResult {
+    if mount_path.is_root() {
+        ROOT_FLAGS.store(flags & MOUNT_OPTIONS, Ordering::Release);
+        return Ok(());
+    }
+    let mnt_dir = mount_path.as_str().trim_end_matches('/');
+    let mut mounted = MOUNTED.lock();
+    let mount = mounted
+        .iter_mut()
+        .find(|m| m.mnt_dir.as_str().trim_end_matches('/') == mnt_dir)
+        .ok_or(LinuxError::EINVAL)?;
+    mount.flags = flags & MOUNT_OPTIONS;
+    Ok(())
+}
+
+/// Check whether a file system is mounted at `path`.
 pub fn check_mounted(path: &FilePath) -> bool {
+    let path = path.as_str().trim_end_matches('/');
     let mounted = MOUNTED.lock();
-    mounted.iter().any(|m| path.starts_with(&m.mnt_dir()))
+    mounted
+        .iter()
+        .any(|m| m.mnt_dir.as_str().trim_end_matches('/') == path)
+}
+
+/// Find the innermost mount containing `path`.
+fn mount_at<'a>(mounted: &'a [MountedFs], path: &str) -> Option<&'a MountedFs> {
+    mounted
+        .iter()
+        .filter(|m| m.contains(path))
+        .max_by_key(|m| m.mnt_dir.as_str().len())
+}
+
+/// Translate `path` below a bind mount to the same place below its source.
+///
+/// Returns `None` if `path` is not below a bind mount.
+pub(crate) fn resolve_bind(path: &str) -> Option<String> {
+    let mounted = MOUNTED.lock();
+    let bind = mounted
+        .iter()
+        .filter(|m| m.bind && m.contains(path))
+        .max_by_key(|m| m.mnt_dir.as_str().len())?;
+    let rest = &path[bind.mnt_dir.as_str().trim_end_matches('/').len()..];
+    Some(format!(
+        "{}{}",
+        bind.device.as_str().trim_end_matches('/'),
+        rest
+    ))
+}
+
+/// Whether `a` and `b` live on the same file system.
+pub(crate) fn same_mount(a: &str, b: &str) -> bool {
+    let mounted = MOUNTED.lock();
+    let mnt_dir = |path| mount_at(&mounted, path).map(|m| m.mnt_dir.as_str());
+    mnt_dir(a) == mnt_dir(b)
+}
+
+/// Get the `MS_*` mount options of the file system `path` lives on.
+pub(crate) fn mount_flags_at(path: &str) -> u32 {
+    mount_at(&MOUNTED.lock(), path).map_or_else(|| ROOT_FLAGS.load(Ordering::Acquire), |m| m.flags)
+}
+
+/// Fail with `EROFS` if `path` lives on a read-only mount.
+pub(crate) fn check_writable(path: &str) -> LinuxResult {
+    if mount_flags_at(path) & MS_RDONLY != 0 {
+        return Err(LinuxError::EROFS);
+    }
+    Ok(())
+}
+
+// The `umount2` flags, see `sys/mount.h`.
+const MNT_FORCE: i32 = 1;
+const MNT_DETACH: i32 = 2;
+const UMOUNT_NOFOLLOW: i32 = 8;
+
+// The `ST_*` flags reported by `statfs`, see `linux/statfs.h`.
+const ST_RDONLY: u32 = 0x0001;
+const ST_NOSUID: u32 = 0x0002;
+const ST_NODEV: u32 = 0x0004;
+const ST_NOEXEC: u32 = 0x0008;
+const ST_VALID: u32 = 0x0020;
+const ST_NOATIME: u32 = 0x0400;
+const ST_RELATIME: u32 = 0x1000;
+
+/// Describe the file system `path` lives on, as `statfs` does.
+///
+/// The innermost mount containing `path` is reported, or the root file
+/// system, which is not in the mount table, if there is none.
+pub(crate) fn statfs_at(path: &str) -> statfs {
+    let mounted = MOUNTED.lock();
+    let mount = mount_at(&mounted, path);
+
+    // SAFETY: valid for statfs
+    let mut buf: statfs = unsafe { core::mem::zeroed() };
+    buf.f_bsize = 4096;
+    buf.f_frsize = 4096;
+    buf.f_namelen = 255;
+    buf.f_type = match mount.map(|m| m.fs_type.as_str()) {
+        Some("vfat") => MSDOS_SUPER_MAGIC,
+        _ => 0,
+    } as _;
+
+    let flags = mount.map_or_else(|| ROOT_FLAGS.load(Ordering::Acquire), |m| m.flags);
+    let st_flags = [
+        (MS_RDONLY, ST_RDONLY),
+        (MS_NOSUID, ST_NOSUID),
+        (MS_NODEV, ST_NODEV),
+        (MS_NOEXEC, ST_NOEXEC),
+        (MS_NOATIME, ST_NOATIME),
+        (MS_RELATIME, ST_RELATIME),
+    ]
+    .iter()
+    .filter(|(ms, _)| flags & ms != 0)
+    .fold(ST_VALID, |acc, (_, st)| acc | st);
+    buf.f_flags = st_flags as _;
+    buf
 }
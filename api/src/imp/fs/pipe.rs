@@ -1,23 +1,42 @@
 use core::ffi::c_int;
 
-use axerrno::LinuxResult;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{O_CLOEXEC, O_NONBLOCK};
 
 use crate::{
-    file::{FileLike, Pipe, close_file_like},
-    ptr::UserPtr,
+    file::{File, FileLike, Pipe, add_file_like, close_file_like, get_file_like},
+    ptr::{UserPtr, nullable},
 };
 
-pub fn sys_pipe2(fds: UserPtr<[c_int; 2]>, flags: i32) -> LinuxResult<isize> {
-    if flags != 0 {
-        warn!("sys_pipe2: unsupported flags: {}", flags);
+/// Hint to move pages instead of copying.
+const SPLICE_F_MOVE: u32 = 1;
+/// Fail with `EAGAIN` instead of blocking on the pipes.
+const SPLICE_F_NONBLOCK: u32 = 2;
+/// Hint that more data will follow.
+const SPLICE_F_MORE: u32 = 4;
+/// Hint that the pages are gifted to the kernel.
+const SPLICE_F_GIFT: u32 = 8;
+
+/// Create a pipe, storing its read and write ends at `fds`.
+///
+/// `O_NONBLOCK` makes both ends non-blocking, and `O_CLOEXEC` sets
+/// `FD_CLOEXEC` on both.
+pub fn sys_pipe2(fds: UserPtr<[c_int; 2]>, flags: u32) -> LinuxResult<isize> {
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0 {
+        return Err(LinuxError::EINVAL);
     }
+    let cloexec = flags & O_CLOEXEC != 0;
 
     let fds = fds.get_as_mut()?;
 
     let (read_end, write_end) = Pipe::new();
-    let read_fd = read_end.add_to_fd_table()?;
-    let write_fd = write_end
-        .add_to_fd_table()
+    if flags & O_NONBLOCK != 0 {
+        read_end.set_nonblocking(true)?;
+        write_end.set_nonblocking(true)?;
+    }
+    let read_fd = add_file_like(Arc::new(read_end), cloexec)?;
+    let write_fd = add_file_like(Arc::new(write_end), cloexec)
         .inspect_err(|_| close_file_like(read_fd).unwrap())?;
 
     fds[0] = read_fd;
@@ -26,3 +45,130 @@ pub fn sys_pipe2(fds: UserPtr<[c_int; 2]>, flags: i32) -> LinuxResult<isize> {
     info!("sys_pipe2 <= fds: {:?}", fds);
     Ok(0)
 }
+
+/// One side of a `splice`.
+enum SpliceEnd {
+    Pipe(Arc<Pipe>),
+    /// A regular file, with the offset to use instead of the file position.
+    File(Arc<File>, Option<u64>),
+    Other(Arc<dyn FileLike>),
+}
+
+impl SpliceEnd {
+    fn new(fd: c_int, offset: Option<i64>) -> LinuxResult<Self> {
+        let f = get_file_like(fd)?;
+        let f = match f.clone().into_any().downcast::<Pipe>() {
+            Ok(_) if offset.is_some() => return Err(LinuxError::ESPIPE),
+            Ok(pipe) => return Ok(Self::Pipe(pipe)),
+            Err(_) => f,
+        };
+        match (f.clone().into_any().downcast::<File>(), offset) {
+            (Ok(file), Some(offset)) => {
+                let offset = u64::try_from(offset).map_err(|_| LinuxError::EINVAL)?;
+                Ok(Self::File(file, Some(offset)))
+            }
+            (Ok(file), None) => Ok(Self::File(file, None)),
+            (Err(_), Some(_)) => Err(LinuxError::ESPIPE),
+            (Err(_), None) => Ok(Self::Other(f)),
+        }
+    }
+
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        match self {
+            Self::File(file, Some(offset)) => file.read_at(*offset, buf),
+            Self::File(file, None) => file.read(buf),
+            Self::Other(f) => f.read(buf),
+            Self::Pipe(_) => unreachable!(),
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        match self {
+            Self::File(file, Some(offset)) => file.write_at(*offset, buf),
+            Self::File(file, None) => file.write(buf),
+            Self::Other(f) => f.write(buf),
+            Self::Pipe(_) => unreachable!(),
+        }
+    }
+}
+
+/// Move up to `len` bytes from `fd_in` to `fd_out` without going through
+/// user memory, where at least one of them is a pipe.
+///
+/// `off_in` and `off_out` give the offset to use on a regular file instead
+/// of its file position, and are advanced past the bytes moved; they must be
+/// NULL for pipes and other unseekable files. With `SPLICE_F_NONBLOCK`, or a
+/// non-blocking pipe, an empty or full pipe fails with `EAGAIN` instead of
+/// blocking. Returns the number of bytes moved, or 0 at the end of input.
+pub fn sys_splice(
+    fd_in: c_int,
+    off_in: UserPtr<i64>,
+    fd_out: c_int,
+    off_out: UserPtr<i64>,
+    len: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_splice <= fd_in: {}, fd_out: {}, len: {}, flags: {:#x}",
+        fd_in, fd_out, len, flags
+    );
+    if flags & !(SPLICE_F_MOVE | SPLICE_F_NONBLOCK | SPLICE_F_MORE | SPLICE_F_GIFT) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let nonblocking = flags & SPLICE_F_NONBLOCK != 0;
+    let off_in = nullable!(off_in.get_as_mut())?;
+    let off_out = nullable!(off_out.get_as_mut())?;
+    let input = SpliceEnd::new(fd_in, off_in.as_deref().copied())?;
+    let output = SpliceEnd::new(fd_out, off_out.as_deref().copied())?;
+    if !matches!(input, SpliceEnd::Pipe(_)) && !matches!(output, SpliceEnd::Pipe(_)) {
+        return Err(LinuxError::EINVAL);
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+
+    let moved = match (&input, &output) {
+        (SpliceEnd::Pipe(input), SpliceEnd::Pipe(output)) => {
+            input.transfer_to(output, len, nonblocking, false)?
+        }
+        (SpliceEnd::Pipe(input), _) => {
+            input.drain_with(len, nonblocking, |buf| output.write(buf))?
+        }
+        (_, SpliceEnd::Pipe(output)) => {
+            output.fill_with(len, nonblocking, |buf| input.read(buf))?
+        }
+        _ => unreachable!(),
+    };
+
+    for offset in [off_in, off_out].into_iter().flatten() {
+        *offset += moved as i64;
+    }
+    Ok(moved as _)
+}
+
+/// Copy up to `len` bytes from the pipe `fd_in` to the pipe `fd_out`,
+/// leaving them in `fd_in`.
+///
+/// Blocks like [`sys_splice`]. Returns the number of bytes copied, or 0 if
+/// `fd_in` is empty and its write end is closed.
+pub fn sys_tee(fd_in: c_int, fd_out: c_int, len: usize, flags: u32) -> LinuxResult<isize> {
+    debug!(
+        "sys_tee <= fd_in: {}, fd_out: {}, len: {}, flags: {:#x}",
+        fd_in, fd_out, len, flags
+    );
+    if flags & !(SPLICE_F_MOVE | SPLICE_F_NONBLOCK | SPLICE_F_MORE | SPLICE_F_GIFT) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let pipe = |fd| {
+        get_file_like(fd)?
+            .into_any()
+            .downcast::<Pipe>()
+            .map_err(|_| LinuxError::EINVAL)
+    };
+    let (input, output) = (pipe(fd_in)?, pipe(fd_out)?);
+    if len == 0 {
+        return Ok(0);
+    }
+    let copied = input.transfer_to(&output, len, flags & SPLICE_F_NONBLOCK != 0, true)?;
+    Ok(copied as _)
+}
@@ -0,0 +1,182 @@
+use core::{mem, time::Duration};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::{
+    arch::TrapFrame,
+    time::{TimeValue, monotonic_time},
+};
+use axsignal::{SignalSet, Signo};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{
+    POLLERR, POLLIN, POLLNVAL, POLLOUT, POLLRDNORM, POLLWRNORM, pollfd, timespec,
+};
+
+use crate::{
+    file::{AX_FILE_LIMIT, get_file_like},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    signal::{check_signals, sleep_interruptible},
+    time::TimeValueLike,
+};
+
+/// How long to sleep between checks of the polled files, which cannot wake
+/// up a poller.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Fill in `revents` of each of `fds`, returning how many are ready.
+fn poll_fds(fds: &mut [pollfd]) -> usize {
+    let mut ready = 0;
+    for pfd in fds {
+        pfd.revents = 0;
+        if pfd.fd < 0 {
+            continue;
+        }
+        let revents = match get_file_like(pfd.fd) {
+            Ok(file) => match file.poll() {
+                Ok(state) => {
+                    let mut revents = 0;
+                    if state.readable {
+                        revents |= POLLIN | POLLRDNORM;
+                    }
+                    if state.writable {
+                        revents |= POLLOUT | POLLWRNORM;
+                    }
+                    revents & pfd.events as u32
+                }
+                Err(_) => POLLERR,
+            },
+            Err(_) => POLLNVAL,
+        };
+        pfd.revents = revents as _;
+        if revents != 0 {
+            ready += 1;
+        }
+    }
+    ready
+}
+
+/// Call `poll` until it finds something ready and return how many, or until
+/// `timeout` passes and return zero.
+///
+/// Files cannot wake up a poller, so `poll` is retried every
+/// [`POLL_INTERVAL`]. With nothing `watched`, this is a plain sleep that only
+/// a signal cuts short.
+pub(crate) fn wait_ready(
+    timeout: Option<TimeValue>,
+    watched: bool,
+    mut poll: impl FnMut() -> usize,
+) -> LinuxResult<usize> {
+    let deadline = timeout.map(|it| monotonic_time() + it);
+    loop {
+        let ready = poll();
+        if ready > 0 {
+            return Ok(ready);
+        }
+        let left = match deadline {
+            Some(deadline) => match deadline.checked_sub(monotonic_time()) {
+                Some(left) if !left.is_zero() => left,
+                _ => return Ok(0),
+            },
+            // Long enough to be forever, short enough not to overflow.
+            None => Duration::from_secs(u32::MAX as u64),
+        };
+        let sleep = if watched {
+            left.min(POLL_INTERVAL)
+        } else {
+            left
+        };
+        if sleep_interruptible(sleep).is_some() {
+            return Err(LinuxError::EINTR);
+        }
+    }
+}
+
+/// Run `f` with the signal mask of the current thread replaced by `sigmask`
+/// if given, as `ppoll` and `epoll_pwait` do while waiting.
+///
+/// If `f` is interrupted, the signal is delivered with `sigmask` still in
+/// place, so that a signal only it leaves unblocked is handled, and the old
+/// mask comes back when the handler returns, like for `rt_sigsuspend`.
+pub(crate) fn with_sigmask(
+    tf: &mut TrapFrame,
+    sigmask: UserConstPtr<SignalSet>,
+    sigsetsize: usize,
+    f: impl FnOnce() -> LinuxResult<isize>,
+) -> LinuxResult<isize> {
+    let Some(set) = nullable!(sigmask.get_as_ref())? else {
+        return f();
+    };
+    if sigsetsize != size_of::<SignalSet>() {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut set = *set;
+    set.remove(Signo::SIGKILL);
+    set.remove(Signo::SIGSTOP);
+
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let old_blocked = signal.with_blocked_mut(|blocked| mem::replace(blocked, set));
+    let result = f();
+    if matches!(result, Err(LinuxError::EINTR)) {
+        tf.set_retval(-LinuxError::EINTR.code() as usize);
+        if check_signals(tf, Some(old_blocked)) {
+            return Ok(tf.retval() as isize);
+        }
+    }
+    signal.with_blocked_mut(|blocked| *blocked = old_blocked);
+    result
+}
+
+fn get_pollfds(fds: UserPtr<pollfd>, nfds: usize) -> LinuxResult<&'static mut [pollfd]> {
+    if nfds > AX_FILE_LIMIT {
+        return Err(LinuxError::EINVAL);
+    }
+    if nfds == 0 {
+        return Ok(&mut []);
+    }
+    fds.get_as_mut_slice(nfds)
+}
+
+/// Wait for one of `fds` to become ready, for at most `timeout` or forever if
+/// it is NULL, with the signal mask replaced by `sigmask` if given.
+///
+/// With no files, this sleeps for `timeout` unless interrupted by a signal.
+/// The time left is written back to `timeout`.
+pub fn sys_ppoll(
+    tf: &mut TrapFrame,
+    fds: UserPtr<pollfd>,
+    nfds: usize,
+    timeout: UserPtr<timespec>,
+    sigmask: UserConstPtr<SignalSet>,
+    sigsetsize: usize,
+) -> LinuxResult<isize> {
+    let fds = get_pollfds(fds, nfds)?;
+    let timeout = nullable!(timeout.get_as_mut())?;
+    let duration = match &timeout {
+        Some(ts) => {
+            if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(ts.to_time_value())
+        }
+        None => None,
+    };
+    let start = monotonic_time();
+    let result = with_sigmask(tf, sigmask, sigsetsize, || {
+        Ok(wait_ready(duration, !fds.is_empty(), || poll_fds(fds))? as _)
+    });
+
+    if let (Some(ts), Some(duration)) = (timeout, duration) {
+        let elapsed = monotonic_time().saturating_sub(start);
+        *ts = timespec::from_time_value(duration.saturating_sub(elapsed));
+    }
+    result
+}
+
+/// Wait for one of `fds` to become ready, for at most `timeout` milliseconds
+/// or forever if it is negative.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_poll(fds: UserPtr<pollfd>, nfds: usize, timeout: i32) -> LinuxResult<isize> {
+    let fds = get_pollfds(fds, nfds)?;
+    let timeout = (timeout >= 0).then(|| Duration::from_millis(timeout as u64));
+    Ok(wait_ready(timeout, !fds.is_empty(), || poll_fds(fds))? as _)
+}
@@ -2,15 +2,25 @@ use core::ffi::{c_char, c_int};
 
 use axerrno::{AxError, LinuxError, LinuxResult};
 use axfs::fops::OpenOptions;
-use linux_raw_sys::general::{AT_EMPTY_PATH, stat, statx};
+use linux_raw_sys::general::{
+    AT_EACCESS, AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_NOFOLLOW, S_IFDIR, S_IFMT, stat, statfs, statx,
+};
 
+use super::{check_writable, statfs_at};
 use crate::{
-    file::{Directory, File, FileLike, Kstat, get_file_like},
-    path::handle_file_path,
+    current_credentials,
+    file::{DevFile, Directory, File, FileLike, Kstat, ProcFile, device_at, get_file_like},
+    path::{handle_file_path, handle_mount_point},
     ptr::{UserConstPtr, UserPtr, nullable},
 };
 
 fn stat_at_path(path: &str) -> LinuxResult<Kstat> {
+    if let Some(dev) = device_at(path).and_then(DevFile::open) {
+        return dev.stat();
+    }
+    if let Some(file) = ProcFile::open(path) {
+        return file.stat();
+    }
     let opts = OpenOptions::new().set_read(true);
     match axfs::fops::File::open(path, &opts) {
         Ok(file) => File::new(file, path.into()).stat(),
@@ -26,10 +36,11 @@ fn stat_at_path(path: &str) -> LinuxResult<Kstat> {
 ///
 /// Return 0 if success.
 pub fn sys_stat(path: UserConstPtr<c_char>, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!("sys_stat <= path: {}", path);
 
-    *statbuf.get_as_mut()? = stat_at_path(path)?.into();
+    let path = handle_file_path(AT_FDCWD, path)?;
+    *statbuf.get_as_mut()? = stat_at_path(path.as_str())?.into();
 
     Ok(0)
 }
@@ -57,7 +68,7 @@ pub fn sys_fstatat(
     statbuf: UserPtr<stat>,
     flags: u32,
 ) -> LinuxResult<isize> {
-    let path = nullable!(path.get_as_str())?;
+    let path = nullable!(path.get_as_path())?;
     debug!(
         "sys_fstatat <= dirfd: {}, path: {:?}, flags: {}",
         dirfd, path, flags
@@ -111,7 +122,7 @@ pub fn sys_statx(
     //        below), then the target file is the one referred to by the
     //        file descriptor dirfd.
 
-    let path = nullable!(path.get_as_str())?;
+    let path = nullable!(path.get_as_path())?;
     debug!(
         "sys_statx <= dirfd: {}, path: {:?}, flags: {}",
         dirfd, path, flags
@@ -130,3 +141,133 @@ pub fn sys_statx(
 
     Ok(0)
 }
+
+/// Get the information about the file system `path` lives on.
+pub fn sys_statfs(path: UserConstPtr<c_char>, buf: UserPtr<statfs>) -> LinuxResult<isize> {
+    // A bind mount reports its own options, not those of what it binds.
+    let path = handle_mount_point(path.get_as_path()?)?;
+    debug!("sys_statfs <= path: {}", path);
+    if !path.exists() {
+        return Err(LinuxError::ENOENT);
+    }
+    *buf.get_as_mut()? = statfs_at(path.as_str());
+    Ok(0)
+}
+
+/// Get the information about the file system the file `fd` lives on.
+///
+/// Files not backed by a file system, such as pipes, report the root file
+/// system.
+pub fn sys_fstatfs(fd: c_int, buf: UserPtr<statfs>) -> LinuxResult<isize> {
+    debug!("sys_fstatfs <= fd: {}", fd);
+    let file = get_file_like(fd)?.into_any();
+    let path = if let Some(file) = file.downcast_ref::<File>() {
+        file.path()
+    } else if let Some(dir) = file.downcast_ref::<Directory>() {
+        dir.path()
+    } else {
+        "/".into()
+    };
+    *buf.get_as_mut()? = statfs_at(&path);
+    Ok(0)
+}
+
+// The modes checked by `access`, see `unistd.h`.
+const F_OK: u32 = 0;
+const X_OK: u32 = 1;
+const W_OK: u32 = 2;
+const R_OK: u32 = 4;
+
+/// Whether the user `uid` in the group `gid` may access a file with the
+/// status `st` for `mode`.
+///
+/// Root may read and write anything, and execute anything executable by
+/// somebody.
+fn may_access(st: &stat, uid: u32, gid: u32, mode: u32) -> bool {
+    let st_mode = st.st_mode as u32;
+    if uid == 0 {
+        return mode & X_OK == 0 || st_mode & 0o111 != 0 || st_mode & S_IFMT == S_IFDIR;
+    }
+    let perm = if st.st_uid as u32 == uid {
+        st_mode >> 6
+    } else if st.st_gid as u32 == gid {
+        st_mode >> 3
+    } else {
+        st_mode
+    };
+    perm & mode == mode
+}
+
+/// Fail with `ENOTDIR` unless `path` is a directory, or with `EACCES` if the
+/// calling process may not search it.
+pub(crate) fn check_searchable(path: &str) -> LinuxResult {
+    let st: stat = stat_at_path(path)?.into();
+    if st.st_mode as u32 & S_IFMT != S_IFDIR {
+        return Err(LinuxError::ENOTDIR);
+    }
+    let cred = current_credentials();
+    if !may_access(&st, cred.euid, cred.egid, X_OK) {
+        return Err(LinuxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Check whether the calling process may access the file `path` for `mode`.
+///
+/// The check uses the real user and group IDs, unless `AT_EACCESS` is set in
+/// `flags`, in which case the effective ones are used.
+pub fn sys_faccessat2(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    mode: u32,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let path = nullable!(path.get_as_path())?;
+    debug!(
+        "sys_faccessat2 <= dirfd: {}, path: {:?}, mode: {}, flags: {}",
+        dirfd, path, mode, flags
+    );
+    if mode & !(R_OK | W_OK | X_OK) != 0
+        || flags & !(AT_EACCESS | AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let (st, path): (stat, _) = if path.is_none_or(|s| s.is_empty()) {
+        if (flags & AT_EMPTY_PATH) == 0 {
+            return Err(LinuxError::ENOENT);
+        }
+        (get_file_like(dirfd)?.stat()?.into(), None)
+    } else {
+        let path = handle_file_path(dirfd, path.unwrap_or_default())?;
+        (stat_at_path(path.as_str())?.into(), Some(path))
+    };
+    if mode == F_OK {
+        return Ok(0);
+    }
+
+    let cred = current_credentials();
+    let (uid, gid) = if flags & AT_EACCESS != 0 {
+        (cred.euid, cred.egid)
+    } else {
+        (cred.uid, cred.gid)
+    };
+    if !may_access(&st, uid, gid, mode) {
+        return Err(LinuxError::EACCES);
+    }
+    if mode & W_OK != 0 {
+        if let Some(path) = path {
+            check_writable(path.as_str())?;
+        }
+    }
+    Ok(0)
+}
+
+pub fn sys_faccessat(dirfd: c_int, path: UserConstPtr<c_char>, mode: u32) -> LinuxResult<isize> {
+    sys_faccessat2(dirfd, path, mode, 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_access(path: UserConstPtr<c_char>, mode: u32) -> LinuxResult<isize> {
+    sys_faccessat2(AT_FDCWD, path, mode, 0)
+}
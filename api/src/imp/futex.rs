@@ -1,14 +1,188 @@
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
 use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axprocess::Pid;
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_REQUEUE, FUTEX_WAIT, FUTEX_WAKE, timespec,
+    FUTEX_BITSET_MATCH_ANY, FUTEX_CLOCK_REALTIME, FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_LOCK_PI,
+    FUTEX_OWNER_DIED, FUTEX_REQUEUE, FUTEX_TID_MASK, FUTEX_TRYLOCK_PI, FUTEX_UNLOCK_PI, FUTEX_WAIT,
+    FUTEX_WAIT_BITSET, FUTEX_WAITERS, FUTEX_WAKE, FUTEX_WAKE_BITSET, ROBUST_LIST_LIMIT,
+    robust_list, robust_list_head, timespec,
+};
+use starry_core::{
+    futex::FutexTable,
+    task::{ThreadData, get_thread},
 };
 
 use crate::{
     ptr::{UserConstPtr, UserPtr, nullable},
-    time::TimeValueLike,
+    signal::has_interrupting_signal,
+    time::{TimeValueLike, wall_time},
 };
 
+/// Converts the timeout of a futex wait into a relative duration.
+///
+/// `FUTEX_WAIT` takes a relative timeout, while `FUTEX_WAIT_BITSET` takes an
+/// absolute one measured against the clock selected by `FUTEX_CLOCK_REALTIME`.
+/// `FUTEX_LOCK_PI` always uses an absolute `CLOCK_REALTIME` timeout.
+fn futex_timeout(
+    timeout: UserConstPtr<timespec>,
+    command: u32,
+    futex_op: u32,
+) -> LinuxResult<Option<Duration>> {
+    let Some(ts) = nullable!(timeout.get_as_ref())? else {
+        return Ok(None);
+    };
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    let timeout = ts.to_time_value();
+    let now = match command {
+        FUTEX_WAIT => return Ok(Some(timeout)),
+        FUTEX_LOCK_PI => wall_time(),
+        _ if futex_op & FUTEX_CLOCK_REALTIME != 0 => wall_time(),
+        _ => monotonic_time(),
+    };
+    Ok(Some(timeout.saturating_sub(now)))
+}
+
+/// Returns the nice value the thread with the given TID runs with.
+fn thread_nice(tid: u32) -> Option<i32> {
+    let thr = get_thread(tid as Pid).ok()?;
+    thr.data::<ThreadData>().map(ThreadData::effective_nice)
+}
+
+/// Run `f` on the data of the thread with the given TID, which owns a PI
+/// futex.
+fn with_owner(tid: u32, f: impl FnOnce(&ThreadData)) {
+    if let Some(data) = get_thread(tid as Pid)
+        .ok()
+        .as_ref()
+        .and_then(|thr| thr.data::<ThreadData>())
+    {
+        f(data);
+    }
+}
+
+/// Returns the highest priority nice value among the threads waiting on the
+/// futex at `addr`, if any.
+fn waiters_nice(futex_table: &FutexTable, addr: usize) -> Option<i32> {
+    futex_table
+        .waiters(addr)
+        .into_iter()
+        .filter_map(thread_nice)
+        .min()
+}
+
+/// Acquire the PI futex at `uaddr` for the current thread.
+///
+/// The futex word holds the TID of the owner, with `FUTEX_WAITERS` set while
+/// other threads are blocked on it. While the current thread waits, the owner
+/// runs with its nice value if that is a higher priority.
+fn futex_lock_pi(
+    futex_table: &FutexTable,
+    uaddr: UserConstPtr<u32>,
+    timeout: Option<Duration>,
+    try_lock: bool,
+) -> LinuxResult<isize> {
+    let addr = uaddr.address().as_usize();
+    let curr = current();
+    let tid = curr.id().as_u64() as u32;
+    let nice = curr.task_ext().thread_data().effective_nice();
+    // SAFETY: the futex word is checked to be a valid, writable `u32`.
+    let word = unsafe { AtomicU32::from_ptr(UserPtr::<u32>::from(addr).get_as_mut()?) };
+
+    let mut woken = false;
+    loop {
+        let val = word.load(Ordering::Acquire);
+        let owner = val & FUTEX_TID_MASK;
+        if owner == tid {
+            // Once woken up, the futex was handed over by `futex_unlock_pi`.
+            return woken.then_some(0).ok_or(LinuxError::EDEADLK);
+        }
+        if owner == 0 {
+            // Keep `FUTEX_WAITERS` and `FUTEX_OWNER_DIED` as they are.
+            if word
+                .compare_exchange(val, val | tid, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Ok(0);
+            }
+            continue;
+        }
+        if try_lock {
+            return Err(LinuxError::EAGAIN);
+        }
+
+        let result = futex_table.wait(addr, FUTEX_BITSET_MATCH_ANY, timeout, || {
+            if has_interrupting_signal() {
+                return Err(LinuxError::EINTR);
+            }
+            let val = word.fetch_or(FUTEX_WAITERS, Ordering::AcqRel);
+            match val & FUTEX_TID_MASK {
+                // Released in the meantime, try again.
+                0 => Err(LinuxError::EAGAIN),
+                owner if owner == tid => Err(LinuxError::EDEADLK),
+                owner => {
+                    with_owner(owner, |data| data.boost_nice(addr, nice));
+                    Ok(())
+                }
+            }
+        });
+        match result {
+            Ok(true) => woken = true,
+            Err(LinuxError::EAGAIN) => {}
+            // Woken up for a signal that does not interrupt, try again.
+            Err(LinuxError::EINTR) if !has_interrupting_signal() => {}
+            Ok(false) | Err(LinuxError::EINTR) => {
+                // Take back what the current thread lent to the owner.
+                let owner = word.load(Ordering::Acquire) & FUTEX_TID_MASK;
+                let nice = waiters_nice(futex_table, addr);
+                with_owner(owner, |data| data.set_pi_nice(addr, nice));
+                return Err(result.err().unwrap_or(LinuxError::ETIMEDOUT));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Release the PI futex at `uaddr` owned by the current thread, and hand it
+/// over to the first waiter.
+///
+/// The current thread gives back the priority it was lent for this futex,
+/// keeping the one lent for the others it owns, and runs with the result
+/// right away. The new owner is lent that of the threads still waiting.
+fn futex_unlock_pi(futex_table: &FutexTable, uaddr: UserConstPtr<u32>) -> LinuxResult<isize> {
+    let addr = uaddr.address().as_usize();
+    let curr = current();
+    let tid = curr.id().as_u64() as u32;
+    // SAFETY: the futex word is checked to be a valid, writable `u32`.
+    let word = unsafe { AtomicU32::from_ptr(UserPtr::<u32>::from(addr).get_as_mut()?) };
+
+    futex_table.wake_pi(addr, |waiters| {
+        if word.load(Ordering::Acquire) & FUTEX_TID_MASK != tid {
+            return Err(LinuxError::EPERM);
+        }
+        let Some((&next, rest)) = waiters.split_first() else {
+            word.store(0, Ordering::Release);
+            return Ok(());
+        };
+        let val = if rest.is_empty() { 0 } else { FUTEX_WAITERS };
+        word.store(next | val, Ordering::Release);
+
+        let nice = rest.iter().filter_map(|&tid| thread_nice(tid)).min();
+        with_owner(next, |data| data.set_pi_nice(addr, nice));
+        Ok(())
+    })?;
+    curr.task_ext().thread_data().unboost_nice(addr);
+    crate::sync_sched_attrs();
+    Ok(0)
+}
+
 pub fn sys_futex(
     uaddr: UserConstPtr<u32>,
     futex_op: u32,
@@ -25,57 +199,157 @@ pub fn sys_futex(
     let addr = uaddr.address().as_usize();
     let command = futex_op & (FUTEX_CMD_MASK as u32);
     match command {
-        FUTEX_WAIT => {
-            if *uaddr.get_as_ref()? != value {
-                return Err(LinuxError::EAGAIN);
-            }
-            let wq = futex_table.get_or_insert(addr);
-
-            if let Some(timeout) = nullable!(timeout.get_as_ref())? {
-                wq.wait_timeout(timeout.to_time_value());
+        FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+            let bitset = if command == FUTEX_WAIT_BITSET {
+                value3
             } else {
-                wq.wait();
+                FUTEX_BITSET_MATCH_ANY
+            };
+            if bitset == 0 {
+                return Err(LinuxError::EINVAL);
             }
+            let timeout = futex_timeout(timeout, command, futex_op)?;
 
+            let woken = futex_table.wait(addr, bitset, timeout, || {
+                if *uaddr.get_as_ref()? != value {
+                    return Err(LinuxError::EAGAIN);
+                }
+                if has_interrupting_signal() {
+                    return Err(LinuxError::EINTR);
+                }
+                Ok(())
+            });
+            let woken = match woken {
+                // Woken up for a signal that does not interrupt, which the
+                // caller takes as a spurious wakeup.
+                Err(LinuxError::EINTR) if !has_interrupting_signal() => true,
+                woken => woken?,
+            };
+            if !woken {
+                return Err(LinuxError::ETIMEDOUT);
+            }
             Ok(0)
         }
-        FUTEX_WAKE => {
-            let wq = futex_table.get(addr);
-            let mut count = 0;
-            if let Some(wq) = wq {
-                for _ in 0..value {
-                    if !wq.notify_one(false) {
-                        break;
-                    }
-                    count += 1;
-                }
+        FUTEX_WAKE | FUTEX_WAKE_BITSET => {
+            let bitset = if command == FUTEX_WAKE_BITSET {
+                value3
+            } else {
+                FUTEX_BITSET_MATCH_ANY
+            };
+            if bitset == 0 {
+                return Err(LinuxError::EINVAL);
             }
+
+            let count = futex_table.wake(addr, value as usize, bitset);
             axtask::yield_now();
-            Ok(count)
+            Ok(count as _)
         }
         FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
-            if command == FUTEX_CMP_REQUEUE && *uaddr.get_as_ref()? != value3 {
-                return Err(LinuxError::EAGAIN);
-            }
+            // `val2` is passed in place of the timeout for requeue operations.
             let value2 = timeout.address().as_usize() as u32;
 
-            let wq = futex_table.get(addr);
-            let wq2 = futex_table.get_or_insert(uaddr2.address().as_usize());
-
-            let mut count = 0;
-            if let Some(wq) = wq {
-                for _ in 0..value {
-                    if !wq.notify_one(false) {
-                        break;
+            let (woken, requeued) = futex_table.requeue(
+                addr,
+                value as usize,
+                uaddr2.address().as_usize(),
+                value2 as usize,
+                || {
+                    if command == FUTEX_CMP_REQUEUE && *uaddr.get_as_ref()? != value3 {
+                        return Err(LinuxError::EAGAIN);
                     }
-                    count += 1;
-                }
-                if count == value as isize {
-                    count += wq.requeue(value2 as usize, &wq2) as isize;
+                    Ok(())
+                },
+            )?;
+            Ok((woken + requeued) as _)
+        }
+        FUTEX_LOCK_PI | FUTEX_TRYLOCK_PI => {
+            let timeout = futex_timeout(timeout, command, futex_op)?;
+            futex_lock_pi(futex_table, uaddr, timeout, command == FUTEX_TRYLOCK_PI)
+        }
+        FUTEX_UNLOCK_PI => futex_unlock_pi(futex_table, uaddr),
+        _ => Err(LinuxError::ENOSYS),
+    }
+}
+
+pub fn sys_set_robust_list(head: UserConstPtr<robust_list_head>, len: usize) -> LinuxResult<isize> {
+    if len != size_of::<robust_list_head>() {
+        return Err(LinuxError::EINVAL);
+    }
+    current()
+        .task_ext()
+        .thread_data()
+        .set_robust_list_head(head.address().as_usize());
+    Ok(0)
+}
+
+pub fn sys_get_robust_list(
+    tid: Pid,
+    head: UserPtr<usize>,
+    len: UserPtr<usize>,
+) -> LinuxResult<isize> {
+    let robust_list_head = if tid == 0 {
+        current().task_ext().thread_data().robust_list_head()
+    } else {
+        get_thread(tid)?
+            .data::<ThreadData>()
+            .ok_or(LinuxError::ESRCH)?
+            .robust_list_head()
+    };
+    *head.get_as_mut()? = robust_list_head;
+    *len.get_as_mut()? = size_of::<robust_list_head>();
+    Ok(0)
+}
+
+/// Release a futex held by the exiting thread `tid`: mark it with
+/// `FUTEX_OWNER_DIED` and wake up one waiter so it can recover the lock.
+fn handle_futex_death(futex_table: &FutexTable, addr: usize, tid: u32) -> LinuxResult {
+    // SAFETY: the futex word is checked to be a valid, writable `u32`.
+    let word = unsafe { AtomicU32::from_ptr(UserPtr::<u32>::from(addr).get_as_mut()?) };
+    let mut val = word.load(Ordering::Acquire);
+    while val & FUTEX_TID_MASK == tid {
+        let new = (val & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        match word.compare_exchange(val, new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => {
+                if val & FUTEX_WAITERS != 0 {
+                    futex_table.wake(addr, 1, FUTEX_BITSET_MATCH_ANY);
                 }
+                break;
             }
-            Ok(count)
+            Err(cur) => val = cur,
         }
-        _ => Err(LinuxError::ENOSYS),
     }
+    Ok(())
+}
+
+/// Walk the robust futex list of the current thread on exit, releasing every
+/// futex it still holds, including the one in `list_op_pending`.
+pub(crate) fn exit_robust_list(head_addr: usize) -> LinuxResult {
+    if head_addr == 0 {
+        return Ok(());
+    }
+    let curr = current();
+    let futex_table = &curr.task_ext().process_data().futex_table;
+    let tid = curr.id().as_u64() as u32;
+
+    let head = UserConstPtr::<robust_list_head>::from(head_addr).get_as_ref()?;
+    let offset = head.futex_offset;
+    // Bit 0 of an entry marks a PI futex, which is released the same way.
+    let pending = head.list_op_pending as usize & !1;
+
+    let mut entry = head.list.next as usize & !1;
+    let mut limit = ROBUST_LIST_LIMIT;
+    while entry != head_addr && entry != 0 && limit > 0 {
+        // Fetch the next entry first, since waking up a waiter might let it
+        // free the current one.
+        let next = UserConstPtr::<robust_list>::from(entry).get_as_ref()?.next as usize & !1;
+        if entry != pending {
+            handle_futex_death(futex_table, entry.wrapping_add_signed(offset as _), tid)?;
+        }
+        entry = next;
+        limit -= 1;
+    }
+    if pending != 0 {
+        handle_futex_death(futex_table, pending.wrapping_add_signed(offset as _), tid)?;
+    }
+    Ok(())
 }
@@ -0,0 +1,128 @@
+//! System V and POSIX inter-process communication.
+
+mod mqueue;
+mod msg;
+mod sem;
+mod shm;
+
+use alloc::collections::btree_map::BTreeMap;
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Pid;
+use axtask::{TaskExtRef, current};
+
+pub use self::{mqueue::*, msg::*, sem::*, shm::*};
+use crate::{current_credentials, time::wall_time};
+
+/// The key asking `*get` for a new object no other process can look up.
+const IPC_PRIVATE: i32 = 0;
+
+/// Create the object if the key does not exist.
+const IPC_CREAT: u32 = 0o1000;
+/// Fail if the key exists.
+const IPC_EXCL: u32 = 0o2000;
+
+/// Remove the object.
+const IPC_RMID: u32 = 0;
+/// Set the ownership and permissions of the object.
+const IPC_SET: u32 = 1;
+/// Get the status of the object.
+const IPC_STAT: u32 = 2;
+/// Set by some C libraries on the `*ctl` commands to ask for the 64-bit
+/// structures, which are the only ones there are.
+const IPC_64: u32 = 0x100;
+
+/// `struct ipc64_perm`, the ownership and permissions of an IPC object.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IpcPerm {
+    key: i32,
+    uid: u32,
+    gid: u32,
+    cuid: u32,
+    cgid: u32,
+    mode: u32,
+    seq: u16,
+    __pad2: u16,
+    __unused1: usize,
+    __unused2: usize,
+}
+
+impl IpcPerm {
+    /// Creates the permissions of a new object owned by the current process,
+    /// with the permission bits of `flags`.
+    fn new(key: i32, flags: u32) -> Self {
+        let cred = current_credentials();
+        Self {
+            key,
+            uid: cred.euid,
+            gid: cred.egid,
+            cuid: cred.euid,
+            cgid: cred.egid,
+            mode: flags & 0o777,
+            seq: 0,
+            __pad2: 0,
+            __unused1: 0,
+            __unused2: 0,
+        }
+    }
+
+    /// Fails with `EACCES` unless the current process may access the object
+    /// for `mode`, made of the `0o4` (read) and `0o2` (write) bits.
+    fn check_access(&self, mode: u32) -> LinuxResult {
+        let cred = current_credentials();
+        if cred.euid == 0 {
+            return Ok(());
+        }
+        let perm = if cred.euid == self.uid || cred.euid == self.cuid {
+            self.mode >> 6
+        } else if cred.egid == self.gid || cred.egid == self.cgid {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        if perm & mode != mode {
+            return Err(LinuxError::EACCES);
+        }
+        Ok(())
+    }
+
+    /// Fails with `EPERM` unless the current process owns or created the
+    /// object, as needed to change or remove it.
+    fn check_owner(&self) -> LinuxResult {
+        let euid = current_credentials().euid;
+        if euid != 0 && euid != self.uid && euid != self.cuid {
+            return Err(LinuxError::EPERM);
+        }
+        Ok(())
+    }
+
+    /// Applies the owner and permission bits of `new`, for `IPC_SET`.
+    fn set(&mut self, new: &IpcPerm) {
+        self.uid = new.uid;
+        self.gid = new.gid;
+        self.mode = (self.mode & !0o777) | (new.mode & 0o777);
+    }
+}
+
+/// The current time in seconds, for the timestamps of IPC objects.
+fn ipc_time() -> i64 {
+    wall_time().as_secs() as _
+}
+
+fn current_pid() -> Pid {
+    current().task_ext().thread.process().pid()
+}
+
+/// Picks the ID of a new object in `objects`, the first free one from
+/// `next_id` on, and moves `next_id` past it.
+///
+/// IDs are not reused right away, so that a stale ID does not refer to a
+/// new object.
+fn alloc_id<T>(objects: &BTreeMap<i32, T>, next_id: &mut i32) -> i32 {
+    let mut id = *next_id;
+    while objects.contains_key(&id) {
+        id = id.checked_add(1).unwrap_or(0);
+    }
+    *next_id = id.checked_add(1).unwrap_or(0);
+    id
+}
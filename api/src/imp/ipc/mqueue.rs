@@ -0,0 +1,244 @@
+use core::ffi::{c_char, c_int, c_long};
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::TimeValue;
+use axsignal::Signo;
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{
+    O_ACCMODE, O_CREAT, O_EXCL, O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY, SIGEV_NONE, SIGEV_SIGNAL,
+    sigevent, timespec,
+};
+
+use super::current_pid;
+use crate::{
+    current_credentials,
+    file::{FileLike, MessageQueue, MqAttr, MqNotification, MqQueue},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    time::TimeValueLike,
+};
+
+/// The default maximum number of messages of a queue.
+const DFLT_MSGMAX: usize = 10;
+/// The default maximum size of a message.
+const DFLT_MSGSIZEMAX: usize = 8192;
+/// The limit on the maximum number of messages of a queue.
+const HARD_MSGMAX: usize = 65536;
+/// The limit on the maximum size of a message.
+const HARD_MSGSIZEMAX: usize = 16 * 1024 * 1024;
+/// Priorities are below this.
+const MQ_PRIO_MAX: u32 = 32768;
+/// The maximum length of a queue name.
+const NAME_MAX: usize = 255;
+
+/// The named message queues.
+static MQUEUES: Mutex<BTreeMap<String, Arc<MqQueue>>> = Mutex::new(BTreeMap::new());
+
+/// Reads the name of a queue, without the leading slash the C library strips.
+fn queue_name(name: UserConstPtr<c_char>) -> LinuxResult<&'static str> {
+    let name = name.get_as_str()?;
+    if name.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    if name.contains('/') {
+        return Err(LinuxError::EACCES);
+    }
+    if name.len() > NAME_MAX {
+        return Err(LinuxError::ENAMETOOLONG);
+    }
+    Ok(name)
+}
+
+/// Fails with `EACCES` unless the current process may open `queue` for
+/// `mode`, made of the `0o4` (read) and `0o2` (write) bits.
+fn check_access(queue: &MqQueue, mode: u32) -> LinuxResult {
+    let cred = current_credentials();
+    if cred.euid == 0 {
+        return Ok(());
+    }
+    let perm = if cred.euid == queue.uid {
+        queue.mode >> 6
+    } else if cred.egid == queue.gid {
+        queue.mode >> 3
+    } else {
+        queue.mode
+    };
+    if perm & mode != mode {
+        return Err(LinuxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Reads the absolute `CLOCK_REALTIME` timeout of a send or receive.
+fn mq_deadline(abs_timeout: UserConstPtr<timespec>) -> LinuxResult<Option<TimeValue>> {
+    let Some(ts) = nullable!(abs_timeout.get_as_ref())? else {
+        return Ok(None);
+    };
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Some(ts.to_time_value()))
+}
+
+fn message_queue(mqdes: c_int) -> LinuxResult<Arc<MessageQueue>> {
+    MessageQueue::from_fd(mqdes).map_err(|_| LinuxError::EBADF)
+}
+
+/// Open the message queue `name`, creating it with `O_CREAT`.
+///
+/// A new queue gets the permission bits of `mode`, and the limits of `attr`
+/// or the default ones.
+pub fn sys_mq_open(
+    name: UserConstPtr<c_char>,
+    flags: u32,
+    mode: u32,
+    attr: UserConstPtr<MqAttr>,
+) -> LinuxResult<isize> {
+    let name = queue_name(name)?;
+    let (readable, writable) = match flags & O_ACCMODE {
+        O_RDONLY => (true, false),
+        O_WRONLY => (false, true),
+        O_RDWR => (true, true),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let access = if readable { 0o4 } else { 0 } | if writable { 0o2 } else { 0 };
+
+    let mut queues = MQUEUES.lock();
+    let queue = match queues.get(name) {
+        Some(queue) => {
+            if flags & O_CREAT != 0 && flags & O_EXCL != 0 {
+                return Err(LinuxError::EEXIST);
+            }
+            check_access(queue, access)?;
+            queue.clone()
+        }
+        None => {
+            if flags & O_CREAT == 0 {
+                return Err(LinuxError::ENOENT);
+            }
+            let (maxmsg, msgsize) = match nullable!(attr.get_as_ref())? {
+                Some(attr) => (attr.mq_maxmsg, attr.mq_msgsize),
+                None => (DFLT_MSGMAX as _, DFLT_MSGSIZEMAX as _),
+            };
+            if !(1..=HARD_MSGMAX as _).contains(&maxmsg)
+                || !(1..=HARD_MSGSIZEMAX as _).contains(&msgsize)
+            {
+                return Err(LinuxError::EINVAL);
+            }
+            let queue = Arc::new(MqQueue::new(maxmsg as _, msgsize as _, mode));
+            queues.insert(name.to_string(), queue.clone());
+            queue
+        }
+    };
+    drop(queues);
+
+    let nonblocking = flags & O_NONBLOCK != 0;
+    // Message queue descriptors are always close-on-exec.
+    let fd = MessageQueue::new(queue, readable, writable, nonblocking).add_to_fd_table(true)?;
+    Ok(fd as _)
+}
+
+/// Remove the message queue `name`, which lives on until the last
+/// descriptor opened on it is closed.
+pub fn sys_mq_unlink(name: UserConstPtr<c_char>) -> LinuxResult<isize> {
+    let name = queue_name(name)?;
+    MQUEUES.lock().remove(name).ok_or(LinuxError::ENOENT)?;
+    Ok(0)
+}
+
+/// Send the `msg_len` bytes at `msg_ptr` with priority `msg_prio`.
+///
+/// A full queue blocks until `abs_timeout` on `CLOCK_REALTIME`, then fails
+/// with `ETIMEDOUT`, or fails right away with `EAGAIN` under `O_NONBLOCK`.
+pub fn sys_mq_timedsend(
+    mqdes: c_int,
+    msg_ptr: UserConstPtr<u8>,
+    msg_len: usize,
+    msg_prio: u32,
+    abs_timeout: UserConstPtr<timespec>,
+) -> LinuxResult<isize> {
+    if msg_prio >= MQ_PRIO_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let mq = message_queue(mqdes)?;
+    let deadline = mq_deadline(abs_timeout)?;
+    mq.send(msg_ptr.get_as_slice(msg_len)?, msg_prio, deadline)?;
+    Ok(0)
+}
+
+/// Receive the oldest message of the highest priority into `msg_ptr`,
+/// storing its priority at `msg_prio` if not NULL.
+///
+/// An empty queue blocks like a full one does in `mq_timedsend`. Returns the
+/// size of the message.
+pub fn sys_mq_timedreceive(
+    mqdes: c_int,
+    msg_ptr: UserPtr<u8>,
+    msg_len: usize,
+    msg_prio: UserPtr<u32>,
+    abs_timeout: UserConstPtr<timespec>,
+) -> LinuxResult<isize> {
+    let mq = message_queue(mqdes)?;
+    let deadline = mq_deadline(abs_timeout)?;
+    let (len, prio) = mq.receive(msg_ptr.get_as_mut_slice(msg_len)?, deadline)?;
+    if let Some(msg_prio) = nullable!(msg_prio.get_as_mut())? {
+        *msg_prio = prio;
+    }
+    Ok(len as _)
+}
+
+/// Get the attributes of the queue into `oldattr`, and set the flags of the
+/// descriptor from `newattr`, where only `O_NONBLOCK` can be changed.
+pub fn sys_mq_getsetattr(
+    mqdes: c_int,
+    newattr: UserConstPtr<MqAttr>,
+    oldattr: UserPtr<MqAttr>,
+) -> LinuxResult<isize> {
+    let mq = message_queue(mqdes)?;
+    let old = mq.attr();
+    if let Some(new) = nullable!(newattr.get_as_ref())? {
+        if new.mq_flags & !(O_NONBLOCK as c_long) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        mq.set_nonblocking(new.mq_flags != 0)?;
+    }
+    if let Some(oldattr) = nullable!(oldattr.get_as_mut())? {
+        *oldattr = old;
+    }
+    Ok(0)
+}
+
+/// Ask for a notification when a message arrives on the empty queue, or
+/// cancel it if `sevp` is NULL.
+///
+/// Only one process can be registered at a time, and the registration is
+/// removed once the notification is sent. Only `SIGEV_NONE` and
+/// `SIGEV_SIGNAL` are supported.
+pub fn sys_mq_notify(mqdes: c_int, sevp: UserConstPtr<sigevent>) -> LinuxResult<isize> {
+    let mq = message_queue(mqdes)?;
+    let pid = current_pid();
+    let Some(sev) = nullable!(sevp.get_as_ref())? else {
+        mq.queue().remove_notification(pid);
+        return Ok(0);
+    };
+
+    let signo = match sev.sigev_notify as u32 {
+        SIGEV_NONE => None,
+        SIGEV_SIGNAL => Some(Signo::from_repr(sev.sigev_signo as u8).ok_or(LinuxError::EINVAL)?),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    // SAFETY: every member of `sigval` is plain data.
+    let value = unsafe { sev.sigev_value.sival_ptr } as usize;
+    mq.queue().set_notification(MqNotification {
+        pid,
+        process: Arc::downgrade(current().task_ext().thread.process()),
+        signo,
+        value,
+    })?;
+    Ok(0)
+}
@@ -0,0 +1,324 @@
+use core::ffi::c_long;
+
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Pid;
+use axsync::Mutex;
+
+use super::{
+    IPC_64, IPC_CREAT, IPC_EXCL, IPC_PRIVATE, IPC_RMID, IPC_SET, IPC_STAT, IpcPerm, alloc_id,
+    current_pid, ipc_time,
+};
+use crate::{
+    current_credentials,
+    file::WaitEvent,
+    ptr::{UserConstPtr, UserPtr},
+    signal::has_interrupting_signal,
+};
+
+/// Fail instead of blocking.
+const IPC_NOWAIT: u32 = 0o4000;
+/// Truncate messages longer than the buffer instead of failing.
+const MSG_NOERROR: u32 = 0o10000;
+/// Receive the first message whose type is not the one asked for.
+const MSG_EXCEPT: u32 = 0o20000;
+
+/// The maximum size of a message.
+const MSGMAX: usize = 8192;
+/// The default maximum number of bytes in a queue.
+const MSGMNB: usize = 16384;
+
+/// `struct msqid64_ds`, the status of a message queue.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MsqidDs {
+    msg_perm: IpcPerm,
+    msg_stime: i64,
+    msg_rtime: i64,
+    msg_ctime: i64,
+    msg_cbytes: usize,
+    msg_qnum: usize,
+    msg_qbytes: usize,
+    msg_lspid: i32,
+    msg_lrpid: i32,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+struct Message {
+    mtype: c_long,
+    text: Vec<u8>,
+}
+
+/// A message queue.
+struct MsgQueue {
+    perm: IpcPerm,
+    messages: VecDeque<Message>,
+    /// The total size of the messages.
+    cbytes: usize,
+    /// The limit on `cbytes`.
+    qbytes: usize,
+    stime: i64,
+    rtime: i64,
+    ctime: i64,
+    lspid: Pid,
+    lrpid: Pid,
+    /// Notified when a message is sent or received, the byte limit changes,
+    /// or the queue is removed, which is what blocked callers wait for.
+    changed: Arc<WaitEvent>,
+}
+
+impl MsgQueue {
+    fn status(&self) -> MsqidDs {
+        MsqidDs {
+            msg_perm: self.perm,
+            msg_stime: self.stime,
+            msg_rtime: self.rtime,
+            msg_ctime: self.ctime,
+            msg_cbytes: self.cbytes,
+            msg_qnum: self.messages.len(),
+            msg_qbytes: self.qbytes,
+            msg_lspid: self.lspid as _,
+            msg_lrpid: self.lrpid as _,
+            __unused4: 0,
+            __unused5: 0,
+        }
+    }
+
+    /// Finds the message `msgrcv` takes for `msgtyp`: the first one if it is
+    /// 0, the first one of that type if it is positive, or the first one of
+    /// the lowest type up to `-msgtyp` if it is negative.
+    fn find(&self, msgtyp: c_long, flags: u32) -> Option<usize> {
+        let messages = self.messages.iter().enumerate();
+        if msgtyp < 0 {
+            let limit = msgtyp.unsigned_abs();
+            messages
+                .filter(|(_, msg)| msg.mtype.unsigned_abs() <= limit)
+                .min_by_key(|(_, msg)| msg.mtype)
+                .map(|(i, _)| i)
+        } else if msgtyp == 0 {
+            (!self.messages.is_empty()).then_some(0)
+        } else {
+            let except = flags & MSG_EXCEPT != 0;
+            messages
+                .filter(|(_, msg)| (msg.mtype == msgtyp) != except)
+                .map(|(i, _)| i)
+                .next()
+        }
+    }
+}
+
+struct MsgTable {
+    queues: BTreeMap<i32, MsgQueue>,
+    next_id: i32,
+}
+
+static MSG_TABLE: Mutex<MsgTable> = Mutex::new(MsgTable {
+    queues: BTreeMap::new(),
+    next_id: 0,
+});
+
+/// Get the ID of the message queue of `key`, creating it if needed.
+///
+/// The key is looked up like with `shmget`.
+pub fn sys_msgget(key: i32, flags: u32) -> LinuxResult<isize> {
+    let mut table = MSG_TABLE.lock();
+    if key != IPC_PRIVATE {
+        let existing = table.queues.iter().find(|(_, queue)| queue.perm.key == key);
+        if let Some((&id, queue)) = existing {
+            if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                return Err(LinuxError::EEXIST);
+            }
+            queue
+                .perm
+                .check_access((flags >> 6 | flags >> 3 | flags) & 0o6)?;
+            return Ok(id as _);
+        }
+        if flags & IPC_CREAT == 0 {
+            return Err(LinuxError::ENOENT);
+        }
+    }
+
+    let queue = MsgQueue {
+        perm: IpcPerm::new(key, flags),
+        messages: VecDeque::new(),
+        cbytes: 0,
+        qbytes: MSGMNB,
+        stime: 0,
+        rtime: 0,
+        ctime: ipc_time(),
+        lspid: 0,
+        lrpid: 0,
+        changed: Arc::new(WaitEvent::new()),
+    };
+    let table = &mut *table;
+    let id = alloc_id(&table.queues, &mut table.next_id);
+    table.queues.insert(id, queue);
+    Ok(id as _)
+}
+
+/// Send the message at `msgp`, a `long` type followed by `msgsz` bytes of
+/// text, to the queue `msqid`.
+///
+/// Blocks while the message does not fit within the byte limit of the
+/// queue. Waiting fails with `EAGAIN` under `IPC_NOWAIT`, with `EINTR` on a
+/// signal, and with `EIDRM` if the queue is removed.
+pub fn sys_msgsnd(msqid: i32, msgp: usize, msgsz: usize, flags: u32) -> LinuxResult<isize> {
+    if msgsz > MSGMAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let mtype = *UserConstPtr::<c_long>::from(msgp).get_as_ref()?;
+    if mtype < 1 {
+        return Err(LinuxError::EINVAL);
+    }
+    let text = UserConstPtr::<u8>::from(msgp + size_of::<c_long>())
+        .get_as_slice(msgsz)?
+        .to_vec();
+    let pid = current_pid();
+
+    let mut waited = false;
+    loop {
+        let mut table = MSG_TABLE.lock();
+        let Some(queue) = table.queues.get_mut(&msqid) else {
+            return Err(if waited {
+                LinuxError::EIDRM
+            } else {
+                LinuxError::EINVAL
+            });
+        };
+        if !waited {
+            queue.perm.check_access(0o2)?;
+        }
+
+        let changed = queue.changed.clone();
+        let seq = changed.seq();
+        if queue.cbytes + msgsz <= queue.qbytes && queue.messages.len() < queue.qbytes {
+            queue.cbytes += msgsz;
+            queue.messages.push_back(Message { mtype, text });
+            queue.stime = ipc_time();
+            queue.lspid = pid;
+            queue.changed.notify();
+            return Ok(0);
+        }
+        drop(table);
+
+        if flags & IPC_NOWAIT != 0 {
+            return Err(LinuxError::EAGAIN);
+        }
+        if has_interrupting_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        waited = true;
+        changed.wait(seq, None);
+    }
+}
+
+/// Receive a message of the queue `msqid` selected by `msgtyp` into `msgp`,
+/// with room for `msgsz` bytes of text.
+///
+/// A longer message fails with `E2BIG` and stays on the queue, unless
+/// `MSG_NOERROR` truncates it. Blocks while there is no such message, until
+/// a signal (`EINTR`) or the removal of the queue (`EIDRM`). Fails with
+/// `ENOMSG` instead under `IPC_NOWAIT`. Returns the size of the text.
+pub fn sys_msgrcv(
+    msqid: i32,
+    msgp: usize,
+    msgsz: isize,
+    msgtyp: c_long,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let msgsz = usize::try_from(msgsz).map_err(|_| LinuxError::EINVAL)?;
+    let pid = current_pid();
+
+    let mut waited = false;
+    let message = loop {
+        let mut table = MSG_TABLE.lock();
+        let Some(queue) = table.queues.get_mut(&msqid) else {
+            return Err(if waited {
+                LinuxError::EIDRM
+            } else {
+                LinuxError::EINVAL
+            });
+        };
+        if !waited {
+            queue.perm.check_access(0o4)?;
+        }
+
+        let changed = queue.changed.clone();
+        let seq = changed.seq();
+        if let Some(index) = queue.find(msgtyp, flags) {
+            if queue.messages[index].text.len() > msgsz && flags & MSG_NOERROR == 0 {
+                return Err(LinuxError::E2BIG);
+            }
+            let message = queue.messages.remove(index).unwrap();
+            queue.cbytes -= message.text.len();
+            queue.rtime = ipc_time();
+            queue.lrpid = pid;
+            queue.changed.notify();
+            break message;
+        }
+        drop(table);
+
+        if flags & IPC_NOWAIT != 0 {
+            return Err(LinuxError::ENOMSG);
+        }
+        if has_interrupting_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        waited = true;
+        changed.wait(seq, None);
+    };
+
+    let len = message.text.len().min(msgsz);
+    *UserPtr::<c_long>::from(msgp).get_as_mut()? = message.mtype;
+    UserPtr::<u8>::from(msgp + size_of::<c_long>())
+        .get_as_mut_slice(len)?
+        .copy_from_slice(&message.text[..len]);
+    Ok(len as _)
+}
+
+/// Get the status of the message queue `msqid`, change its ownership,
+/// permissions and byte limit, or remove it.
+///
+/// Raising the byte limit above the default needs root. Removing the queue
+/// discards its messages and wakes up the blocked callers with `EIDRM`.
+pub fn sys_msgctl(msqid: i32, cmd: u32, buf: UserPtr<MsqidDs>) -> LinuxResult<isize> {
+    let cmd = cmd & !IPC_64;
+    let new = match cmd {
+        IPC_SET => Some(*UserConstPtr::<MsqidDs>::from(buf.address().as_usize()).get_as_ref()?),
+        IPC_STAT | IPC_RMID => None,
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let mut table = MSG_TABLE.lock();
+    let queue = table.queues.get_mut(&msqid).ok_or(LinuxError::EINVAL)?;
+    match cmd {
+        IPC_STAT => {
+            queue.perm.check_access(0o4)?;
+            let status = queue.status();
+            drop(table);
+            *buf.get_as_mut()? = status;
+        }
+        IPC_SET => {
+            let new = new.unwrap();
+            queue.perm.check_owner()?;
+            if new.msg_qbytes > MSGMNB && current_credentials().euid != 0 {
+                return Err(LinuxError::EPERM);
+            }
+            queue.perm.set(&new.msg_perm);
+            queue.qbytes = new.msg_qbytes;
+            queue.ctime = ipc_time();
+            queue.changed.notify();
+        }
+        _ => {
+            queue.perm.check_owner()?;
+            queue.changed.notify();
+            table.queues.remove(&msqid);
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,374 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Pid;
+use axsync::Mutex;
+
+use super::{
+    IPC_64, IPC_CREAT, IPC_EXCL, IPC_PRIVATE, IPC_RMID, IPC_SET, IPC_STAT, IpcPerm, alloc_id,
+    current_pid, ipc_time,
+};
+use crate::{
+    file::WaitEvent,
+    ptr::{UserConstPtr, UserPtr},
+    signal::has_interrupting_signal,
+};
+
+/// Fail with `EAGAIN` instead of blocking.
+const IPC_NOWAIT: i16 = 0o4000;
+/// Undo the operation when the process exits.
+const SEM_UNDO: i16 = 0x1000;
+
+const GETPID: u32 = 11;
+const GETVAL: u32 = 12;
+const GETALL: u32 = 13;
+const SETVAL: u32 = 16;
+const SETALL: u32 = 17;
+
+/// The maximum number of semaphores in a set.
+const SEMMSL: usize = 32000;
+/// The maximum number of operations in a `semop` call.
+const SEMOPM: usize = 500;
+/// The maximum value of a semaphore.
+const SEMVMX: i32 = 32767;
+
+/// `struct sembuf`, an operation of `semop`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Sembuf {
+    sem_num: u16,
+    sem_op: i16,
+    sem_flg: i16,
+}
+
+/// `struct semid64_ds`, the status of a semaphore set.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SemidDs {
+    sem_perm: IpcPerm,
+    sem_otime: i64,
+    #[cfg(target_arch = "x86_64")]
+    __unused1: usize,
+    sem_ctime: i64,
+    #[cfg(target_arch = "x86_64")]
+    __unused2: usize,
+    sem_nsems: usize,
+    __unused3: usize,
+    __unused4: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Semaphore {
+    val: i32,
+    /// The process of the last operation.
+    pid: Pid,
+}
+
+/// A semaphore set.
+struct SemSet {
+    perm: IpcPerm,
+    sems: Vec<Semaphore>,
+    otime: i64,
+    ctime: i64,
+    /// Notified when the semaphores change or the set is removed, which is
+    /// what blocked `semop` calls wait for.
+    changed: Arc<WaitEvent>,
+}
+
+impl SemSet {
+    fn status(&self) -> SemidDs {
+        SemidDs {
+            sem_perm: self.perm,
+            sem_otime: self.otime,
+            #[cfg(target_arch = "x86_64")]
+            __unused1: 0,
+            sem_ctime: self.ctime,
+            #[cfg(target_arch = "x86_64")]
+            __unused2: 0,
+            sem_nsems: self.sems.len(),
+            __unused3: 0,
+            __unused4: 0,
+        }
+    }
+
+    /// Applies all of `sops` or none of them.
+    ///
+    /// Returns `false` if an operation has to wait, or fails with `EAGAIN`
+    /// if that operation has `IPC_NOWAIT`.
+    fn apply(&mut self, sops: &[Sembuf], pid: Pid) -> LinuxResult<bool> {
+        let mut vals: Vec<i32> = self.sems.iter().map(|sem| sem.val).collect();
+        for sop in sops {
+            let val = &mut vals[sop.sem_num as usize];
+            let new = *val + sop.sem_op as i32;
+            let blocks = if sop.sem_op == 0 { *val != 0 } else { new < 0 };
+            if blocks {
+                if sop.sem_flg & IPC_NOWAIT != 0 {
+                    return Err(LinuxError::EAGAIN);
+                }
+                return Ok(false);
+            }
+            if new > SEMVMX {
+                return Err(LinuxError::ERANGE);
+            }
+            *val = new;
+        }
+        for (sem, val) in self.sems.iter_mut().zip(vals) {
+            sem.val = val;
+        }
+        for sop in sops {
+            self.sems[sop.sem_num as usize].pid = pid;
+        }
+        self.otime = ipc_time();
+        Ok(true)
+    }
+}
+
+struct SemTable {
+    sets: BTreeMap<i32, SemSet>,
+    next_id: i32,
+    /// The `SEM_UNDO` adjustments of each process to each set, applied when
+    /// the process exits.
+    undos: BTreeMap<(Pid, i32), Vec<i32>>,
+}
+
+impl SemTable {
+    /// Drops the adjustments to the semaphore `semnum` of the set `semid`,
+    /// or to all of them if `semnum` is `None`, after they are set directly.
+    fn clear_undos(&mut self, semid: i32, semnum: Option<usize>) {
+        for ((_, id), adjs) in self.undos.iter_mut() {
+            if *id != semid {
+                continue;
+            }
+            match semnum {
+                Some(num) => adjs[num] = 0,
+                None => adjs.fill(0),
+            }
+        }
+    }
+}
+
+static SEM_TABLE: Mutex<SemTable> = Mutex::new(SemTable {
+    sets: BTreeMap::new(),
+    next_id: 0,
+    undos: BTreeMap::new(),
+});
+
+/// Get the ID of the semaphore set of `key`, creating it with `nsems`
+/// semaphores if needed.
+///
+/// The key is looked up like with `shmget`. The semaphores of a new set are
+/// all zero.
+pub fn sys_semget(key: i32, nsems: i32, flags: u32) -> LinuxResult<isize> {
+    let nsems = usize::try_from(nsems).map_err(|_| LinuxError::EINVAL)?;
+    let mut table = SEM_TABLE.lock();
+    if key != IPC_PRIVATE {
+        let existing = table.sets.iter().find(|(_, set)| set.perm.key == key);
+        if let Some((&id, set)) = existing {
+            if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                return Err(LinuxError::EEXIST);
+            }
+            if nsems > set.sems.len() {
+                return Err(LinuxError::EINVAL);
+            }
+            set.perm
+                .check_access((flags >> 6 | flags >> 3 | flags) & 0o6)?;
+            return Ok(id as _);
+        }
+        if flags & IPC_CREAT == 0 {
+            return Err(LinuxError::ENOENT);
+        }
+    }
+    if nsems == 0 || nsems > SEMMSL {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let set = SemSet {
+        perm: IpcPerm::new(key, flags),
+        sems: vec![Semaphore { val: 0, pid: 0 }; nsems],
+        otime: 0,
+        ctime: ipc_time(),
+        changed: Arc::new(WaitEvent::new()),
+    };
+    let table = &mut *table;
+    let id = alloc_id(&table.sets, &mut table.next_id);
+    table.sets.insert(id, set);
+    Ok(id as _)
+}
+
+/// Apply the `nsops` operations at `sops` to the semaphore set `semid`.
+///
+/// The operations are applied together once none of them has to wait: a
+/// decrement waits until the semaphore is large enough, and a zero operation
+/// waits until it is zero. Waiting fails with `EAGAIN` under `IPC_NOWAIT`,
+/// with `EINTR` on a signal, and with `EIDRM` if the set is removed.
+pub fn sys_semop(semid: i32, sops: UserConstPtr<Sembuf>, nsops: usize) -> LinuxResult<isize> {
+    if nsops == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if nsops > SEMOPM {
+        return Err(LinuxError::E2BIG);
+    }
+    let sops = sops.get_as_slice(nsops)?.to_vec();
+    let pid = current_pid();
+
+    let mut waited = false;
+    loop {
+        let mut table = SEM_TABLE.lock();
+        let Some(set) = table.sets.get_mut(&semid) else {
+            return Err(if waited {
+                LinuxError::EIDRM
+            } else {
+                LinuxError::EINVAL
+            });
+        };
+        if !waited {
+            if sops
+                .iter()
+                .any(|sop| sop.sem_num as usize >= set.sems.len())
+            {
+                return Err(LinuxError::EFBIG);
+            }
+            let alters = sops.iter().any(|sop| sop.sem_op != 0);
+            set.perm.check_access(if alters { 0o2 } else { 0o4 })?;
+        }
+
+        let changed = set.changed.clone();
+        let seq = changed.seq();
+        if set.apply(&sops, pid)? {
+            if sops.iter().any(|sop| sop.sem_op != 0) {
+                set.changed.notify();
+            }
+            let nsems = set.sems.len();
+            for sop in sops.iter().filter(|sop| sop.sem_flg & SEM_UNDO != 0) {
+                let adjs = table
+                    .undos
+                    .entry((pid, semid))
+                    .or_insert_with(|| vec![0; nsems]);
+                adjs[sop.sem_num as usize] -= sop.sem_op as i32;
+            }
+            return Ok(0);
+        }
+        drop(table);
+
+        if has_interrupting_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        waited = true;
+        changed.wait(seq, None);
+    }
+}
+
+/// Control the semaphore set `semid`.
+///
+/// `arg` is the `union semun` argument: a value for `SETVAL`, or a pointer
+/// for `IPC_STAT`, `IPC_SET`, `GETALL` and `SETALL`.
+pub fn sys_semctl(semid: i32, semnum: i32, cmd: u32, arg: usize) -> LinuxResult<isize> {
+    let cmd = cmd & !IPC_64;
+    let mut table = SEM_TABLE.lock();
+    let set = table.sets.get_mut(&semid).ok_or(LinuxError::EINVAL)?;
+    let nsems = set.sems.len();
+    let sem_index = || {
+        usize::try_from(semnum)
+            .ok()
+            .filter(|&num| num < nsems)
+            .ok_or(LinuxError::EINVAL)
+    };
+
+    match cmd {
+        IPC_STAT => {
+            set.perm.check_access(0o4)?;
+            let status = set.status();
+            drop(table);
+            *UserPtr::<SemidDs>::from(arg).get_as_mut()? = status;
+            Ok(0)
+        }
+        IPC_SET => {
+            set.perm.check_owner()?;
+            let new = UserConstPtr::<SemidDs>::from(arg).get_as_ref()?;
+            set.perm.set(&new.sem_perm);
+            set.ctime = ipc_time();
+            Ok(0)
+        }
+        IPC_RMID => {
+            set.perm.check_owner()?;
+            set.changed.notify();
+            table.sets.remove(&semid);
+            table.undos.retain(|&(_, id), _| id != semid);
+            Ok(0)
+        }
+        GETVAL => {
+            set.perm.check_access(0o4)?;
+            Ok(set.sems[sem_index()?].val as _)
+        }
+        GETPID => {
+            set.perm.check_access(0o4)?;
+            Ok(set.sems[sem_index()?].pid as _)
+        }
+        GETALL => {
+            set.perm.check_access(0o4)?;
+            let vals: Vec<u16> = set.sems.iter().map(|sem| sem.val as u16).collect();
+            drop(table);
+            UserPtr::<u16>::from(arg)
+                .get_as_mut_slice(nsems)?
+                .copy_from_slice(&vals);
+            Ok(0)
+        }
+        SETVAL => {
+            set.perm.check_access(0o2)?;
+            let num = sem_index()?;
+            let val = arg as i32;
+            if !(0..=SEMVMX).contains(&val) {
+                return Err(LinuxError::ERANGE);
+            }
+            set.sems[num] = Semaphore {
+                val,
+                pid: current_pid(),
+            };
+            set.ctime = ipc_time();
+            set.changed.notify();
+            table.clear_undos(semid, Some(num));
+            Ok(0)
+        }
+        SETALL => {
+            set.perm.check_access(0o2)?;
+            let vals = UserConstPtr::<u16>::from(arg).get_as_slice(nsems)?;
+            if vals.iter().any(|&val| val as i32 > SEMVMX) {
+                return Err(LinuxError::ERANGE);
+            }
+            let pid = current_pid();
+            for (sem, &val) in set.sems.iter_mut().zip(vals) {
+                *sem = Semaphore { val: val as _, pid };
+            }
+            set.ctime = ipc_time();
+            set.changed.notify();
+            table.clear_undos(semid, None);
+            Ok(0)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Apply the `SEM_UNDO` adjustments of the exiting process `pid`.
+///
+/// Semaphores are kept within their range rather than going negative.
+pub fn sem_exit(pid: Pid) {
+    let mut table = SEM_TABLE.lock();
+    let undos: Vec<_> = table
+        .undos
+        .range((pid, i32::MIN)..=(pid, i32::MAX))
+        .map(|(&key, _)| key)
+        .collect();
+    for key in undos {
+        let adjs = table.undos.remove(&key).unwrap();
+        let Some(set) = table.sets.get_mut(&key.1) else {
+            continue;
+        };
+        for (sem, adj) in set.sems.iter_mut().zip(adjs) {
+            if adj != 0 {
+                sem.val = (sem.val + adj).clamp(0, SEMVMX);
+                sem.pid = pid;
+            }
+        }
+        set.otime = ipc_time();
+        set.changed.notify();
+    }
+}
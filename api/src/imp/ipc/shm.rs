@@ -0,0 +1,358 @@
+use core::{alloc::Layout, ptr::NonNull};
+
+use alloc::{
+    alloc::{alloc_zeroed, dealloc},
+    collections::btree_map::BTreeMap,
+    format,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::{mem::virt_to_phys, paging::MappingFlags};
+use axprocess::Pid;
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use memory_addr::{PAGE_SIZE_4K, PhysAddr, VirtAddr, VirtAddrRange, align_down_4k, align_up_4k};
+use starry_core::mm::MemoryMap;
+
+use super::{
+    IPC_64, IPC_CREAT, IPC_EXCL, IPC_PRIVATE, IPC_RMID, IPC_SET, IPC_STAT, IpcPerm, alloc_id,
+    current_pid, ipc_time,
+};
+use crate::ptr::{UserConstPtr, UserPtr};
+
+/// Attach the segment read-only.
+const SHM_RDONLY: u32 = 0o10000;
+/// Round the attach address down to a multiple of `SHMLBA`.
+const SHM_RND: u32 = 0o20000;
+/// Allow executing the contents of the segment.
+const SHM_EXEC: u32 = 0o100000;
+/// Set in the mode of a segment marked for removal.
+const SHM_DEST: u32 = 0o1000;
+
+/// The alignment of attach addresses.
+const SHMLBA: usize = PAGE_SIZE_4K;
+
+/// `struct shmid64_ds`, the status of a segment.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ShmidDs {
+    shm_perm: IpcPerm,
+    shm_segsz: usize,
+    shm_atime: i64,
+    shm_dtime: i64,
+    shm_ctime: i64,
+    shm_cpid: i32,
+    shm_lpid: i32,
+    shm_nattch: usize,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+/// Zeroed, page-aligned kernel memory backing a segment.
+///
+/// Kernel memory is linearly mapped, so the pages are physically contiguous
+/// and can be mapped into any address space with `map_linear`.
+struct ShmMemory {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// SAFETY: the memory is only accessed through user mappings.
+unsafe impl Send for ShmMemory {}
+unsafe impl Sync for ShmMemory {}
+
+impl ShmMemory {
+    fn new(size: usize) -> LinuxResult<Self> {
+        let layout = Layout::from_size_align(align_up_4k(size), PAGE_SIZE_4K)
+            .map_err(|_| LinuxError::EINVAL)?;
+        // SAFETY: the layout is not zero-sized.
+        let ptr = NonNull::new(unsafe { alloc_zeroed(layout) }).ok_or(LinuxError::ENOMEM)?;
+        Ok(Self { ptr, layout })
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        virt_to_phys(VirtAddr::from_mut_ptr_of(self.ptr.as_ptr()))
+    }
+
+    fn mapped_size(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+impl Drop for ShmMemory {
+    fn drop(&mut self) {
+        // SAFETY: allocated with the same layout in `ShmMemory::new`.
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// A shared memory segment.
+struct ShmSegment {
+    perm: IpcPerm,
+    size: usize,
+    memory: ShmMemory,
+    atime: i64,
+    dtime: i64,
+    ctime: i64,
+    cpid: Pid,
+    lpid: Pid,
+    nattch: usize,
+}
+
+impl ShmSegment {
+    fn removed(&self) -> bool {
+        self.perm.mode & SHM_DEST != 0
+    }
+
+    fn status(&self) -> ShmidDs {
+        ShmidDs {
+            shm_perm: self.perm,
+            shm_segsz: self.size,
+            shm_atime: self.atime,
+            shm_dtime: self.dtime,
+            shm_ctime: self.ctime,
+            shm_cpid: self.cpid as _,
+            shm_lpid: self.lpid as _,
+            shm_nattch: self.nattch,
+            __unused4: 0,
+            __unused5: 0,
+        }
+    }
+}
+
+struct ShmTable {
+    segments: BTreeMap<i32, ShmSegment>,
+    next_id: i32,
+    /// The segment attached at each address of each process.
+    attachments: BTreeMap<(Pid, usize), i32>,
+}
+
+impl ShmTable {
+    /// Counts one attachment less for the segment `shmid`, removing it if it
+    /// was marked for removal and this was the last one.
+    fn detach(&mut self, shmid: i32, pid: Pid) {
+        let Some(seg) = self.segments.get_mut(&shmid) else {
+            return;
+        };
+        seg.nattch -= 1;
+        seg.dtime = ipc_time();
+        seg.lpid = pid;
+        if seg.removed() && seg.nattch == 0 {
+            self.segments.remove(&shmid);
+        }
+    }
+}
+
+static SHM_TABLE: Mutex<ShmTable> = Mutex::new(ShmTable {
+    segments: BTreeMap::new(),
+    next_id: 0,
+    attachments: BTreeMap::new(),
+});
+
+/// Get the ID of the shared memory segment of `key`, creating it if needed.
+///
+/// `IPC_PRIVATE` always creates a new segment. Otherwise a segment is only
+/// created with `IPC_CREAT`, and `IPC_EXCL` makes an existing key fail with
+/// `EEXIST`.
+pub fn sys_shmget(key: i32, size: usize, flags: u32) -> LinuxResult<isize> {
+    let mut table = SHM_TABLE.lock();
+    if key != IPC_PRIVATE {
+        let existing = table
+            .segments
+            .iter()
+            .find(|(_, seg)| !seg.removed() && seg.perm.key == key);
+        if let Some((&id, seg)) = existing {
+            if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                return Err(LinuxError::EEXIST);
+            }
+            if size > seg.size {
+                return Err(LinuxError::EINVAL);
+            }
+            seg.perm
+                .check_access((flags >> 6 | flags >> 3 | flags) & 0o6)?;
+            return Ok(id as _);
+        }
+        if flags & IPC_CREAT == 0 {
+            return Err(LinuxError::ENOENT);
+        }
+    }
+    if size == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let now = ipc_time();
+    let segment = ShmSegment {
+        perm: IpcPerm::new(key, flags),
+        size,
+        memory: ShmMemory::new(size)?,
+        atime: 0,
+        dtime: 0,
+        ctime: now,
+        cpid: current_pid(),
+        lpid: 0,
+        nattch: 0,
+    };
+    let table = &mut *table;
+    let id = alloc_id(&table.segments, &mut table.next_id);
+    table.segments.insert(id, segment);
+    Ok(id as _)
+}
+
+/// Attach the segment `shmid` to the address space of the current process.
+///
+/// The segment is placed at `shmaddr`, rounded down with `SHM_RND`, or
+/// anywhere if it is NULL. All attachments map the same pages, so writes are
+/// visible to every process that attached the segment.
+pub fn sys_shmat(shmid: i32, shmaddr: usize, flags: u32) -> LinuxResult<isize> {
+    let pid = current_pid();
+    let mut table = SHM_TABLE.lock();
+    let seg = table.segments.get_mut(&shmid).ok_or(LinuxError::EINVAL)?;
+
+    let mut access = 0o4;
+    let mut mapping_flags = MappingFlags::USER | MappingFlags::READ;
+    if flags & SHM_RDONLY == 0 {
+        access |= 0o2;
+        mapping_flags |= MappingFlags::WRITE;
+    }
+    if flags & SHM_EXEC != 0 {
+        mapping_flags |= MappingFlags::EXECUTE;
+    }
+    seg.perm.check_access(access)?;
+
+    let size = seg.memory.mapped_size();
+    let curr = current();
+    let mut aspace = curr.task_ext().process_data().aspace.lock();
+    let addr = if shmaddr == 0 {
+        aspace
+            .find_free_area(
+                aspace.base(),
+                size,
+                VirtAddrRange::new(aspace.base(), aspace.end()),
+            )
+            .ok_or(LinuxError::ENOMEM)?
+    } else {
+        let addr = if flags & SHM_RND != 0 {
+            shmaddr - shmaddr % SHMLBA
+        } else {
+            shmaddr
+        };
+        if addr == 0 || addr != align_down_4k(addr) {
+            return Err(LinuxError::EINVAL);
+        }
+        let addr = VirtAddr::from(addr);
+        let range = VirtAddrRange::from_start_size(addr, size);
+        if aspace.find_free_area(addr, size, range) != Some(addr) {
+            return Err(LinuxError::EINVAL);
+        }
+        addr
+    };
+    aspace.map_linear(addr, seg.memory.paddr(), size, mapping_flags)?;
+    let mut map = MemoryMap::anonymous(addr.as_usize(), addr.as_usize() + size, mapping_flags)
+        .named(format!("/SYSV{:08x} (deleted)", seg.perm.key));
+    map.shared = true;
+    curr.task_ext().process_data().maps.lock().insert(map);
+    drop(aspace);
+
+    seg.nattch += 1;
+    seg.atime = ipc_time();
+    seg.lpid = pid;
+    table.attachments.insert((pid, addr.as_usize()), shmid);
+    Ok(addr.as_usize() as _)
+}
+
+/// Detach the segment attached at `shmaddr` by `shmat`.
+pub fn sys_shmdt(shmaddr: usize) -> LinuxResult<isize> {
+    let pid = current_pid();
+    let mut table = SHM_TABLE.lock();
+    let &shmid = table
+        .attachments
+        .get(&(pid, shmaddr))
+        .ok_or(LinuxError::EINVAL)?;
+    if let Some(seg) = table.segments.get(&shmid) {
+        let curr = current();
+        let mut aspace = curr.task_ext().process_data().aspace.lock();
+        let size = seg.memory.mapped_size();
+        let mut maps = curr.task_ext().process_data().maps.lock();
+        if maps.is_sealed(shmaddr, shmaddr + size) {
+            return Err(LinuxError::EPERM);
+        }
+        maps.record_rss(&aspace);
+        aspace.unmap(VirtAddr::from(shmaddr), size)?;
+        maps.remove(shmaddr, shmaddr + size);
+        axhal::arch::flush_tlb(None);
+    }
+    table.attachments.remove(&(pid, shmaddr));
+    table.detach(shmid, pid);
+    Ok(0)
+}
+
+/// Get the status of the segment `shmid`, change its ownership and
+/// permissions, or mark it for removal.
+///
+/// A segment marked for removal with `IPC_RMID` can no longer be looked up
+/// by key, and is destroyed once the last attachment is detached.
+pub fn sys_shmctl(shmid: i32, cmd: u32, buf: UserPtr<ShmidDs>) -> LinuxResult<isize> {
+    let cmd = cmd & !IPC_64;
+    let new = match cmd {
+        IPC_SET => Some(*UserConstPtr::<ShmidDs>::from(buf.address().as_usize()).get_as_ref()?),
+        IPC_STAT | IPC_RMID => None,
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let mut table = SHM_TABLE.lock();
+    let seg = table.segments.get_mut(&shmid).ok_or(LinuxError::EINVAL)?;
+    match cmd {
+        IPC_STAT => {
+            seg.perm.check_access(0o4)?;
+            let status = seg.status();
+            drop(table);
+            *buf.get_as_mut()? = status;
+        }
+        IPC_SET => {
+            seg.perm.check_owner()?;
+            seg.perm.set(&new.unwrap().shm_perm);
+            seg.ctime = ipc_time();
+        }
+        _ => {
+            seg.perm.check_owner()?;
+            seg.perm.key = IPC_PRIVATE;
+            seg.perm.mode |= SHM_DEST;
+            seg.ctime = ipc_time();
+            if seg.nattch == 0 {
+                table.segments.remove(&shmid);
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Copy the attachments of `parent` to `child`, a fork with a copy of its
+/// address space where the segments are still mapped.
+pub fn shm_fork(parent: Pid, child: Pid) {
+    let mut table = SHM_TABLE.lock();
+    let inherited: alloc::vec::Vec<_> = table
+        .attachments
+        .range((parent, 0)..=(parent, usize::MAX))
+        .map(|(&(_, addr), &shmid)| (addr, shmid))
+        .collect();
+    for (addr, shmid) in inherited {
+        if let Some(seg) = table.segments.get_mut(&shmid) {
+            seg.nattch += 1;
+        }
+        table.attachments.insert((child, addr), shmid);
+    }
+}
+
+/// Drop the attachments of `pid` once its address space is gone, on exit or
+/// `execve`.
+pub fn shm_detach_all(pid: Pid) {
+    let mut table = SHM_TABLE.lock();
+    let attached: alloc::vec::Vec<_> = table
+        .attachments
+        .range((pid, 0)..=(pid, usize::MAX))
+        .map(|(&key, &shmid)| (key, shmid))
+        .collect();
+    for (key, shmid) in attached {
+        table.attachments.remove(&key);
+        table.detach(shmid, pid);
+    }
+}
@@ -0,0 +1,117 @@
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{AxCpuMask, TaskExtRef, current};
+use num_enum::TryFromPrimitive;
+
+use crate::online_cpus;
+
+/// Commands of `membarrier`, see `linux/membarrier.h`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, TryFromPrimitive)]
+#[repr(i32)]
+enum MembarrierCmd {
+    /// Query the set of supported commands.
+    Query = 0,
+    /// Issue a barrier on all running threads of all processes.
+    Global = 1 << 0,
+    /// Issue a barrier on the running threads of all processes registered
+    /// with `RegisterGlobalExpedited`.
+    GlobalExpedited = 1 << 1,
+    /// Register the intent to receive `GlobalExpedited` barriers.
+    RegisterGlobalExpedited = 1 << 2,
+    /// Issue a barrier on the running threads of the calling process.
+    PrivateExpedited = 1 << 3,
+    /// Register the intent to use `PrivateExpedited`.
+    RegisterPrivateExpedited = 1 << 4,
+    /// Like `PrivateExpedited`, also serializing the instruction stream.
+    PrivateExpeditedSyncCore = 1 << 5,
+    /// Register the intent to use `PrivateExpeditedSyncCore`.
+    RegisterPrivateExpeditedSyncCore = 1 << 6,
+}
+
+const SUPPORTED_CMDS: i32 = MembarrierCmd::Global as i32
+    | MembarrierCmd::GlobalExpedited as i32
+    | MembarrierCmd::RegisterGlobalExpedited as i32
+    | MembarrierCmd::PrivateExpedited as i32
+    | MembarrierCmd::RegisterPrivateExpedited as i32
+    | MembarrierCmd::PrivateExpeditedSyncCore as i32
+    | MembarrierCmd::RegisterPrivateExpeditedSyncCore as i32;
+
+/// Run `f` on every online CPU in turn.
+///
+/// To get there, the current task has to be switched in on each CPU, and the
+/// task that ran there before switched out, which orders the memory accesses
+/// of both.
+fn on_each_cpu(f: impl Fn()) {
+    let curr = current();
+    let thr = curr.task_ext().thread_data();
+    let cpus = online_cpus();
+    for cpu in (0..usize::BITS as usize).filter(|cpu| cpus & (1 << cpu) != 0) {
+        axtask::set_current_affinity(AxCpuMask::from_raw_bits(1 << cpu));
+        f();
+    }
+    axtask::set_current_affinity(AxCpuMask::from_raw_bits(thr.cpumask() & cpus));
+}
+
+/// Make instruction fetches on the current CPU see the code written before.
+///
+/// x86 needs nothing, as returning to user space with `iret` serializes the
+/// instruction stream by itself.
+fn sync_core() {
+    // SAFETY: these only synchronize the instruction stream.
+    #[cfg(target_arch = "riscv64")]
+    unsafe {
+        core::arch::asm!("fence.i")
+    };
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("isb")
+    };
+    #[cfg(target_arch = "loongarch64")]
+    unsafe {
+        core::arch::asm!("ibar 0")
+    };
+}
+
+pub fn sys_membarrier(cmd: i32, flags: u32, cpu_id: i32) -> LinuxResult<isize> {
+    let cmd = MembarrierCmd::try_from(cmd).map_err(|_| LinuxError::EINVAL)?;
+    debug!(
+        "sys_membarrier: cmd = {:?}, flags = {:#x}, cpu_id = {}",
+        cmd, flags, cpu_id
+    );
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let curr = current();
+    let proc_data = curr.task_ext().process_data();
+    match cmd {
+        MembarrierCmd::Query => return Ok(SUPPORTED_CMDS as _),
+        MembarrierCmd::RegisterGlobalExpedited
+        | MembarrierCmd::RegisterPrivateExpedited
+        | MembarrierCmd::RegisterPrivateExpeditedSyncCore => {
+            proc_data.register_membarrier(cmd as u32);
+            return Ok(0);
+        }
+        MembarrierCmd::PrivateExpedited => {
+            if !proc_data.is_membarrier_registered(MembarrierCmd::RegisterPrivateExpedited as u32) {
+                return Err(LinuxError::EPERM);
+            }
+        }
+        MembarrierCmd::PrivateExpeditedSyncCore => {
+            if !proc_data
+                .is_membarrier_registered(MembarrierCmd::RegisterPrivateExpeditedSyncCore as u32)
+            {
+                return Err(LinuxError::EPERM);
+            }
+        }
+        MembarrierCmd::Global | MembarrierCmd::GlobalExpedited => {}
+    }
+
+    // Every CPU is visited, not only those running threads of the process,
+    // since the threads may move in the meantime.
+    if cmd == MembarrierCmd::PrivateExpeditedSyncCore {
+        on_each_cpu(sync_core);
+    } else {
+        on_each_cpu(|| {});
+    }
+    Ok(0)
+}
@@ -1,12 +1,25 @@
 use axerrno::LinuxResult;
 use axtask::{TaskExtRef, current};
 
+use super::check_as_limit;
+
+/// Move the top of the heap to `addr`, within the heap region, returning the
+/// new top or the old one if it cannot move.
+///
+/// The heap region is mapped in full up front, so it already counts towards
+/// `RLIMIT_AS`, but the heap may only grow while the address space is within
+/// the limit.
 pub fn sys_brk(addr: usize) -> LinuxResult<isize> {
     let task = current();
     let process_data = task.task_ext().process_data();
     let mut return_val: isize = process_data.get_heap_top() as isize;
     let heap_bottom = process_data.get_heap_bottom() as usize;
-    if addr != 0 && addr >= heap_bottom && addr <= heap_bottom + axconfig::plat::USER_HEAP_SIZE {
+    let grows = addr > process_data.get_heap_top();
+    if addr != 0
+        && addr >= heap_bottom
+        && addr <= heap_bottom + axconfig::plat::USER_HEAP_SIZE
+        && (!grows || check_as_limit(process_data.maps.lock().total_size()).is_ok())
+    {
         process_data.set_heap_top(addr);
         return_val = addr as isize;
     }
@@ -3,12 +3,13 @@ use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    MAP_ANONYMOUS, MAP_FIXED, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, MAP_STACK, PROT_EXEC,
-    PROT_GROWSDOWN, PROT_GROWSUP, PROT_READ, PROT_WRITE,
+    MAP_ANONYMOUS, MAP_FIXED, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, MAP_STACK, MREMAP_FIXED,
+    MREMAP_MAYMOVE, PROT_EXEC, PROT_GROWSDOWN, PROT_GROWSUP, PROT_READ, PROT_WRITE, RLIMIT_AS,
 };
-use memory_addr::{VirtAddr, VirtAddrRange};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange, align_up_4k, is_aligned_4k};
+use starry_core::mm::MemoryMap;
 
-use crate::file::{File, FileLike};
+use crate::file::{DevFile, File, FileLike};
 
 bitflags::bitflags! {
     /// `PROT_*` flags for use with [`sys_mmap`].
@@ -31,6 +32,11 @@ bitflags::bitflags! {
 
 impl From<MmapProt> for MappingFlags {
     fn from(value: MmapProt) -> Self {
+        // With no access at all, the pages must not be present, or some
+        // architectures would still let them be read.
+        if !value.intersects(MmapProt::READ | MmapProt::WRITE | MmapProt::EXEC) {
+            return MappingFlags::empty();
+        }
         let mut flags = MappingFlags::USER;
         if value.contains(MmapProt::READ) {
             flags |= MappingFlags::READ;
@@ -66,6 +72,19 @@ bitflags::bitflags! {
     }
 }
 
+/// Check that the address space of the current process may grow to `size`
+/// bytes under its `RLIMIT_AS`.
+///
+/// Fails with `ENOMEM` otherwise. Lowering the limit below what is mapped
+/// only keeps the address space from growing.
+pub(crate) fn check_as_limit(size: usize) -> LinuxResult {
+    let limit = current().task_ext().process_data().rlimits.read()[RLIMIT_AS].current;
+    if size as u64 > limit {
+        return Err(LinuxError::ENOMEM);
+    }
+    Ok(())
+}
+
 pub fn sys_mmap(
     addr: usize,
     length: usize,
@@ -95,10 +114,23 @@ pub fn sys_mmap(
         start, end, aligned_length
     );
 
-    let start_addr = if map_flags.contains(MmapFlags::FIXED) {
+    let fixed = map_flags.contains(MmapFlags::FIXED);
+    let mut maps = process_data.maps.lock();
+    let replaced = if fixed {
+        maps.mapped_size(start, end)
+    } else {
+        0
+    };
+    check_as_limit(maps.total_size() - replaced + aligned_length)?;
+
+    let start_addr = if fixed {
         if start == 0 {
             return Err(LinuxError::EINVAL);
         }
+        if maps.is_sealed(start, end) {
+            return Err(LinuxError::EPERM);
+        }
+        maps.record_rss(&aspace);
         let dst_addr = VirtAddr::from(start);
         aspace.unmap(dst_addr, aligned_length)?;
         dst_addr
@@ -117,21 +149,23 @@ pub fn sys_mmap(
             .ok_or(LinuxError::ENOMEM)?
     };
 
-    let populate = if fd == -1 {
-        false
-    } else {
-        !map_flags.contains(MmapFlags::ANONYMOUS)
-    };
+    // Mapping `/dev/zero` gives anonymous memory.
+    let populate = fd != -1
+        && !map_flags.contains(MmapFlags::ANONYMOUS)
+        && !DevFile::from_fd(fd).is_ok_and(|dev| dev.is_zero());
 
-    aspace.map_alloc(
-        start_addr,
-        aligned_length,
-        permission_flags.into(),
-        populate,
-    )?;
+    let mapping_flags = permission_flags.into();
+    aspace.map_alloc(start_addr, aligned_length, mapping_flags, populate)?;
+    let mut map = MemoryMap::anonymous(
+        start_addr.as_usize(),
+        start_addr.as_usize() + aligned_length,
+        mapping_flags,
+    );
+    map.shared = map_flags.contains(MmapFlags::SHARED);
 
     if populate {
         let file = File::from_fd(fd)?;
+        map = map.named(file.path());
         let file = file.inner();
         let file_size = file.get_attr()?.size() as usize;
         if offset < 0 || offset as usize >= file_size {
@@ -142,7 +176,9 @@ pub fn sys_mmap(
         let mut buf = vec![0u8; length];
         file.read_at(offset as u64, &mut buf)?;
         aspace.write(start_addr, &buf)?;
+        map.offset = offset;
     }
+    maps.insert(map);
     Ok(start_addr.as_usize() as _)
 }
 
@@ -151,12 +187,129 @@ pub fn sys_munmap(addr: usize, length: usize) -> LinuxResult<isize> {
     let process_data = curr.task_ext().process_data();
     let mut aspace = process_data.aspace.lock();
     let length = memory_addr::align_up_4k(length);
+    let mut maps = process_data.maps.lock();
+    if maps.is_sealed(addr, addr + length) {
+        return Err(LinuxError::EPERM);
+    }
+    maps.record_rss(&aspace);
     let start_addr = VirtAddr::from(addr);
     aspace.unmap(start_addr, length)?;
+    maps.remove(addr, addr + length);
     axhal::arch::flush_tlb(None);
     Ok(0)
 }
 
+/// Resize the mapping of `[old_addr, old_addr + old_size)` to `new_size`
+/// bytes.
+///
+/// Shrinking unmaps the tail and keeps the address. Growing extends the
+/// mapping in place if the pages after it are free, or else moves it if
+/// `MREMAP_MAYMOVE` is given, to `new_addr` with `MREMAP_FIXED`. Moving
+/// copies the pages that are present.
+pub fn sys_mremap(
+    old_addr: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: u32,
+    new_addr: usize,
+) -> LinuxResult<isize> {
+    info!(
+        "sys_mremap: old_addr: {:#x}, old_size: {:#x}, new_size: {:#x}, flags: {:#x}, new_addr: {:#x}",
+        old_addr, old_size, new_size, flags, new_addr
+    );
+    let fixed = flags & MREMAP_FIXED != 0;
+    let may_move = flags & MREMAP_MAYMOVE != 0;
+    if flags & !(MREMAP_MAYMOVE | MREMAP_FIXED) != 0
+        || fixed && !may_move
+        || !is_aligned_4k(old_addr)
+        || old_size == 0
+        || new_size == 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let old_size = align_up_4k(old_size);
+    let new_size = align_up_4k(new_size);
+    let old_end = old_addr.checked_add(old_size).ok_or(LinuxError::EINVAL)?;
+    let new_end = old_addr.checked_add(new_size).ok_or(LinuxError::ENOMEM)?;
+    if fixed && (!is_aligned_4k(new_addr) || new_addr < old_end && old_addr < new_addr + new_size) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let curr = current();
+    let process_data = curr.task_ext().process_data();
+    let mut aspace = process_data.aspace.lock();
+    let mut maps = process_data.maps.lock();
+    let map = maps
+        .find(old_addr)
+        .filter(|map| old_end <= map.end)
+        .cloned()
+        .ok_or(LinuxError::EFAULT)?;
+    if map.sealed || fixed && maps.is_sealed(new_addr, new_addr + new_size) {
+        return Err(LinuxError::EPERM);
+    }
+    if new_size > old_size {
+        let replaced = if fixed {
+            maps.mapped_size(new_addr, new_addr + new_size)
+        } else {
+            0
+        };
+        check_as_limit(maps.total_size() - replaced + new_size - old_size)?;
+    }
+    maps.record_rss(&aspace);
+
+    if !fixed && new_size <= old_size {
+        if new_size < old_size {
+            aspace.unmap(VirtAddr::from(new_end), old_size - new_size)?;
+            maps.remove(new_end, old_end);
+            axhal::arch::flush_tlb(None);
+        }
+        return Ok(old_addr as _);
+    }
+
+    let range = VirtAddrRange::new(aspace.base(), aspace.end());
+    let grow = new_size - old_size;
+    if !fixed
+        && old_end == map.end
+        && aspace.find_free_area(VirtAddr::from(old_end), grow, range) == Some(old_end.into())
+    {
+        aspace.map_alloc(VirtAddr::from(old_end), grow, map.flags, false)?;
+        maps.extend(old_end, new_end);
+        return Ok(old_addr as _);
+    }
+    if !may_move {
+        return Err(LinuxError::ENOMEM);
+    }
+
+    let dst = if fixed {
+        aspace.unmap(VirtAddr::from(new_addr), new_size)?;
+        maps.remove(new_addr, new_addr + new_size);
+        VirtAddr::from(new_addr)
+    } else {
+        aspace
+            .find_free_area(aspace.base(), new_size, range)
+            .ok_or(LinuxError::ENOMEM)?
+    };
+    aspace.map_alloc(dst, new_size, map.flags, true)?;
+    let mut page = vec![0u8; PAGE_SIZE_4K];
+    for offset in (0..old_size.min(new_size)).step_by(PAGE_SIZE_4K) {
+        let src = VirtAddr::from(old_addr + offset);
+        if aspace.page_table().query(src).is_ok() {
+            aspace.read(src, &mut page)?;
+            aspace.write(dst + offset, &page)?;
+        }
+    }
+    aspace.unmap(VirtAddr::from(old_addr), old_size)?;
+    axhal::arch::flush_tlb(None);
+
+    let mut moved = map.clone();
+    moved.offset += old_addr - map.start;
+    moved.start = dst.as_usize();
+    moved.end = dst.as_usize() + new_size;
+    maps.remove(old_addr, old_end);
+    maps.insert(moved);
+    Ok(dst.as_usize() as _)
+}
+
 pub fn sys_mprotect(addr: usize, length: usize, prot: u32) -> LinuxResult<isize> {
     // TODO: implement PROT_GROWSUP & PROT_GROWSDOWN
     let Some(permission_flags) = MmapProt::from_bits(prot) else {
@@ -170,8 +323,86 @@ pub fn sys_mprotect(addr: usize, length: usize, prot: u32) -> LinuxResult<isize>
     let process_data = curr.task_ext().process_data();
     let mut aspace = process_data.aspace.lock();
     let length = memory_addr::align_up_4k(length);
+    let mut maps = process_data.maps.lock();
+    if maps.is_sealed(addr, addr + length) {
+        return Err(LinuxError::EPERM);
+    }
     let start_addr = VirtAddr::from(addr);
-    aspace.protect(start_addr, length, permission_flags.into())?;
+    let mapping_flags = permission_flags.into();
+    aspace.protect(start_addr, length, mapping_flags)?;
+    maps.protect(addr, addr + length, mapping_flags);
+
+    Ok(0)
+}
+
+/// Seal the mappings in `[addr, addr + len)`, so that they can no longer be
+/// unmapped, moved, replaced or have their permissions changed.
+///
+/// Sealing cannot be undone, and the whole range must be mapped.
+pub fn sys_mseal(addr: usize, len: usize, flags: u32) -> LinuxResult<isize> {
+    info!(
+        "sys_mseal: addr: {:#x}, len: {:#x}, flags: {:#x}",
+        addr, len, flags
+    );
+    if flags != 0 || !is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+    let end = addr
+        .checked_add(align_up_4k(len))
+        .ok_or(LinuxError::EINVAL)?;
+    if end == addr {
+        return Ok(0);
+    }
+
+    let curr = current();
+    let mut maps = curr.task_ext().process_data().maps.lock();
+    if !maps.is_mapped(addr, end) {
+        return Err(LinuxError::ENOMEM);
+    }
+    maps.seal(addr, end);
+    Ok(0)
+}
+
+/// Map a shadow stack for control-flow integrity, see `map_shadow_stack(2)`.
+///
+/// User shadow stacks need CET to be enabled in the CPU and their pages to
+/// be marked as shadow stack memory in the page tables, neither of which is
+/// done here. So they are reported as unsupported with `EOPNOTSUPP`, as on
+/// CPUs without them. Other architectures have no such syscall.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_map_shadow_stack(addr: usize, size: usize, flags: u32) -> LinuxResult<isize> {
+    info!(
+        "sys_map_shadow_stack: addr: {:#x}, size: {:#x}, flags: {:#x}",
+        addr, size, flags
+    );
+    Err(LinuxError::EOPNOTSUPP)
+}
+
+/// Flush the instruction cache of the calling hart only, rather than of all
+/// the harts running the process, see `riscv_flush_icache`.
+#[cfg(target_arch = "riscv64")]
+const SYS_RISCV_FLUSH_ICACHE_LOCAL: u32 = 1;
+
+/// Make the instructions written to `[start, end)` visible to instruction
+/// fetches, as JIT compilers need before running the code they generate.
+///
+/// The range must be mapped, or this fails with `EFAULT`.
+#[cfg(target_arch = "riscv64")]
+pub fn sys_riscv_flush_icache(start: usize, end: usize, flags: u32) -> LinuxResult<isize> {
+    info!(
+        "sys_riscv_flush_icache: start: {:#x}, end: {:#x}, flags: {:#x}",
+        start, end, flags
+    );
+    if flags & !SYS_RISCV_FLUSH_ICACHE_LOCAL != 0 || start > end {
+        return Err(LinuxError::EINVAL);
+    }
+    let maps = current().task_ext().process_data().maps.lock();
+    if start < end && !maps.is_mapped(memory_addr::align_down_4k(start), align_up_4k(end)) {
+        return Err(LinuxError::EFAULT);
+    }
 
+    // TODO: have the other harts fence as well, as a thread may run there
+    // or move there later. For now only the calling hart is fenced.
+    unsafe { core::arch::asm!("fence.i") };
     Ok(0)
 }
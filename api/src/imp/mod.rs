@@ -1,9 +1,13 @@
 mod fs;
 mod futex;
+mod ipc;
+mod membarrier;
 mod mm;
 mod signal;
 mod sys;
 mod task;
 mod time;
 
-pub use self::{fs::*, futex::*, mm::*, signal::*, sys::*, task::*, time::*};
+pub use self::{
+    fs::*, futex::*, ipc::*, membarrier::*, mm::*, signal::*, sys::*, task::*, time::*,
+};
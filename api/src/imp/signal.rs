@@ -1,4 +1,4 @@
-use core::{mem, time::Duration};
+use core::{ffi::c_int, mem};
 
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
@@ -7,14 +7,18 @@ use axprocess::{Pid, Thread};
 use axsignal::{SignalInfo, SignalSet, SignalStack, Signo};
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    MINSIGSTKSZ, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, kernel_sigaction, siginfo,
-    timespec,
+    MINSIGSTKSZ, O_CLOEXEC, O_NONBLOCK, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK,
+    SS_AUTODISARM, SS_DISABLE, SS_ONSTACK, kernel_sigaction, siginfo, timespec,
 };
 use starry_core::task::{get_process, get_process_group, get_thread, processes};
 
 use crate::{
+    file::{FileLike, PidFd, SignalFd, add_file_like},
     ptr::{UserConstPtr, UserPtr, nullable},
-    signal::{check_signals, send_signal_process, send_signal_process_group, send_signal_thread},
+    signal::{
+        check_signals, send_signal_process, send_signal_process_group, send_signal_thread,
+        wait_signal_interruptible,
+    },
     time::TimeValueLike,
 };
 
@@ -90,12 +94,21 @@ pub fn sys_rt_sigpending(set: UserPtr<SignalSet>, sigsetsize: usize) -> LinuxRes
     Ok(0)
 }
 
+/// Build the `siginfo_t` of a signal sent by the current process, filling in
+/// the sender's PID and UID.
 fn make_siginfo(signo: u32, code: i32) -> LinuxResult<Option<SignalInfo>> {
     if signo == 0 {
         return Ok(None);
     }
     let signo = parse_signo(signo)?;
-    Ok(Some(SignalInfo::new(signo, code)))
+    let mut sig = SignalInfo::new(signo, code);
+    // SAFETY: `_kill` is the active member for `SI_USER` and `SI_TKILL`.
+    unsafe {
+        let kill = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._kill;
+        kill._pid = current().task_ext().thread.process().pid() as _;
+        kill._uid = 0;
+    }
+    Ok(Some(sig))
 }
 
 pub fn sys_kill(pid: i32, signo: u32) -> LinuxResult<isize> {
@@ -145,7 +158,12 @@ pub fn sys_tkill(tid: Pid, signo: u32) -> LinuxResult<isize> {
     Ok(0)
 }
 
+/// Send a signal to the thread `tid`, which must belong to the process
+/// `tgid`.
 pub fn sys_tgkill(tgid: Pid, tid: Pid, signo: u32) -> LinuxResult<isize> {
+    if tgid as i32 <= 0 || tid as i32 <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
     let Some(sig) = make_siginfo(signo, SI_TKILL)? else {
         // TODO: should also check permissions
         return Ok(0);
@@ -163,46 +181,80 @@ fn find_thread_in_group(tgid: Pid, tid: Pid) -> LinuxResult<Arc<Thread>> {
     Ok(thr)
 }
 
+/// Build the `siginfo_t` of a signal queued with a caller-supplied payload.
+///
+/// Only the process itself may forge the `si_code` of a signal sent by the
+/// kernel or by `kill`/`tgkill`.
 fn make_queue_signal_info(
     tgid: Pid,
     signo: u32,
     sig: UserConstPtr<SignalInfo>,
-) -> LinuxResult<SignalInfo> {
-    let signo = parse_signo(signo)?;
+) -> LinuxResult<Option<SignalInfo>> {
     let mut sig = sig.get_as_ref()?.clone();
-    sig.set_signo(signo);
     if current().task_ext().thread.process().pid() != tgid
         && (sig.code() >= 0 || sig.code() == SI_TKILL)
     {
         return Err(LinuxError::EPERM);
     }
-    Ok(sig)
+    if signo == 0 {
+        return Ok(None);
+    }
+    sig.set_signo(parse_signo(signo)?);
+    Ok(Some(sig))
 }
 
+/// Queue a signal with a payload to a process.
+///
+/// Unlike standard signals, multiple instances of a real-time signal are
+/// queued rather than coalesced, so each of them carries its own `si_value`.
 pub fn sys_rt_sigqueueinfo(
     tgid: Pid,
     signo: u32,
     sig: UserConstPtr<SignalInfo>,
-    sigsetsize: usize,
 ) -> LinuxResult<isize> {
-    check_sigset_size(sigsetsize)?;
-
-    let sig = make_queue_signal_info(tgid, signo, sig)?;
-    send_signal_process(get_process(tgid)?.as_ref(), sig)?;
+    let proc = get_process(tgid)?;
+    if let Some(sig) = make_queue_signal_info(tgid, signo, sig)? {
+        send_signal_process(&proc, sig)?;
+    }
     Ok(0)
 }
 
+/// Queue a signal with a payload to a thread of a process.
 pub fn sys_rt_tgsigqueueinfo(
     tgid: Pid,
     tid: Pid,
     signo: u32,
     sig: UserConstPtr<SignalInfo>,
-    sigsetsize: usize,
 ) -> LinuxResult<isize> {
-    check_sigset_size(sigsetsize)?;
+    let thr = find_thread_in_group(tgid, tid)?;
+    if let Some(sig) = make_queue_signal_info(tgid, signo, sig)? {
+        send_signal_thread(&thr, sig)?;
+    }
+    Ok(0)
+}
 
-    let sig = make_queue_signal_info(tgid, signo, sig)?;
-    send_signal_thread(find_thread_in_group(tgid, tid)?.as_ref(), sig)?;
+/// Send a signal to the process referred to by `pidfd`.
+///
+/// If `info` is given, it is used as the `siginfo_t` of the signal like with
+/// `rt_sigqueueinfo`.
+pub fn sys_pidfd_send_signal(
+    pidfd: i32,
+    signo: u32,
+    info: UserConstPtr<SignalInfo>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let proc = PidFd::from_fd(pidfd)?.process()?;
+    let sig = if info.is_null() {
+        make_siginfo(signo, SI_USER as _)?
+    } else {
+        make_queue_signal_info(proc.pid(), signo, info)?
+    };
+    if let Some(sig) = sig {
+        send_signal_process(&proc, sig)?;
+    }
     Ok(0)
 }
 
@@ -212,6 +264,14 @@ pub fn sys_rt_sigreturn(tf: &mut TrapFrame) -> LinuxResult<isize> {
     Ok(tf.retval() as isize)
 }
 
+/// Wait for a signal in `set` to become pending and accept it synchronously.
+///
+/// The signal is removed from the pending set without running its handler,
+/// and its number is returned. The signals in `set` are treated as blocked
+/// while waiting, so they cannot be delivered to a handler in the meantime.
+///
+/// Fails with `EAGAIN` once the timeout expires, or with `EINTR` if another
+/// signal the thread neither blocks nor ignores arrives first.
 pub fn sys_rt_sigtimedwait(
     set: UserConstPtr<SignalSet>,
     info: UserPtr<siginfo>,
@@ -220,15 +280,25 @@ pub fn sys_rt_sigtimedwait(
 ) -> LinuxResult<isize> {
     check_sigset_size(sigsetsize)?;
 
-    let set = *set.get_as_ref()?;
-    let timeout: Option<Duration> = nullable!(timeout.get_as_ref())?.map(|ts| ts.to_time_value());
+    let mut set = *set.get_as_ref()?;
+    set.remove(Signo::SIGKILL);
+    set.remove(Signo::SIGSTOP);
+    let timeout = nullable!(timeout.get_as_ref())?
+        .map(|ts| {
+            if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(ts.to_time_value())
+        })
+        .transpose()?;
 
-    let Some(sig) = current()
-        .task_ext()
-        .thread_data()
-        .signal
-        .wait_timeout(set, timeout)
-    else {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let old_blocked = signal.with_blocked_mut(|blocked| mem::replace(blocked, *blocked | set));
+    let sig = wait_signal_interruptible(set, timeout);
+    signal.with_blocked_mut(|blocked| *blocked = old_blocked);
+
+    let Some(sig) = sig? else {
         return Err(LinuxError::EAGAIN);
     };
 
@@ -236,7 +306,7 @@ pub fn sys_rt_sigtimedwait(
         *info = sig.0;
     }
 
-    Ok(0)
+    Ok(sig.signo() as _)
 }
 
 pub fn sys_rt_sigsuspend(
@@ -269,26 +339,82 @@ pub fn sys_rt_sigsuspend(
     Ok(0)
 }
 
+/// Create a signalfd reading the signals in `mask`, or replace the mask of
+/// the signalfd `fd` if it is not -1.
+pub fn sys_signalfd4(
+    fd: c_int,
+    mask: UserConstPtr<SignalSet>,
+    sizemask: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    check_sigset_size(sizemask)?;
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut mask = *mask.get_as_ref()?;
+    mask.remove(Signo::SIGKILL);
+    mask.remove(Signo::SIGSTOP);
+
+    if fd != -1 {
+        SignalFd::from_fd(fd)?.set_mask(mask);
+        return Ok(fd as _);
+    }
+    let signalfd = SignalFd::new(mask, flags & O_NONBLOCK != 0);
+    let fd = add_file_like(Arc::new(signalfd), flags & O_CLOEXEC != 0)?;
+    Ok(fd as _)
+}
+
+/// Set and/or get the alternate signal stack of the current thread.
+///
+/// Handlers installed with `SA_ONSTACK` run on this stack. It cannot be
+/// changed while the thread is executing on it.
 pub fn sys_sigaltstack(
+    tf: &TrapFrame,
     ss: UserConstPtr<SignalStack>,
     old_ss: UserPtr<SignalStack>,
 ) -> LinuxResult<isize> {
+    let sp = tf.sp();
     current()
         .task_ext()
         .thread_data()
         .signal
         .with_stack_mut(|stack| {
+            let on_stack = stack.flags & SS_DISABLE == 0 && sp.wrapping_sub(stack.sp) < stack.size;
+
             if let Some(old_ss) = nullable!(old_ss.get_as_mut())? {
                 *old_ss = stack.clone();
+                old_ss.flags = if on_stack {
+                    SS_ONSTACK
+                } else if stack.flags & SS_DISABLE != 0 {
+                    SS_DISABLE
+                } else {
+                    0
+                };
             }
             if let Some(ss) = nullable!(ss.get_as_ref())? {
-                if ss.size <= MINSIGSTKSZ as usize {
-                    return Err(LinuxError::ENOMEM);
+                if on_stack {
+                    return Err(LinuxError::EPERM);
+                }
+                if ss.flags & !(SS_DISABLE | SS_AUTODISARM) != 0 {
+                    return Err(LinuxError::EINVAL);
                 }
-                let stack_ptr: UserConstPtr<u8> = ss.sp.into();
-                let _ = stack_ptr.get_as_slice(ss.size)?;
 
-                *stack = ss.clone();
+                if ss.flags & SS_DISABLE != 0 {
+                    *stack = SignalStack {
+                        sp: 0,
+                        flags: SS_DISABLE,
+                        size: 0,
+                    };
+                } else {
+                    if ss.size < MINSIGSTKSZ as usize {
+                        return Err(LinuxError::ENOMEM);
+                    }
+                    let stack_ptr: UserConstPtr<u8> = ss.sp.into();
+                    let _ = stack_ptr.get_as_slice(ss.size)?;
+
+                    *stack = ss.clone();
+                }
             }
             Ok(0)
         })
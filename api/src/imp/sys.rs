@@ -1,24 +1,60 @@
-use core::ffi::c_char;
+use core::{ffi::c_char, mem, sync::atomic::Ordering};
 
-use axerrno::LinuxResult;
-use linux_raw_sys::system::new_utsname;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axprocess::Pid;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{RLIM_NLIMITS, rlimit, rlimit64, rusage},
+    system::{new_utsname, sysinfo},
+};
+use starry_core::{
+    resources::Rlimit,
+    task::{ProcessData, get_process, processes},
+};
+
+use super::{make_rusage, process_cpu_time};
+use crate::{
+    file::{memory_usage, sync_all},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    random::fill_random,
+};
+
+/// The user and group IDs of a process.
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub euid: u32,
+    pub egid: u32,
+}
 
-use crate::ptr::UserPtr;
+/// Get the credentials of the current process.
+///
+/// They are the same for every process for now: the real IDs are those of
+/// root, while the effective IDs are not.
+pub fn current_credentials() -> Credentials {
+    Credentials {
+        uid: 0,
+        gid: 0,
+        euid: 1,
+        egid: 1,
+    }
+}
 
 pub fn sys_getuid() -> LinuxResult<isize> {
-    Ok(0)
+    Ok(current_credentials().uid as _)
 }
 
 pub fn sys_geteuid() -> LinuxResult<isize> {
-    Ok(1)
+    Ok(current_credentials().euid as _)
 }
 
 pub fn sys_getgid() -> LinuxResult<isize> {
-    Ok(0)
+    Ok(current_credentials().gid as _)
 }
 
 pub fn sys_getegid() -> LinuxResult<isize> {
-    Ok(1)
+    Ok(current_credentials().egid as _)
 }
 
 const fn pad_str(info: &str) -> [c_char; 65] {
@@ -31,16 +67,224 @@ const fn pad_str(info: &str) -> [c_char; 65] {
     data
 }
 
+/// The machine hardware name, as Linux reports it for the target.
+const MACHINE: &str = if cfg!(target_arch = "x86_64") {
+    "x86_64"
+} else if cfg!(target_arch = "riscv64") {
+    "riscv64"
+} else if cfg!(target_arch = "aarch64") {
+    "aarch64"
+} else if cfg!(target_arch = "loongarch64") {
+    "loongarch64"
+} else {
+    "unknown"
+};
+
+/// Pretend to be Linux, since programs parse these to find out which
+/// features the kernel has.
 const UTSNAME: new_utsname = new_utsname {
-    sysname: pad_str("Starry"),
-    nodename: pad_str("Starry - machine[0]"),
-    release: pad_str("10.0.0"),
-    version: pad_str("10.0.0"),
-    machine: pad_str("10.0.0"),
-    domainname: pad_str("https://github.com/oscomp/starry-next"),
+    sysname: pad_str("Linux"),
+    nodename: pad_str("starry"),
+    release: pad_str("6.1.0-wenyi"),
+    version: pad_str("#1 SMP PREEMPT"),
+    machine: pad_str(MACHINE),
+    domainname: pad_str("(none)"),
 };
 
 pub fn sys_uname(name: UserPtr<new_utsname>) -> LinuxResult<isize> {
     *name.get_as_mut()? = UTSNAME;
     Ok(0)
 }
+
+/// Get the uptime, memory totals and number of processes.
+///
+/// Load averages are not tracked and reported as zero, and the memory figures
+/// are those of `/proc/meminfo`, in bytes.
+pub fn sys_sysinfo(info: UserPtr<sysinfo>) -> LinuxResult<isize> {
+    let info = info.get_as_mut()?;
+    let (total, free) = memory_usage();
+    // SAFETY: the struct is plain integers.
+    *info = unsafe { mem::zeroed() };
+    info.uptime = monotonic_time().as_secs() as _;
+    info.totalram = total as _;
+    info.freeram = free as _;
+    info.procs = processes().len().min(u16::MAX as usize) as _;
+    info.mem_unit = 1;
+    Ok(0)
+}
+
+/// Get the limit on `resource` of the process `pid` (or the calling process
+/// if 0), and replace it with `new_limit` if given.
+///
+/// Returns the old limit.
+fn do_prlimit(pid: Pid, resource: u32, new_limit: Option<Rlimit>) -> LinuxResult<Rlimit> {
+    if resource >= RLIM_NLIMITS {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let proc = if pid == 0 || pid == curr.task_ext().thread.process().pid() {
+        curr.task_ext().thread.process().clone()
+    } else {
+        get_process(pid)?
+    };
+    let proc_data = proc.data::<ProcessData>().ok_or(LinuxError::ESRCH)?;
+
+    let mut rlimits = proc_data.rlimits.write();
+    let old_limit = rlimits[resource];
+    if let Some(new_limit) = new_limit {
+        if new_limit.current > new_limit.max {
+            return Err(LinuxError::EINVAL);
+        }
+        rlimits[resource] = new_limit;
+    }
+    Ok(old_limit)
+}
+
+pub fn sys_getrlimit(resource: u32, rlim: UserPtr<rlimit>) -> LinuxResult<isize> {
+    let limit = do_prlimit(0, resource, None)?;
+    *rlim.get_as_mut()? = rlimit {
+        rlim_cur: limit.current as _,
+        rlim_max: limit.max as _,
+    };
+    Ok(0)
+}
+
+pub fn sys_setrlimit(resource: u32, rlim: UserConstPtr<rlimit>) -> LinuxResult<isize> {
+    let rlim = rlim.get_as_ref()?;
+    let new_limit = Rlimit {
+        current: rlim.rlim_cur as _,
+        max: rlim.rlim_max as _,
+    };
+    do_prlimit(0, resource, Some(new_limit))?;
+    Ok(0)
+}
+
+pub fn sys_prlimit64(
+    pid: Pid,
+    resource: u32,
+    new_limit: UserConstPtr<rlimit64>,
+    old_limit: UserPtr<rlimit64>,
+) -> LinuxResult<isize> {
+    let new_limit = nullable!(new_limit.get_as_ref())?.map(|rlim| Rlimit {
+        current: rlim.rlim_cur,
+        max: rlim.rlim_max,
+    });
+    let limit = do_prlimit(pid, resource, new_limit)?;
+    if let Some(old_limit) = nullable!(old_limit.get_as_mut())? {
+        *old_limit = rlimit64 {
+            rlim_cur: limit.current,
+            rlim_max: limit.max,
+        };
+    }
+    Ok(0)
+}
+
+// The `who` of `getrusage`, see `linux/resource.h`.
+const RUSAGE_SELF: i32 = 0;
+const RUSAGE_CHILDREN: i32 = -1;
+const RUSAGE_THREAD: i32 = 1;
+
+/// Get the resource usage of the current process, its reaped children or
+/// the calling thread.
+///
+/// The CPU time is the same as for `times` and the CPU time clocks. The
+/// peak resident size is that of the address space, also for a thread, and
+/// for children the largest among them. Other fields, such as the context
+/// switch counts, are not tracked and are zero.
+pub fn sys_getrusage(who: i32, usage: UserPtr<rusage>) -> LinuxResult<isize> {
+    let curr = current();
+    let proc_data = curr.task_ext().process_data();
+    let ((utime_ns, stime_ns), max_rss) = match who {
+        RUSAGE_SELF | RUSAGE_THREAD => {
+            let time = if who == RUSAGE_SELF {
+                process_cpu_time()
+            } else {
+                curr.task_ext().time_stat_output()
+            };
+            let aspace = proc_data.aspace.lock();
+            let mut maps = proc_data.maps.lock();
+            maps.record_rss(&aspace);
+            (time, maps.max_rss())
+        }
+        RUSAGE_CHILDREN => (
+            proc_data.children_cpu_time.get(),
+            proc_data.children_max_rss.load(Ordering::Relaxed),
+        ),
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let mut result = make_rusage(utime_ns, stime_ns);
+    result.ru_maxrss = (max_rss / 1024) as _;
+    *usage.get_as_mut()? = result;
+    Ok(0)
+}
+
+// The magic numbers and commands of `reboot`, see `linux/reboot.h`.
+const LINUX_REBOOT_MAGIC1: u32 = 0xfee1_dead;
+const LINUX_REBOOT_MAGIC2: [u32; 4] = [672274793, 85072278, 369367448, 537993216];
+const LINUX_REBOOT_CMD_RESTART: u32 = 0x0123_4567;
+const LINUX_REBOOT_CMD_HALT: u32 = 0xcdef_0123;
+const LINUX_REBOOT_CMD_CAD_ON: u32 = 0x89ab_cdef;
+const LINUX_REBOOT_CMD_CAD_OFF: u32 = 0;
+const LINUX_REBOOT_CMD_POWER_OFF: u32 = 0x4321_fedc;
+const LINUX_REBOOT_CMD_RESTART2: u32 = 0xa1b2_c3d4;
+
+/// Write back the buffered data of every open file, as `sync` does, and
+/// power off, printing `reason` like Linux does.
+///
+/// Run when the kernel is done with the user apps, and by `reboot`.
+pub fn shutdown(reason: Option<&str>) -> ! {
+    sync_all();
+    if let Some(reason) = reason {
+        ax_println!("reboot: {}", reason);
+    }
+    axhal::misc::terminate()
+}
+
+/// Halt, power off or restart the system, after writing back every open
+/// file.
+///
+/// There is no way to restart, so that powers off too. Ctrl-Alt-Del is
+/// never trapped, so enabling or disabling it does nothing.
+///
+/// Like `prlimit`, it takes no privilege: every process runs with the same
+/// fixed credentials, so requiring one would leave no process able to do it.
+pub fn sys_reboot(magic1: u32, magic2: u32, cmd: u32, _arg: usize) -> LinuxResult<isize> {
+    if magic1 != LINUX_REBOOT_MAGIC1 || !LINUX_REBOOT_MAGIC2.contains(&magic2) {
+        return Err(LinuxError::EINVAL);
+    }
+    match cmd {
+        LINUX_REBOOT_CMD_CAD_ON | LINUX_REBOOT_CMD_CAD_OFF => Ok(0),
+        LINUX_REBOOT_CMD_HALT => shutdown(Some("System halted")),
+        LINUX_REBOOT_CMD_POWER_OFF => shutdown(Some("Power down")),
+        LINUX_REBOOT_CMD_RESTART | LINUX_REBOOT_CMD_RESTART2 => shutdown(Some("Restarting system")),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Fail with `EAGAIN` instead of blocking until the pool is initialized.
+const GRND_NONBLOCK: u32 = 1;
+/// Read from the blocking `/dev/random` pool.
+const GRND_RANDOM: u32 = 2;
+/// Read bytes even if the pool is not initialized.
+const GRND_INSECURE: u32 = 4;
+
+/// Fill `buf` with `buflen` random bytes, returning how many were written.
+///
+/// The bytes come from the ChaCha20 generator in [`crate::random`], which is
+/// usable from boot, so this never blocks and `GRND_NONBLOCK` never fails
+/// with `EAGAIN`. There is only one generator, which `GRND_RANDOM` reads like
+/// the default.
+pub fn sys_getrandom(buf: UserPtr<u8>, buflen: usize, flags: u32) -> LinuxResult<isize> {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    if buflen == 0 {
+        return Ok(0);
+    }
+    let buf = buf.get_as_mut_slice(buflen)?;
+    fill_random(buf);
+    Ok(buflen as _)
+}
@@ -1,3 +1,5 @@
+use core::mem;
+
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use axfs::{CURRENT_DIR, CURRENT_DIR_PATH};
@@ -8,12 +10,17 @@ use axsync::Mutex;
 use axtask::{TaskExtRef, current};
 use bitflags::bitflags;
 use linux_raw_sys::general::*;
+use memory_addr::PAGE_SIZE_4K;
 use starry_core::{
     mm::copy_from_kernel,
     task::{ProcessData, TaskExt, ThreadData, add_thread_to_table, new_user_task},
 };
 
-use crate::{file::FD_TABLE, ptr::UserPtr};
+use crate::{
+    file::{FD_TABLE, FileLike, PidFd},
+    ptr::{UserConstPtr, UserPtr},
+    shm_fork,
+};
 
 bitflags! {
     /// Options for use with [`sys_clone`].
@@ -75,9 +82,33 @@ bitflags! {
         const NEWNET = CLONE_NEWNET;
         /// The new process shares an I/O context with the calling process.
         const IO = CLONE_IO;
+        /// A pidfd referring to the child process is allocated in the
+        /// parent.
+        const PIDFD = CLONE_PIDFD;
     }
 }
 
+/// The arguments of [`sys_clone`] and [`sys_clone3`] in a common form.
+struct CloneArgs {
+    flags: CloneFlags,
+    exit_signal: u32,
+    /// The initial stack pointer of the child, or 0 to keep the caller's.
+    stack: usize,
+    tls: usize,
+    parent_tid: usize,
+    child_tid: usize,
+    /// Where to store the pidfd with `CLONE_PIDFD`.
+    pidfd: usize,
+}
+
+/// Create a child process or thread.
+///
+/// The order of `child_tid` and `tls` follows the architecture's syscall ABI.
+/// With `CLONE_SETTLS`, `tls` is written to the thread pointer register of the
+/// child's user context (`fs` base on x86_64, `tpidr_el0` on aarch64, `tp` on
+/// riscv64 and loongarch64). The register is part of the trap frame, so it is
+/// saved on every trap and restored on the way back to user space, which
+/// keeps it private to each thread across context switches.
 pub fn sys_clone(
     tf: &TrapFrame,
     flags: u32,
@@ -96,12 +127,108 @@ pub fn sys_clone(
         flags, exit_signal, stack, parent_tid, child_tid, tls
     );
 
+    // The pidfd is returned in place of the parent TID.
+    if flags.contains(CloneFlags::PIDFD | CloneFlags::PARENT_SETTID) {
+        return Err(LinuxError::EINVAL);
+    }
+    do_clone(
+        tf,
+        CloneArgs {
+            flags,
+            exit_signal,
+            stack,
+            tls,
+            parent_tid,
+            child_tid,
+            pidfd: parent_tid,
+        },
+    )
+}
+
+/// Create a child process or thread, with the arguments passed in a
+/// `struct clone_args` of `size` bytes.
+///
+/// Unlike [`sys_clone`], the stack is given as a base address and a size.
+pub fn sys_clone3(tf: &TrapFrame, cl_args: UserConstPtr<u8>, size: usize) -> LinuxResult<isize> {
+    if size < CLONE_ARGS_SIZE_VER0 as usize {
+        return Err(LinuxError::EINVAL);
+    }
+    if size > PAGE_SIZE_4K {
+        return Err(LinuxError::E2BIG);
+    }
+    // Newer versions of the struct may be larger, but then the fields we do
+    // not know of must be zero.
+    let bytes = cl_args.get_as_slice(size)?;
+    let known = size.min(size_of::<clone_args>());
+    if bytes[known..].iter().any(|&b| b != 0) {
+        return Err(LinuxError::E2BIG);
+    }
+    // SAFETY: valid for clone_args
+    let mut args: clone_args = unsafe { mem::zeroed() };
+    // SAFETY: at most `size_of::<clone_args>()` bytes are copied.
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            &mut args as *mut clone_args as *mut u8,
+            known,
+        );
+    }
+
+    info!("sys_clone3 <= {:?}", args);
+
+    let flags = u32::try_from(args.flags)
+        .ok()
+        .and_then(CloneFlags::from_bits)
+        .ok_or(LinuxError::EINVAL)?;
+    let exit_signal = u8::try_from(args.exit_signal).map_err(|_| LinuxError::EINVAL)?;
+    if (exit_signal != 0 && Signo::from_repr(exit_signal).is_none())
+        || (args.stack == 0) != (args.stack_size == 0)
+        || args.set_tid != 0
+        || args.set_tid_size != 0
+        || args.cgroup != 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let stack = args
+        .stack
+        .checked_add(args.stack_size)
+        .ok_or(LinuxError::EINVAL)?;
+
+    do_clone(
+        tf,
+        CloneArgs {
+            flags,
+            exit_signal: exit_signal as u32,
+            stack: stack as usize,
+            tls: args.tls as usize,
+            parent_tid: args.parent_tid as usize,
+            child_tid: args.child_tid as usize,
+            pidfd: args.pidfd as usize,
+        },
+    )
+}
+
+fn do_clone(tf: &TrapFrame, args: CloneArgs) -> LinuxResult<isize> {
+    let CloneArgs {
+        flags,
+        exit_signal,
+        stack,
+        tls,
+        parent_tid,
+        child_tid,
+        pidfd,
+    } = args;
+
     if exit_signal != 0 && flags.contains(CloneFlags::THREAD | CloneFlags::PARENT) {
         return Err(LinuxError::EINVAL);
     }
     if flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::VM | CloneFlags::SIGHAND) {
         return Err(LinuxError::EINVAL);
     }
+    if flags.contains(CloneFlags::THREAD | CloneFlags::PIDFD) {
+        return Err(LinuxError::EINVAL);
+    }
     let exit_signal = Signo::from_repr(exit_signal as u8);
 
     let mut new_uctx = UspaceContext::from(tf);
@@ -149,13 +276,16 @@ pub fn sys_clone(
         };
         let builder = parent.fork(tid);
 
-        let aspace = if flags.contains(CloneFlags::VM) {
-            curr.task_ext().process_data().aspace.clone()
+        let (aspace, maps) = if flags.contains(CloneFlags::VM) {
+            let process_data = curr.task_ext().process_data();
+            (process_data.aspace.clone(), process_data.maps.clone())
         } else {
             let mut aspace = curr.task_ext().process_data().aspace.lock();
             let mut aspace = aspace.clone_or_err()?;
             copy_from_kernel(&mut aspace)?;
-            Arc::new(Mutex::new(aspace))
+            let mut maps = curr.task_ext().process_data().maps.lock().clone();
+            maps.reset_max_rss(&aspace);
+            (Arc::new(Mutex::new(aspace)), Arc::new(Mutex::new(maps)))
         };
         new_task
             .ctx_mut()
@@ -171,9 +301,13 @@ pub fn sys_clone(
         let process_data = ProcessData::new(
             curr.task_ext().process_data().exe_path.read().clone(),
             aspace,
+            maps,
             signal_actions,
             exit_signal,
         );
+        *process_data.rlimits.write() = curr.task_ext().process_data().rlimits.read().clone();
+        process_data.replace_umask(curr.task_ext().process_data().umask());
+        *process_data.root.write() = curr.task_ext().process_data().root.read().clone();
 
         if flags.contains(CloneFlags::FILES) {
             FD_TABLE
@@ -200,13 +334,26 @@ pub fn sys_clone(
                 .deref_from(&process_data.ns)
                 .init_new(CURRENT_DIR_PATH.copy_inner());
         }
+        if !flags.contains(CloneFlags::VM) {
+            shm_fork(curr.task_ext().thread.process().pid(), tid);
+        }
         &builder.data(process_data).build()
     };
 
+    if flags.contains(CloneFlags::PIDFD) {
+        // pidfds are always close-on-exec.
+        let fd = PidFd::new(process).add_to_fd_table(true)?;
+        *UserPtr::<i32>::from(pidfd).get_as_mut()? = fd;
+    }
+
     let thread_data = ThreadData::new(process.data().unwrap());
     if flags.contains(CloneFlags::CHILD_CLEARTID) {
         thread_data.set_clear_child_tid(child_tid);
     }
+    #[cfg(target_arch = "x86_64")]
+    thread_data.set_gs_base(curr.task_ext().thread_data().gs_base());
+    thread_data.set_cpumask(curr.task_ext().thread_data().cpumask());
+    thread_data.set_nice(curr.task_ext().thread_data().nice());
 
     let thread = process.new_thread(tid).data(thread_data).build();
     add_thread_to_table(&thread);
@@ -1,12 +1,74 @@
 use core::ffi::c_char;
 
-use alloc::{string::ToString, vec::Vec};
-use axerrno::{LinuxError, LinuxResult};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfs::fops::OpenOptions;
 use axhal::arch::TrapFrame;
 use axtask::{TaskExtRef, current};
-use starry_core::mm::{load_user_app, map_trampoline};
+use linux_raw_sys::general::{AT_FDCWD, MS_NOEXEC, RLIMIT_STACK};
+use starry_core::mm::{MAX_SCRIPT_DEPTH, load_user_app, map_trampoline, parse_shebang};
 
-use crate::ptr::UserConstPtr;
+use crate::{
+    file::{FD_TABLE, file_perm},
+    mount_flags_at,
+    path::{current_root, handle_file_path},
+    ptr::UserConstPtr,
+    shm_detach_all,
+};
+
+/// Fail with `EACCES` unless `path` is a file that may be executed, and
+/// return where it is from the root of the kernel and its first bytes.
+///
+/// Files that were never given permissions are taken to be executable,
+/// since the file systems do not keep them.
+fn read_executable_head(path: &str) -> LinuxResult<(String, Vec<u8>)> {
+    let path = handle_file_path(AT_FDCWD, path)?;
+    let path = path.as_str();
+    if mount_flags_at(path) & MS_NOEXEC != 0 || file_perm(path, 0o755) & 0o111 == 0 {
+        return Err(LinuxError::EACCES);
+    }
+    let mut file = match axfs::fops::File::open(path, &OpenOptions::new().set_read(true)) {
+        Err(AxError::IsADirectory) => return Err(LinuxError::EACCES),
+        r => r?,
+    };
+    let mut head = [0; 256];
+    let len = file.read(&mut head)?;
+    Ok((path.into(), head[..len].to_vec()))
+}
+
+/// Find the program that runs `path` with `args`, following the `#!` lines
+/// of scripts, and the arguments it is run with.
+///
+/// Paths are resolved under the root directory of the calling process, and
+/// the program is returned as a path from the root of the kernel.
+///
+/// A script is run by its interpreter with the arguments `[interp, optarg,
+/// path, args[1..]]`. Fails with `ELOOP` if the interpreters are scripts
+/// more than `MAX_SCRIPT_DEPTH` deep, and with `ENOEXEC` if a `#!` line
+/// names no interpreter.
+fn resolve_program(mut path: String, mut args: Vec<String>) -> LinuxResult<(String, Vec<String>)> {
+    for _ in 0..=MAX_SCRIPT_DEPTH {
+        let (real_path, head) = read_executable_head(&path)?;
+        let Some((interp, arg)) = parse_shebang(&head) else {
+            return Ok((real_path, args));
+        };
+        if interp.is_empty() {
+            return Err(LinuxError::ENOEXEC);
+        }
+        let rest = args.into_iter().skip(1);
+        args = [interp.clone()]
+            .into_iter()
+            .chain(arg)
+            .chain([path])
+            .chain(rest)
+            .collect();
+        path = interp;
+    }
+    Err(LinuxError::ELOOP)
+}
 
 pub fn sys_execve(
     tf: &mut TrapFrame,
@@ -14,7 +76,7 @@ pub fn sys_execve(
     argv: UserConstPtr<UserConstPtr<c_char>>,
     envp: UserConstPtr<UserConstPtr<c_char>>,
 ) -> LinuxResult<isize> {
-    let path = path.get_as_str()?.to_string();
+    let path = path.get_as_path()?.to_string();
 
     let args = argv
         .get_as_null_terminated()?
@@ -32,6 +94,14 @@ pub fn sys_execve(
         path, args, envs
     );
 
+    // Scripts are resolved before the old program is torn down, so that
+    // errors are still reported to it.
+    let name = path
+        .rsplit_once('/')
+        .map_or(path.as_str(), |(_, name)| name)
+        .to_string();
+    let (path, args) = resolve_program(path, args)?;
+
     let curr = current();
     let curr_ext = curr.task_ext();
 
@@ -42,24 +112,38 @@ pub fn sys_execve(
     }
 
     let mut aspace = curr_ext.process_data().aspace.lock();
+    let mut maps = curr_ext.process_data().maps.lock();
+    maps.record_rss(&aspace);
     aspace.unmap_user_areas()?;
-    map_trampoline(&mut aspace)?;
+    maps.clear();
+    map_trampoline(&mut aspace, &mut maps)?;
     axhal::arch::flush_tlb(None);
 
-    let (entry_point, user_stack_base) =
-        load_user_app(&mut aspace, &args, &envs).map_err(|_| {
-            error!("Failed to load app {}", path);
-            LinuxError::ENOENT
-        })?;
+    let root = current_root();
+    let stack_size = curr_ext.process_data().rlimits.read()[RLIMIT_STACK].current;
+    let stack_size = stack_size.min(usize::MAX as u64) as usize;
+    let (entry_point, user_stack_base) = load_user_app(
+        &mut aspace,
+        &mut maps,
+        &root,
+        &path,
+        &args,
+        &envs,
+        stack_size,
+    )
+    .map_err(|_| {
+        error!("Failed to load app {}", path);
+        LinuxError::ENOENT
+    })?;
+    drop(maps);
     drop(aspace);
 
-    let name = path
-        .rsplit_once('/')
-        .map_or(path.as_str(), |(_, name)| name);
-    curr.set_name(name);
+    curr.set_name(&name);
     *curr_ext.process_data().exe_path.write() = path;
-
-    // TODO: fd close-on-exec
+    curr_ext.process_data().timers.clear();
+    curr_ext.process_data().clear_membarrier();
+    shm_detach_all(curr_ext.thread.process().pid());
+    FD_TABLE.close_on_exec();
 
     tf.set_ip(entry_point.as_usize());
     tf.set_sp(user_stack_base.as_usize());
@@ -1,12 +1,14 @@
 use axprocess::Pid;
 use axsignal::{SignalInfo, Signo};
 use axtask::{TaskExtRef, current};
-use linux_raw_sys::general::SI_KERNEL;
-use starry_core::task::ProcessData;
+use linux_raw_sys::general::{FUTEX_BITSET_MATCH_ANY, SI_KERNEL};
+use starry_core::task::{ProcessData, ThreadData};
 
 use crate::{
-    file::FD_TABLE,
-    ptr::UserPtr,
+    exit_robust_list,
+    file::{FD_TABLE, console},
+    ptr::{UserPtr, nullable},
+    sem_exit, shm_detach_all,
     signal::{send_signal_process, send_signal_thread},
 };
 
@@ -17,22 +19,57 @@ pub fn do_exit(exit_code: i32, group_exit: bool) -> ! {
     let thread = &curr_ext.thread;
     info!("{:?} exit with code: {}", thread, exit_code);
 
+    if let Err(err) = exit_robust_list(curr_ext.thread_data().robust_list_head()) {
+        warn!("Failed to release robust futexes: {:?}", err);
+    }
+
+    // Let the joiner of this thread know that it is gone, see
+    // `sys_set_tid_address`.
     let clear_child_tid = UserPtr::<Pid>::from(curr_ext.thread_data().clear_child_tid());
-    if let Ok(clear_tid) = clear_child_tid.get_as_mut() {
+    if let Ok(Some(clear_tid)) = nullable!(clear_child_tid.get_as_mut()) {
         *clear_tid = 0;
 
-        let guard = curr_ext
-            .process_data()
-            .futex_table
-            .get(clear_tid as *const _ as usize);
-        if let Some(futex) = guard {
-            futex.notify_one(false);
-        }
+        curr_ext.process_data().futex_table.wake(
+            clear_tid as *const _ as usize,
+            1,
+            FUTEX_BITSET_MATCH_ANY,
+        );
         axtask::yield_now();
     }
 
+    let (utime_ns, stime_ns) = curr_ext.time_stat_output();
+    curr_ext.process_data().cpu_time.add(utime_ns, stime_ns);
+    curr_ext.thread_data().cpu_time.set(0, 0);
+
     let process = thread.process();
     if thread.exit(exit_code) {
+        let sid = process.group().session().sid();
+        if sid == process.pid() {
+            console().disassociate(sid);
+        }
+
+        // Children are reparented on exit, so look them up before.
+        for child in process.children() {
+            for thr in child.threads() {
+                let signo = thr.data::<ThreadData>().and_then(|it| it.pdeath_signal());
+                if let Some(signo) = signo {
+                    let _ = send_signal_thread(&thr, SignalInfo::new(signo, SI_KERNEL as _));
+                }
+            }
+        }
+
+        // The peak resident size is reported to the parent once reaped.
+        let aspace = curr_ext.process_data().aspace.lock();
+        curr_ext.process_data().maps.lock().record_rss(&aspace);
+        drop(aspace);
+
+        curr_ext.process_data().timers.clear();
+        if let Some(timer) = curr_ext.process_data().real_timer.get() {
+            timer.delete();
+        }
+        shm_detach_all(process.pid());
+        sem_exit(process.pid());
+
         process.exit();
         if let Some(parent) = process.parent() {
             if let Some(signo) = process.data::<ProcessData>().and_then(|it| it.exit_signal) {
@@ -0,0 +1,101 @@
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::{Pid, Process};
+use axtask::{TaskExtRef, current};
+use starry_core::task::{add_process_group_to_table, get_process, get_process_group};
+
+use crate::file::console;
+
+/// Create a new session led by the calling process, with a new process group
+/// inside it.
+///
+/// The new session has no controlling terminal, so the caller is detached
+/// from the one of its old session. It gets one again when it opens a
+/// terminal.
+pub fn sys_setsid() -> LinuxResult<isize> {
+    let curr = current();
+    let process = curr.task_ext().thread.process();
+    // Fails if the caller is already a process group leader.
+    let (session, group) = process.create_session().ok_or(LinuxError::EPERM)?;
+    add_process_group_to_table(&group);
+    Ok(session.sid() as _)
+}
+
+/// Get the process `pid`, or the calling process if 0.
+fn process_or_current(pid: Pid) -> LinuxResult<Arc<Process>> {
+    if pid == 0 {
+        Ok(current().task_ext().thread.process().clone())
+    } else {
+        get_process(pid)
+    }
+}
+
+/// Move the process `pid` (or the caller if 0) into the process group `pgid`,
+/// or into a new group it leads if `pgid` is 0 or its own PID.
+///
+/// The target must be the caller or one of its children, in the caller's
+/// session and not leading it, and an existing group must belong to the
+/// same session.
+pub fn sys_setpgid(pid: i32, pgid: i32) -> LinuxResult<isize> {
+    if pid < 0 || pgid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let caller = curr.task_ext().thread.process();
+    let target = if pid == 0 || pid as Pid == caller.pid() {
+        caller.clone()
+    } else {
+        // TODO: fail with EACCES once the child has called execve
+        caller
+            .children()
+            .into_iter()
+            .find(|child| child.pid() == pid as Pid)
+            .ok_or(LinuxError::ESRCH)?
+    };
+
+    let sid = caller.group().session().sid();
+    if target.group().session().sid() != sid || target.pid() == sid {
+        return Err(LinuxError::EPERM);
+    }
+
+    let pgid = if pgid == 0 { target.pid() } else { pgid as Pid };
+    if pgid == target.pid() {
+        if let Some(group) = target.create_group() {
+            add_process_group_to_table(&group);
+        }
+    } else {
+        let group = get_process_group(pgid).map_err(|_| LinuxError::EPERM)?;
+        if group.session().sid() != sid || !target.move_to_group(&group) {
+            return Err(LinuxError::EPERM);
+        }
+    }
+    Ok(0)
+}
+
+/// Get the process group of the process `pid`, or of the caller if 0.
+pub fn sys_getpgid(pid: Pid) -> LinuxResult<isize> {
+    Ok(process_or_current(pid)?.group().pgid() as _)
+}
+
+/// Get the process group of the calling process.
+pub fn sys_getpgrp() -> LinuxResult<isize> {
+    sys_getpgid(0)
+}
+
+/// Get the session of the process `pid`, or of the caller if 0.
+pub fn sys_getsid(pid: Pid) -> LinuxResult<isize> {
+    Ok(process_or_current(pid)?.group().session().sid() as _)
+}
+
+/// Simulate a hangup of the controlling terminal of the calling process.
+///
+/// This does nothing if the caller has no controlling terminal.
+pub fn sys_vhangup() -> LinuxResult<isize> {
+    // TODO: require CAP_SYS_TTY_CONFIG once there are credentials
+    let curr = current();
+    let terminal = console();
+    if terminal.is_controlling(curr.task_ext().thread.process()) {
+        terminal.hangup();
+    }
+    Ok(0)
+}
@@ -1,6 +1,7 @@
 mod clone;
 mod execve;
 mod exit;
+mod job;
 mod schedule;
 mod thread;
 mod wait;
@@ -8,6 +9,7 @@ mod wait;
 pub use self::clone::*;
 pub use self::execve::*;
 pub use self::exit::*;
+pub use self::job::*;
 pub use self::schedule::*;
 pub use self::thread::*;
 pub use self::wait::*;
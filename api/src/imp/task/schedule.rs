@@ -1,11 +1,24 @@
+use alloc::{sync::Arc, vec, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
-use linux_raw_sys::general::timespec;
+use axhal::time::monotonic_time;
+use axprocess::{Pid, Thread};
+use axtask::{AxCpuMask, TaskExtRef, current};
+use linux_raw_sys::general::{
+    __kernel_clockid_t, CLOCK_MONOTONIC, CLOCK_REALTIME, PRIO_PGRP, PRIO_PROCESS, PRIO_USER,
+    TIMER_ABSTIME, timespec,
+};
+use starry_core::task::{ThreadData, get_process_group, get_thread, processes};
 
 use crate::{
     ptr::{UserConstPtr, UserPtr, nullable},
-    time::TimeValueLike,
+    signal::sleep_interruptible,
+    time::{TimeValueLike, wall_time},
 };
 
+/// Relinquish the CPU.
+///
+/// The current task is put back at the tail of the run queue, behind every
+/// other runnable task, so yielding tasks make progress in turn.
 pub fn sys_sched_yield() -> LinuxResult<isize> {
     axtask::yield_now();
     Ok(0)
@@ -13,30 +26,199 @@ pub fn sys_sched_yield() -> LinuxResult<isize> {
 
 /// Sleep some nanoseconds
 ///
-/// TODO: should be woken by signals, and set errno
+/// If woken up early by a signal, the remaining time is written to `rem` and
+/// `EINTR` is returned.
 pub fn sys_nanosleep(req: UserConstPtr<timespec>, rem: UserPtr<timespec>) -> LinuxResult<isize> {
-    let req = req.get_as_ref()?;
+    sys_clock_nanosleep(CLOCK_MONOTONIC as _, 0, req, rem)
+}
 
+/// Sleep on the clock `clock_id`, either for the duration in `req` or, with
+/// `TIMER_ABSTIME`, until the clock reaches `req`.
+///
+/// Like [`sys_nanosleep`], an early wakeup by a signal fails with `EINTR`,
+/// but the remaining time is only written to `rem` for relative sleeps.
+pub fn sys_clock_nanosleep(
+    clock_id: __kernel_clockid_t,
+    flags: u32,
+    req: UserConstPtr<timespec>,
+    rem: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    let req = req.get_as_ref()?;
     if req.tv_nsec < 0 || req.tv_nsec > 999_999_999 || req.tv_sec < 0 {
         return Err(LinuxError::EINVAL);
     }
+    let now = match clock_id as u32 {
+        CLOCK_REALTIME => wall_time,
+        CLOCK_MONOTONIC => monotonic_time,
+        _ => {
+            warn!(
+                "Called sys_clock_nanosleep for unsupported clock {}",
+                clock_id
+            );
+            return Err(LinuxError::EINVAL);
+        }
+    };
 
-    let dur = req.to_time_value();
-    debug!("sys_nanosleep <= {:?}", dur);
+    let absolute = flags & TIMER_ABSTIME != 0;
+    let dur = if absolute {
+        req.to_time_value().saturating_sub(now())
+    } else {
+        req.to_time_value()
+    };
+    debug!("sys_clock_nanosleep <= {:?}", dur);
 
-    let now = axhal::time::monotonic_time();
+    let Some(left) = sleep_interruptible(dur) else {
+        return Ok(0);
+    };
+    if !absolute {
+        if let Some(rem) = nullable!(rem.get_as_mut())? {
+            *rem = timespec::from_time_value(left);
+        }
+    }
+    Err(LinuxError::EINTR)
+}
 
-    axtask::sleep(dur);
+/// The mask of the CPUs that are online.
+pub(crate) fn online_cpus() -> usize {
+    let cpus = axconfig::plat::CPU_NUM;
+    if cpus >= usize::BITS as usize {
+        usize::MAX
+    } else {
+        (1 << cpus) - 1
+    }
+}
 
-    let after = axhal::time::monotonic_time();
-    let actual = after - now;
+fn affinity_target<R>(pid: Pid, f: impl FnOnce(&ThreadData) -> R) -> LinuxResult<R> {
+    if pid == 0 {
+        return Ok(f(current().task_ext().thread_data()));
+    }
+    let thread = get_thread(pid)?;
+    Ok(f(thread.data().ok_or(LinuxError::ESRCH)?))
+}
 
-    if let Some(diff) = dur.checked_sub(actual) {
-        if let Some(rem) = nullable!(rem.get_as_mut())? {
-            *rem = timespec::from_time_value(diff);
+/// Set the CPUs the thread `pid` (or the calling thread if 0) may run on.
+///
+/// CPUs that are not online are ignored, and `EINVAL` is returned if none is
+/// left.
+pub fn sys_sched_setaffinity(
+    pid: Pid,
+    cpusetsize: usize,
+    mask: UserConstPtr<u8>,
+) -> LinuxResult<isize> {
+    let bytes = mask.get_as_slice(cpusetsize)?;
+    // CPUs beyond the width of a word are never online.
+    let mut raw = [0u8; size_of::<usize>()];
+    let len = cpusetsize.min(raw.len());
+    raw[..len].copy_from_slice(&bytes[..len]);
+
+    let cpumask = usize::from_ne_bytes(raw) & online_cpus();
+    if cpumask == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    affinity_target(pid, |thr| thr.set_cpumask(cpumask))?;
+    Ok(0)
+}
+
+/// Get the CPUs the thread `pid` (or the calling thread if 0) may run on.
+///
+/// Returns the number of bytes written to `mask`.
+pub fn sys_sched_getaffinity(pid: Pid, cpusetsize: usize, mask: UserPtr<u8>) -> LinuxResult<isize> {
+    const SIZE: usize = size_of::<usize>();
+    if cpusetsize < SIZE || cpusetsize % SIZE != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let cpumask = affinity_target(pid, |thr| thr.cpumask())? & online_cpus();
+    mask.get_as_mut_slice(SIZE)?
+        .copy_from_slice(&cpumask.to_ne_bytes());
+    Ok(SIZE as _)
+}
+
+const MIN_NICE: i32 = -20;
+const MAX_NICE: i32 = 19;
+
+/// Collect the threads `getpriority` and `setpriority` act on.
+fn priority_targets(which: u32, who: u32) -> LinuxResult<Vec<Arc<Thread>>> {
+    let curr = current();
+    let threads = match which {
+        PRIO_PROCESS if who == 0 => vec![curr.task_ext().thread.clone()],
+        PRIO_PROCESS => vec![get_thread(who)?],
+        PRIO_PGRP => {
+            let group = if who == 0 {
+                curr.task_ext().thread.process().group()
+            } else {
+                get_process_group(who)?
+            };
+            group
+                .processes()
+                .iter()
+                .flat_map(|proc| proc.threads())
+                .collect()
         }
-        Err(LinuxError::EINTR)
-    } else {
-        Ok(0)
+        // Every process runs as root.
+        PRIO_USER if who == 0 => processes().iter().flat_map(|proc| proc.threads()).collect(),
+        PRIO_USER => Vec::new(),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    if threads.is_empty() {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(threads)
+}
+
+/// Get the highest priority among the threads selected by `which` and `who`.
+///
+/// Returns `20 - nice`, from 1 to 40, so that the result is never negative.
+pub fn sys_getpriority(which: u32, who: u32) -> LinuxResult<isize> {
+    let nice = priority_targets(which, who)?
+        .iter()
+        .filter_map(|thr| thr.data::<ThreadData>())
+        .map(ThreadData::nice)
+        .min()
+        .ok_or(LinuxError::ESRCH)?;
+    Ok((20 - nice) as _)
+}
+
+/// Set the nice value of the threads selected by `which` and `who`.
+///
+/// `prio` is clamped to the range of nice values, -20 to 19.
+pub fn sys_setpriority(which: u32, who: u32, prio: i32) -> LinuxResult<isize> {
+    let nice = prio.clamp(MIN_NICE, MAX_NICE);
+    let threads = priority_targets(which, who)?;
+    // TODO: allow CAP_SYS_NICE to go below 0 once there are credentials
+    if nice < 0 {
+        return Err(LinuxError::EACCES);
+    }
+    for thr in threads.iter().filter_map(|thr| thr.data::<ThreadData>()) {
+        thr.set_nice(nice);
+    }
+    Ok(0)
+}
+
+/// Add `inc` to the nice value of the calling thread.
+pub fn sys_nice(inc: i32) -> LinuxResult<isize> {
+    let nice = current()
+        .task_ext()
+        .thread_data()
+        .nice()
+        .saturating_add(inc);
+    match sys_setpriority(PRIO_PROCESS, 0, nice) {
+        Err(LinuxError::EACCES) => Err(LinuxError::EPERM),
+        res => res,
+    }
+}
+
+/// Pass the CPU affinity and nice value of the current thread on to the
+/// scheduler.
+///
+/// The scheduler can only change them for the current task, so this runs
+/// every time a thread returns to user space.
+pub(crate) fn sync_sched_attrs() {
+    let curr = current();
+    let thr = curr.task_ext().thread_data();
+    if let Some(cpumask) = thr.take_cpumask_change() {
+        axtask::set_current_affinity(AxCpuMask::from_raw_bits(cpumask & online_cpus()));
+    }
+    if let Some(nice) = thr.take_nice_change() {
+        axtask::set_priority(nice as _);
     }
 }
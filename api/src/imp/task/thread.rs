@@ -1,6 +1,19 @@
-use axerrno::LinuxResult;
+use alloc::string::{String, ToString};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Pid;
+use axsignal::Signo;
 use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::O_NONBLOCK,
+    prctl::{PR_GET_NAME, PR_GET_PDEATHSIG, PR_SET_NAME, PR_SET_PDEATHSIG},
+};
 use num_enum::TryFromPrimitive;
+use starry_core::task::get_process;
+
+use crate::{
+    file::{FileLike, PidFd},
+    ptr::{UserConstPtr, UserPtr},
+};
 
 pub fn sys_getpid() -> LinuxResult<isize> {
     Ok(axtask::current().task_ext().thread.process().pid() as _)
@@ -20,6 +33,82 @@ pub fn sys_gettid() -> LinuxResult<isize> {
     Ok(axtask::current().id().as_u64() as _)
 }
 
+/// Obtain a file descriptor referring to the process `pid`, which is always
+/// close-on-exec.
+pub fn sys_pidfd_open(pid: Pid, flags: u32) -> LinuxResult<isize> {
+    // `PIDFD_NONBLOCK` is the same as `O_NONBLOCK`, and has no effect since
+    // pidfds cannot be waited on yet.
+    if flags & !O_NONBLOCK != 0 || pid == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let proc = get_process(pid)?;
+    Ok(PidFd::new(&proc).add_to_fd_table(true)? as _)
+}
+
+/// The size of a thread name including the trailing NUL, see `TASK_COMM_LEN`
+/// in `linux/sched.h`.
+pub(crate) const TASK_COMM_LEN: usize = 16;
+
+/// Get the name of the current thread as seen by `PR_GET_NAME` and
+/// `/proc/self/comm`, which is at most `TASK_COMM_LEN - 1` bytes long.
+pub fn current_comm() -> String {
+    let curr = current();
+    let name = curr.name();
+    let mut len = name.len().min(TASK_COMM_LEN - 1);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    name[..len].to_string()
+}
+
+/// Operations on the current thread or process.
+///
+/// Only the thread name and the parent death signal are supported.
+pub fn sys_prctl(
+    option: u32,
+    arg2: usize,
+    _arg3: usize,
+    _arg4: usize,
+    _arg5: usize,
+) -> LinuxResult<isize> {
+    debug!("sys_prctl <= option: {}, arg2: {:#x}", option, arg2);
+    let curr = current();
+    match option {
+        PR_SET_NAME => {
+            let name = UserConstPtr::<u8>::from(arg2).get_as_null_terminated()?;
+            let name = &name[..name.len().min(TASK_COMM_LEN - 1)];
+            curr.set_name(&String::from_utf8_lossy(name));
+        }
+        PR_GET_NAME => {
+            let buf = UserPtr::<u8>::from(arg2).get_as_mut_slice(TASK_COMM_LEN)?;
+            let comm = current_comm();
+            buf.fill(0);
+            buf[..comm.len()].copy_from_slice(comm.as_bytes());
+        }
+        PR_SET_PDEATHSIG => {
+            let signo = match arg2 {
+                0 => None,
+                _ => Some(
+                    u8::try_from(arg2)
+                        .ok()
+                        .and_then(Signo::from_repr)
+                        .ok_or(LinuxError::EINVAL)?,
+                ),
+            };
+            curr.task_ext().thread_data().set_pdeath_signal(signo);
+        }
+        PR_GET_PDEATHSIG => {
+            let signo = curr.task_ext().thread_data().pdeath_signal();
+            *UserPtr::<i32>::from(arg2).get_as_mut()? = signo.map_or(0, |it| it as i32);
+        }
+        _ => {
+            warn!("Unsupported prctl option: {}", option);
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    Ok(0)
+}
+
 /// ARCH_PRCTL codes
 ///
 /// It is only avaliable on x86_64, and is not convenient
@@ -43,7 +132,11 @@ enum ArchPrctlCode {
 
 /// To set the clear_child_tid field in the task extended data.
 ///
-/// The set_tid_address() always succeeds
+/// When the thread exits, zero is written to `clear_child_tid` and one waiter
+/// of the futex at that address is woken up, which is how `pthread_join`
+/// learns that the thread is gone.
+///
+/// The set_tid_address() always succeeds and returns the caller's TID.
 pub fn sys_set_tid_address(clear_child_tid: usize) -> LinuxResult<isize> {
     let curr = current();
     curr.task_ext()
@@ -58,8 +151,6 @@ pub fn sys_arch_prctl(
     code: i32,
     addr: usize,
 ) -> LinuxResult<isize> {
-    use crate::ptr::UserPtr;
-
     let code = ArchPrctlCode::try_from(code).map_err(|_| axerrno::LinuxError::EINVAL)?;
     debug!("sys_arch_prctl: code = {:?}, addr = {:#x}", code, addr);
 
@@ -75,11 +166,13 @@ pub fn sys_arch_prctl(
             Ok(0)
         }
         ArchPrctlCode::GetGs => {
-            *UserPtr::from(addr).get_as_mut()? =
-                unsafe { x86::msr::rdmsr(x86::msr::IA32_KERNEL_GSBASE) };
+            *UserPtr::from(addr).get_as_mut()? = current().task_ext().thread_data().gs_base();
             Ok(0)
         }
         ArchPrctlCode::SetGs => {
+            current().task_ext().thread_data().set_gs_base(addr);
+            // While in the kernel the user GS base lives in `KERNEL_GSBASE`
+            // and is swapped in by `swapgs` on the way back.
             unsafe {
                 x86::msr::wrmsr(x86::msr::IA32_KERNEL_GSBASE, addr as _);
             }
@@ -89,3 +182,46 @@ pub fn sys_arch_prctl(
         ArchPrctlCode::SetCpuid => Err(axerrno::LinuxError::ENODEV),
     }
 }
+
+/// Set a TLS segment for the current thread.
+///
+/// Like Linux for 64-bit tasks, this fails with `ENOSYS`: there are no TLS
+/// segment descriptors, and the TLS pointer is kept in the FS base instead,
+/// see [`sys_arch_prctl`].
+#[cfg(target_arch = "x86_64")]
+pub fn sys_set_thread_area(u_info: usize) -> LinuxResult<isize> {
+    debug!("sys_set_thread_area: u_info = {:#x}", u_info);
+    Err(axerrno::LinuxError::ENOSYS)
+}
+
+/// Get a TLS segment of the current thread, which fails with `ENOSYS` like
+/// [`sys_set_thread_area`].
+#[cfg(target_arch = "x86_64")]
+pub fn sys_get_thread_area(u_info: usize) -> LinuxResult<isize> {
+    debug!("sys_get_thread_area: u_info = {:#x}", u_info);
+    Err(axerrno::LinuxError::ENOSYS)
+}
+
+/// Reload the user GS base of the current thread before returning to user
+/// space, so that it survives context switches between threads.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn restore_user_gs_base() {
+    let gs_base = current().task_ext().thread_data().gs_base();
+    unsafe {
+        x86::msr::wrmsr(x86::msr::IA32_KERNEL_GSBASE, gs_base as _);
+    }
+}
+
+/// Read or write the local descriptor table of the calling process.
+///
+/// 64-bit user programs keep their TLS pointer in the FS base (see
+/// [`sys_arch_prctl`]) and never rely on LDT segments, so no LDT is
+/// maintained and every request is rejected with `ENOSYS`.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_modify_ldt(func: i32, ptr: usize, bytecount: usize) -> LinuxResult<isize> {
+    debug!(
+        "sys_modify_ldt: func = {}, ptr = {:#x}, bytecount = {}",
+        func, ptr, bytecount
+    );
+    Err(axerrno::LinuxError::ENOSYS)
+}
@@ -1,14 +1,22 @@
+use core::{sync::atomic::Ordering, time::Duration};
+
 use alloc::{sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
+use axhal::time::NANOS_PER_SEC;
 use axprocess::{Pid, Process};
+use axsignal::{SignalInfo, Signo};
 use axtask::{TaskExtRef, current};
 use bitflags::bitflags;
 use linux_raw_sys::general::{
-    __WALL, __WCLONE, __WNOTHREAD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED,
+    __WALL, __WCLONE, __WNOTHREAD, CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED,
+    P_ALL, P_PGID, P_PID, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED, rusage, siginfo,
 };
-use starry_core::task::ProcessData;
+use starry_core::task::{JobEvent, ProcessData};
 
-use crate::ptr::{UserPtr, nullable};
+use crate::{
+    ptr::{UserPtr, nullable},
+    time::TimeValueLike,
+};
 
 bitflags! {
     #[derive(Debug)]
@@ -55,24 +63,39 @@ impl WaitPid {
     }
 }
 
-pub fn sys_waitpid(pid: i32, exit_code_ptr: UserPtr<i32>, options: u32) -> LinuxResult<isize> {
-    let options = WaitOptions::from_bits_truncate(options);
-    info!("sys_waitpid <= pid: {:?}, options: {:?}", pid, options);
+/// The CPU time used by a process and its reaped children, in nanoseconds.
+fn total_cpu_time(proc_data: &ProcessData) -> (usize, usize) {
+    let (utime_ns, stime_ns) = proc_data.cpu_time.get();
+    let (child_utime_ns, child_stime_ns) = proc_data.children_cpu_time.get();
+    (utime_ns + child_utime_ns, stime_ns + child_stime_ns)
+}
+
+pub(crate) fn make_rusage(utime_ns: usize, stime_ns: usize) -> rusage {
+    // SAFETY: valid for rusage
+    let mut usage: rusage = unsafe { core::mem::zeroed() };
+    usage.ru_utime = TimeValueLike::from_time_value(Duration::from_nanos(utime_ns as _));
+    usage.ru_stime = TimeValueLike::from_time_value(Duration::from_nanos(stime_ns as _));
+    usage
+}
+
+/// A change of state of a child collected by [`do_wait`].
+struct WaitResult {
+    pid: Pid,
+    /// The status in the layout of `wstatus`.
+    status: i32,
+    utime_ns: usize,
+    stime_ns: usize,
+}
 
+/// Wait for a child selected by `pid` to change state as selected by
+/// `options`, reaping it if it exited unless `WNOWAIT` is given.
+///
+/// Returns `None` if `WNOHANG` is given and no child is ready.
+fn do_wait(pid: WaitPid, options: WaitOptions) -> LinuxResult<Option<WaitResult>> {
     let curr = current();
     let proc_data = curr.task_ext().process_data();
     let process = curr.task_ext().thread.process();
 
-    let pid = if pid == -1 {
-        WaitPid::Any
-    } else if pid == 0 {
-        WaitPid::Pgid(process.group().pgid())
-    } else if pid > 0 {
-        WaitPid::Pid(pid as _)
-    } else {
-        WaitPid::Pgid(-pid as _)
-    };
-
     let children = process
         .children()
         .into_iter()
@@ -87,20 +110,166 @@ pub fn sys_waitpid(pid: i32, exit_code_ptr: UserPtr<i32>, options: u32) -> Linux
         return Err(LinuxError::ECHILD);
     }
 
-    let exit_code = nullable!(exit_code_ptr.get_as_mut())?;
     loop {
-        if let Some(child) = children.iter().find(|child| child.is_zombie()) {
-            if !options.contains(WaitOptions::WNOWAIT) {
-                child.free();
+        let report = children.iter().find_map(|child| {
+            if child.is_zombie() {
+                return options
+                    .contains(WaitOptions::WEXITED)
+                    .then(|| (child, child.exit_code(), None));
+            }
+            match child.data::<ProcessData>()?.job_event()? {
+                event @ JobEvent::Stopped(signo) if options.contains(WaitOptions::WUNTRACED) => {
+                    Some((child, ((signo as i32) << 8) | 0x7f, Some(event)))
+                }
+                event @ JobEvent::Continued if options.contains(WaitOptions::WCONTINUED) => {
+                    Some((child, 0xffff, Some(event)))
+                }
+                _ => None,
             }
-            if let Some(exit_code) = exit_code {
-                *exit_code = child.exit_code();
+        });
+
+        if let Some((child, status, event)) = report {
+            let child_data = child.data::<ProcessData>().unwrap();
+            let (utime_ns, stime_ns) = total_cpu_time(child_data);
+            if !options.contains(WaitOptions::WNOWAIT) {
+                match event {
+                    Some(event) => child_data.clear_job_event(event),
+                    None => {
+                        proc_data.children_cpu_time.add(utime_ns, stime_ns);
+                        let max_rss = child_data
+                            .maps
+                            .lock()
+                            .max_rss()
+                            .max(child_data.children_max_rss.load(Ordering::Relaxed));
+                        proc_data
+                            .children_max_rss
+                            .fetch_max(max_rss, Ordering::Relaxed);
+                        child.free();
+                    }
+                }
             }
-            return Ok(child.pid() as _);
+            return Ok(Some(WaitResult {
+                pid: child.pid(),
+                status,
+                utime_ns,
+                stime_ns,
+            }));
         } else if options.contains(WaitOptions::WNOHANG) {
-            return Ok(0);
+            return Ok(None);
         } else {
             proc_data.child_exit_wq.wait();
         }
     }
 }
+
+/// Wait for a child to exit, or to stop or continue with `WUNTRACED` or
+/// `WCONTINUED`.
+///
+/// The status is encoded into `wstatus` the way the `W*` macros of libc
+/// expect, and `rusage` receives the CPU time used by the child.
+pub fn sys_wait4(
+    pid: i32,
+    wstatus: UserPtr<i32>,
+    options: u32,
+    rusage: UserPtr<rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitOptions::from_bits_truncate(options) | WaitOptions::WEXITED;
+    info!("sys_wait4 <= pid: {:?}, options: {:?}", pid, options);
+
+    let pid = if pid == -1 {
+        WaitPid::Any
+    } else if pid == 0 {
+        WaitPid::Pgid(current().task_ext().thread.process().group().pgid())
+    } else if pid > 0 {
+        WaitPid::Pid(pid as _)
+    } else {
+        WaitPid::Pgid(-pid as _)
+    };
+
+    let wstatus = nullable!(wstatus.get_as_mut())?;
+    let rusage = nullable!(rusage.get_as_mut())?;
+    let Some(result) = do_wait(pid, options)? else {
+        return Ok(0);
+    };
+    if let Some(wstatus) = wstatus {
+        *wstatus = result.status;
+    }
+    if let Some(rusage) = rusage {
+        *rusage = make_rusage(result.utime_ns, result.stime_ns);
+    }
+    Ok(result.pid as _)
+}
+
+/// The unit of `si_utime` and `si_stime`.
+const USER_HZ: usize = 100;
+
+/// Wait for a child to change state, reporting it in a `siginfo_t`.
+///
+/// Unlike `wait4`, the changes of state to wait for must be given
+/// explicitly, and `WNOWAIT` leaves the child as it is to be waited for
+/// again. If `WNOHANG` is given and no child is ready, `si_pid` is zero.
+pub fn sys_waitid(
+    idtype: u32,
+    id: i32,
+    infop: UserPtr<siginfo>,
+    options: u32,
+    rusage: UserPtr<rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+    info!(
+        "sys_waitid <= idtype: {}, id: {}, options: {:?}",
+        idtype, id, options
+    );
+    if !options.intersects(WaitOptions::WEXITED | WaitOptions::WUNTRACED | WaitOptions::WCONTINUED)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let pid = match idtype {
+        P_ALL => WaitPid::Any,
+        P_PID if id > 0 => WaitPid::Pid(id as _),
+        P_PGID if id == 0 => WaitPid::Pgid(current().task_ext().thread.process().group().pgid()),
+        P_PGID if id > 0 => WaitPid::Pgid(id as _),
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let infop = nullable!(infop.get_as_mut())?;
+    let rusage = nullable!(rusage.get_as_mut())?;
+    let Some(result) = do_wait(pid, options)? else {
+        if let Some(infop) = infop {
+            // SAFETY: valid for siginfo
+            *infop = unsafe { core::mem::zeroed() };
+        }
+        return Ok(0);
+    };
+
+    if let Some(infop) = infop {
+        let status = result.status;
+        let (code, status) = if status == 0xffff {
+            (CLD_CONTINUED, Signo::SIGCONT as i32)
+        } else if status & 0xff == 0x7f {
+            (CLD_STOPPED, (status >> 8) & 0xff)
+        } else if status & 0x7f == 0 {
+            (CLD_EXITED, (status >> 8) & 0xff)
+        } else if status & 0x80 != 0 {
+            (CLD_DUMPED, status & 0x7f)
+        } else {
+            (CLD_KILLED, status & 0x7f)
+        };
+
+        let mut sig = SignalInfo::new(Signo::SIGCHLD, code as _);
+        // SAFETY: `_sigchld` is the active member for `SIGCHLD`.
+        unsafe {
+            let sigchld = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigchld;
+            sigchld._pid = result.pid as _;
+            sigchld._status = status;
+            sigchld._utime = (result.utime_ns / (NANOS_PER_SEC as usize / USER_HZ)) as _;
+            sigchld._stime = (result.stime_ns / (NANOS_PER_SEC as usize / USER_HZ)) as _;
+        }
+        *infop = sig.0;
+    }
+    if let Some(rusage) = rusage {
+        *rusage = make_rusage(result.utime_ns, result.stime_ns);
+    }
+    Ok(0)
+}
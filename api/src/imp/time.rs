@@ -1,28 +1,86 @@
+use core::ffi::c_int;
+
+use alloc::sync::{Arc, Weak};
 use axerrno::{LinuxError, LinuxResult};
-use axhal::time::{monotonic_time, monotonic_time_nanos, nanos_to_ticks, wall_time};
+use axhal::time::{NANOS_PER_SEC, TimeValue, monotonic_time, monotonic_time_nanos, ticks_to_nanos};
+use axprocess::{Process, Thread};
+use axsignal::{SignalInfo, Signo};
+use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    __kernel_clockid_t, CLOCK_MONOTONIC, CLOCK_REALTIME, timespec, timeval,
+    __kernel_clockid_t, __kernel_timer_t, CLOCK_BOOTTIME, CLOCK_MONOTONIC,
+    CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID, ITIMER_PROF, ITIMER_REAL,
+    ITIMER_VIRTUAL, O_CLOEXEC, O_NONBLOCK, SI_KERNEL, SI_TIMER, SIGEV_NONE, SIGEV_SIGNAL,
+    SIGEV_THREAD_ID, TIMER_ABSTIME, itimerspec, itimerval, sigevent, sigval, timespec, timeval,
+};
+use starry_core::{
+    task::{ProcessData, ThreadData, get_thread},
+    timer::{IntervalTimer, drive_timer},
 };
-use starry_core::task::time_stat_output;
 
-use crate::{ptr::UserPtr, time::TimeValueLike};
+use crate::{
+    file::{FileLike, TimerFd, add_file_like},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    signal::{send_signal_process, send_signal_thread},
+    time::{TimeValueLike, set_wall_time, wall_time},
+};
 
-pub fn sys_clock_gettime(
-    clock_id: __kernel_clockid_t,
-    ts: UserPtr<timespec>,
-) -> LinuxResult<isize> {
-    let now = match clock_id as u32 {
-        CLOCK_REALTIME => wall_time(),
-        CLOCK_MONOTONIC => monotonic_time(),
+/// The user and kernel time of the current process, in nanoseconds.
+///
+/// The time of the other threads is as of their last switch between user
+/// and kernel mode.
+pub(crate) fn process_cpu_time() -> (usize, usize) {
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    let (mut utime_ns, mut stime_ns) = curr.task_ext().process_data().cpu_time.get();
+    for thr in thread.process().threads() {
+        let (thr_utime_ns, thr_stime_ns) = if Arc::ptr_eq(&thr, thread) {
+            curr.task_ext().time_stat_output()
+        } else {
+            thr.data::<ThreadData>()
+                .map_or((0, 0), |data| data.cpu_time.get())
+        };
+        utime_ns += thr_utime_ns;
+        stime_ns += thr_stime_ns;
+    }
+    (utime_ns, stime_ns)
+}
+
+/// Reads the clock `clock_id`.
+fn read_clock(clock_id: __kernel_clockid_t) -> LinuxResult<TimeValue> {
+    let (utime_ns, stime_ns) = match clock_id as u32 {
+        CLOCK_REALTIME => return Ok(wall_time()),
+        CLOCK_MONOTONIC => return Ok(monotonic_time()),
+        CLOCK_THREAD_CPUTIME_ID => current().task_ext().time_stat_output(),
+        CLOCK_PROCESS_CPUTIME_ID => process_cpu_time(),
         _ => {
-            warn!(
-                "Called sys_clock_gettime for unsupported clock {}",
-                clock_id
-            );
+            warn!("Unsupported clock {}", clock_id);
             return Err(LinuxError::EINVAL);
         }
     };
-    *ts.get_as_mut()? = timespec::from_time_value(now);
+    Ok(TimeValue::from_nanos((utime_ns + stime_ns) as _))
+}
+
+pub fn sys_clock_gettime(
+    clock_id: __kernel_clockid_t,
+    ts: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    *ts.get_as_mut()? = timespec::from_time_value(read_clock(clock_id)?);
+    Ok(0)
+}
+
+/// Get the resolution of the clock `clock_id`.
+///
+/// All the clocks are read from the same hardware counter, so they share its
+/// period as resolution.
+pub fn sys_clock_getres(
+    clock_id: __kernel_clockid_t,
+    res: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    read_clock(clock_id)?;
+    if let Some(res) = nullable!(res.get_as_mut())? {
+        let period = TimeValue::from_nanos(ticks_to_nanos(1).max(1));
+        *res = timespec::from_time_value(period);
+    }
     Ok(0)
 }
 
@@ -31,6 +89,45 @@ pub fn sys_gettimeofday(ts: UserPtr<timeval>) -> LinuxResult<isize> {
     Ok(0)
 }
 
+/// Set the clock `clock_id`, which can only be `CLOCK_REALTIME`.
+///
+/// There are no capabilities, so any process may set it.
+pub fn sys_clock_settime(
+    clock_id: __kernel_clockid_t,
+    tp: UserConstPtr<timespec>,
+) -> LinuxResult<isize> {
+    if clock_id as u32 != CLOCK_REALTIME {
+        return Err(LinuxError::EINVAL);
+    }
+    let ts = tp.get_as_ref()?;
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    set_wall_time(ts.to_time_value());
+    Ok(0)
+}
+
+/// Set `CLOCK_REALTIME` to `tv` if not NULL, like [`sys_clock_settime`].
+///
+/// The obsolete time zone is ignored.
+pub fn sys_settimeofday(tv: UserConstPtr<timeval>, _tz: usize) -> LinuxResult<isize> {
+    if let Some(tv) = nullable!(tv.get_as_ref())? {
+        if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+            return Err(LinuxError::EINVAL);
+        }
+        set_wall_time(tv.to_time_value());
+    }
+    Ok(0)
+}
+
+/// The frequency of the clock ticks `times` counts in, `sysconf(_SC_CLK_TCK)`.
+const USER_HZ: u64 = 100;
+
+/// Converts nanoseconds to clock ticks of [`USER_HZ`].
+fn nanos_to_clock_ticks(nanos: u64) -> usize {
+    (nanos / (NANOS_PER_SEC / USER_HZ)) as usize
+}
+
 #[repr(C)]
 pub struct Tms {
     /// user time
@@ -43,13 +140,415 @@ pub struct Tms {
     tms_cstime: usize,
 }
 
+/// Get the CPU time of the process and of its reaped children, in clock
+/// ticks.
+///
+/// Returns the clock ticks elapsed since boot.
 pub fn sys_times(tms: UserPtr<Tms>) -> LinuxResult<isize> {
-    let (_, utime_us, _, stime_us) = time_stat_output();
-    *tms.get_as_mut()? = Tms {
-        tms_utime: utime_us,
-        tms_stime: stime_us,
-        tms_cutime: utime_us,
-        tms_cstime: stime_us,
-    };
-    Ok(nanos_to_ticks(monotonic_time_nanos()) as _)
+    let (utime_ns, stime_ns) = process_cpu_time();
+    let (cutime_ns, cstime_ns) = current().task_ext().process_data().children_cpu_time.get();
+    if let Some(tms) = nullable!(tms.get_as_mut())? {
+        *tms = Tms {
+            tms_utime: nanos_to_clock_ticks(utime_ns as _),
+            tms_stime: nanos_to_clock_ticks(stime_ns as _),
+            tms_cutime: nanos_to_clock_ticks(cutime_ns as _),
+            tms_cstime: nanos_to_clock_ticks(cstime_ns as _),
+        };
+    }
+    Ok(nanos_to_clock_ticks(monotonic_time_nanos()) as _)
+}
+
+/// Where the signal of a timer goes.
+enum TimerTarget {
+    Process(Weak<Process>),
+    Thread(Weak<Thread>),
+}
+
+/// What to do when a timer expires, as given by its `sigevent`.
+struct TimerEvent {
+    /// The ID of the timer, or `None` for the `ITIMER_REAL` timer.
+    id: Option<i32>,
+    /// The signal to send, or `None` for `SIGEV_NONE`.
+    signo: Option<Signo>,
+    target: TimerTarget,
+    /// The `sigev_value` passed on in `si_value`.
+    value: usize,
+}
+
+impl TimerEvent {
+    /// Notifies the target of an expiration after `missed` periods went by
+    /// unnoticed.
+    ///
+    /// If the signal of a previous expiration is still pending, no new one is
+    /// sent and the expirations count as overruns instead. Returns `false`
+    /// once the target is gone.
+    fn notify(&self, timer: &IntervalTimer, missed: u64) -> bool {
+        let Some(signo) = self.signo else {
+            return true;
+        };
+        let sig = match self.id {
+            Some(id) => {
+                let mut sig = SignalInfo::new(signo, SI_TIMER);
+                // SAFETY: `_timer` is the active member for `SI_TIMER`.
+                unsafe {
+                    let info = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._timer;
+                    info._tid = id;
+                    info._overrun = missed.min(i32::MAX as u64) as _;
+                    info._sigval = sigval {
+                        sival_ptr: self.value as _,
+                    };
+                }
+                sig
+            }
+            None => SignalInfo::new(signo, SI_KERNEL as _),
+        };
+
+        match &self.target {
+            TimerTarget::Process(proc) => {
+                let Some(proc) = proc.upgrade() else {
+                    return false;
+                };
+                let Some(proc_data) = proc.data::<ProcessData>() else {
+                    return false;
+                };
+                if proc_data.signal.pending().has(signo) {
+                    timer.add_overrun(missed + 1);
+                } else {
+                    timer.set_overrun(missed);
+                    let _ = send_signal_process(&proc, sig);
+                }
+            }
+            TimerTarget::Thread(thr) => {
+                let Some(thr) = thr.upgrade() else {
+                    return false;
+                };
+                let Some(thr_data) = thr.data::<ThreadData>() else {
+                    return false;
+                };
+                if thr_data.signal.pending().has(signo) {
+                    timer.add_overrun(missed + 1);
+                } else {
+                    timer.set_overrun(missed);
+                    let _ = send_signal_thread(&thr, sig);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Parses the `sigevent` of a new timer.
+///
+/// Without one, the timer sends `SIGALRM` to the process with its ID as the
+/// value.
+fn parse_sigevent(id: i32, sevp: UserConstPtr<sigevent>) -> LinuxResult<TimerEvent> {
+    let curr = current();
+    let proc = Arc::downgrade(curr.task_ext().thread.process());
+    let Some(sev) = nullable!(sevp.get_as_ref())? else {
+        return Ok(TimerEvent {
+            id: Some(id),
+            signo: Some(Signo::SIGALRM),
+            target: TimerTarget::Process(proc),
+            value: id as usize,
+        });
+    };
+
+    let parse_signo = || Signo::from_repr(sev.sigev_signo as u8).ok_or(LinuxError::EINVAL);
+    // SAFETY: every member of `sigval` is plain data.
+    let value = unsafe { sev.sigev_value.sival_ptr } as usize;
+    let (signo, target) = match sev.sigev_notify as u32 {
+        SIGEV_NONE => (None, TimerTarget::Process(proc)),
+        SIGEV_SIGNAL => (Some(parse_signo()?), TimerTarget::Process(proc)),
+        SIGEV_THREAD_ID => {
+            // SAFETY: `_tid` is the active member for `SIGEV_THREAD_ID`.
+            let tid = unsafe { sev._sigev_un._tid };
+            let thr = get_thread(tid as _).map_err(|_| LinuxError::EINVAL)?;
+            if thr.process().pid() != curr.task_ext().thread.process().pid() {
+                return Err(LinuxError::EINVAL);
+            }
+            (
+                Some(parse_signo()?),
+                TimerTarget::Thread(Arc::downgrade(&thr)),
+            )
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    Ok(TimerEvent {
+        id: Some(id),
+        signo,
+        target,
+        value,
+    })
+}
+
+/// Notify `event` on the expirations of `timer`, until the timer is deleted
+/// or the target is gone.
+fn start_timer(timer: Arc<IntervalTimer>, event: TimerEvent) {
+    drive_timer(timer, move |timer, missed| event.notify(timer, missed));
+}
+
+fn itimerspec_to_time_values(spec: &itimerspec) -> LinuxResult<(TimeValue, TimeValue)> {
+    for ts in [&spec.it_value, &spec.it_interval] {
+        if ts.tv_nsec < 0 || ts.tv_nsec > 999_999_999 || ts.tv_sec < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    Ok((
+        spec.it_value.to_time_value(),
+        spec.it_interval.to_time_value(),
+    ))
+}
+
+fn itimerspec_from_time_values((value, interval): (TimeValue, TimeValue)) -> itimerspec {
+    itimerspec {
+        it_interval: timespec::from_time_value(interval),
+        it_value: timespec::from_time_value(value),
+    }
+}
+
+/// Create a POSIX interval timer measuring `clock_id`.
+///
+/// The timer starts disarmed. The kernel task driving all timers delivers the
+/// signal described by `sevp` on its expirations until it is deleted.
+pub fn sys_timer_create(
+    clock_id: __kernel_clockid_t,
+    sevp: UserConstPtr<sigevent>,
+    timerid: UserPtr<__kernel_timer_t>,
+) -> LinuxResult<isize> {
+    let clock = match clock_id as u32 {
+        CLOCK_REALTIME => wall_time,
+        CLOCK_MONOTONIC => monotonic_time,
+        _ => {
+            warn!("Called sys_timer_create for unsupported clock {}", clock_id);
+            return Err(LinuxError::EINVAL);
+        }
+    };
+    let timerid = timerid.get_as_mut()?;
+
+    let timers = &current().task_ext().process_data().timers;
+    let timer = Arc::new(IntervalTimer::new(clock));
+    let id = timers.add(timer.clone())?;
+    let event = match parse_sigevent(id, sevp) {
+        Ok(event) => event,
+        Err(err) => {
+            timers.remove(id)?;
+            return Err(err);
+        }
+    };
+    *timerid = id;
+
+    start_timer(timer, event);
+    Ok(0)
+}
+
+/// Arm or disarm a timer, reporting the previous setting in `old_value`.
+///
+/// A zero `it_value` disarms the timer. With `TIMER_ABSTIME`, `it_value` is
+/// a point in time on the clock of the timer rather than a delay.
+pub fn sys_timer_settime(
+    timerid: __kernel_timer_t,
+    flags: u32,
+    new_value: UserConstPtr<itimerspec>,
+    old_value: UserPtr<itimerspec>,
+) -> LinuxResult<isize> {
+    let timer = current().task_ext().process_data().timers.get(timerid)?;
+    let (value, interval) = itimerspec_to_time_values(new_value.get_as_ref()?)?;
+    let deadline = if value.is_zero() {
+        None
+    } else if flags & TIMER_ABSTIME != 0 {
+        Some(value)
+    } else {
+        Some(timer.now() + value)
+    };
+    let old = timer.set(deadline, interval);
+    if let Some(old_value) = nullable!(old_value.get_as_mut())? {
+        *old_value = itimerspec_from_time_values(old);
+    }
+    Ok(0)
+}
+
+/// Get the time until the next expiration of a timer and its interval.
+pub fn sys_timer_gettime(
+    timerid: __kernel_timer_t,
+    curr_value: UserPtr<itimerspec>,
+) -> LinuxResult<isize> {
+    let timer = current().task_ext().process_data().timers.get(timerid)?;
+    *curr_value.get_as_mut()? = itimerspec_from_time_values(timer.get());
+    Ok(0)
+}
+
+/// Get the number of expirations of a timer that were merged into the last
+/// signal delivered for it.
+pub fn sys_timer_getoverrun(timerid: __kernel_timer_t) -> LinuxResult<isize> {
+    let timer = current().task_ext().process_data().timers.get(timerid)?;
+    Ok(timer.overrun() as _)
+}
+
+/// Delete a timer, cancelling its pending expiration.
+pub fn sys_timer_delete(timerid: __kernel_timer_t) -> LinuxResult<isize> {
+    current().task_ext().process_data().timers.remove(timerid)?;
+    Ok(0)
+}
+
+/// Report `ECANCELED` on reads once `CLOCK_REALTIME` is set.
+const TFD_TIMER_CANCEL_ON_SET: u32 = 2;
+
+/// Create a timerfd measuring `clock_id`, which starts disarmed.
+pub fn sys_timerfd_create(clock_id: __kernel_clockid_t, flags: u32) -> LinuxResult<isize> {
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let realtime = match clock_id as u32 {
+        CLOCK_REALTIME => true,
+        // Nothing is ever suspended, so the two clocks are the same.
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => false,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let timerfd = TimerFd::new(realtime, flags & O_NONBLOCK != 0);
+    let fd = add_file_like(Arc::new(timerfd), flags & O_CLOEXEC != 0)?;
+    Ok(fd as _)
+}
+
+/// Arm or disarm a timerfd like [`sys_timer_settime`].
+///
+/// `TFD_TIMER_CANCEL_ON_SET` applies to an absolute `CLOCK_REALTIME` timer,
+/// and is ignored otherwise.
+pub fn sys_timerfd_settime(
+    fd: c_int,
+    flags: u32,
+    new_value: UserConstPtr<itimerspec>,
+    old_value: UserPtr<itimerspec>,
+) -> LinuxResult<isize> {
+    if flags & !(TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let timerfd = TimerFd::from_fd(fd)?;
+    let (value, interval) = itimerspec_to_time_values(new_value.get_as_ref()?)?;
+    let absolute = flags & TIMER_ABSTIME != 0;
+    let deadline = if value.is_zero() {
+        None
+    } else if absolute {
+        Some(value)
+    } else {
+        Some(timerfd.now() + value)
+    };
+    let cancel_on_set = absolute && flags & TFD_TIMER_CANCEL_ON_SET != 0;
+    let old = timerfd.set(deadline, interval, cancel_on_set);
+    if let Some(old_value) = nullable!(old_value.get_as_mut())? {
+        *old_value = itimerspec_from_time_values(old);
+    }
+    Ok(0)
+}
+
+/// Get the time until the next expiration of a timerfd and its interval.
+pub fn sys_timerfd_gettime(fd: c_int, curr_value: UserPtr<itimerspec>) -> LinuxResult<isize> {
+    let timerfd = TimerFd::from_fd(fd)?;
+    *curr_value.get_as_mut()? = itimerspec_from_time_values(timerfd.get());
+    Ok(0)
+}
+
+/// Returns the `ITIMER_REAL` timer of the current process, creating it on
+/// first use.
+fn real_timer() -> Arc<IntervalTimer> {
+    let curr = current();
+    let proc = curr.task_ext().thread.process();
+    let proc_data = proc.data::<ProcessData>().unwrap();
+    proc_data
+        .real_timer
+        .call_once(|| {
+            let timer = Arc::new(IntervalTimer::new(monotonic_time));
+            let event = TimerEvent {
+                id: None,
+                signo: Some(Signo::SIGALRM),
+                target: TimerTarget::Process(Arc::downgrade(proc)),
+                value: 0,
+            };
+            start_timer(timer.clone(), event);
+            timer
+        })
+        .clone()
+}
+
+fn itimerval_to_time_values(val: &itimerval) -> LinuxResult<(TimeValue, TimeValue)> {
+    for tv in [&val.it_value, &val.it_interval] {
+        if tv.tv_usec < 0 || tv.tv_usec > 999_999 || tv.tv_sec < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    Ok((
+        val.it_value.to_time_value(),
+        val.it_interval.to_time_value(),
+    ))
+}
+
+fn itimerval_from_time_values((value, interval): (TimeValue, TimeValue)) -> itimerval {
+    itimerval {
+        it_interval: timeval::from_time_value(interval),
+        it_value: timeval::from_time_value(value),
+    }
+}
+
+/// Converts the interval and time left of a CPU time timer, in nanoseconds,
+/// to the time left and interval.
+fn cpu_timer_setting((interval_ns, remained_ns): (usize, usize)) -> (TimeValue, TimeValue) {
+    (
+        TimeValue::from_nanos(remained_ns as _),
+        TimeValue::from_nanos(interval_ns as _),
+    )
+}
+
+/// Get the time until the next expiration of the interval timer `which` and
+/// its interval.
+pub fn sys_getitimer(which: u32, curr_value: UserPtr<itimerval>) -> LinuxResult<isize> {
+    let curr = current();
+    let setting = match which {
+        ITIMER_REAL => curr
+            .task_ext()
+            .process_data()
+            .real_timer
+            .get()
+            .map_or((TimeValue::ZERO, TimeValue::ZERO), |it| it.get()),
+        ITIMER_VIRTUAL | ITIMER_PROF => {
+            cpu_timer_setting(curr.task_ext().cpu_timer(which).ok_or(LinuxError::EINVAL)?)
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    *curr_value.get_as_mut()? = itimerval_from_time_values(setting);
+    Ok(0)
+}
+
+/// Arm or disarm the interval timer `which`, reporting the previous setting
+/// in `old_value`.
+///
+/// - `ITIMER_REAL` counts real time and sends `SIGALRM` to the process.
+/// - `ITIMER_VIRTUAL` counts the user time of the thread and sends it
+///   `SIGVTALRM`.
+/// - `ITIMER_PROF` counts the user and kernel time of the thread and sends it
+///   `SIGPROF`.
+///
+/// CPU time is only accounted on system calls, so the CPU time timers expire
+/// on the first system call after their time is up.
+pub fn sys_setitimer(
+    which: u32,
+    new_value: UserConstPtr<itimerval>,
+    old_value: UserPtr<itimerval>,
+) -> LinuxResult<isize> {
+    let (value, interval) = itimerval_to_time_values(new_value.get_as_ref()?)?;
+    let curr = current();
+    let old = match which {
+        ITIMER_REAL => {
+            let deadline = (!value.is_zero()).then(|| monotonic_time() + value);
+            real_timer().set(deadline, interval)
+        }
+        ITIMER_VIRTUAL | ITIMER_PROF => cpu_timer_setting(
+            curr.task_ext()
+                .set_cpu_timer(which, interval.as_nanos() as _, value.as_nanos() as _)
+                .ok_or(LinuxError::EINVAL)?,
+        ),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    if let Some(old_value) = nullable!(old_value.get_as_mut())? {
+        *old_value = itimerval_from_time_values(old);
+    }
+    Ok(0)
 }
@@ -8,6 +8,7 @@ extern crate alloc;
 pub mod file;
 pub mod path;
 pub mod ptr;
+pub mod random;
 pub mod signal;
 pub mod sockaddr;
 pub mod time;
@@ -2,14 +2,19 @@ use core::{ffi::c_int, fmt, ops::Deref};
 
 use alloc::{
     collections::btree_map::BTreeMap,
+    format,
     string::{String, ToString},
 };
 use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
 use axfs::api::canonicalize;
+use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::AT_FDCWD;
 use spin::RwLock;
 
-use crate::file::{Directory, File, FileLike};
+use crate::{
+    file::{Directory, File, FileLike, remove_file},
+    resolve_bind,
+};
 
 /// 一个规范化的文件路径表示
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
@@ -94,7 +99,8 @@ impl FilePath {
 
     /// Whether the path exists
     pub fn exists(&self) -> bool {
-        axfs::api::absolute_path_exists(&self.0)
+        let path = resolve_bind(&self.0);
+        axfs::api::absolute_path_exists(path.as_deref().unwrap_or(&self.0))
     }
 
     /// 判断此路径是否以给定前缀路径开头
@@ -220,11 +226,8 @@ impl HardlinkManager {
     /// 否则返回链接的目标路径
     pub fn remove_link(&self, src: &FilePath) -> Option<String> {
         let mut inner = self.inner.write();
-        self.atomic_link_remove(&mut inner, src).or_else(|| {
-            axfs::api::remove_file(src.as_str())
-                .ok()
-                .map(|_| src.to_string())
-        })
+        self.atomic_link_remove(&mut inner, src)
+            .or_else(|| remove_file(src.as_str()).ok().map(|_| src.to_string()))
     }
 
     pub fn real_path(&self, path: &str) -> String {
@@ -278,7 +281,7 @@ impl HardlinkManager {
                 *count -= 1;
                 if *count == 0 {
                     inner.ref_counts.remove(path);
-                    axfs::api::remove_file(path).ok()?
+                    remove_file(path).ok()?
                 }
                 Some(())
             }
@@ -290,17 +293,66 @@ impl HardlinkManager {
     }
 }
 
+/// The root directory of the calling process, without a trailing slash, so
+/// empty if it is `/`.
+pub fn current_root() -> String {
+    let curr = current();
+    let root = curr.task_ext().process_data().root.read();
+    root.trim_end_matches('/').into()
+}
+
+/// `path` as seen from `root`, or `None` if it is outside.
+pub fn path_in_root<'a>(root: &str, path: &'a str) -> Option<&'a str> {
+    match path.strip_prefix(root)? {
+        "" => Some("/"),
+        rest if rest.starts_with('/') => Some(rest),
+        _ => None,
+    }
+}
+
+/// Join `path` to the directory `base`, where `/` is `root`.
+///
+/// Neither an absolute `path` nor `..` can leave `root`, unless `base` is
+/// already outside it, as when it was opened before `chroot`.
+fn join_in_root(root: &str, base: &str, path: &str) -> AxResult<String> {
+    let path = match (path.starts_with('/'), path_in_root(root, base)) {
+        (true, _) => canonicalize(path)?,
+        (false, Some(base)) => canonicalize(format!("{base}/{path}"))?,
+        (false, None) => return Ok(format!("{base}/{path}")),
+    };
+    Ok(format!("{root}{path}"))
+}
+
+/// Resolve `path` relative to the directory `dirfd`, or to the file `dirfd`
+/// itself if it is empty, under the root of the calling process.
+///
+/// Paths below a bind mount resolve to the same places below its source.
 pub fn handle_file_path(dirfd: c_int, path: &str) -> LinuxResult<FilePath> {
-    if path.starts_with('/') {
-        Ok(FilePath::new(path)?)
-    } else if path.is_empty() {
-        Ok(FilePath::new(File::from_fd(dirfd)?.path())?)
+    let path = resolve_path(dirfd, path)?;
+    Ok(FilePath::new(resolve_bind(&path).unwrap_or(path))?)
+}
+
+/// Resolve `path` as a mount point, like [`handle_file_path`] relative to
+/// the current directory, but naming a bind mount itself instead of what it
+/// binds.
+pub fn handle_mount_point(path: &str) -> LinuxResult<FilePath> {
+    Ok(FilePath::new(resolve_path(AT_FDCWD, path)?)?)
+}
+
+fn resolve_path(dirfd: c_int, path: &str) -> LinuxResult<String> {
+    if path.is_empty() {
+        return Ok(File::from_fd(dirfd)?.path());
+    }
+    let base = if path.starts_with('/') {
+        String::new()
+    } else if dirfd == AT_FDCWD {
+        axfs::api::current_dir()?
     } else {
-        let base = if dirfd == AT_FDCWD {
-            FilePath::new("")?
-        } else {
-            FilePath::new(Directory::from_fd(dirfd)?.path())?
-        };
-        Ok(base.join(path)?)
+        Directory::from_fd(dirfd)?.path()
+    };
+    let mut real = join_in_root(&current_root(), base.trim_end_matches('/'), path)?;
+    if path.ends_with('/') && !real.ends_with('/') {
+        real.push('/');
     }
+    Ok(real)
 }
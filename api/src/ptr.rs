@@ -3,12 +3,20 @@ use core::{alloc::Layout, ffi::c_char, mem::transmute, ptr, slice, str};
 use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
 use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::PATH_MAX;
 use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
 use starry_core::mm::access_user_memory;
 
+/// Check that the region of `layout` at `start` is accessible with
+/// `access_flags`, and populate it.
+///
+/// A null pointer is not special here, it fails with `EFAULT` like any other
+/// unmapped address. Syscalls for which null means to omit an argument check
+/// for it first with `nullable!`.
 fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) -> LinuxResult<()> {
     let align = layout.align();
-    if start.as_usize() & (align - 1) != 0 {
+    if start.as_usize() & (align - 1) != 0 || start.as_usize().checked_add(layout.size()).is_none()
+    {
         return Err(LinuxError::EFAULT);
     }
 
@@ -29,9 +37,20 @@ fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) ->
     Ok(())
 }
 
+/// The longest string or array read up to its terminator, unless a tighter
+/// limit applies. The same as Linux's `MAX_ARG_STRLEN`.
+const MAX_NULL_TERMINATED_LEN: usize = 32 * PAGE_SIZE_4K;
+
+/// Find the length of the array at `start` up to its terminator, checking
+/// that it is accessible with `access_flags`.
+///
+/// Fails with `ENAMETOOLONG` if there is no terminator among the first
+/// `max_len` elements, so an unterminated string is not read until it runs
+/// into an unmapped page.
 fn check_null_terminated<T: PartialEq + Default>(
     start: VirtAddr,
     access_flags: MappingFlags,
+    max_len: usize,
 ) -> LinuxResult<usize> {
     let align = Layout::new::<T>().align();
     if start.as_usize() & (align - 1) != 0 {
@@ -76,6 +95,9 @@ fn check_null_terminated<T: PartialEq + Default>(
                 break;
             }
             len += 1;
+            if len >= max_len {
+                return Err(LinuxError::ENAMETOOLONG);
+            }
         }
         Ok(())
     })?;
@@ -119,7 +141,7 @@ impl<T> UserPtr<T> {
     pub fn get_as_mut_slice(self, len: usize) -> LinuxResult<&'static mut [T]> {
         check_region(
             self.address(),
-            Layout::array::<T>(len).unwrap(),
+            Layout::array::<T>(len).map_err(|_| LinuxError::EFAULT)?,
             Self::ACCESS_FLAGS,
         )?;
         Ok(unsafe { slice::from_raw_parts_mut(self.0, len) })
@@ -129,7 +151,11 @@ impl<T> UserPtr<T> {
     where
         T: PartialEq + Default,
     {
-        let len = check_null_terminated::<T>(self.address(), Self::ACCESS_FLAGS)?;
+        let len = check_null_terminated::<T>(
+            self.address(),
+            Self::ACCESS_FLAGS,
+            MAX_NULL_TERMINATED_LEN,
+        )?;
         Ok(unsafe { slice::from_raw_parts_mut(self.0, len) })
     }
 }
@@ -170,7 +196,7 @@ impl<T> UserConstPtr<T> {
     pub fn get_as_slice(self, len: usize) -> LinuxResult<&'static [T]> {
         check_region(
             self.address(),
-            Layout::array::<T>(len).unwrap(),
+            Layout::array::<T>(len).map_err(|_| LinuxError::EFAULT)?,
             Self::ACCESS_FLAGS,
         )?;
         Ok(unsafe { slice::from_raw_parts(self.0, len) })
@@ -180,15 +206,35 @@ impl<T> UserConstPtr<T> {
     where
         T: PartialEq + Default,
     {
-        let len = check_null_terminated::<T>(self.address(), Self::ACCESS_FLAGS)?;
+        let len = check_null_terminated::<T>(
+            self.address(),
+            Self::ACCESS_FLAGS,
+            MAX_NULL_TERMINATED_LEN,
+        )?;
         Ok(unsafe { slice::from_raw_parts(self.0, len) })
     }
 }
 
 impl UserConstPtr<c_char> {
     /// Get the pointer as `&str`, validating the memory region.
+    ///
+    /// Fails with `ENAMETOOLONG` if the string is `MAX_ARG_STRLEN` bytes or
+    /// longer.
     pub fn get_as_str(self) -> LinuxResult<&'static str> {
-        let slice = self.get_as_null_terminated()?;
+        self.get_as_str_limited(MAX_NULL_TERMINATED_LEN)
+    }
+
+    /// Get the pointer as a path, like [`Self::get_as_str`] but failing with
+    /// `ENAMETOOLONG` if it does not fit in `PATH_MAX` bytes with its
+    /// terminator.
+    pub fn get_as_path(self) -> LinuxResult<&'static str> {
+        self.get_as_str_limited(PATH_MAX as usize)
+    }
+
+    fn get_as_str_limited(self, max_len: usize) -> LinuxResult<&'static str> {
+        let len = check_null_terminated::<c_char>(self.address(), Self::ACCESS_FLAGS, max_len)?;
+        // SAFETY: The region was checked above.
+        let slice = unsafe { slice::from_raw_parts(self.0, len) };
         // SAFETY: c_char is u8
         let slice = unsafe { transmute::<&[c_char], &[u8]>(slice) };
 
@@ -0,0 +1,178 @@
+//! The source of random bytes behind `getrandom` and `/dev/random`.
+//!
+//! The output is drawn from ChaCha20, whose key is replaced by the first
+//! block of every request ("fast key erasure"), so that earlier output cannot
+//! be recovered from the current key.
+//!
+//! The key is seeded at boot from the hardware random number generator of
+//! the CPU where there is one, the cycle counter and the real-time clock.
+//! Every request then mixes in the cycle counter and the timing of the traps
+//! taken since the last one.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axhal::time::{current_ticks, monotonic_time_nanos, wall_time_nanos};
+use axsync::Mutex;
+
+/// The ChaCha20 constant, "expand 32-byte k".
+const SIGMA: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+static KEY: Mutex<[u32; 8]> = Mutex::new([0; 8]);
+
+/// The cycle counter at the traps taken since the last request, folded
+/// together.
+static TRAP_TIMING: AtomicU64 = AtomicU64::new(0);
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// Computes the ChaCha20 block `counter` for `key` and `nonce`, see RFC 8439.
+fn chacha20_block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u32; 16] {
+    let mut state = [0; 16];
+    state[..4].copy_from_slice(&SIGMA);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter;
+    state[13..].copy_from_slice(nonce);
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for (x, state) in x.iter_mut().zip(state) {
+        *x = x.wrapping_add(state);
+    }
+    x
+}
+
+/// XORs `input` into `key`, then replaces it with a block computed from it,
+/// so that every bit of the input affects all of the key.
+fn mix(key: &mut [u32; 8], input: &[u64]) {
+    for (i, value) in input.iter().enumerate() {
+        key[i * 2 % 8] ^= *value as u32;
+        key[(i * 2 + 1) % 8] ^= (*value >> 32) as u32;
+    }
+    let block = chacha20_block(key, 0, &[0; 3]);
+    key.copy_from_slice(&block[..8]);
+}
+
+/// Reads a random number from the CPU, preferring `RDSEED`, which draws
+/// straight from its entropy source, to `RDRAND`.
+#[cfg(target_arch = "x86_64")]
+#[allow(unused_unsafe)] // CPUID is only safe to call in newer toolchains.
+fn hardware_random() -> Option<u64> {
+    use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+    // SAFETY: CPUID is always there on x86_64.
+    let (max_leaf, leaf1_ecx) = unsafe { (__cpuid(0).eax, __cpuid(1).ecx) };
+    // SAFETY: as above, and leaf 7 is only read where it exists.
+    if max_leaf >= 7 && unsafe { __cpuid_count(7, 0) }.ebx & (1 << 18) != 0 {
+        // SAFETY: CPUID reports `RDSEED`.
+        if let Some(value) = unsafe { rdseed() } {
+            return Some(value);
+        }
+    }
+    if leaf1_ecx & (1 << 30) != 0 {
+        // SAFETY: CPUID reports `RDRAND`.
+        return unsafe { rdrand() };
+    }
+    None
+}
+
+/// There is no random number instruction to rely on elsewhere.
+#[cfg(not(target_arch = "x86_64"))]
+fn hardware_random() -> Option<u64> {
+    None
+}
+
+/// Runs `RDSEED` until it succeeds, at most 10 times, as it fails while the
+/// entropy source catches up.
+///
+/// # Safety
+///
+/// The CPU must support `RDSEED`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "rdseed")]
+#[allow(unused_unsafe)]
+unsafe fn rdseed() -> Option<u64> {
+    let mut value = 0;
+    (0..10).find_map(|_| {
+        // SAFETY: the caller makes sure that the CPU supports it.
+        let ok = unsafe { core::arch::x86_64::_rdseed64_step(&mut value) };
+        (ok == 1).then_some(value)
+    })
+}
+
+/// Runs `RDRAND` until it succeeds, at most 10 times, like [`rdseed`].
+///
+/// # Safety
+///
+/// The CPU must support `RDRAND`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "rdrand")]
+#[allow(unused_unsafe)]
+unsafe fn rdrand() -> Option<u64> {
+    let mut value = 0;
+    (0..10).find_map(|_| {
+        // SAFETY: the caller makes sure that the CPU supports it.
+        let ok = unsafe { core::arch::x86_64::_rdrand64_step(&mut value) };
+        (ok == 1).then_some(value)
+    })
+}
+
+/// Seeds the key, once at boot before any user task runs.
+pub fn seed_random() {
+    let mut input = [0; 7];
+    for value in &mut input[..4] {
+        *value = hardware_random().unwrap_or_default();
+    }
+    input[4] = current_ticks();
+    input[5] = wall_time_nanos();
+    input[6] = monotonic_time_nanos();
+    mix(&mut KEY.lock(), &input);
+}
+
+/// Folds the cycle counter into the input of the next request, on every
+/// trap.
+pub fn add_trap_timing() {
+    let ticks = current_ticks();
+    let _ = TRAP_TIMING.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |timing| {
+        Some(timing.rotate_left(7) ^ ticks)
+    });
+}
+
+/// Fills `buf` with random bytes.
+pub fn fill_random(buf: &mut [u8]) {
+    let now = monotonic_time_nanos();
+    let nonce = [now as u32, (now >> 32) as u32, 0];
+
+    let mut next_key = KEY.lock();
+    let timing = TRAP_TIMING.swap(0, Ordering::Relaxed);
+    mix(&mut next_key, &[timing, current_ticks()]);
+    let key = *next_key;
+    let block = chacha20_block(&key, 0, &nonce);
+    next_key.copy_from_slice(&block[..8]);
+    drop(next_key);
+
+    for (counter, chunk) in (1..).zip(buf.chunks_mut(64)) {
+        let block = chacha20_block(&key, counter, &nonce);
+        let bytes = block.iter().flat_map(|word| word.to_le_bytes());
+        for (byte, random) in chunk.iter_mut().zip(bytes) {
+            *byte = random;
+        }
+    }
+}
@@ -1,15 +1,23 @@
+use core::{ffi::c_ulong, time::Duration};
+
 use axerrno::{LinuxError, LinuxResult};
 use axhal::{
     arch::TrapFrame,
+    time::monotonic_time,
     trap::{POST_TRAP, register_trap_handler},
 };
 use axprocess::{Process, ProcessGroup, Thread};
-use axsignal::{SignalInfo, SignalOSAction, SignalSet};
+use axsignal::{SignalInfo, SignalOSAction, SignalSet, Signo};
 use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{CLD_CONTINUED, CLD_STOPPED, SA_NOCLDSTOP, kernel_sigaction};
 use starry_core::task::{ProcessData, ThreadData};
 
 use crate::do_exit;
 
+// The `sa_handler` values of the default and ignoring dispositions.
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
 pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) -> bool {
     let Some((sig, os_action)) = current()
         .task_ext()
@@ -23,18 +31,17 @@ pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) ->
     let signo = sig.signo();
     match os_action {
         SignalOSAction::Terminate => {
-            do_exit(128 + signo as i32, true);
+            do_exit(signo as i32, true);
         }
         SignalOSAction::CoreDump => {
-            // TODO: implement core dump
-            do_exit(128 + signo as i32, true);
+            // TODO: implement core dump, and report it with `WCOREFLAG`
+            do_exit(signo as i32, true);
         }
         SignalOSAction::Stop => {
-            // TODO: implement stop
-            do_exit(1, true);
+            stop_current_process(signo);
         }
         SignalOSAction::Continue => {
-            // TODO: implement continue
+            // The process was resumed when the signal was sent.
         }
         SignalOSAction::Handler => {
             // do nothing
@@ -43,30 +50,106 @@ pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) ->
     true
 }
 
+/// Notify the parent of `proc` that it stopped or continued.
+///
+/// `SIGCHLD` is only sent if the parent did not set `SA_NOCLDSTOP`.
+fn notify_parent(proc: &Process, code: u32, status: i32) {
+    let Some(parent) = proc.parent() else {
+        return;
+    };
+    let Some(parent_data) = parent.data::<ProcessData>() else {
+        return;
+    };
+    parent_data.child_exit_wq.notify_all(false);
+
+    // SAFETY: valid for kernel_sigaction
+    let mut action: kernel_sigaction = unsafe { core::mem::zeroed() };
+    parent_data.signal.actions.lock()[Signo::SIGCHLD].to_ctype(&mut action);
+    if action.sa_flags & SA_NOCLDSTOP as c_ulong != 0 {
+        return;
+    }
+    let mut sig = SignalInfo::new(Signo::SIGCHLD, code as _);
+    // SAFETY: `_sigchld` is the active member for `SIGCHLD`.
+    unsafe {
+        let sigchld = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigchld;
+        sigchld._pid = proc.pid() as _;
+        sigchld._status = status;
+    }
+    let _ = send_signal_process(&parent, sig);
+}
+
+/// Stop the current process on `signo`, until it is resumed by `SIGCONT` or
+/// `SIGKILL`.
+fn stop_current_process(signo: Signo) {
+    let curr = current();
+    if curr.task_ext().process_data().stop(signo) {
+        notify_parent(curr.task_ext().thread.process(), CLD_STOPPED, signo as _);
+    }
+    wait_while_stopped();
+}
+
+/// Block the current thread while its process is stopped.
+///
+/// The thread that dequeues a stop signal stops right away, while the other
+/// threads of the process stop the next time they return to user space.
+fn wait_while_stopped() {
+    let curr = current();
+    let proc_data = curr.task_ext().process_data();
+    proc_data.stop_wq.wait_until(|| !proc_data.is_stopped());
+}
+
+/// Resume `proc` if it is stopped, as sending it `SIGCONT` or `SIGKILL` does.
+fn resume_process(proc: &Process, signo: Signo) {
+    let Some(proc_data) = proc.data::<ProcessData>() else {
+        return;
+    };
+    if proc_data.resume() && signo == Signo::SIGCONT {
+        notify_parent(proc, CLD_CONTINUED, Signo::SIGCONT as _);
+    }
+}
+
 #[register_trap_handler(POST_TRAP)]
 fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
+    crate::random::add_trap_timing();
     if !from_user {
         return;
     }
 
+    wait_while_stopped();
     check_signals(tf, None);
+    crate::sync_sched_attrs();
+
+    #[cfg(target_arch = "x86_64")]
+    crate::restore_user_gs_base();
 }
 
 pub fn send_signal_thread(thr: &Thread, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to thread {}", sig.signo(), thr.tid());
-    let Some(thr) = thr.data::<ThreadData>() else {
+    let Some(thr_data) = thr.data::<ThreadData>() else {
         return Err(LinuxError::EPERM);
     };
-    thr.signal.send_signal(sig);
+    if matches!(sig.signo(), Signo::SIGCONT | Signo::SIGKILL) {
+        resume_process(thr.process(), sig.signo());
+    }
+    thr_data.signal.send_signal(sig);
+    thr_data.interrupt();
     Ok(())
 }
 
 pub fn send_signal_process(proc: &Process, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to process {}", sig.signo(), proc.pid());
-    let Some(proc) = proc.data::<ProcessData>() else {
+    let Some(proc_data) = proc.data::<ProcessData>() else {
         return Err(LinuxError::EPERM);
     };
-    proc.signal.send_signal(sig);
+    if matches!(sig.signo(), Signo::SIGCONT | Signo::SIGKILL) {
+        resume_process(proc, sig.signo());
+    }
+    proc_data.signal.send_signal(sig);
+    for thr in proc.threads() {
+        if let Some(thr_data) = thr.data::<ThreadData>() {
+            thr_data.interrupt();
+        }
+    }
     Ok(())
 }
 
@@ -82,3 +165,135 @@ pub fn send_signal_process_group(pg: &ProcessGroup, sig: SignalInfo) -> usize {
     }
     count
 }
+
+/// Returns the `sa_handler` of `signo` in the current process.
+fn signal_handler(signo: Signo) -> usize {
+    // SAFETY: valid for kernel_sigaction
+    let mut action: kernel_sigaction = unsafe { core::mem::zeroed() };
+    let curr = current();
+    curr.task_ext().process_data().signal.actions.lock()[signo].to_ctype(&mut action);
+    action.sa_handler_kernel.map_or(SIG_DFL, |it| it as usize)
+}
+
+/// Whether `signo` would be discarded if delivered to the current process.
+fn signal_ignored(signo: Signo) -> bool {
+    match signal_handler(signo) {
+        SIG_DFL => matches!(
+            signo,
+            Signo::SIGCHLD | Signo::SIGURG | Signo::SIGWINCH | Signo::SIGCONT
+        ),
+        SIG_IGN => true,
+        _ => false,
+    }
+}
+
+/// Whether `signo` should interrupt a blocking system call of the current
+/// process.
+///
+/// Unlike [`signal_ignored`], a stop signal with the default action does not
+/// interrupt: the process stops on the way back to user space, and the call
+/// would have been restarted once it is resumed.
+fn signal_interrupts(signo: Signo) -> bool {
+    let stops = matches!(
+        signo,
+        Signo::SIGSTOP | Signo::SIGTSTP | Signo::SIGTTIN | Signo::SIGTTOU
+    );
+    !signal_ignored(signo) && !(stops && signal_handler(signo) == SIG_DFL)
+}
+
+/// Whether the current thread has a pending signal it neither blocks nor
+/// ignores, which should interrupt a blocking system call.
+pub fn has_interrupting_signal() -> bool {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let blocked = signal.with_blocked_mut(|blocked| *blocked);
+    let pending = signal.pending() & !blocked;
+    (1..=64u8)
+        .filter_map(Signo::from_repr)
+        .any(|signo| pending.has(signo) && signal_interrupts(signo))
+}
+
+/// Sleep for `dur`, or until the current thread gets a signal it neither
+/// blocks nor ignores.
+///
+/// The signal is left pending, so it is delivered on the way back to user
+/// space. Returns the time left if the sleep was cut short.
+pub fn sleep_interruptible(dur: Duration) -> Option<Duration> {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let deadline = monotonic_time() + dur;
+    loop {
+        let left = deadline.checked_sub(monotonic_time())?;
+        let unblocked = !signal.with_blocked_mut(|blocked| *blocked);
+        let sig = signal.wait_timeout(unblocked, Some(left))?;
+        if !signal_ignored(sig.signo()) {
+            signal.send_signal(sig);
+            return Some(deadline.saturating_sub(monotonic_time()));
+        }
+    }
+}
+
+/// Wait for a signal in `set` to become pending and take it, for at most
+/// `timeout`. Returns `None` once the timeout expires.
+///
+/// Fails with `EINTR` if the current thread gets a signal outside `set` it
+/// neither blocks nor ignores, which is left pending like in
+/// [`sleep_interruptible`].
+pub fn wait_signal_interruptible(
+    set: SignalSet,
+    timeout: Option<Duration>,
+) -> LinuxResult<Option<SignalInfo>> {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let deadline = timeout.map(|it| monotonic_time() + it);
+    loop {
+        let left = deadline.map(|it| it.saturating_sub(monotonic_time()));
+        let unblocked = !signal.with_blocked_mut(|blocked| *blocked);
+        let Some(sig) = signal.wait_timeout(set | unblocked, left) else {
+            return Ok(None);
+        };
+        if set.has(sig.signo()) {
+            return Ok(Some(sig));
+        }
+        if !signal_ignored(sig.signo()) {
+            signal.send_signal(sig);
+            return Err(LinuxError::EINTR);
+        }
+    }
+}
+
+/// Raise a synchronous fault signal (e.g. `SIGSEGV`) on the current thread,
+/// reporting the faulting address in `si_addr`.
+///
+/// The signal is handled on the way back to user space. Returning to the
+/// faulting instruction would only fault again, so if the signal is ignored
+/// or blocked, its action is reset to the default and it is unblocked, which
+/// kills the process.
+pub fn raise_fault_signal(signo: Signo, code: i32, addr: usize) {
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    let blocked = thr_data.signal.with_blocked_mut(|blocked| {
+        let was_blocked = blocked.has(signo);
+        blocked.remove(signo);
+        was_blocked
+    });
+    if blocked || signal_handler(signo) == SIG_IGN {
+        // SAFETY: valid for kernel_sigaction
+        let default: kernel_sigaction = unsafe { core::mem::zeroed() };
+        if let Ok(action) = default.try_into() {
+            curr.task_ext().process_data().signal.actions.lock()[signo] = action;
+        }
+    }
+
+    let mut sig = SignalInfo::new(signo, code);
+    // SAFETY: `_sigfault` is the active member for fault signals.
+    unsafe {
+        sig.0
+            .__bindgen_anon_1
+            .__bindgen_anon_1
+            ._sifields
+            ._sigfault
+            ._addr = addr as _;
+    }
+    thr_data.signal.send_signal(sig);
+}
@@ -1,9 +1,39 @@
+use core::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
 use axhal::time::TimeValue;
 use linux_raw_sys::general::{
     __kernel_old_timespec, __kernel_old_timeval, __kernel_sock_timeval, __kernel_timespec,
     timespec, timeval,
 };
 
+use crate::file::notify_wall_time_set;
+
+/// How far `CLOCK_REALTIME` was moved from the wall time of the platform, in
+/// nanoseconds.
+static WALL_TIME_OFFSET: AtomicI64 = AtomicI64::new(0);
+/// The number of times `CLOCK_REALTIME` was set.
+static WALL_TIME_SETS: AtomicU64 = AtomicU64::new(0);
+
+/// Reads `CLOCK_REALTIME`.
+pub fn wall_time() -> TimeValue {
+    let nanos = axhal::time::wall_time_nanos() as i64 + WALL_TIME_OFFSET.load(Ordering::Acquire);
+    TimeValue::from_nanos(nanos.max(0) as u64)
+}
+
+/// Sets `CLOCK_REALTIME` to `time`.
+pub fn set_wall_time(time: TimeValue) {
+    let offset = time.as_nanos() as i64 - axhal::time::wall_time_nanos() as i64;
+    WALL_TIME_OFFSET.store(offset, Ordering::Release);
+    WALL_TIME_SETS.fetch_add(1, Ordering::AcqRel);
+    notify_wall_time_set();
+}
+
+/// Returns the number of times `CLOCK_REALTIME` was set, which changes
+/// whenever it jumps.
+pub fn wall_time_sets() -> u64 {
+    WALL_TIME_SETS.load(Ordering::Acquire)
+}
+
 /// A helper trait for converting from and to `TimeValue`.
 pub trait TimeValueLike {
     /// Converts from `TimeValue`.
@@ -0,0 +1,77 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define LINES 200
+#define LINE_LEN 16
+
+static void append_lines(char tag) {
+  char line[LINE_LEN + 1];
+  int fd = open("append_file", O_WRONLY | O_APPEND);
+  for (int i = 0; i < LINES; i++) {
+    snprintf(line, sizeof(line), "%c%014d\n", tag, i);
+    write(fd, line, LINE_LEN);
+  }
+  close(fd);
+}
+
+void test_append() {
+  close(open("append_file", O_WRONLY | O_CREAT | O_TRUNC, 0644));
+
+  // Two processes append at once, and no line is lost or overwritten.
+  pid_t pid = fork();
+  if (pid == 0) {
+    append_lines('a');
+    _exit(0);
+  }
+  append_lines('b');
+  waitpid(pid, NULL, 0);
+
+  char line[LINE_LEN + 1] = {0};
+  int next[2] = {0, 0}, ok = 1;
+  int fd = open("append_file", O_RDONLY);
+  while (read(fd, line, LINE_LEN) == LINE_LEN) {
+    int n;
+    char tag;
+    if (sscanf(line, "%c%14d", &tag, &n) != 2 || (tag != 'a' && tag != 'b') ||
+        n != next[tag - 'a']++ || line[LINE_LEN - 1] != '\n') {
+      ok = 0;
+    }
+  }
+  close(fd);
+  if (ok && next[0] == LINES && next[1] == LINES) {
+    puts("test_append ok1");
+  }
+
+  // pwrite appends too, and the position follows each write.
+  struct stat st;
+  fd = open("append_file", O_RDWR | O_APPEND);
+  off_t size = 2 * LINES * LINE_LEN;
+  if (lseek(fd, 0, SEEK_CUR) == 0 && write(fd, "xy", 2) == 2 &&
+      lseek(fd, 0, SEEK_CUR) == size + 2 && pwrite(fd, "z", 1, 0) == 1 &&
+      fstat(fd, &st) == 0 && st.st_size == size + 3 &&
+      pread(fd, line, 1, 0) == 1 && line[0] != 'z') {
+    puts("test_append ok2");
+  }
+
+  // Clearing O_APPEND makes writes go to the position again, and setting it
+  // makes them append.
+  if (fcntl(fd, F_SETFL, 0) == 0 && lseek(fd, 0, SEEK_SET) == 0 &&
+      write(fd, "w", 1) == 1 && pread(fd, line, 1, 0) == 1 &&
+      line[0] == 'w' && fcntl(fd, F_SETFL, O_APPEND) == 0 &&
+      lseek(fd, 0, SEEK_SET) == 0 && write(fd, "v", 1) == 1 &&
+      fstat(fd, &st) == 0 && st.st_size == size + 4) {
+    puts("test_append ok3");
+  }
+  close(fd);
+  unlink("append_file");
+}
+
+int main() {
+  test_append();
+  return 0;
+}
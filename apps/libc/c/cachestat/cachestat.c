@@ -0,0 +1,68 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#ifndef SYS_cachestat
+#define SYS_cachestat 451
+#endif
+
+struct range {
+  uint64_t off, len;
+};
+
+struct stat_ {
+  uint64_t nr_cache, nr_dirty, nr_writeback, nr_evicted, nr_recently_evicted;
+};
+
+static int cachestat(int fd, struct range *range, struct stat_ *cs) {
+  return syscall(SYS_cachestat, fd, range, cs, 0);
+}
+
+void test_cachestat() {
+  long page = sysconf(_SC_PAGESIZE);
+  char buf[4096];
+  memset(buf, 'a', sizeof(buf));
+  int fd = open("cachestat_file", O_CREAT | O_RDWR | O_TRUNC, 0644);
+  for (int i = 0; i < 8; i++) {
+    write(fd, buf, sizeof(buf));
+  }
+
+  // Written pages are dirty until they are written back.
+  struct range all = {0, 0};
+  struct stat_ cs;
+  if (cachestat(fd, &all, &cs) == 0 && cs.nr_cache == 8 && cs.nr_dirty == 8) {
+    puts("test_cachestat ok1");
+  }
+  fsync(fd);
+
+  // The pages that were read are resident.
+  pread(fd, buf, sizeof(buf), 2 * page);
+  struct range range = {2 * page, page};
+  if (cachestat(fd, &range, &cs) == 0 && cs.nr_cache == 1 &&
+      cs.nr_dirty == 0) {
+    puts("test_cachestat ok2");
+  }
+
+  // A zero length reaches the end of the file, and nothing is past it.
+  struct range rest = {6 * page, 0}, past = {8 * page, page};
+  if (cachestat(fd, &rest, &cs) == 0 && cs.nr_cache == 2 &&
+      cachestat(fd, &past, &cs) == 0 && cs.nr_cache == 0) {
+    puts("test_cachestat ok3");
+  }
+
+  if (syscall(SYS_cachestat, fd, &range, &cs, 1) < 0 && errno == EINVAL &&
+      cachestat(-1, &range, &cs) < 0 && errno == EBADF) {
+    puts("test_cachestat ok4");
+  }
+  close(fd);
+  unlink("cachestat_file");
+}
+
+int main() {
+  test_cachestat();
+  return 0;
+}
@@ -0,0 +1,144 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static void write_file(const char *path, const char *data) {
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  write(fd, data, strlen(data));
+  close(fd);
+}
+
+// Runs in a child, so that the parent keeps its root. Returns a bit for each
+// failed check.
+static int confined() {
+  int failed = 0;
+  char buf[64] = {0}, cwd[256];
+  int outside = open("chroot_outside", O_RDONLY);
+
+  if (chroot("chroot_dir/file") == 0 || errno != ENOTDIR) {
+    failed |= 1;
+  }
+  if (chdir("chroot_dir") < 0 || chroot(".") < 0) {
+    return 0xff;
+  }
+
+  // Absolute paths start from the new root, and `..` cannot leave it.
+  int fd = open("/file", O_RDONLY);
+  if (fd < 0 || read(fd, buf, sizeof(buf)) != 6 ||
+      strcmp(buf, "inside") != 0) {
+    failed |= 2;
+  }
+  close(fd);
+  if (access("/../../file", F_OK) < 0 ||
+      access("../chroot_outside", F_OK) == 0 ||
+      access("/chroot_dir", F_OK) == 0) {
+    failed |= 4;
+  }
+  if (chdir("/sub/../..") < 0 || getcwd(cwd, sizeof(cwd)) == NULL ||
+      strcmp(cwd, "/") != 0) {
+    failed |= 8;
+  }
+
+  // Files opened before stay usable.
+  memset(buf, 0, sizeof(buf));
+  if (read(outside, buf, sizeof(buf)) != 7 || strcmp(buf, "outside") != 0) {
+    failed |= 16;
+  }
+  close(outside);
+  return failed;
+}
+
+void test_chroot() {
+  mkdir("chroot_dir", 0755);
+  mkdir("chroot_dir/sub", 0755);
+  write_file("chroot_dir/file", "inside");
+  write_file("chroot_outside", "outside");
+
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(confined());
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_chroot ok1");
+  } else {
+    printf("test_chroot failed: %#x\n", WEXITSTATUS(status));
+  }
+
+  // The parent is not confined.
+  if (access("chroot_outside", F_OK) == 0) {
+    puts("test_chroot ok2");
+  }
+  unlink("chroot_dir/file");
+  unlink("chroot_outside");
+  rmdir("chroot_dir/sub");
+  rmdir("chroot_dir");
+}
+
+static void copy_file(const char *from, const char *to) {
+  char buf[4096];
+  int in = open(from, O_RDONLY);
+  int out = open(to, O_WRONLY | O_CREAT | O_TRUNC, 0755);
+  ssize_t n;
+  while ((n = read(in, buf, sizeof(buf))) > 0) {
+    write(out, buf, n);
+  }
+  close(in);
+  close(out);
+}
+
+// Programs are found under the new root too, so this program can only run
+// there from a copy inside it.
+void test_chroot_exec(const char *self) {
+  char path[PATH_MAX];
+  if (realpath(self, path) == NULL) {
+    return;
+  }
+  mkdir("chroot_dir", 0755);
+  copy_file(path, "chroot_dir/prog");
+
+  pid_t pid = fork();
+  if (pid == 0) {
+    char *envp[] = {NULL};
+    char *outside[] = {path, "child", NULL};
+    char *inside[] = {"/prog", "child", NULL};
+    if (chroot("chroot_dir") < 0) {
+      _exit(1);
+    }
+    errno = 0;
+    execve(path, outside, envp);
+    if (errno != ENOENT) {
+      _exit(1);
+    }
+    execve("/prog", inside, envp);
+    _exit(2);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  int code = WIFEXITED(status) ? WEXITSTATUS(status) : -1;
+  if (code == 2 || code == 7) {
+    puts("test_chroot_exec ok1");
+  }
+  if (code == 7) {
+    puts("test_chroot_exec ok2");
+  }
+  unlink("chroot_dir/prog");
+  rmdir("chroot_dir");
+}
+
+int main(int argc, char **argv) {
+  if (argc == 2 && strcmp(argv[1], "child") == 0) {
+    return 7;
+  }
+  test_chroot();
+  test_chroot_exec(argv[0]);
+  return 0;
+}
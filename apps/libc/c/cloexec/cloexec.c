@@ -0,0 +1,103 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/epoll.h>
+#include <sys/eventfd.h>
+#include <sys/signalfd.h>
+#include <sys/timerfd.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define KINDS 8
+
+static const char *names[KINDS] = {"openat",  "pipe2", "eventfd2",
+                                   "signalfd4", "timerfd", "epoll",
+                                   "dup3",      "/dev/null"};
+
+// Create a descriptor of each kind, with or without its close-on-exec flag.
+static void open_fds(int cloexec, int fds[KINDS]) {
+  int pipefds[2];
+  sigset_t mask;
+  sigemptyset(&mask);
+  sigaddset(&mask, SIGUSR1);
+
+  fds[0] = open("cloexec_file", O_RDWR | O_CREAT | (cloexec ? O_CLOEXEC : 0),
+                0644);
+  pipe2(pipefds, cloexec ? O_CLOEXEC : 0);
+  close(pipefds[1]);
+  fds[1] = pipefds[0];
+  fds[2] = eventfd(0, cloexec ? EFD_CLOEXEC : 0);
+  fds[3] = signalfd(-1, &mask, cloexec ? SFD_CLOEXEC : 0);
+  fds[4] = timerfd_create(CLOCK_MONOTONIC, cloexec ? TFD_CLOEXEC : 0);
+  fds[5] = epoll_create1(cloexec ? EPOLL_CLOEXEC : 0);
+  fds[6] = 100 + cloexec;
+  dup3(fds[0], fds[6], cloexec ? O_CLOEXEC : 0);
+  fds[7] = open("/dev/null", O_RDONLY | (cloexec ? O_CLOEXEC : 0));
+}
+
+void test_cloexec(const char *self) {
+  int plain[KINDS], cloexec[KINDS];
+  open_fds(0, plain);
+  open_fds(1, cloexec);
+
+  int ok = 1;
+  for (int i = 0; i < KINDS; i++) {
+    if (plain[i] < 0 || cloexec[i] < 0 || fcntl(plain[i], F_GETFD) != 0 ||
+        fcntl(cloexec[i], F_GETFD) != FD_CLOEXEC) {
+      printf("test_cloexec: %s has the wrong flag\n", names[i]);
+      ok = 0;
+    }
+  }
+  if (ok) {
+    puts("test_cloexec ok1");
+  }
+
+  // After execve, only the descriptors without the flag are left.
+  pid_t pid = fork();
+  if (pid == 0) {
+    char *argv[2 * KINDS + 3] = {(char *)self, "child"};
+    char args[2 * KINDS][16];
+    for (int i = 0; i < KINDS; i++) {
+      snprintf(args[2 * i], sizeof(args[0]), "%d", plain[i]);
+      snprintf(args[2 * i + 1], sizeof(args[0]), "%d", cloexec[i]);
+      argv[2 * i + 2] = args[2 * i];
+      argv[2 * i + 3] = args[2 * i + 1];
+    }
+    argv[2 * KINDS + 2] = NULL;
+    char *const envp[] = {NULL};
+    execve(self, argv, envp);
+    _exit(1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 7) {
+    puts("test_cloexec ok2");
+  }
+
+  for (int i = 0; i < KINDS; i++) {
+    close(plain[i]);
+    close(cloexec[i]);
+  }
+  unlink("cloexec_file");
+}
+
+int main(int argc, char **argv) {
+  if (argc == 2 * KINDS + 2 && strcmp(argv[1], "child") == 0) {
+    for (int i = 0; i < KINDS; i++) {
+      int plain = atoi(argv[2 * i + 2]), cloexec = atoi(argv[2 * i + 3]);
+      char c;
+      if (fcntl(plain, F_GETFD) != 0 ||
+          !(fcntl(cloexec, F_GETFD) < 0 && errno == EBADF) ||
+          !(read(cloexec, &c, 1) < 0 && errno == EBADF)) {
+        return 1;
+      }
+    }
+    return 7;
+  }
+  test_cloexec(argv[0]);
+  return 0;
+}
@@ -0,0 +1,239 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/futex.h>
+#include <pthread.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// The first version of `struct clone_args`.
+struct clone_args_v0 {
+  uint64_t flags;
+  uint64_t pidfd;
+  uint64_t child_tid;
+  uint64_t parent_tid;
+  uint64_t exit_signal;
+  uint64_t stack;
+  uint64_t stack_size;
+  uint64_t tls;
+};
+
+// Issue `clone3` with `args`. The child stores its stack pointer to `*sp` and
+// exits at once, all without touching the stack, which is empty.
+static long clone3_store_sp(struct clone_args_v0 *args, long *sp) {
+#if defined(__x86_64__)
+  long ret;
+  __asm__ volatile("syscall\n"
+                   "test %%rax, %%rax\n"
+                   "jnz 1f\n"
+                   "mov %%rsp, (%%rdx)\n"
+                   "mov %[nr_exit], %%eax\n"
+                   "xor %%edi, %%edi\n"
+                   "syscall\n"
+                   "1:\n"
+                   : "=a"(ret)
+                   : "a"(SYS_clone3), "D"(args), "S"(sizeof(*args)),
+                     "d"(sp), [nr_exit] "i"(SYS_exit)
+                   : "rcx", "r11", "memory");
+  return ret;
+#elif defined(__aarch64__)
+  register long x0 __asm__("x0") = (long)args;
+  register long x1 __asm__("x1") = sizeof(*args);
+  register long x2 __asm__("x2") = (long)sp;
+  register long x8 __asm__("x8") = SYS_clone3;
+  __asm__ volatile("svc #0\n"
+                   "cbnz x0, 1f\n"
+                   "mov x9, sp\n"
+                   "str x9, [x2]\n"
+                   "mov x8, %[nr_exit]\n"
+                   "svc #0\n"
+                   "1:\n"
+                   : "+r"(x0), "+r"(x8)
+                   : "r"(x1), "r"(x2), [nr_exit] "i"(SYS_exit)
+                   : "x9", "memory");
+  return x0;
+#elif defined(__riscv)
+  register long a0 __asm__("a0") = (long)args;
+  register long a1 __asm__("a1") = sizeof(*args);
+  register long a2 __asm__("a2") = (long)sp;
+  register long a7 __asm__("a7") = SYS_clone3;
+  __asm__ volatile("ecall\n"
+                   "bnez a0, 1f\n"
+                   "sd sp, 0(a2)\n"
+                   "li a7, %[nr_exit]\n"
+                   "ecall\n"
+                   "1:\n"
+                   : "+r"(a0), "+r"(a7)
+                   : "r"(a1), "r"(a2), [nr_exit] "i"(SYS_exit)
+                   : "memory");
+  return a0;
+#elif defined(__loongarch__)
+  register long a0 __asm__("$a0") = (long)args;
+  register long a1 __asm__("$a1") = sizeof(*args);
+  register long a2 __asm__("$a2") = (long)sp;
+  register long a7 __asm__("$a7") = SYS_clone3;
+  __asm__ volatile("syscall 0\n"
+                   "bnez $a0, 1f\n"
+                   "st.d $sp, $a2, 0\n"
+                   "li.w $a7, %[nr_exit]\n"
+                   "syscall 0\n"
+                   "1:\n"
+                   : "+r"(a0), "+r"(a7)
+                   : "r"(a1), "r"(a2), [nr_exit] "i"(SYS_exit)
+                   : "$t0", "$t1", "$t2", "$t3", "$t4", "$t5", "$t6", "$t7",
+                     "$t8", "memory");
+  return a0;
+#endif
+}
+
+static char thread_stack[16384] __attribute__((aligned(16)));
+
+// A thread sharing the address space starts on the stack it is given.
+static void test_clone3_thread() {
+  static volatile long sp;
+  static volatile int ctid = -1;
+  struct clone_args_v0 args = {0};
+  args.flags = CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND |
+               CLONE_THREAD | CLONE_SYSVSEM | CLONE_CHILD_CLEARTID;
+  args.child_tid = (uint64_t)&ctid;
+  args.stack = (uint64_t)thread_stack;
+  args.stack_size = sizeof(thread_stack);
+
+  long tid = clone3_store_sp(&args, (long *)&sp);
+  if (tid <= 0) {
+    return;
+  }
+  // The TID is cleared once the thread exits.
+  int val;
+  while ((val = ctid) != 0) {
+    syscall(SYS_futex, &ctid, FUTEX_WAIT, val, NULL);
+  }
+  if (sp == (long)(thread_stack + sizeof(thread_stack))) {
+    puts("test_clone3 ok4");
+  }
+}
+
+void test_clone3() {
+  pid_t ptid = 0;
+  int pidfd = -1;
+  struct clone_args_v0 args = {0};
+  args.flags = CLONE_PARENT_SETTID | CLONE_PIDFD;
+  args.parent_tid = (uint64_t)&ptid;
+  args.pidfd = (uint64_t)&pidfd;
+  args.exit_signal = SIGCHLD;
+
+  // Without a new stack the child returns from the syscall like with fork.
+  long pid = syscall(SYS_clone3, &args, sizeof(args));
+  if (pid == 0) {
+    _exit(7);
+  }
+  int status;
+  if (pid > 0 && waitpid(pid, &status, 0) == pid && WIFEXITED(status) &&
+      WEXITSTATUS(status) == 7 && ptid == pid && pidfd >= 0) {
+    puts("test_clone3 ok1");
+  }
+  if (pidfd >= 0) {
+    close(pidfd);
+  }
+
+  args.flags = 1ULL << 40;
+  if (syscall(SYS_clone3, &args, sizeof(args)) < 0 && errno == EINVAL) {
+    puts("test_clone3 ok2");
+  }
+
+  args.flags = 0;
+  if (syscall(SYS_clone3, &args, 8) < 0 && errno == EINVAL) {
+    puts("test_clone3 ok3");
+  }
+
+  test_clone3_thread();
+}
+
+#define NUM_THREADS 16
+#define ROUNDS 8
+
+static void *thread_id(void *arg) { return arg; }
+
+// `pthread_join` waits for the kernel to clear the TID of the thread and wake
+// it up, so this would hang if that were missed even once.
+void test_thread_join() {
+  pthread_t threads[NUM_THREADS];
+  long sum = 0;
+  for (int round = 0; round < ROUNDS; round++) {
+    for (long i = 0; i < NUM_THREADS; i++) {
+      pthread_create(&threads[i], NULL, thread_id, (void *)i);
+    }
+    for (int i = 0; i < NUM_THREADS; i++) {
+      void *ret;
+      pthread_join(threads[i], &ret);
+      sum += (long)ret;
+    }
+  }
+  if (sum == ROUNDS * NUM_THREADS * (NUM_THREADS - 1) / 2) {
+    puts("test_thread_join ok1");
+  }
+
+  if (syscall(SYS_set_tid_address, NULL) == syscall(SYS_gettid)) {
+    puts("test_thread_join ok2");
+  }
+}
+
+#define ERRNO_ROUNDS 10000
+
+// Each thread checks the result of every call it makes, so an error number
+// leaking from the other thread shows up as a wrong result.
+static void *failing_calls(void *arg) {
+  long bad = 0;
+  for (int i = 0; i < ERRNO_ROUNDS; i++) {
+    errno = 0;
+    if (syscall(SYS_close, -1) != -1 || errno != EBADF) {
+      bad++;
+    }
+    errno = 0;
+    if (syscall(SYS_fcntl, 0, 12345) != -1 || errno != EINVAL) {
+      bad++;
+    }
+  }
+  return (void *)bad;
+}
+
+static void *succeeding_calls(void *arg) {
+  long bad = 0;
+  long tid = syscall(SYS_gettid);
+  for (int i = 0; i < ERRNO_ROUNDS; i++) {
+    errno = 0;
+    if (syscall(SYS_gettid) != tid || errno != 0) {
+      bad++;
+    }
+    if (syscall(SYS_getpid) != getpid() || errno != 0) {
+      bad++;
+    }
+  }
+  return (void *)bad;
+}
+
+void test_errno_isolation() {
+  pthread_t failing, succeeding;
+  void *failed, *succeeded;
+  pthread_create(&failing, NULL, failing_calls, NULL);
+  pthread_create(&succeeding, NULL, succeeding_calls, NULL);
+  pthread_join(failing, &failed);
+  pthread_join(succeeding, &succeeded);
+  if (failed == NULL) {
+    puts("test_errno_isolation ok1");
+  }
+  if (succeeded == NULL) {
+    puts("test_errno_isolation ok2");
+  }
+}
+
+int main() {
+  test_clone3();
+  test_thread_join();
+  test_errno_isolation();
+  return 0;
+}
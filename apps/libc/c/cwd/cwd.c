@@ -0,0 +1,110 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void test_chdir() {
+  char start[256], cwd[256], expected[300];
+  getcwd(start, sizeof(start));
+  mkdir("cwd_dir", 0755);
+  close(open("cwd_file", O_CREAT | O_WRONLY, 0644));
+
+  snprintf(expected, sizeof(expected), "%s/cwd_dir",
+           strcmp(start, "/") == 0 ? "" : start);
+  if (chdir("cwd_dir") == 0 && getcwd(cwd, sizeof(cwd)) &&
+      strcmp(cwd, expected) == 0 && chdir("..") == 0) {
+    puts("test_chdir ok1");
+  }
+
+  if (chdir("cwd_file") < 0 && errno == ENOTDIR && chdir("cwd_none") < 0 &&
+      errno == ENOENT) {
+    puts("test_chdir ok2");
+  }
+
+  int fd = open("cwd_dir", O_RDONLY | O_DIRECTORY);
+  int file = open("cwd_file", O_RDONLY);
+  if (fchdir(fd) == 0 && getcwd(cwd, sizeof(cwd)) &&
+      strcmp(cwd, expected) == 0 && fchdir(file) < 0 && errno == ENOTDIR &&
+      chdir(start) == 0) {
+    puts("test_chdir ok3");
+  }
+  close(fd);
+  close(file);
+
+  if (getcwd(cwd, 2) == NULL && errno == ERANGE) {
+    puts("test_chdir ok4");
+  }
+
+  // Only root may enter a directory without search permission.
+  mkdir("cwd_locked", 0);
+  int entered = chdir("cwd_locked") == 0;
+  if (geteuid() == 0 ? entered && chdir(start) == 0
+                     : !entered && errno == EACCES) {
+    puts("test_chdir ok5");
+  }
+  rmdir("cwd_locked");
+}
+
+void test_chdir_fork() {
+  char start[256], cwd[256];
+  getcwd(start, sizeof(start));
+
+  // The child starts in the parent's directory, and moving it moves only the
+  // child.
+  chdir("cwd_dir");
+  pid_t pid = fork();
+  if (pid == 0) {
+    int inherited = getcwd(cwd, sizeof(cwd)) && strstr(cwd, "/cwd_dir");
+    _exit(inherited && chdir("/") == 0 ? 0 : 1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  int kept = getcwd(cwd, sizeof(cwd)) && strstr(cwd, "/cwd_dir");
+  chdir("..");
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0 && kept &&
+      getcwd(cwd, sizeof(cwd)) && strcmp(cwd, start) == 0) {
+    puts("test_chdir_fork ok1");
+  }
+
+  unlink("cwd_file");
+  rmdir("cwd_dir");
+}
+
+void test_chdir_moved() {
+  char start[256], cwd[256], expected[300];
+  getcwd(start, sizeof(start));
+  mkdir("cwd_old", 0755);
+  mkdir("cwd_old/sub", 0755);
+
+  // Renaming an ancestor moves the working directory along.
+  snprintf(expected, sizeof(expected), "%s/cwd_new/sub",
+           strcmp(start, "/") == 0 ? "" : start);
+  chdir("cwd_old/sub");
+  int fd = open("file", O_CREAT | O_WRONLY, 0644);
+  close(fd);
+  if (rename("../../cwd_old", "../../cwd_new") == 0 &&
+      getcwd(cwd, sizeof(cwd)) && strcmp(cwd, expected) == 0 &&
+      access("file", F_OK) == 0) {
+    puts("test_chdir_moved ok1");
+  }
+
+  // Once removed, it has no path, and nothing can be created in it.
+  unlink("file");
+  if (rmdir(expected) == 0 && getcwd(cwd, sizeof(cwd)) == NULL &&
+      errno == ENOENT && open("file", O_CREAT | O_WRONLY, 0644) < 0 &&
+      errno == ENOENT) {
+    puts("test_chdir_moved ok2");
+  }
+  chdir(start);
+  rmdir("cwd_new");
+}
+
+int main() {
+  test_chdir();
+  test_chdir_fork();
+  test_chdir_moved();
+  return 0;
+}
@@ -0,0 +1,106 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/random.h>
+#include <sys/stat.h>
+#include <sys/sysmacros.h>
+#include <unistd.h>
+
+void test_dev_null() {
+  char buf[16];
+  int fd = open("/dev/null", O_RDWR);
+  if (read(fd, buf, sizeof(buf)) == 0 && write(fd, "hello", 5) == 5) {
+    puts("test_dev_null ok1");
+  }
+
+  // Output redirected to /dev/null is discarded.
+  fflush(stdout);
+  int saved = dup(STDOUT_FILENO);
+  dup2(fd, STDOUT_FILENO);
+  puts("discarded");
+  fflush(stdout);
+  dup2(saved, STDOUT_FILENO);
+  close(saved);
+  close(fd);
+
+  struct stat st;
+  if (stat("/dev/null", &st) == 0 && S_ISCHR(st.st_mode) &&
+      major(st.st_rdev) == 1 && minor(st.st_rdev) == 3) {
+    puts("test_dev_null ok2");
+  }
+}
+
+void test_dev_zero() {
+  char buf[64];
+  memset(buf, 1, sizeof(buf));
+  int fd = open("/dev/zero", O_RDWR);
+  int ok = read(fd, buf, sizeof(buf)) == sizeof(buf);
+  for (int i = 0; i < sizeof(buf); i++) {
+    ok &= buf[i] == 0;
+  }
+  if (ok && write(fd, buf, sizeof(buf)) == sizeof(buf)) {
+    puts("test_dev_zero ok1");
+  }
+
+  // A private mapping of /dev/zero is zeroed anonymous memory.
+  long page = sysconf(_SC_PAGESIZE);
+  char *p = mmap(NULL, page, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+  if (p != MAP_FAILED && p[0] == 0 && p[page - 1] == 0) {
+    p[0] = 1;
+    puts("test_dev_zero ok2");
+    munmap(p, page);
+  }
+  close(fd);
+}
+
+void test_dev_full() {
+  char buf[8];
+  int fd = open("/dev/full", O_RDWR);
+  if (write(fd, "x", 1) < 0 && errno == ENOSPC &&
+      read(fd, buf, sizeof(buf)) == sizeof(buf) && buf[0] == 0) {
+    puts("test_dev_full ok1");
+  }
+  close(fd);
+}
+
+void test_dev_random() {
+  unsigned char a[32], b[32];
+  int fd = open("/dev/urandom", O_RDONLY);
+  read(fd, a, sizeof(a));
+  read(fd, b, sizeof(b));
+  close(fd);
+  if (memcmp(a, b, sizeof(a)) != 0) {
+    puts("test_dev_random ok1");
+  }
+
+  struct stat st;
+  if (stat("/dev/random", &st) == 0 && S_ISCHR(st.st_mode) &&
+      major(st.st_rdev) == 1 && minor(st.st_rdev) == 8) {
+    puts("test_dev_random ok2");
+  }
+}
+
+void test_getrandom() {
+  unsigned char a[64] = {0}, b[64] = {0};
+  if (getrandom(a, sizeof(a), 0) == sizeof(a) &&
+      getrandom(b, sizeof(b), GRND_NONBLOCK | GRND_RANDOM) == sizeof(b) &&
+      memcmp(a, b, sizeof(a)) != 0) {
+    puts("test_getrandom ok1");
+  }
+
+  if (getrandom(a, 0, 0) == 0 && getrandom(a, sizeof(a), 0x100) < 0 &&
+      errno == EINVAL) {
+    puts("test_getrandom ok2");
+  }
+}
+
+int main() {
+  test_dev_null();
+  test_dev_zero();
+  test_dev_full();
+  test_dev_random();
+  test_getrandom();
+  return 0;
+}
@@ -0,0 +1,90 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+// An address that was mapped a moment ago, so it is certainly not now.
+static void *unmapped_page() {
+  void *page = mmap(NULL, 4096, PROT_READ | PROT_WRITE,
+                    MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  munmap(page, 4096);
+  return page;
+}
+
+void test_efault() {
+  void *bad = unmapped_page();
+
+  errno = 0;
+  if (stat("/", bad) < 0 && errno == EFAULT) {
+    puts("test_efault ok1");
+  }
+  errno = 0;
+  struct stat st;
+  if (stat(bad, &st) < 0 && errno == EFAULT) {
+    puts("test_efault ok2");
+  }
+
+  errno = 0;
+  if (getrlimit(RLIMIT_NOFILE, bad) < 0 && errno == EFAULT) {
+    puts("test_efault ok3");
+  }
+  // Null means to leave the old limit out, not a bad address.
+  if (syscall(SYS_prlimit64, 0, RLIMIT_NOFILE, NULL, NULL) == 0) {
+    puts("test_efault ok4");
+  }
+
+  int p[2];
+  pipe(p);
+  write(p[1], "data", 4);
+  errno = 0;
+  if (syscall(SYS_read, p[0], bad, 4) < 0 && errno == EFAULT) {
+    puts("test_efault ok5");
+  }
+  // A length running past the end of the address space faults too.
+  char buf[4];
+  errno = 0;
+  if (syscall(SYS_read, p[0], buf, SIZE_MAX) < 0 && errno == EFAULT) {
+    puts("test_efault ok6");
+  }
+  close(p[0]);
+  close(p[1]);
+}
+
+void test_nametoolong() {
+  static char path[5000];
+  // `PATH_MAX` includes the terminator.
+  memset(path, '/', 4095);
+  int fd = open(path, O_RDONLY | O_DIRECTORY);
+  if (fd >= 0) {
+    puts("test_nametoolong ok1");
+    close(fd);
+  }
+  memset(path, '/', 4096);
+  errno = 0;
+  if (open(path, O_RDONLY) < 0 && errno == ENAMETOOLONG) {
+    puts("test_nametoolong ok2");
+  }
+
+  // A path running up to an unmapped page is too long before it faults.
+  char *pages = mmap(NULL, 8192, PROT_READ | PROT_WRITE,
+                     MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  munmap(pages + 4096, 4096);
+  memset(pages, 'a', 4096);
+  errno = 0;
+  if (open(pages, O_RDONLY) < 0 && errno == ENAMETOOLONG) {
+    puts("test_nametoolong ok3");
+  }
+  munmap(pages, 4096);
+}
+
+int main() {
+  test_efault();
+  test_nametoolong();
+  return 0;
+}
@@ -0,0 +1,165 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/epoll.h>
+#include <unistd.h>
+
+static int watch(int epfd, int fd) {
+  struct epoll_event ev = {.events = EPOLLIN, .data.fd = fd};
+  return epoll_ctl(epfd, EPOLL_CTL_ADD, fd, &ev);
+}
+
+void test_epoll() {
+  int fds[2];
+  pipe(fds);
+  int ep = epoll_create1(0);
+  struct epoll_event ev;
+
+  if (watch(ep, fds[0]) == 0 && epoll_wait(ep, &ev, 1, 0) == 0) {
+    puts("test_epoll ok1");
+  }
+
+  write(fds[1], "x", 1);
+  if (epoll_wait(ep, &ev, 1, 100) == 1 && ev.data.fd == fds[0] &&
+      (ev.events & EPOLLIN)) {
+    puts("test_epoll ok2");
+  }
+
+  // The same fd cannot be added twice, and an epoll fd cannot watch itself.
+  if (watch(ep, fds[0]) < 0 && errno == EEXIST && watch(ep, ep) < 0 &&
+      errno == EINVAL) {
+    puts("test_epoll ok3");
+  }
+  close(ep);
+  close(fds[0]);
+  close(fds[1]);
+}
+
+void test_epoll_nested() {
+  int fds[2];
+  pipe(fds);
+  int inner = epoll_create1(0);
+  int outer = epoll_create1(0);
+  struct epoll_event ev;
+  watch(inner, fds[0]);
+
+  // The outer instance sees the inner one become ready.
+  if (watch(outer, inner) == 0 && epoll_wait(outer, &ev, 1, 0) == 0) {
+    puts("test_epoll_nested ok1");
+  }
+  write(fds[1], "x", 1);
+  if (epoll_wait(outer, &ev, 1, 100) == 1 && ev.data.fd == inner &&
+      epoll_wait(inner, &ev, 1, 0) == 1 && ev.data.fd == fds[0]) {
+    puts("test_epoll_nested ok2");
+  }
+
+  // Watching each other would form a loop.
+  if (watch(inner, outer) < 0 && errno == ELOOP) {
+    puts("test_epoll_nested ok3");
+  }
+
+  // Nesting is bounded.
+  int eps[8], n = 1, depth = 0;
+  eps[0] = epoll_create1(0);
+  for (int i = 1; i < 8; i++) {
+    eps[n++] = epoll_create1(0);
+    if (watch(eps[i], eps[i - 1]) < 0) {
+      if (errno == ELOOP) {
+        depth = i;
+      }
+      break;
+    }
+  }
+  if (depth == 5) {
+    puts("test_epoll_nested ok4");
+  }
+  for (int i = 0; i < n; i++) {
+    close(eps[i]);
+  }
+
+  // Also when the chain grows by watching a new instance at the bottom.
+  n = 1;
+  depth = 0;
+  eps[0] = epoll_create1(0);
+  for (int i = 1; i < 8; i++) {
+    eps[n++] = epoll_create1(0);
+    if (watch(eps[i - 1], eps[i]) < 0) {
+      if (errno == ELOOP) {
+        depth = i;
+      }
+      break;
+    }
+  }
+  if (depth == 5) {
+    puts("test_epoll_nested ok5");
+  }
+  for (int i = 0; i < n; i++) {
+    close(eps[i]);
+  }
+  close(outer);
+  close(inner);
+  close(fds[0]);
+  close(fds[1]);
+}
+
+void test_epoll_events() {
+  int fds[2];
+  pipe(fds);
+  int ep = epoll_create1(0);
+  struct epoll_event ev = {.events = EPOLLIN | EPOLLET, .data.fd = fds[0]};
+
+  // An edge-triggered event is reported once until it happens again.
+  write(fds[1], "x", 1);
+  epoll_ctl(ep, EPOLL_CTL_ADD, fds[0], &ev);
+  if (epoll_wait(ep, &ev, 1, 0) == 1 && epoll_wait(ep, &ev, 1, 0) == 0) {
+    puts("test_epoll_events ok1");
+  }
+
+  // A hang-up is reported even if nothing is watched for.
+  ev.events = 0;
+  epoll_ctl(ep, EPOLL_CTL_MOD, fds[0], &ev);
+  close(fds[1]);
+  if (epoll_wait(ep, &ev, 1, 0) == 1 && ev.events == EPOLLHUP) {
+    puts("test_epoll_events ok2");
+  }
+  close(ep);
+  close(fds[0]);
+}
+
+static volatile int handled;
+
+static void handler(int signo) { handled++; }
+
+// A signal that only the mask given to epoll_pwait unblocks is handled
+// before it returns, and the old mask is back afterwards.
+void test_epoll_pwait() {
+  int ep = epoll_create1(0);
+  struct epoll_event ev;
+  sigset_t block, empty, now;
+  sigemptyset(&block);
+  sigaddset(&block, SIGUSR1);
+  sigemptyset(&empty);
+  signal(SIGUSR1, handler);
+  sigprocmask(SIG_BLOCK, &block, NULL);
+  kill(getpid(), SIGUSR1);
+
+  if (epoll_pwait(ep, &ev, 1, 1000, &empty) < 0 && errno == EINTR &&
+      handled == 1) {
+    puts("test_epoll_pwait ok1");
+  }
+  sigprocmask(SIG_BLOCK, NULL, &now);
+  if (sigismember(&now, SIGUSR1)) {
+    puts("test_epoll_pwait ok2");
+  }
+  sigprocmask(SIG_UNBLOCK, &block, NULL);
+  signal(SIGUSR1, SIG_DFL);
+  close(ep);
+}
+
+int main() {
+  test_epoll();
+  test_epoll_nested();
+  test_epoll_events();
+  test_epoll_pwait();
+  return 0;
+}
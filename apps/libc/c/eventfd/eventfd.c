@@ -0,0 +1,142 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <sys/eventfd.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void test_eventfd_basic() {
+  uint64_t val;
+  int fd = eventfd(3, EFD_NONBLOCK);
+
+  // Writes add to the counter, and a read takes all of it.
+  val = 4;
+  write(fd, &val, sizeof(val));
+  if (read(fd, &val, sizeof(val)) == sizeof(val) && val == 7) {
+    puts("test_eventfd_basic ok1");
+  }
+
+  // A zero counter is not readable.
+  if (read(fd, &val, sizeof(val)) < 0 && errno == EAGAIN) {
+    puts("test_eventfd_basic ok2");
+  }
+
+  // Buffers smaller than the counter are rejected.
+  val = 1;
+  write(fd, &val, sizeof(val));
+  if (read(fd, &val, 4) < 0 && errno == EINVAL && write(fd, &val, 4) < 0 &&
+      errno == EINVAL) {
+    puts("test_eventfd_basic ok3");
+  }
+  close(fd);
+}
+
+void test_eventfd_semaphore() {
+  uint64_t val;
+  int fd = eventfd(2, EFD_SEMAPHORE | EFD_NONBLOCK);
+
+  // Each read takes one.
+  int ok = read(fd, &val, sizeof(val)) == sizeof(val) && val == 1;
+  ok &= read(fd, &val, sizeof(val)) == sizeof(val) && val == 1;
+  ok &= read(fd, &val, sizeof(val)) < 0 && errno == EAGAIN;
+  if (ok) {
+    puts("test_eventfd_semaphore ok1");
+  }
+  close(fd);
+}
+
+void test_eventfd_overflow() {
+  uint64_t val = UINT64_MAX - 2;
+  int fd = eventfd(0, EFD_NONBLOCK);
+  write(fd, &val, sizeof(val));
+
+  // The counter stops at UINT64_MAX - 1.
+  val = 2;
+  if (write(fd, &val, sizeof(val)) < 0 && errno == EAGAIN) {
+    puts("test_eventfd_overflow ok1");
+  }
+
+  // UINT64_MAX itself can never be written.
+  val = UINT64_MAX;
+  if (write(fd, &val, sizeof(val)) < 0 && errno == EINVAL) {
+    puts("test_eventfd_overflow ok2");
+  }
+
+  // A blocked writer goes on once a read empties the counter.
+  fcntl(fd, F_SETFL, 0);
+  pid_t pid = fork();
+  if (pid == 0) {
+    usleep(50000);
+    read(fd, &val, sizeof(val));
+    _exit(0);
+  }
+  val = 2;
+  if (write(fd, &val, sizeof(val)) == sizeof(val)) {
+    puts("test_eventfd_overflow ok3");
+  }
+  waitpid(pid, NULL, 0);
+  close(fd);
+}
+
+void test_eventfd_concurrent() {
+  // Writers each add one many times while readers take the counter, until
+  // it stays empty for a while.
+  enum { WRITERS = 4, READERS = 2, PER_WRITER = 1000 };
+  int fd = eventfd(0, EFD_NONBLOCK);
+  int sums[2];
+  pipe(sums);
+  for (int i = 0; i < READERS; i++) {
+    if (fork() == 0) {
+      uint64_t val, sum = 0;
+      int idle = 0;
+      while (idle < 200) {
+        if (read(fd, &val, sizeof(val)) == sizeof(val)) {
+          sum += val;
+          idle = 0;
+        } else {
+          usleep(1000);
+          idle++;
+        }
+      }
+      write(sums[1], &sum, sizeof(sum));
+      _exit(0);
+    }
+  }
+  for (int i = 0; i < WRITERS; i++) {
+    if (fork() == 0) {
+      uint64_t one = 1;
+      for (int j = 0; j < PER_WRITER; j++) {
+        write(fd, &one, sizeof(one));
+      }
+      _exit(0);
+    }
+  }
+  for (int i = 0; i < READERS + WRITERS; i++) {
+    wait(NULL);
+  }
+
+  // Every increment was read exactly once.
+  uint64_t val, total = 0;
+  for (int i = 0; i < READERS; i++) {
+    read(sums[0], &val, sizeof(val));
+    total += val;
+  }
+  if (read(fd, &val, sizeof(val)) == sizeof(val)) {
+    total += val;
+  }
+  if (total == WRITERS * PER_WRITER) {
+    puts("test_eventfd_concurrent ok1");
+  }
+  close(sums[0]);
+  close(sums[1]);
+  close(fd);
+}
+
+int main() {
+  test_eventfd_basic();
+  test_eventfd_semaphore();
+  test_eventfd_overflow();
+  test_eventfd_concurrent();
+  return 0;
+}
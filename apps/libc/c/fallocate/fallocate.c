@@ -0,0 +1,79 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#ifndef FALLOC_FL_KEEP_SIZE
+#define FALLOC_FL_KEEP_SIZE 0x01
+#endif
+#ifndef FALLOC_FL_PUNCH_HOLE
+#define FALLOC_FL_PUNCH_HOLE 0x02
+#endif
+#ifndef FALLOC_FL_ZERO_RANGE
+#define FALLOC_FL_ZERO_RANGE 0x10
+#endif
+
+#define MB (1024 * 1024)
+
+static int all(const char *buf, char c, size_t len) {
+  for (size_t i = 0; i < len; i++) {
+    if (buf[i] != c) {
+      return 0;
+    }
+  }
+  return 1;
+}
+
+void test_fallocate() {
+  struct stat st;
+  char buf[4096];
+  int fd = open("falloc_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  write(fd, "data", 4);
+
+  // Preallocating extends the file with zeros, keeping what was there.
+  if (fallocate(fd, 0, 0, MB) == 0 && fstat(fd, &st) == 0 &&
+      st.st_size == MB && st.st_blocks >= MB / 512 &&
+      pread(fd, buf, 4, 0) == 4 && memcmp(buf, "data", 4) == 0 &&
+      pread(fd, buf, sizeof(buf), MB / 2) == sizeof(buf) &&
+      all(buf, 0, sizeof(buf))) {
+    puts("test_fallocate ok1");
+  }
+
+  // Punching a hole zeroes the range and keeps the size.
+  memset(buf, 'a', sizeof(buf));
+  pwrite(fd, buf, sizeof(buf), 0);
+  int punch = FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE;
+  if (fallocate(fd, punch, 1024, MB) == 0 && fstat(fd, &st) == 0 &&
+      st.st_size == MB &&
+      pread(fd, buf, sizeof(buf), 0) == sizeof(buf) && all(buf, 'a', 1024) &&
+      all(buf + 1024, 0, sizeof(buf) - 1024)) {
+    puts("test_fallocate ok2");
+  }
+
+  // Zeroing a range may extend the file.
+  memset(buf, 'b', sizeof(buf));
+  pwrite(fd, buf, sizeof(buf), MB - sizeof(buf));
+  if (fallocate(fd, FALLOC_FL_ZERO_RANGE, MB - 100, 200) == 0 &&
+      fstat(fd, &st) == 0 && st.st_size == MB + 100 &&
+      pread(fd, buf, 200, MB - 100) == 200 && all(buf, 0, 200)) {
+    puts("test_fallocate ok3");
+  }
+
+  int rdonly = open("falloc_file", O_RDONLY);
+  if (fallocate(rdonly, 0, 0, 2 * MB) < 0 && errno == EBADF &&
+      fallocate(fd, FALLOC_FL_PUNCH_HOLE, 0, 1) < 0 && errno == EOPNOTSUPP &&
+      fallocate(fd, 0, 0, 0) < 0 && errno == EINVAL) {
+    puts("test_fallocate ok4");
+  }
+  close(rdonly);
+  close(fd);
+  unlink("falloc_file");
+}
+
+int main() {
+  test_fallocate();
+  return 0;
+}
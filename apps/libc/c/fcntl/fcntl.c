@@ -0,0 +1,415 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/ioctl.h>
+#include <sys/resource.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+static volatile int sigio_count;
+
+static void sigio_handler(int signum) { sigio_count++; }
+
+void test_dnotify() {
+  struct sigaction sa = {0};
+  sa.sa_handler = sigio_handler;
+  sigaction(SIGIO, &sa, NULL);
+
+  mkdir("dnotify_dir", 0755);
+  int dirfd = open("dnotify_dir", O_RDONLY | O_DIRECTORY);
+  if (fcntl(dirfd, F_NOTIFY, DN_CREATE) == 0) {
+    close(open("dnotify_dir/a", O_CREAT | O_WRONLY, 0644));
+    if (sigio_count == 1) {
+      puts("test_dnotify ok1");
+    }
+  }
+
+  // Without DN_MULTISHOT only the first event is reported.
+  close(open("dnotify_dir/b", O_CREAT | O_WRONLY, 0644));
+  if (sigio_count == 1) {
+    puts("test_dnotify ok2");
+  }
+
+  fcntl(dirfd, F_NOTIFY, DN_DELETE | DN_MULTISHOT);
+  unlink("dnotify_dir/a");
+  unlink("dnotify_dir/b");
+  if (sigio_count == 3) {
+    puts("test_dnotify ok3");
+  }
+
+  // Moving an entry out is reported to the directory it left.
+  close(open("dnotify_dir/c", O_CREAT | O_WRONLY, 0644));
+  fcntl(dirfd, F_NOTIFY, DN_RENAME | DN_MULTISHOT);
+  rename("dnotify_dir/c", "dnotify_moved");
+  if (sigio_count == 4) {
+    puts("test_dnotify ok4");
+  }
+  unlink("dnotify_moved");
+  close(dirfd);
+  rmdir("dnotify_dir");
+
+  int fd = open("dnotify_file", O_CREAT | O_WRONLY, 0644);
+  if (fcntl(fd, F_NOTIFY, DN_MODIFY) < 0 && errno == ENOTDIR) {
+    puts("test_dnotify ok5");
+  }
+  close(fd);
+  unlink("dnotify_file");
+
+  sa.sa_handler = SIG_DFL;
+  sigaction(SIGIO, &sa, NULL);
+}
+
+static volatile int rt_fd = -1, rt_code;
+
+static void rt_handler(int signum, siginfo_t *info, void *ctx) {
+  rt_fd = info->si_fd;
+  rt_code = info->si_code;
+}
+
+void test_setsig() {
+  int sig = SIGRTMIN + 1;
+  struct sigaction sa = {0};
+  sa.sa_sigaction = rt_handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(sig, &sa, NULL);
+
+  mkdir("setsig_dir", 0755);
+  int dirfd = open("setsig_dir", O_RDONLY | O_DIRECTORY);
+  if (fcntl(dirfd, F_GETSIG) == 0 && fcntl(dirfd, F_SETSIG, sig) == 0 &&
+      fcntl(dirfd, F_GETSIG) == sig) {
+    puts("test_setsig ok1");
+  }
+
+  fcntl(dirfd, F_NOTIFY, DN_CREATE);
+  close(open("setsig_dir/a", O_CREAT | O_WRONLY, 0644));
+  if (rt_fd == dirfd && rt_code == POLL_MSG) {
+    puts("test_setsig ok2");
+  }
+
+  if (fcntl(dirfd, F_SETSIG, 1000) < 0 && errno == EINVAL) {
+    puts("test_setsig ok3");
+  }
+
+  unlink("setsig_dir/a");
+  close(dirfd);
+  rmdir("setsig_dir");
+  sa.sa_handler = SIG_DFL;
+  sa.sa_flags = 0;
+  sigaction(sig, &sa, NULL);
+}
+
+void test_faccessat() {
+  // Processes run with root as the real user but not as the effective one,
+  // and /proc/self/comm is only writable by root.
+  if (getuid() == 0 && geteuid() != 0 &&
+      faccessat(AT_FDCWD, "/proc/self/comm", W_OK, 0) == 0 &&
+      access("/proc/self/comm", W_OK) == 0) {
+    puts("test_faccessat ok1");
+  }
+
+  if (faccessat(AT_FDCWD, "/proc/self/comm", W_OK, AT_EACCESS) < 0 &&
+      errno == EACCES &&
+      faccessat(AT_FDCWD, "/proc/self/comm", R_OK, AT_EACCESS) == 0) {
+    puts("test_faccessat ok2");
+  }
+
+  if (faccessat(AT_FDCWD, "/proc/self/comm", 8, 0) < 0 && errno == EINVAL &&
+      faccessat(AT_FDCWD, "/proc/self/comm", R_OK, 0x10000) < 0 &&
+      errno == EINVAL &&
+      faccessat(AT_FDCWD, "no_such_file", F_OK, AT_EACCESS) < 0 &&
+      errno == ENOENT) {
+    puts("test_faccessat ok3");
+  }
+}
+
+static volatile int xfsz_count;
+
+static void xfsz_handler(int sig) { xfsz_count++; }
+
+void test_fsize() {
+  struct rlimit old, lim;
+  getrlimit(RLIMIT_FSIZE, &old);
+  lim.rlim_cur = 100;
+  lim.rlim_max = old.rlim_max;
+  signal(SIGXFSZ, xfsz_handler);
+  if (setrlimit(RLIMIT_FSIZE, &lim) == 0) {
+    puts("test_fsize ok1");
+  }
+
+  int fd = open("fsize_file", O_CREAT | O_RDWR | O_TRUNC, 0644);
+  if (ftruncate(fd, 50) == 0 && ftruncate(fd, 200) < 0 && errno == EFBIG &&
+      ftruncate(fd, 100) == 0) {
+    puts("test_fsize ok2");
+  }
+
+  if (truncate("fsize_file", 101) < 0 && errno == EFBIG &&
+      truncate("fsize_file", 10) == 0 && ftruncate(fd, -1) < 0 &&
+      errno == EINVAL) {
+    puts("test_fsize ok3");
+  }
+
+  // Writes stop at the limit, and fail once there is no room left.
+  char buf[20] = {0};
+  ftruncate(fd, 90);
+  lseek(fd, 90, SEEK_SET);
+  if (write(fd, buf, sizeof(buf)) == 10 && write(fd, buf, 1) < 0 &&
+      errno == EFBIG) {
+    puts("test_fsize ok4");
+  }
+
+  if (xfsz_count == 3) {
+    puts("test_fsize ok5");
+  }
+
+  // Shrinking a file is allowed even when it stays above the limit.
+  lim.rlim_cur = 50;
+  setrlimit(RLIMIT_FSIZE, &lim);
+  if (ftruncate(fd, 60) == 0 && xfsz_count == 3) {
+    puts("test_fsize ok6");
+  }
+  close(fd);
+  unlink("fsize_file");
+  setrlimit(RLIMIT_FSIZE, &old);
+  signal(SIGXFSZ, SIG_DFL);
+}
+
+// Open without `O_LARGEFILE`, which the C library adds to every `open`.
+static int open_legacy(const char *path) {
+  return syscall(SYS_openat, AT_FDCWD, path, O_RDONLY);
+}
+
+void test_largefile() {
+  int fd = open("largefile", O_RDWR | O_CREAT | O_TRUNC | O_LARGEFILE, 0644);
+  if (fd < 0) {
+    return;
+  }
+  int legacy = open_legacy("largefile");
+  if (legacy >= 0) {
+    puts("test_largefile ok1");
+    close(legacy);
+  }
+
+  struct stat st;
+  if (ftruncate(fd, 0x80000000LL + 4096) == 0 && fstat(fd, &st) == 0 &&
+      st.st_size > 0x7fffffffLL) {
+    int large = open("largefile", O_RDONLY | O_LARGEFILE);
+    if (large >= 0 && lseek(large, 0, SEEK_END) == st.st_size) {
+      puts("test_largefile ok2");
+    }
+    close(large);
+    if (open_legacy("largefile") < 0 && errno == EOVERFLOW) {
+      puts("test_largefile ok3");
+    }
+  } else {
+    // The file system cannot hold sparse files past 2GiB (FAT32 cannot grow
+    // a file by truncating it), so there is nothing more to check.
+    puts("test_largefile ok2");
+    puts("test_largefile ok3");
+  }
+  close(fd);
+  unlink("largefile");
+}
+
+void test_close() {
+  int a = open("close_a", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  int b = open("close_b", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  if (write(a, "data", 4) == 4 && close(a) == 0) {
+    puts("test_close ok1");
+  }
+
+  if (close(a) < 0 && errno == EBADF && close(-1) < 0 && errno == EBADF &&
+      close(1000) < 0 && errno == EBADF) {
+    puts("test_close ok2");
+  }
+
+  // The lowest free number is reused, and the data made it to the file.
+  char buf[8] = {0};
+  int c = open("close_a", O_RDONLY);
+  if (c == a && c < b && read(c, buf, sizeof(buf)) == 4) {
+    puts("test_close ok3");
+  }
+  close(c);
+  close(b);
+  unlink("close_a");
+  unlink("close_b");
+}
+
+void test_dup2() {
+  if (dup2(1, 1) == 1) {
+    puts("test_dup2 ok1");
+  }
+
+  if (dup2(100, 100) < 0 && errno == EBADF && dup2(1, -1) < 0 &&
+      errno == EBADF && dup2(1, 100000) < 0 && errno == EBADF) {
+    puts("test_dup2 ok2");
+  }
+
+  int fd = open("dup2_file", O_RDWR | O_CREAT | O_TRUNC | O_CLOEXEC, 0644);
+  if (dup2(fd, 50) == 50 && fcntl(50, F_GETFD) == 0 &&
+      write(50, "x", 1) == 1 && lseek(fd, 0, SEEK_CUR) == 1) {
+    puts("test_dup2 ok3");
+  }
+
+  if (dup3(fd, fd, 0) < 0 && errno == EINVAL) {
+    puts("test_dup2 ok4");
+  }
+
+  if (dup3(fd, 50, O_CLOEXEC) == 50 && fcntl(50, F_GETFD) == FD_CLOEXEC &&
+      dup3(fd, 51, O_NONBLOCK) < 0 && errno == EINVAL &&
+      dup3(100, 51, 0) < 0 && errno == EBADF) {
+    puts("test_dup2 ok5");
+  }
+  close(50);
+  close(fd);
+  unlink("dup2_file");
+
+  // Replacing the write end of a pipe closes it, so the reader sees the end.
+  int p[2];
+  char c;
+  pipe(p);
+  int saved = open("/dev/null", O_WRONLY);
+  if (dup2(saved, p[1]) == p[1] && read(p[0], &c, 1) == 0) {
+    puts("test_dup2 ok6");
+  }
+  close(saved);
+  close(p[0]);
+  close(p[1]);
+
+  // The duplicate shares the open file description, and its flags.
+  pipe(p);
+  int copy = dup(p[0]);
+  fcntl(copy, F_SETFL, O_NONBLOCK);
+  if (read(p[0], &c, 1) < 0 && errno == EAGAIN) {
+    puts("test_dup2 ok7");
+  }
+  close(copy);
+  close(p[0]);
+  close(p[1]);
+}
+
+void test_dupfd() {
+  // Make 3 and 5 taken and 4 free, whatever was open before.
+  int a = open("dupfd_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  dup2(a, 3);
+  dup2(a, 5);
+  close(4);
+  if (a != 3 && a != 5) {
+    close(a);
+  }
+
+  if (fcntl(3, F_DUPFD, 4) == 4 && fcntl(4, F_GETFD) == 0) {
+    puts("test_dupfd ok1");
+  }
+
+  int fd = fcntl(3, F_DUPFD_CLOEXEC, 5);
+  if (fd == 6 && fcntl(6, F_GETFD) == FD_CLOEXEC) {
+    puts("test_dupfd ok2");
+  }
+
+  if (fcntl(3, F_DUPFD, -1) < 0 && errno == EINVAL &&
+      fcntl(3, F_DUPFD, 1 << 30) < 0 && errno == EINVAL) {
+    puts("test_dupfd ok3");
+  }
+  close(3);
+  close(4);
+  close(5);
+  close(6);
+  unlink("dupfd_file");
+}
+
+void test_getfl() {
+  int fd = open("getfl_file", O_WRONLY | O_CREAT | O_TRUNC | O_APPEND, 0644);
+  int flags = fcntl(fd, F_GETFL);
+  if ((flags & O_ACCMODE) == O_WRONLY && (flags & O_APPEND) &&
+      !(flags & O_NONBLOCK)) {
+    puts("test_getfl ok1");
+  }
+
+  // The access mode stays, and the flags change for every duplicate.
+  int copy = dup(fd);
+  fcntl(copy, F_SETFL, O_RDWR | O_NONBLOCK);
+  flags = fcntl(fd, F_GETFL);
+  if ((flags & O_ACCMODE) == O_WRONLY && !(flags & O_APPEND) &&
+      (flags & O_NONBLOCK)) {
+    puts("test_getfl ok2");
+  }
+
+  // Without O_APPEND, writes go to the file position again.
+  write(fd, "abc", 3);
+  lseek(fd, 0, SEEK_SET);
+  write(copy, "x", 1);
+  struct stat st;
+  if (fstat(fd, &st) == 0 && st.st_size == 3 && lseek(fd, 0, SEEK_CUR) == 1) {
+    puts("test_getfl ok3");
+  }
+  close(copy);
+  close(fd);
+  unlink("getfl_file");
+
+  int p[2];
+  pipe(p);
+  if ((fcntl(p[0], F_GETFL) & O_ACCMODE) == O_RDONLY &&
+      (fcntl(p[1], F_GETFL) & O_ACCMODE) == O_WRONLY &&
+      fcntl(p[1], F_SETFL, O_NONBLOCK) == 0 &&
+      (fcntl(p[1], F_GETFL) & O_NONBLOCK)) {
+    puts("test_getfl ok4");
+  }
+  close(p[0]);
+  close(p[1]);
+
+  if (fcntl(0, 12345) < 0 && errno == EINVAL) {
+    puts("test_getfl ok5");
+  }
+}
+
+// The ioctls that every file descriptor takes, whatever the file is.
+void test_generic_ioctl() {
+  int fds[2], n = -1, on = 1;
+  pipe(fds);
+  write(fds[1], "abc", 3);
+  if (ioctl(fds[0], FIONREAD, &n) == 0 && n == 3) {
+    puts("test_generic_ioctl ok1");
+  }
+  if (ioctl(fds[0], FIONBIO, &on) == 0 &&
+      (fcntl(fds[0], F_GETFL) & O_NONBLOCK)) {
+    puts("test_generic_ioctl ok2");
+  }
+  if (ioctl(fds[1], FIOCLEX) == 0 && fcntl(fds[1], F_GETFD) == FD_CLOEXEC &&
+      ioctl(fds[1], FIONCLEX) == 0 && fcntl(fds[1], F_GETFD) == 0) {
+    puts("test_generic_ioctl ok3");
+  }
+  close(fds[0]);
+  close(fds[1]);
+
+  int fd = open("ioctl_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  write(fd, "hello", 5);
+  lseek(fd, 1, SEEK_SET);
+  n = -1;
+  if (ioctl(fd, FIONREAD, &n) == 0 && n == 4) {
+    puts("test_generic_ioctl ok4");
+  }
+  // Requests for terminals are not taken by other files.
+  struct winsize ws;
+  if (ioctl(fd, TIOCGWINSZ, &ws) < 0 && errno == ENOTTY) {
+    puts("test_generic_ioctl ok5");
+  }
+  close(fd);
+  unlink("ioctl_file");
+}
+
+int main() {
+  test_dnotify();
+  test_setsig();
+  test_faccessat();
+  test_fsize();
+  test_largefile();
+  test_close();
+  test_dup2();
+  test_dupfd();
+  test_getfl();
+  test_generic_ioctl();
+  return 0;
+}
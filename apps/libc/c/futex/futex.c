@@ -0,0 +1,181 @@
+#include <errno.h>
+#include <linux/futex.h>
+#include <pthread.h>
+#include <sched.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define NUM_WAITERS 8
+
+static pthread_mutex_t lock = PTHREAD_MUTEX_INITIALIZER;
+static pthread_cond_t cond = PTHREAD_COND_INITIALIZER;
+static int ready = 0;
+static int waiting = 0;
+static int woken = 0;
+
+static void *cond_waiter(void *arg) {
+  pthread_mutex_lock(&lock);
+  waiting++;
+  while (!ready) {
+    pthread_cond_wait(&cond, &lock);
+  }
+  woken++;
+  pthread_mutex_unlock(&lock);
+  return NULL;
+}
+
+void test_cond_broadcast() {
+  pthread_t threads[NUM_WAITERS];
+  for (int i = 0; i < NUM_WAITERS; i++) {
+    pthread_create(&threads[i], NULL, cond_waiter, NULL);
+  }
+
+  // Wait until every thread is blocked on the condition variable
+  for (;;) {
+    pthread_mutex_lock(&lock);
+    int n = waiting;
+    pthread_mutex_unlock(&lock);
+    if (n == NUM_WAITERS) {
+      break;
+    }
+    sched_yield();
+  }
+
+  pthread_mutex_lock(&lock);
+  ready = 1;
+  pthread_cond_broadcast(&cond);
+  pthread_mutex_unlock(&lock);
+
+  for (int i = 0; i < NUM_WAITERS; i++) {
+    pthread_join(threads[i], NULL);
+  }
+  if (woken == NUM_WAITERS) {
+    puts("test_cond_broadcast ok");
+  }
+}
+
+// The owner is a child process, so that it can die holding the lock without
+// leaving a thread behind that the parent would have to join.
+void test_robust_mutex() {
+  pthread_mutex_t *robust_lock =
+      mmap(NULL, sizeof(pthread_mutex_t), PROT_READ | PROT_WRITE,
+           MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+  pthread_mutexattr_t attr;
+  pthread_mutexattr_init(&attr);
+  pthread_mutexattr_setrobust(&attr, PTHREAD_MUTEX_ROBUST);
+  pthread_mutexattr_setpshared(&attr, PTHREAD_PROCESS_SHARED);
+  pthread_mutex_init(robust_lock, &attr);
+
+  pid_t pid = fork();
+  if (pid == 0) {
+    pthread_mutex_lock(robust_lock);
+    // Exit without unlocking, so that only the kernel walks the robust list.
+    _exit(0);
+  }
+  waitpid(pid, NULL, 0);
+
+  if (pthread_mutex_lock(robust_lock) == EOWNERDEAD) {
+    puts("test_robust_mutex ok1");
+  }
+  pthread_mutex_consistent(robust_lock);
+  if (pthread_mutex_unlock(robust_lock) == 0) {
+    puts("test_robust_mutex ok2");
+  }
+  munmap(robust_lock, sizeof(pthread_mutex_t));
+}
+
+struct group_lock {
+  pthread_mutex_t mutex;
+  int held;
+};
+
+static struct group_lock *group_lock;
+static int never_woken;
+
+static void *group_lock_holder(void *arg) {
+  pthread_mutex_lock(&group_lock->mutex);
+  __atomic_store_n(&group_lock->held, 1, __ATOMIC_RELEASE);
+  syscall(SYS_futex, &never_woken, FUTEX_WAIT, 0, NULL, NULL, 0);
+  return NULL;
+}
+
+// A thread blocked in the kernel when another one exits the group still
+// releases its robust futexes as it dies.
+void test_robust_group_exit() {
+  group_lock = mmap(NULL, sizeof(*group_lock), PROT_READ | PROT_WRITE,
+                    MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+  pthread_mutexattr_t attr;
+  pthread_mutexattr_init(&attr);
+  pthread_mutexattr_setrobust(&attr, PTHREAD_MUTEX_ROBUST);
+  pthread_mutexattr_setpshared(&attr, PTHREAD_PROCESS_SHARED);
+  pthread_mutex_init(&group_lock->mutex, &attr);
+
+  pid_t pid = fork();
+  if (pid == 0) {
+    pthread_t thread;
+    pthread_create(&thread, NULL, group_lock_holder, NULL);
+    while (!__atomic_load_n(&group_lock->held, __ATOMIC_ACQUIRE)) {
+      sched_yield();
+    }
+    _exit(0);
+  }
+
+  // Give up after a while rather than hang if the holder never dies.
+  int exited = 0;
+  for (int i = 0; i < 200 && !exited; i++) {
+    exited = waitpid(pid, NULL, WNOHANG) == pid;
+    usleep(10000);
+  }
+  if (exited && pthread_mutex_trylock(&group_lock->mutex) == EOWNERDEAD) {
+    puts("test_robust_group_exit ok1");
+    pthread_mutex_consistent(&group_lock->mutex);
+    pthread_mutex_unlock(&group_lock->mutex);
+  }
+  if (exited) {
+    munmap(group_lock, sizeof(*group_lock));
+  }
+}
+
+static unsigned int pi_word;
+static volatile int pi_waiter_tid;
+
+static void *pi_waiter(void *arg) {
+  pi_waiter_tid = syscall(SYS_gettid);
+  syscall(SYS_futex, &pi_word, FUTEX_LOCK_PI, 0, NULL, NULL, 0);
+  long owned = (pi_word & FUTEX_TID_MASK) == pi_waiter_tid;
+  syscall(SYS_futex, &pi_word, FUTEX_UNLOCK_PI, 0, NULL, NULL, 0);
+  return (void *)owned;
+}
+
+// Unlocking a PI futex hands it over to the waiter, so that the owner cannot
+// take it back before the waiter runs.
+void test_pi_handoff() {
+  pthread_t thread;
+  void *owned;
+  // Taken in user space, like the C library does when there is no waiter.
+  pi_word = syscall(SYS_gettid);
+  pthread_create(&thread, NULL, pi_waiter, NULL);
+  while (!(__atomic_load_n(&pi_word, __ATOMIC_ACQUIRE) & FUTEX_WAITERS)) {
+    sched_yield();
+  }
+
+  syscall(SYS_futex, &pi_word, FUTEX_UNLOCK_PI, 0, NULL, NULL, 0);
+  if ((pi_word & FUTEX_TID_MASK) == pi_waiter_tid) {
+    puts("test_pi_handoff ok1");
+  }
+  pthread_join(thread, &owned);
+  if (owned && pi_word == 0) {
+    puts("test_pi_handoff ok2");
+  }
+}
+
+int main() {
+  test_cond_broadcast();
+  test_robust_mutex();
+  test_robust_group_exit();
+  test_pi_handoff();
+  return 0;
+}
@@ -0,0 +1,159 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define KEPT 64
+
+struct linux_dirent64 {
+  unsigned long long d_ino;
+  long long d_off;
+  unsigned short d_reclen;
+  unsigned char d_type;
+  char d_name[];
+};
+
+// Read the whole directory with a small buffer, so it takes many calls, and
+// count how often each kept file is seen. Returns -1 on errors.
+static int scan(int fd, int seen[KEPT]) {
+  char buf[256];
+  memset(seen, 0, KEPT * sizeof(int));
+  lseek(fd, 0, SEEK_SET);
+  for (;;) {
+    long n = syscall(SYS_getdents64, fd, buf, sizeof(buf));
+    if (n < 0) {
+      return -1;
+    }
+    if (n == 0) {
+      return 0;
+    }
+    for (long off = 0; off < n;) {
+      struct linux_dirent64 *ent = (struct linux_dirent64 *)(buf + off);
+      int i;
+      if (sscanf(ent->d_name, "keep_%d", &i) == 1 && i >= 0 && i < KEPT) {
+        seen[i]++;
+      }
+      off += ent->d_reclen;
+    }
+  }
+}
+
+void test_getdents_concurrent() {
+  char path[64];
+  mkdir("getdents_dir", 0755);
+  for (int i = 0; i < KEPT; i++) {
+    snprintf(path, sizeof(path), "getdents_dir/keep_%d", i);
+    close(open(path, O_CREAT | O_WRONLY, 0644));
+  }
+
+  // Another process keeps adding and removing files while we read.
+  pid_t pid = fork();
+  if (pid == 0) {
+    for (int n = 0;; n = (n + 1) % 32) {
+      snprintf(path, sizeof(path), "getdents_dir/tmp_%d", n);
+      close(open(path, O_CREAT | O_WRONLY, 0644));
+      snprintf(path, sizeof(path), "getdents_dir/tmp_%d", (n + 16) % 32);
+      unlink(path);
+    }
+  }
+
+  int fd = open("getdents_dir", O_RDONLY | O_DIRECTORY);
+  int ok = 1;
+  for (int round = 0; round < 20 && ok; round++) {
+    int seen[KEPT];
+    if (scan(fd, seen) < 0) {
+      ok = 0;
+    }
+    for (int i = 0; i < KEPT; i++) {
+      if (seen[i] != 1) {
+        ok = 0;
+      }
+    }
+  }
+  if (ok) {
+    puts("test_getdents_concurrent ok1");
+  }
+
+  kill(pid, SIGKILL);
+  waitpid(pid, NULL, 0);
+
+  // d_off of an entry is where the next one starts.
+  char buf[256];
+  lseek(fd, 0, SEEK_SET);
+  long n = syscall(SYS_getdents64, fd, buf, sizeof(buf));
+  struct linux_dirent64 *first = (struct linux_dirent64 *)buf;
+  struct linux_dirent64 *second =
+      (struct linux_dirent64 *)(buf + first->d_reclen);
+  char name[256];
+  strcpy(name, second->d_name);
+  if (n > first->d_reclen &&
+      lseek(fd, first->d_off, SEEK_SET) == first->d_off &&
+      syscall(SYS_getdents64, fd, buf, sizeof(buf)) > 0 &&
+      strcmp(((struct linux_dirent64 *)buf)->d_name, name) == 0) {
+    puts("test_getdents_concurrent ok2");
+  }
+  close(fd);
+
+  DIR *dir = opendir("getdents_dir");
+  struct dirent *ent;
+  while ((ent = readdir(dir)) != NULL) {
+    if (ent->d_name[0] != '.') {
+      char file[300];
+      snprintf(file, sizeof(file), "getdents_dir/%s", ent->d_name);
+      unlink(file);
+    }
+  }
+  closedir(dir);
+  rmdir("getdents_dir");
+}
+
+void test_getdents_types() {
+  mkdir("types_dir", 0755);
+  mkdir("types_dir/sub", 0755);
+  close(open("types_dir/reg", O_CREAT | O_WRONLY, 0644));
+
+  // Everything fits in one call, which the next one follows with nothing.
+  char buf[1024];
+  int fd = open("types_dir", O_RDONLY | O_DIRECTORY);
+  long n = syscall(SYS_getdents64, fd, buf, sizeof(buf));
+  int found = 0, ok = n > 0;
+  for (long off = 0; off < n;) {
+    struct linux_dirent64 *ent = (struct linux_dirent64 *)(buf + off);
+    const char *names[] = {".", "..", "sub", "reg"};
+    const unsigned char types[] = {DT_DIR, DT_DIR, DT_DIR, DT_REG};
+    for (int i = 0; i < 4; i++) {
+      if (strcmp(ent->d_name, names[i]) == 0) {
+        found |= 1 << i;
+        ok &= ent->d_type == types[i];
+      }
+    }
+    off += ent->d_reclen;
+  }
+  if (ok && found == 0xf &&
+      syscall(SYS_getdents64, fd, buf, sizeof(buf)) == 0) {
+    puts("test_getdents_types ok1");
+  }
+
+  // A buffer too small for a single entry is refused.
+  lseek(fd, 0, SEEK_SET);
+  if (syscall(SYS_getdents64, fd, buf, 8) < 0 && errno == EINVAL) {
+    puts("test_getdents_types ok2");
+  }
+  close(fd);
+  unlink("types_dir/reg");
+  rmdir("types_dir/sub");
+  rmdir("types_dir");
+}
+
+int main() {
+  test_getdents_concurrent();
+  test_getdents_types();
+  return 0;
+}
@@ -0,0 +1,111 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/times.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+static long long usecs(struct timeval tv) {
+  return tv.tv_sec * 1000000LL + tv.tv_usec;
+}
+
+static void spin(long long ms) {
+  struct timespec start, now;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  do {
+    clock_gettime(CLOCK_MONOTONIC, &now);
+  } while ((now.tv_sec - start.tv_sec) * 1000 +
+               (now.tv_nsec - start.tv_nsec) / 1000000 <
+           ms);
+}
+
+void test_getrusage() {
+  spin(50);
+
+  // The CPU time agrees with the CPU time clock.
+  struct rusage ru;
+  struct timespec before, after;
+  clock_gettime(CLOCK_PROCESS_CPUTIME_ID, &before);
+  int ok = getrusage(RUSAGE_SELF, &ru) == 0;
+  clock_gettime(CLOCK_PROCESS_CPUTIME_ID, &after);
+  long long used = usecs(ru.ru_utime) + usecs(ru.ru_stime);
+  if (ok && used >= before.tv_sec * 1000000LL + before.tv_nsec / 1000 &&
+      used <= after.tv_sec * 1000000LL + after.tv_nsec / 1000 + 1) {
+    puts("test_getrusage ok1");
+  }
+
+  // Touching memory raises the peak, which stays after it is unmapped.
+  long size = 8 << 20;
+  char *p = mmap(NULL, size, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  memset(p, 1, size);
+  munmap(p, size);
+  struct rusage self;
+  if (getrusage(RUSAGE_SELF, &self) == 0 &&
+      self.ru_maxrss >= ru.ru_maxrss + (size >> 10) / 2 &&
+      getrusage(RUSAGE_THREAD, &ru) == 0 &&
+      usecs(ru.ru_utime) + usecs(ru.ru_stime) > 0) {
+    puts("test_getrusage ok2");
+  }
+
+  // The children count once reaped.
+  struct rusage children;
+  getrusage(RUSAGE_CHILDREN, &children);
+  pid_t pid = fork();
+  if (pid == 0) {
+    spin(50);
+    _exit(0);
+  }
+  waitpid(pid, NULL, 0);
+  struct rusage reaped;
+  if (getrusage(RUSAGE_CHILDREN, &reaped) == 0 &&
+      usecs(reaped.ru_utime) + usecs(reaped.ru_stime) >=
+          usecs(children.ru_utime) + usecs(children.ru_stime) + 40000 &&
+      reaped.ru_maxrss > 0) {
+    puts("test_getrusage ok3");
+  }
+
+  if (getrusage(5, &ru) < 0 && errno == EINVAL) {
+    puts("test_getrusage ok4");
+  }
+}
+
+static volatile int stop_spinning;
+
+static void *spinner(void *arg) {
+  while (!stop_spinning) {
+  }
+  return NULL;
+}
+
+static long long cpu_ns(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_PROCESS_CPUTIME_ID, &ts);
+  return ts.tv_sec * 1000000000LL + ts.tv_nsec;
+}
+
+// The time of the other threads counts while they still run.
+void test_process_cputime() {
+  pthread_t thread;
+  struct timespec pause = {0, 100 * 1000000};
+  long long before = cpu_ns();
+  pthread_create(&thread, NULL, spinner, NULL);
+  nanosleep(&pause, NULL);
+  long long during = cpu_ns();
+  stop_spinning = 1;
+  pthread_join(thread, NULL);
+  if (during - before >= 50 * 1000000LL) {
+    puts("test_process_cputime ok");
+  }
+}
+
+int main() {
+  test_getrusage();
+  test_process_cputime();
+  return 0;
+}
@@ -0,0 +1,58 @@
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+// A function returning 42.
+#if defined(__x86_64__)
+static const unsigned char code[] = {0xb8, 0x2a, 0, 0, 0, 0xc3};
+#elif defined(__riscv)
+static const unsigned int code[] = {0x02a00513, 0x00008067};
+#elif defined(__aarch64__)
+static const unsigned int code[] = {0x52800540, 0xd65f03c0};
+#elif defined(__loongarch__)
+static const unsigned int code[] = {0x0280a804, 0x4c000020};
+#endif
+
+// Make the code at `[start, end)` visible to instruction fetches.
+static int flush_icache(void *start, void *end) {
+#ifdef SYS_riscv_flush_icache
+  return syscall(SYS_riscv_flush_icache, start, end, 0);
+#else
+  __builtin___clear_cache(start, end);
+  return 0;
+#endif
+}
+
+void test_icache() {
+  long page = sysconf(_SC_PAGESIZE);
+  char *p = mmap(NULL, page, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  memcpy(p, code, sizeof(code));
+  mprotect(p, page, PROT_READ | PROT_EXEC);
+  if (flush_icache(p, p + sizeof(code)) == 0 && ((int (*)(void))p)() == 42) {
+    puts("test_icache ok1");
+  }
+
+#ifdef SYS_riscv_flush_icache
+  // The flags are checked, and the range must be mapped.
+  int checked =
+      syscall(SYS_riscv_flush_icache, p, p + page, 2) < 0 && errno == EINVAL;
+  munmap(p, page);
+  checked = checked && syscall(SYS_riscv_flush_icache, p, p + page, 0) < 0 &&
+            errno == EFAULT;
+#else
+  int checked = 1;
+  munmap(p, page);
+#endif
+  if (checked) {
+    puts("test_icache ok2");
+  }
+}
+
+int main() {
+  test_icache();
+  return 0;
+}
@@ -0,0 +1,343 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <mqueue.h>
+#include <poll.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <time.h>
+#include <sys/ipc.h>
+#include <sys/msg.h>
+#include <sys/sem.h>
+#include <sys/shm.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void test_shm() {
+  int id = shmget(IPC_PRIVATE, 4096, IPC_CREAT | 0600);
+  char *mem = id < 0 ? (char *)-1 : shmat(id, NULL, 0);
+  if (mem != (char *)-1 && mem[0] == 0 && mem[4095] == 0) {
+    puts("test_shm ok1");
+  }
+
+  // The child writes through its inherited attachment.
+  pid_t pid = fork();
+  if (pid == 0) {
+    strcpy(mem, "hello");
+    shmdt(mem);
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (strcmp(mem, "hello") == 0) {
+    puts("test_shm ok2");
+  }
+
+  struct shmid_ds ds;
+  if (shmctl(id, IPC_STAT, &ds) == 0 && ds.shm_nattch == 1 &&
+      ds.shm_segsz == 4096 && ds.shm_cpid == getpid()) {
+    puts("test_shm ok3");
+  }
+
+  // A second attachment sees the same memory, but may not write to it.
+  char *ro = shmat(id, NULL, SHM_RDONLY);
+  if (ro != (char *)-1 && ro != mem && strcmp(ro, "hello") == 0 &&
+      shmctl(id, IPC_STAT, &ds) == 0 && ds.shm_nattch == 2) {
+    puts("test_shm ok4");
+  }
+  pid = fork();
+  if (pid == 0) {
+    ro[0] = 'x';
+    _exit(0);
+  }
+  waitpid(pid, &status, 0);
+  if (WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV) {
+    puts("test_shm ok5");
+  }
+
+  // A removed segment lives on until the last detach.
+  if (shmctl(id, IPC_RMID, NULL) == 0 && shmdt(ro) == 0 &&
+      strcmp(mem, "hello") == 0 && shmdt(mem) == 0 &&
+      shmctl(id, IPC_STAT, &ds) < 0 && errno == EINVAL) {
+    puts("test_shm ok6");
+  }
+
+  if (shmdt(mem) < 0 && errno == EINVAL) {
+    puts("test_shm ok7");
+  }
+}
+
+void test_shm_key() {
+  key_t key = 0x5eed;
+  int id = shmget(key, 100, IPC_CREAT | IPC_EXCL | 0600);
+  if (id >= 0 && shmget(key, 0, 0) == id && shmget(key, 50, IPC_CREAT) == id) {
+    puts("test_shm_key ok1");
+  }
+
+  if (shmget(key, 100, IPC_CREAT | IPC_EXCL | 0600) < 0 && errno == EEXIST) {
+    puts("test_shm_key ok2");
+  }
+
+  if (shmget(key, 200, 0) < 0 && errno == EINVAL) {
+    puts("test_shm_key ok3");
+  }
+
+  if (shmctl(id, IPC_RMID, NULL) == 0 && shmget(key, 0, 0) < 0 &&
+      errno == ENOENT) {
+    puts("test_shm_key ok4");
+  }
+}
+
+union semun {
+  int val;
+  struct semid_ds *buf;
+  unsigned short *array;
+};
+
+void test_sem() {
+  int id = semget(IPC_PRIVATE, 2, IPC_CREAT | 0600);
+  unsigned short vals[2] = {3, 0};
+  struct semid_ds ds;
+  if (id >= 0 && semctl(id, 0, SETALL, (union semun){.array = vals}) == 0 &&
+      semctl(id, 0, GETVAL) == 3 && semctl(id, 1, GETVAL) == 0 &&
+      semctl(id, 0, IPC_STAT, (union semun){.buf = &ds}) == 0 &&
+      ds.sem_nsems == 2) {
+    puts("test_sem ok1");
+  }
+
+  // The operations are applied all together or not at all.
+  struct sembuf both[2] = {{0, -1, IPC_NOWAIT}, {1, -1, IPC_NOWAIT}};
+  if (semop(id, both, 2) < 0 && errno == EAGAIN &&
+      semctl(id, 0, GETVAL) == 3) {
+    puts("test_sem ok2");
+  }
+
+  // The parent blocks until the child raises the second semaphore.
+  pid_t pid = fork();
+  if (pid == 0) {
+    usleep(50000);
+    struct sembuf up = {1, 1, 0};
+    semop(id, &up, 1);
+    _exit(0);
+  }
+  both[0].sem_flg = both[1].sem_flg = 0;
+  int status;
+  if (semop(id, both, 2) == 0 && semctl(id, 0, GETVAL) == 2 &&
+      semctl(id, 1, GETVAL) == 0 && semctl(id, 1, GETPID) == getpid()) {
+    puts("test_sem ok3");
+  }
+  waitpid(pid, &status, 0);
+
+  // The decrement of the child is undone when it exits.
+  pid = fork();
+  if (pid == 0) {
+    struct sembuf down = {0, -2, SEM_UNDO};
+    semop(id, &down, 1);
+    _exit(semctl(id, 0, GETVAL));
+  }
+  waitpid(pid, &status, 0);
+  if (WEXITSTATUS(status) == 0 && semctl(id, 0, GETVAL) == 2) {
+    puts("test_sem ok4");
+  }
+
+  if (semctl(id, 0, SETVAL, (union semun){.val = 40000}) < 0 &&
+      errno == ERANGE) {
+    puts("test_sem ok5");
+  }
+
+  // Removing the set wakes up the waiting child.
+  pid = fork();
+  if (pid == 0) {
+    struct sembuf down = {1, -1, 0};
+    _exit(semop(id, &down, 1) < 0 && errno == EIDRM);
+  }
+  usleep(50000);
+  semctl(id, 0, IPC_RMID);
+  waitpid(pid, &status, 0);
+  if (WEXITSTATUS(status) == 1 && semctl(id, 0, GETVAL) < 0 &&
+      errno == EINVAL) {
+    puts("test_sem ok6");
+  }
+}
+
+struct test_msg {
+  long mtype;
+  char mtext[16];
+};
+
+static int send_msg(int id, long type, const char *text, int flags) {
+  struct test_msg msg = {type};
+  strcpy(msg.mtext, text);
+  return msgsnd(id, &msg, strlen(text) + 1, flags);
+}
+
+void test_msg() {
+  int id = msgget(IPC_PRIVATE, IPC_CREAT | 0600);
+  struct test_msg msg;
+  send_msg(id, 3, "three", 0);
+  send_msg(id, 1, "one", 0);
+  send_msg(id, 2, "two", 0);
+  send_msg(id, 1, "uno", 0);
+
+  // Messages are picked by type: the first one, the first of a type, or the
+  // first of the lowest type up to a bound.
+  if (msgrcv(id, &msg, sizeof(msg.mtext), 0, 0) == 6 && msg.mtype == 3 &&
+      strcmp(msg.mtext, "three") == 0 &&
+      msgrcv(id, &msg, sizeof(msg.mtext), 2, 0) == 4 &&
+      strcmp(msg.mtext, "two") == 0 &&
+      msgrcv(id, &msg, sizeof(msg.mtext), -5, 0) == 4 &&
+      strcmp(msg.mtext, "one") == 0) {
+    puts("test_msg ok1");
+  }
+
+  // Messages too long for the buffer stay queued, unless truncated.
+  if (msgrcv(id, &msg, 2, 0, 0) < 0 && errno == E2BIG &&
+      msgrcv(id, &msg, 2, 0, MSG_NOERROR) == 2 &&
+      memcmp(msg.mtext, "un", 2) == 0) {
+    puts("test_msg ok2");
+  }
+
+  if (msgrcv(id, &msg, sizeof(msg.mtext), 0, IPC_NOWAIT) < 0 &&
+      errno == ENOMSG) {
+    puts("test_msg ok3");
+  }
+
+  // A full queue blocks the sender until the receiver makes room.
+  struct msqid_ds ds;
+  msgctl(id, IPC_STAT, &ds);
+  ds.msg_qbytes = 8;
+  msgctl(id, IPC_SET, &ds);
+  send_msg(id, 1, "1234567", 0);
+  if (send_msg(id, 1, "x", IPC_NOWAIT) < 0 && errno == EAGAIN) {
+    puts("test_msg ok4");
+  }
+  pid_t pid = fork();
+  if (pid == 0) {
+    usleep(50000);
+    msgrcv(id, &msg, sizeof(msg.mtext), 0, 0);
+    _exit(0);
+  }
+  int status;
+  if (send_msg(id, 1, "x", 0) == 0 && msgctl(id, IPC_STAT, &ds) == 0 &&
+      ds.msg_qnum == 1 && ds.msg_cbytes == 2 && ds.msg_qbytes == 8) {
+    puts("test_msg ok5");
+  }
+  waitpid(pid, &status, 0);
+
+  // Removing the queue wakes up the waiting receiver.
+  pid = fork();
+  if (pid == 0) {
+    _exit(msgrcv(id, &msg, sizeof(msg.mtext), 5, 0) < 0 && errno == EIDRM);
+  }
+  usleep(50000);
+  msgctl(id, IPC_RMID, NULL);
+  waitpid(pid, &status, 0);
+  if (WEXITSTATUS(status) == 1 && msgsnd(id, &msg, 1, 0) < 0 &&
+      errno == EINVAL) {
+    puts("test_msg ok6");
+  }
+}
+
+static volatile sig_atomic_t mq_signals;
+static volatile int mq_value;
+
+static void on_mq_signal(int sig, siginfo_t *info, void *ctx) {
+  mq_signals++;
+  mq_value = info->si_code == SI_MESGQ ? info->si_value.sival_int : -1;
+}
+
+void test_mq() {
+  struct mq_attr attr = {.mq_maxmsg = 3, .mq_msgsize = 16};
+  mq_unlink("/test_mq");
+  mqd_t mq = mq_open("/test_mq", O_RDWR | O_CREAT | O_EXCL, 0600, &attr);
+  char buf[16];
+  unsigned prio[3];
+  mq_send(mq, "low", 4, 1);
+  mq_send(mq, "high", 5, 5);
+  mq_send(mq, "low2", 5, 1);
+  // The highest priority comes first, then the oldest message.
+  if (mq != (mqd_t)-1 && mq_receive(mq, buf, sizeof(buf), &prio[0]) == 5 &&
+      strcmp(buf, "high") == 0 &&
+      mq_receive(mq, buf, sizeof(buf), &prio[1]) == 4 &&
+      strcmp(buf, "low") == 0 &&
+      mq_receive(mq, buf, sizeof(buf), &prio[2]) == 5 &&
+      strcmp(buf, "low2") == 0 && prio[0] == 5 && prio[1] == 1 &&
+      prio[2] == 1) {
+    puts("test_mq ok1");
+  }
+
+  if (mq_send(mq, buf, 17, 0) < 0 && errno == EMSGSIZE &&
+      mq_receive(mq, buf, 8, NULL) < 0 && errno == EMSGSIZE) {
+    puts("test_mq ok2");
+  }
+
+  // An empty queue blocks until the timeout.
+  struct timespec deadline;
+  clock_gettime(CLOCK_REALTIME, &deadline);
+  deadline.tv_nsec += 50000000;
+  if (deadline.tv_nsec >= 1000000000) {
+    deadline.tv_sec++;
+    deadline.tv_nsec -= 1000000000;
+  }
+  if (mq_timedreceive(mq, buf, sizeof(buf), NULL, &deadline) < 0 &&
+      errno == ETIMEDOUT) {
+    puts("test_mq ok3");
+  }
+
+  // Non-blocking queues fail instead, whether empty or full.
+  struct mq_attr new_attr = {.mq_flags = O_NONBLOCK}, old_attr;
+  mq_setattr(mq, &new_attr, &old_attr);
+  int full = mq_receive(mq, buf, sizeof(buf), NULL) < 0 && errno == EAGAIN;
+  for (int i = 0; i < 3; i++) {
+    mq_send(mq, "x", 2, 0);
+  }
+  full = full && mq_send(mq, "x", 2, 0) < 0 && errno == EAGAIN;
+  mq_getattr(mq, &attr);
+  struct pollfd pfd = {mq, POLLIN | POLLOUT};
+  if (full && old_attr.mq_flags == 0 && attr.mq_flags == O_NONBLOCK &&
+      attr.mq_curmsgs == 3 && attr.mq_maxmsg == 3 &&
+      poll(&pfd, 1, 0) == 1 && pfd.revents == POLLIN) {
+    puts("test_mq ok4");
+  }
+  while (mq_receive(mq, buf, sizeof(buf), NULL) > 0) {
+  }
+
+  // A message on the empty queue notifies the registered process once.
+  struct sigaction sa = {0};
+  sa.sa_sigaction = on_mq_signal;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGUSR2, &sa, NULL);
+  struct sigevent sev = {0};
+  sev.sigev_notify = SIGEV_SIGNAL;
+  sev.sigev_signo = SIGUSR2;
+  sev.sigev_value.sival_int = 42;
+  mq_notify(mq, &sev);
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(mq_notify(mq, &sev) < 0 && errno == EBUSY);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  mq_send(mq, "a", 2, 0);
+  mq_send(mq, "b", 2, 0);
+  if (WEXITSTATUS(status) == 1 && mq_signals == 1 && mq_value == 42) {
+    puts("test_mq ok5");
+  }
+
+  mq_close(mq);
+  if (mq_open("/test_mq", O_RDWR | O_CREAT | O_EXCL, 0600, &attr) ==
+          (mqd_t)-1 &&
+      errno == EEXIST && mq_unlink("/test_mq") == 0 &&
+      mq_open("/test_mq", O_RDWR) == (mqd_t)-1 && errno == ENOENT) {
+    puts("test_mq ok6");
+  }
+}
+
+int main() {
+  test_shm();
+  test_shm_key();
+  test_sem();
+  test_msg();
+  test_mq();
+  return 0;
+}
@@ -0,0 +1,58 @@
+#include <errno.h>
+#include <linux/membarrier.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int membarrier(int cmd, unsigned int flags) {
+  return syscall(SYS_membarrier, cmd, flags, 0);
+}
+
+void test_membarrier() {
+  int cmds = membarrier(MEMBARRIER_CMD_QUERY, 0);
+  if (cmds > 0 && (cmds & MEMBARRIER_CMD_PRIVATE_EXPEDITED)) {
+    puts("test_membarrier ok1");
+  }
+
+  if (membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0) < 0 && errno == EPERM) {
+    puts("test_membarrier ok2");
+  }
+
+  if (membarrier(MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED, 0) == 0 &&
+      membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0) == 0) {
+    puts("test_membarrier ok3");
+  }
+
+  if (membarrier(MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED_SYNC_CORE, 0) == 0 &&
+      membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE, 0) == 0) {
+    puts("test_membarrier ok4");
+  }
+}
+
+// The registrations are dropped by `execve`.
+void test_membarrier_exec(char *self) {
+  membarrier(MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED, 0);
+  pid_t pid = fork();
+  if (pid == 0) {
+    char *argv[] = {self, "exec", NULL};
+    execv(self, argv);
+    _exit(2);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_membarrier_exec ok");
+  }
+}
+
+int main(int argc, char **argv) {
+  if (argc == 2 && strcmp(argv[1], "exec") == 0) {
+    int ret = membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED, 0);
+    return ret < 0 && errno == EPERM ? 0 : 1;
+  }
+  test_membarrier();
+  test_membarrier_exec(argv[0]);
+  return 0;
+}
@@ -0,0 +1,292 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/statfs.h>
+#include <sys/statvfs.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// Bind `dir` onto itself, and give the new mount the options in `flags`.
+static int mount_self(const char *dir, unsigned long flags) {
+  if (mount(dir, dir, NULL, MS_BIND, NULL) < 0) {
+    return -1;
+  }
+  return mount(NULL, dir, NULL, MS_REMOUNT | MS_BIND | flags, NULL);
+}
+
+void test_statfs_flags() {
+  struct statfs st;
+  mkdir("mnt_statfs", 0755);
+  if (mount_self("mnt_statfs", MS_RDONLY | MS_NOEXEC) == 0) {
+    puts("test_statfs_flags ok1");
+  }
+
+  if (statfs("mnt_statfs", &st) == 0 && (st.f_flags & ST_RDONLY) &&
+      (st.f_flags & ST_NOEXEC) && !(st.f_flags & ST_NOSUID)) {
+    puts("test_statfs_flags ok2");
+  }
+
+  umount("mnt_statfs");
+  if (statfs("mnt_statfs", &st) == 0 && !(st.f_flags & ST_RDONLY)) {
+    puts("test_statfs_flags ok3");
+  }
+  rmdir("mnt_statfs");
+}
+
+void test_mount_rdonly() {
+  mkdir("mnt_ro", 0755);
+  close(open("mnt_ro/file", O_CREAT | O_WRONLY, 0644));
+  mount_self("mnt_ro", MS_RDONLY);
+
+  if (open("mnt_ro/file", O_WRONLY) < 0 && errno == EROFS) {
+    puts("test_mount_rdonly ok1");
+  }
+
+  int fd = open("mnt_ro/file", O_RDONLY);
+  if (fd >= 0) {
+    puts("test_mount_rdonly ok2");
+    close(fd);
+  }
+
+  if (mkdir("mnt_ro/dir", 0755) < 0 && errno == EROFS &&
+      unlink("mnt_ro/file") < 0 && errno == EROFS) {
+    puts("test_mount_rdonly ok3");
+  }
+
+  umount("mnt_ro");
+  if (unlink("mnt_ro/file") == 0) {
+    puts("test_mount_rdonly ok4");
+  }
+  rmdir("mnt_ro");
+}
+
+static void copy_file(const char *from, const char *to) {
+  char buf[4096];
+  int in = open(from, O_RDONLY);
+  int out = open(to, O_CREAT | O_WRONLY | O_TRUNC, 0755);
+  ssize_t n;
+  while ((n = read(in, buf, sizeof(buf))) > 0) {
+    write(out, buf, n);
+  }
+  close(in);
+  close(out);
+}
+
+void test_mount_noexec(const char *self) {
+  char *const argv[] = {"mnt_noexec/prog", "child", NULL};
+  char *const envp[] = {NULL};
+  mkdir("mnt_noexec", 0755);
+  copy_file(self, "mnt_noexec/prog");
+  mount_self("mnt_noexec", MS_NOEXEC);
+
+  if (execve(argv[0], argv, envp) < 0 && errno == EACCES) {
+    puts("test_mount_noexec ok1");
+  }
+
+  // The same binary runs once the mount is gone.
+  umount("mnt_noexec");
+  pid_t pid = fork();
+  if (pid == 0) {
+    execve(argv[0], argv, envp);
+    _exit(1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 7) {
+    puts("test_mount_noexec ok2");
+  }
+  unlink("mnt_noexec/prog");
+  rmdir("mnt_noexec");
+}
+
+void test_remount() {
+  mkdir("mnt_remount", 0755);
+  mount_self("mnt_remount", MS_RDONLY);
+  if (open("mnt_remount/file", O_CREAT | O_WRONLY, 0644) < 0 &&
+      errno == EROFS) {
+    puts("test_remount ok1");
+  }
+
+  if (mount(NULL, "mnt_remount", NULL, MS_REMOUNT | MS_BIND, NULL) == 0) {
+    int fd = open("mnt_remount/file", O_CREAT | O_WRONLY, 0644);
+    if (fd >= 0 && write(fd, "x", 1) == 1) {
+      puts("test_remount ok2");
+    }
+    close(fd);
+  }
+
+  struct statfs st;
+  unsigned long ro = MS_REMOUNT | MS_BIND | MS_RDONLY;
+  if (mount(NULL, "mnt_remount", NULL, ro, NULL) == 0 &&
+      unlink("mnt_remount/file") < 0 && errno == EROFS &&
+      statfs("mnt_remount", &st) == 0 && (st.f_flags & ST_RDONLY)) {
+    puts("test_remount ok3");
+  }
+
+  mkdir("mnt_plain", 0755);
+  if (mount(NULL, "mnt_plain", NULL, MS_REMOUNT, NULL) < 0 && errno == EINVAL) {
+    puts("test_remount ok4");
+  }
+  rmdir("mnt_plain");
+
+  umount("mnt_remount");
+  unlink("mnt_remount/file");
+  rmdir("mnt_remount");
+}
+
+static int atime_changes(const char *path) {
+  char buf[4];
+  struct stat before, after;
+  int fd = open(path, O_RDONLY);
+  stat(path, &before);
+  // Make sure a new timestamp would differ from the old one.
+  usleep(1000);
+  read(fd, buf, sizeof(buf));
+  close(fd);
+  stat(path, &after);
+  return before.st_atim.tv_sec != after.st_atim.tv_sec ||
+         before.st_atim.tv_nsec != after.st_atim.tv_nsec;
+}
+
+void test_atime() {
+  mkdir("mnt_atime", 0755);
+  int fd = open("mnt_atime/file", O_CREAT | O_WRONLY, 0644);
+  write(fd, "data", 4);
+  close(fd);
+
+  if (atime_changes("mnt_atime/file") && atime_changes("mnt_atime/file")) {
+    puts("test_atime ok1");
+  }
+
+  // Under relatime, only the first read after the write updates the atime.
+  struct statfs st;
+  mount_self("mnt_atime", MS_RELATIME);
+  fd = open("mnt_atime/file", O_WRONLY);
+  write(fd, "data", 4);
+  close(fd);
+  if (statfs("mnt_atime", &st) == 0 && (st.f_flags & ST_RELATIME) &&
+      atime_changes("mnt_atime/file") && !atime_changes("mnt_atime/file")) {
+    puts("test_atime ok2");
+  }
+
+  mount(NULL, "mnt_atime", NULL, MS_REMOUNT | MS_BIND | MS_NOATIME, NULL);
+  fd = open("mnt_atime/file", O_WRONLY);
+  write(fd, "data", 4);
+  close(fd);
+  if (!atime_changes("mnt_atime/file")) {
+    puts("test_atime ok3");
+  }
+
+  umount("mnt_atime");
+  unlink("mnt_atime/file");
+  rmdir("mnt_atime");
+}
+
+// Whether the file `path` holds exactly `data`.
+static int has_contents(const char *path, const char *data) {
+  char buf[32] = {0};
+  int fd = open(path, O_RDONLY);
+  if (fd < 0) {
+    return 0;
+  }
+  read(fd, buf, sizeof(buf) - 1);
+  close(fd);
+  return strcmp(buf, data) == 0;
+}
+
+void test_bind_rec() {
+  struct statfs st;
+  mkdir("bind_src", 0755);
+  mount_self("bind_src", MS_NOEXEC);
+  mkdir("bind_src/inner", 0755);
+  mount_self("bind_src/inner", MS_NOSUID);
+  int fd = open("bind_src/inner/file", O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  write(fd, "inner", 5);
+  close(fd);
+  mkdir("bind_rec", 0755);
+  mkdir("bind_top", 0755);
+
+  // A recursive bind brings the mounts below the source along.
+  if (mount("bind_src", "bind_rec", NULL, MS_BIND | MS_REC, NULL) == 0 &&
+      statfs("bind_rec", &st) == 0 && (st.f_flags & ST_NOEXEC) &&
+      statfs("bind_rec/inner", &st) == 0 &&
+      (st.f_flags & ST_NOSUID)) {
+    puts("test_bind_rec ok1");
+  }
+
+  // A plain one only binds the top.
+  if (mount("bind_src", "bind_top", NULL, MS_BIND, NULL) == 0 &&
+      statfs("bind_top", &st) == 0 && (st.f_flags & ST_NOEXEC) &&
+      statfs("bind_top/inner", &st) == 0 && !(st.f_flags & ST_NOSUID)) {
+    puts("test_bind_rec ok2");
+  }
+
+  // The files of the submount are found through the target.
+  if (has_contents("bind_rec/inner/file", "inner")) {
+    puts("test_bind_rec ok3");
+  }
+
+  // And files made through the target show up in the source.
+  fd = open("bind_rec/made", O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  write(fd, "made", 4);
+  close(fd);
+  if (has_contents("bind_src/made", "made")) {
+    puts("test_bind_rec ok4");
+  }
+
+  unlink("bind_src/made");
+  unlink("bind_src/inner/file");
+  umount("bind_top");
+  umount("bind_rec/inner");
+  umount("bind_rec");
+  umount("bind_src/inner");
+  umount("bind_src");
+  rmdir("bind_top");
+  rmdir("bind_rec");
+  rmdir("bind_src/inner");
+  rmdir("bind_src");
+}
+
+void test_umount_detach() {
+  mkdir("detach_src", 0755);
+  close(open("detach_src/file", O_CREAT | O_WRONLY, 0644));
+  mkdir("mnt_detach", 0755);
+  mount("detach_src", "mnt_detach", NULL, MS_BIND, NULL);
+  int fd = open("mnt_detach/file", O_RDWR);
+  if (fd >= 0 && umount("mnt_detach") < 0 && errno == EBUSY) {
+    puts("test_umount_detach ok1");
+  }
+
+  // New lookups no longer see it, while the open file stays usable.
+  if (umount2("mnt_detach", MNT_DETACH) == 0 &&
+      access("mnt_detach/file", F_OK) < 0 && errno == ENOENT &&
+      write(fd, "x", 1) == 1) {
+    puts("test_umount_detach ok2");
+  }
+
+  close(fd);
+  if (umount("mnt_detach") < 0 && errno == EINVAL &&
+      has_contents("detach_src/file", "x")) {
+    puts("test_umount_detach ok3");
+  }
+  unlink("detach_src/file");
+  rmdir("detach_src");
+  rmdir("mnt_detach");
+}
+
+int main(int argc, char **argv) {
+  if (argc > 1 && strcmp(argv[1], "child") == 0) {
+    return 7;
+  }
+  test_statfs_flags();
+  test_mount_rdonly();
+  test_mount_noexec(argv[0]);
+  test_remount();
+  test_atime();
+  test_bind_rec();
+  test_umount_detach();
+  return 0;
+}
@@ -0,0 +1,57 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define PAGE 4096
+
+// Whether reading `addr` kills a child with SIGSEGV.
+static int faults(volatile char *addr) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    (void)*addr;
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  return WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV;
+}
+
+void test_mprotect_none() {
+  // A guard page in the middle leaves its neighbours alone.
+  char *p = mmap(NULL, 3 * PAGE, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  memset(p, 'x', 3 * PAGE);
+  if (mprotect(p + PAGE, PAGE, PROT_NONE) == 0 && faults(p + PAGE) &&
+      !faults(p) && !faults(p + 2 * PAGE)) {
+    puts("test_mprotect_none ok1");
+  }
+  if (mprotect(p + PAGE, PAGE, PROT_READ | PROT_WRITE) == 0 &&
+      p[PAGE] == 'x' && p[2 * PAGE - 1] == 'x') {
+    puts("test_mprotect_none ok2");
+  }
+  munmap(p, 3 * PAGE);
+
+  // The contents of a file mapping survive too.
+  char buf[PAGE];
+  memset(buf, 'f', sizeof(buf));
+  int fd = open("mprotect_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  write(fd, buf, sizeof(buf));
+  p = mmap(NULL, PAGE, PROT_READ, MAP_PRIVATE, fd, 0);
+  if (p != MAP_FAILED && mprotect(p, PAGE, PROT_NONE) == 0 && faults(p) &&
+      mprotect(p, PAGE, PROT_READ) == 0 && memcmp(p, buf, PAGE) == 0) {
+    puts("test_mprotect_none ok3");
+  }
+  munmap(p, PAGE);
+  close(fd);
+  unlink("mprotect_file");
+}
+
+int main() {
+  test_mprotect_none();
+  return 0;
+}
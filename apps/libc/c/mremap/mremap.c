@@ -0,0 +1,66 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define PAGE 4096
+
+// Whether touching `addr` kills a child with SIGSEGV.
+static int faults(volatile char *addr) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    *addr = 1;
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  return WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV;
+}
+
+static void *map(void *addr, size_t len, int flags) {
+  return mmap(addr, len, PROT_READ | PROT_WRITE,
+              MAP_PRIVATE | MAP_ANONYMOUS | flags, -1, 0);
+}
+
+void test_mremap() {
+  // Shrinking keeps the head in place and unmaps the tail.
+  char *p = map(NULL, 4 * PAGE, 0);
+  for (int i = 0; i < 4; i++) {
+    memset(p + i * PAGE, 'a' + i, PAGE);
+  }
+  if (mremap(p, 4 * PAGE, 2 * PAGE, 0) == p && p[0] == 'a' &&
+      p[2 * PAGE - 1] == 'b') {
+    puts("test_mremap ok1");
+  }
+  if (faults(p + 2 * PAGE) && faults(p + 4 * PAGE - 1)) {
+    puts("test_mremap ok2");
+  }
+
+  // Growing may move it, taking the contents along.
+  char *q = mremap(p, 2 * PAGE, 8 * PAGE, MREMAP_MAYMOVE);
+  if (q != MAP_FAILED && q[0] == 'a' && q[2 * PAGE - 1] == 'b') {
+    q[8 * PAGE - 1] = 'z';
+    puts("test_mremap ok3");
+  }
+  munmap(q, 8 * PAGE);
+
+  // Without MREMAP_MAYMOVE, it cannot grow into the next mapping.
+  p = map(NULL, 3 * PAGE, 0);
+  munmap(p + 2 * PAGE, PAGE);
+  map(p + 2 * PAGE, PAGE, MAP_FIXED);
+  if (mremap(p, 2 * PAGE, 3 * PAGE, 0) == MAP_FAILED && errno == ENOMEM &&
+      mremap(p + PAGE, PAGE, 2 * PAGE, MREMAP_FIXED) == MAP_FAILED &&
+      errno == EINVAL) {
+    puts("test_mremap ok4");
+  }
+  munmap(p, 3 * PAGE);
+}
+
+int main() {
+  test_mremap();
+  return 0;
+}
@@ -0,0 +1,53 @@
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#ifndef SYS_mseal
+#define SYS_mseal 462
+#endif
+
+static int seal(void *addr, size_t len) {
+  return syscall(SYS_mseal, addr, len, 0);
+}
+
+void test_mseal() {
+  long page = sysconf(_SC_PAGESIZE);
+  char *p = mmap(NULL, 2 * page, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  strcpy(p, "sealed");
+  if (seal(p, 2 * page) == 0) {
+    puts("test_mseal ok1");
+  }
+
+  // The mapping can no longer be changed, even in part.
+  if (mprotect(p, page, PROT_READ) < 0 && errno == EPERM &&
+      munmap(p + page, page) < 0 && errno == EPERM &&
+      mremap(p, 2 * page, page, 0) == MAP_FAILED && errno == EPERM &&
+      mmap(p, page, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1,
+           0) == MAP_FAILED &&
+      errno == EPERM) {
+    puts("test_mseal ok2");
+  }
+
+  // But it can still be used.
+  p[page] = 'x';
+  if (strcmp(p, "sealed") == 0 && p[page] == 'x') {
+    puts("test_mseal ok3");
+  }
+
+  // The range must be mapped, and the flags are reserved.
+  char *q = mmap(NULL, page, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  munmap(q, page);
+  if (seal(q, page) < 0 && errno == ENOMEM &&
+      syscall(SYS_mseal, p, page, 1) < 0 && errno == EINVAL) {
+    puts("test_mseal ok4");
+  }
+}
+
+int main() {
+  test_mseal();
+  return 0;
+}
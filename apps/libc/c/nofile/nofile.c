@@ -0,0 +1,63 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <unistd.h>
+
+void test_nofile() {
+  struct rlimit old, lim;
+  getrlimit(RLIMIT_NOFILE, &old);
+
+  // With room for 4 more descriptors, the 5th open fails.
+  int base = dup(0);
+  close(base);
+  lim.rlim_cur = base + 4;
+  lim.rlim_max = old.rlim_max;
+  setrlimit(RLIMIT_NOFILE, &lim);
+  int fds[5], opened = 0;
+  for (int i = 0; i < 5; i++) {
+    fds[i] = open("/dev/null", O_RDONLY);
+    if (fds[i] < 0) {
+      break;
+    }
+    opened++;
+  }
+  if (opened == 4 && fds[4] < 0 && errno == EMFILE) {
+    puts("test_nofile ok1");
+  }
+
+  int p[2];
+  if (dup(0) < 0 && errno == EMFILE && pipe(p) < 0 && errno == EMFILE &&
+      fcntl(0, F_DUPFD, 0) < 0 && errno == EMFILE) {
+    puts("test_nofile ok2");
+  }
+
+  // A target at or above the limit is rejected.
+  if (dup2(0, base + 4) < 0 && errno == EBADF &&
+      fcntl(0, F_DUPFD, base + 4) < 0 && errno == EINVAL) {
+    puts("test_nofile ok3");
+  }
+
+  // Lowering the limit below the open descriptors keeps them open, but
+  // nothing new can be opened until enough are closed.
+  lim.rlim_cur = base;
+  int lowered = setrlimit(RLIMIT_NOFILE, &lim) == 0;
+  int blocked = open("/dev/null", O_RDONLY) < 0 && errno == EMFILE;
+  int still_open = fcntl(fds[3], F_GETFD) >= 0;
+  for (int i = 0; i < 4; i++) {
+    close(fds[i]);
+  }
+  close(2);
+  int fd = open("/dev/null", O_RDONLY);
+  if (lowered && blocked && still_open && fd == 2) {
+    puts("test_nofile ok4");
+  }
+
+  setrlimit(RLIMIT_NOFILE, &old);
+  dup2(1, 2);
+}
+
+int main() {
+  test_nofile();
+  return 0;
+}
@@ -0,0 +1,408 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void test_pipe_read() {
+  int fds[2];
+  char buf[16];
+  pipe(fds);
+
+  // Data present is returned right away, even if less than asked for.
+  write(fds[1], "hello", 5);
+  if (read(fds[0], buf, sizeof(buf)) == 5 && memcmp(buf, "hello", 5) == 0) {
+    puts("test_pipe_read ok1");
+  }
+
+  // Empty with the write end open: non-blocking reads fail with EAGAIN.
+  fcntl(fds[0], F_SETFL, O_NONBLOCK);
+  if (read(fds[0], buf, sizeof(buf)) < 0 && errno == EAGAIN) {
+    puts("test_pipe_read ok2");
+  }
+  fcntl(fds[0], F_SETFL, 0);
+
+  // Blocking reads wait for the writer.
+  pid_t pid = fork();
+  if (pid == 0) {
+    usleep(50000);
+    write(fds[1], "x", 1);
+    _exit(0);
+  }
+  if (read(fds[0], buf, sizeof(buf)) == 1 && buf[0] == 'x') {
+    puts("test_pipe_read ok3");
+  }
+  waitpid(pid, NULL, 0);
+
+  // Empty with the write end closed by everyone: EOF.
+  close(fds[1]);
+  if (read(fds[0], buf, sizeof(buf)) == 0) {
+    puts("test_pipe_read ok4");
+  }
+
+  // Data still buffered is read before EOF is reported.
+  pipe(fds);
+  write(fds[1], "ab", 2);
+  close(fds[1]);
+  if (read(fds[0], buf, 1) == 1 && read(fds[0], buf, sizeof(buf)) == 1 &&
+      buf[0] == 'b' && read(fds[0], buf, sizeof(buf)) == 0) {
+    puts("test_pipe_read ok5");
+  }
+  close(fds[0]);
+}
+
+void test_pipe_write() {
+  int fds[2];
+  char buf[512];
+  memset(buf, 'w', sizeof(buf));
+  pipe(fds);
+
+  // Fill the pipe up: a blocking zero-byte write still returns right away.
+  fcntl(fds[1], F_SETFL, O_NONBLOCK);
+  while (write(fds[1], buf, sizeof(buf)) > 0) {
+  }
+  fcntl(fds[1], F_SETFL, 0);
+  if (write(fds[1], buf, 0) == 0) {
+    puts("test_pipe_write ok1");
+  }
+
+  // A full pipe blocks the writer until the reader makes room.
+  pid_t pid = fork();
+  if (pid == 0) {
+    char drain[4096];
+    usleep(50000);
+    read(fds[0], drain, sizeof(drain));
+    _exit(0);
+  }
+  if (write(fds[1], buf, 1) == 1) {
+    puts("test_pipe_write ok2");
+  }
+  waitpid(pid, NULL, 0);
+  close(fds[0]);
+  close(fds[1]);
+
+  // Several writers and a slow reader: every byte makes it through, and
+  // every writer finishes.
+  enum { WRITERS = 4, PER_WRITER = 20000 };
+  pipe(fds);
+  pid_t writers[WRITERS];
+  for (int i = 0; i < WRITERS; i++) {
+    writers[i] = fork();
+    if (writers[i] == 0) {
+      close(fds[0]);
+      int left = PER_WRITER;
+      while (left > 0) {
+        int n = write(fds[1], buf, left < 300 ? left : 300);
+        if (n <= 0) {
+          _exit(1);
+        }
+        left -= n;
+      }
+      _exit(0);
+    }
+  }
+  close(fds[1]);
+  long total = 0;
+  int n, reads = 0;
+  while ((n = read(fds[0], buf, 100)) > 0) {
+    total += n;
+    if (++reads % 64 == 0) {
+      usleep(1000);
+    }
+  }
+  int ok = 1;
+  for (int i = 0; i < WRITERS; i++) {
+    int status;
+    waitpid(writers[i], &status, 0);
+    ok &= WIFEXITED(status) && WEXITSTATUS(status) == 0;
+  }
+  if (ok && total == WRITERS * PER_WRITER) {
+    puts("test_pipe_write ok3");
+  }
+  close(fds[0]);
+}
+
+void test_pipe_atomic() {
+  // Writers each send records of PIPE_BUF bytes filled with their own
+  // letter, more than the pipe holds at once.
+  enum { WRITERS = 4, RECORDS = 8 };
+  static char data[WRITERS * RECORDS * PIPE_BUF];
+  int fds[2];
+  pipe(fds);
+  for (int i = 0; i < WRITERS; i++) {
+    if (fork() == 0) {
+      char record[PIPE_BUF];
+      memset(record, 'a' + i, sizeof(record));
+      for (int j = 0; j < RECORDS; j++) {
+        if (write(fds[1], record, sizeof(record)) != sizeof(record)) {
+          _exit(1);
+        }
+      }
+      _exit(0);
+    }
+  }
+  close(fds[1]);
+  long total = 0;
+  int n;
+  while ((n = read(fds[0], data + total, sizeof(data) - total)) > 0) {
+    total += n;
+  }
+  for (int i = 0; i < WRITERS; i++) {
+    wait(NULL);
+  }
+  close(fds[0]);
+
+  // No record was split by the data of another writer.
+  int ok = total == sizeof(data);
+  for (long i = 0; ok && i < total; i += PIPE_BUF) {
+    for (int j = 1; j < PIPE_BUF; j++) {
+      ok &= data[i + j] == data[i];
+    }
+  }
+  if (ok) {
+    puts("test_pipe_atomic ok1");
+  }
+}
+
+// Whether all the children exit with 0 before they are killed by their
+// alarm.
+static int children_done(int count) {
+  int ok = 1;
+  for (int i = 0; i < count; i++) {
+    int status;
+    wait(&status);
+    ok &= WIFEXITED(status) && WEXITSTATUS(status) == 0;
+  }
+  return ok;
+}
+
+void test_pipe_wake() {
+  enum { WAITERS = 3 };
+  static char buf[65536];
+  int fds[2];
+  pipe(fds);
+
+  // Readers blocked on an empty pipe each get a byte of a single write.
+  for (int i = 0; i < WAITERS; i++) {
+    if (fork() == 0) {
+      alarm(5);
+      _exit(read(fds[0], buf, 1) == 1 ? 0 : 1);
+    }
+  }
+  usleep(50000);
+  write(fds[1], "abc", WAITERS);
+  if (children_done(WAITERS)) {
+    puts("test_pipe_wake ok1");
+  }
+
+  // Writers blocked on a full pipe each get room from a read emptying it.
+  fcntl(fds[1], F_SETFL, O_NONBLOCK);
+  while (write(fds[1], "x", 1) == 1) {
+  }
+  fcntl(fds[1], F_SETFL, 0);
+  for (int i = 0; i < WAITERS; i++) {
+    if (fork() == 0) {
+      alarm(5);
+      _exit(write(fds[1], "y", 1) == 1 ? 0 : 1);
+    }
+  }
+  usleep(50000);
+  read(fds[0], buf, sizeof(buf));
+  if (children_done(WAITERS)) {
+    puts("test_pipe_wake ok2");
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+void test_pipe2(const char *self) {
+  int fds[2];
+  char buf[512];
+
+  // O_NONBLOCK applies to both ends.
+  pipe2(fds, O_NONBLOCK);
+  if (read(fds[0], buf, sizeof(buf)) < 0 && errno == EAGAIN) {
+    puts("test_pipe2 ok1");
+  }
+  while (write(fds[1], buf, sizeof(buf)) > 0) {
+  }
+  if (errno == EAGAIN) {
+    puts("test_pipe2 ok2");
+  }
+  close(fds[0]);
+  close(fds[1]);
+
+  // O_CLOEXEC sets FD_CLOEXEC on both ends, and F_SETFD can change it.
+  int plain[2];
+  pipe(plain);
+  pipe2(fds, O_CLOEXEC);
+  if (fcntl(fds[0], F_GETFD) == FD_CLOEXEC &&
+      fcntl(fds[1], F_GETFD) == FD_CLOEXEC && fcntl(plain[0], F_GETFD) == 0 &&
+      fcntl(plain[0], F_SETFD, FD_CLOEXEC) == 0 &&
+      fcntl(plain[0], F_GETFD) == FD_CLOEXEC &&
+      fcntl(plain[0], F_SETFD, 0) == 0) {
+    puts("test_pipe2 ok3");
+  }
+
+  // execve closes the FD_CLOEXEC descriptors and keeps the others.
+  pid_t pid = fork();
+  if (pid == 0) {
+    char cloexec_fd[16], plain_fd[16];
+    snprintf(cloexec_fd, sizeof(cloexec_fd), "%d", fds[0]);
+    snprintf(plain_fd, sizeof(plain_fd), "%d", plain[0]);
+    char *const argv[] = {(char *)self, "child", cloexec_fd, plain_fd, NULL};
+    char *const envp[] = {NULL};
+    execve(self, argv, envp);
+    _exit(1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 7) {
+    puts("test_pipe2 ok4");
+  }
+  close(fds[0]);
+  close(fds[1]);
+  close(plain[0]);
+  close(plain[1]);
+
+  // Unknown flags are rejected.
+  if (pipe2(fds, O_APPEND) < 0 && errno == EINVAL) {
+    puts("test_pipe2 ok5");
+  }
+}
+
+void test_pipe_broken() {
+  int fds[2];
+
+  // Writing with no reader left raises SIGPIPE, which kills by default.
+  pipe(fds);
+  close(fds[0]);
+  pid_t pid = fork();
+  if (pid == 0) {
+    write(fds[1], "x", 1);
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFSIGNALED(status) && WTERMSIG(status) == SIGPIPE) {
+    puts("test_pipe_broken ok1");
+  }
+
+  // With SIGPIPE ignored, the write fails with EPIPE instead.
+  signal(SIGPIPE, SIG_IGN);
+  if (write(fds[1], "x", 1) < 0 && errno == EPIPE) {
+    puts("test_pipe_broken ok2");
+  }
+  signal(SIGPIPE, SIG_DFL);
+  close(fds[1]);
+}
+
+void test_splice() {
+  int fds[2], other[2];
+  char buf[16];
+  int fd = open("splice_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  write(fd, "hello world", 11);
+  lseek(fd, 0, SEEK_SET);
+  pipe(fds);
+
+  // From a file at an offset: the offset advances, the file position not.
+  loff_t off = 6;
+  if (splice(fd, &off, fds[1], NULL, 5, 0) == 5 && off == 11 &&
+      lseek(fd, 0, SEEK_CUR) == 0 && read(fds[0], buf, sizeof(buf)) == 5 &&
+      memcmp(buf, "world", 5) == 0) {
+    puts("test_splice ok1");
+  }
+
+  // To a file at its position, with less data than asked for.
+  write(fds[1], "abc", 3);
+  if (splice(fds[0], NULL, fd, NULL, 100, 0) == 3 &&
+      lseek(fd, 0, SEEK_CUR) == 3 && pread(fd, buf, 5, 0) == 5 &&
+      memcmp(buf, "abclo", 5) == 0) {
+    puts("test_splice ok2");
+  }
+
+  // Between pipes: the data leaves the first one.
+  pipe(other);
+  write(fds[1], "xyz", 3);
+  if (splice(fds[0], NULL, other[1], NULL, 2, 0) == 2 &&
+      read(other[0], buf, sizeof(buf)) == 2 && memcmp(buf, "xy", 2) == 0 &&
+      read(fds[0], buf, sizeof(buf)) == 1 && buf[0] == 'z') {
+    puts("test_splice ok3");
+  }
+
+  // One end must be a pipe, and pipes have no offset.
+  if (splice(fd, NULL, fd, NULL, 1, 0) < 0 && errno == EINVAL &&
+      splice(fds[0], &off, fd, NULL, 1, 0) < 0 && errno == ESPIPE) {
+    puts("test_splice ok4");
+  }
+
+  // SPLICE_F_NONBLOCK does not wait for data, but EOF still reads as 0.
+  if (splice(fds[0], NULL, fd, NULL, 1, SPLICE_F_NONBLOCK) < 0 &&
+      errno == EAGAIN) {
+    close(fds[1]);
+    if (splice(fds[0], NULL, fd, NULL, 1, 0) == 0) {
+      puts("test_splice ok5");
+    }
+  }
+  close(fds[0]);
+  close(other[0]);
+  close(other[1]);
+  close(fd);
+  unlink("splice_file");
+}
+
+void test_tee() {
+  int fds[2], other[2];
+  char buf[16];
+  pipe(fds);
+  pipe(other);
+
+  // The data is copied and stays in the source pipe.
+  write(fds[1], "tee", 3);
+  if (tee(fds[0], other[1], 10, 0) == 3 &&
+      read(other[0], buf, sizeof(buf)) == 3 && memcmp(buf, "tee", 3) == 0 &&
+      read(fds[0], buf, sizeof(buf)) == 3 && memcmp(buf, "tee", 3) == 0) {
+    puts("test_tee ok1");
+  }
+
+  // Only pipes can be used.
+  int fd = open("tee_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  if (tee(fds[0], fd, 1, 0) < 0 && errno == EINVAL) {
+    puts("test_tee ok2");
+  }
+  close(fd);
+  unlink("tee_file");
+
+  // An empty source blocks, unless SPLICE_F_NONBLOCK or its writer is gone.
+  if (tee(fds[0], other[1], 1, SPLICE_F_NONBLOCK) < 0 && errno == EAGAIN) {
+    close(fds[1]);
+    if (tee(fds[0], other[1], 1, 0) == 0) {
+      puts("test_tee ok3");
+    }
+  }
+  close(fds[0]);
+  close(other[0]);
+  close(other[1]);
+}
+
+int main(int argc, char **argv) {
+  if (argc > 3 && strcmp(argv[1], "child") == 0) {
+    int closed = fcntl(atoi(argv[2]), F_GETFD) < 0 && errno == EBADF;
+    int open = fcntl(atoi(argv[3]), F_GETFD) == 0;
+    return closed && open ? 7 : 1;
+  }
+  test_pipe_read();
+  test_pipe_write();
+  test_pipe_atomic();
+  test_pipe_wake();
+  test_pipe2(argv[0]);
+  test_pipe_broken();
+  test_splice();
+  test_tee();
+  return 0;
+}
@@ -0,0 +1,102 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <poll.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/time.h>
+#include <time.h>
+#include <unistd.h>
+
+static long elapsed_ms(struct timespec *start) {
+  struct timespec now;
+  clock_gettime(CLOCK_MONOTONIC, &now);
+  return (now.tv_sec - start->tv_sec) * 1000 +
+         (now.tv_nsec - start->tv_nsec) / 1000000;
+}
+
+static volatile int handled;
+
+static void handler(int signo) { handled++; }
+
+void test_ppoll_sleep() {
+  struct timespec start, timeout = {0, 50 * 1000000};
+  clock_gettime(CLOCK_MONOTONIC, &start);
+
+  // Without any fd, ppoll sleeps for the whole timeout.
+  int ret = ppoll(NULL, 0, &timeout, NULL);
+  long ms = elapsed_ms(&start);
+  if (ret == 0 && ms >= 50 && ms < 500) {
+    puts("test_ppoll_sleep ok1");
+  }
+
+  // A signal cuts the sleep short.
+  struct sigaction sa = {0};
+  sa.sa_handler = handler;
+  sigaction(SIGALRM, &sa, NULL);
+  struct itimerval timer = {{0, 0}, {0, 20000}};
+  setitimer(ITIMER_REAL, &timer, NULL);
+  timeout.tv_sec = 2;
+  timeout.tv_nsec = 0;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  ret = ppoll(NULL, 0, &timeout, NULL);
+  ms = elapsed_ms(&start);
+  if (ret < 0 && errno == EINTR && ms < 1000) {
+    puts("test_ppoll_sleep ok2");
+  }
+}
+
+void test_ppoll_pipe() {
+  int fds[2];
+  pipe(fds);
+  struct pollfd pfd = {fds[0], POLLIN, 0};
+  struct timespec timeout = {0, 10 * 1000000};
+
+  // Nothing to read yet.
+  if (ppoll(&pfd, 1, &timeout, NULL) == 0 && pfd.revents == 0) {
+    puts("test_ppoll_pipe ok1");
+  }
+
+  write(fds[1], "x", 1);
+  if (ppoll(&pfd, 1, NULL, NULL) == 1 && (pfd.revents & POLLIN)) {
+    puts("test_ppoll_pipe ok2");
+  }
+
+  // A closed fd is reported as invalid.
+  close(fds[0]);
+  timeout.tv_nsec = 0;
+  if (ppoll(&pfd, 1, &timeout, NULL) == 1 && pfd.revents == POLLNVAL) {
+    puts("test_ppoll_pipe ok3");
+  }
+  close(fds[1]);
+}
+
+// A signal that only the mask given to ppoll unblocks is handled before
+// ppoll returns, and the old mask is back afterwards.
+void test_ppoll_sigmask() {
+  struct timespec timeout = {1, 0};
+  sigset_t block, empty, now;
+  sigemptyset(&block);
+  sigaddset(&block, SIGUSR1);
+  sigemptyset(&empty);
+  signal(SIGUSR1, handler);
+  sigprocmask(SIG_BLOCK, &block, NULL);
+  kill(getpid(), SIGUSR1);
+
+  handled = 0;
+  if (ppoll(NULL, 0, &timeout, &empty) < 0 && errno == EINTR && handled == 1) {
+    puts("test_ppoll_sigmask ok1");
+  }
+  sigprocmask(SIG_BLOCK, NULL, &now);
+  if (sigismember(&now, SIGUSR1)) {
+    puts("test_ppoll_sigmask ok2");
+  }
+  sigprocmask(SIG_UNBLOCK, &block, NULL);
+  signal(SIGUSR1, SIG_DFL);
+}
+
+int main() {
+  test_ppoll_sleep();
+  test_ppoll_pipe();
+  test_ppoll_sigmask();
+  return 0;
+}
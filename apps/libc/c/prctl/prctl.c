@@ -0,0 +1,99 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int read_comm(char *buf, int size) {
+  int fd = open("/proc/self/comm", O_RDONLY);
+  if (fd < 0) {
+    return -1;
+  }
+  int len = read(fd, buf, size - 1);
+  close(fd);
+  buf[len < 0 ? 0 : len] = '\0';
+  return len;
+}
+
+void test_name() {
+  char name[16] = {0};
+  char comm[32];
+  if (prctl(PR_SET_NAME, "worker") == 0 && prctl(PR_GET_NAME, name) == 0 &&
+      strcmp(name, "worker") == 0) {
+    puts("test_name ok1");
+  }
+
+  if (read_comm(comm, sizeof(comm)) > 0 && strcmp(comm, "worker\n") == 0) {
+    puts("test_name ok2");
+  }
+
+  // Names are cut at 15 bytes, leaving room for the trailing NUL.
+  memset(name, 'x', sizeof(name));
+  if (prctl(PR_SET_NAME, "abcdefghijklmnopqrstuvwxyz") == 0 &&
+      prctl(PR_GET_NAME, name) == 0 &&
+      strcmp(name, "abcdefghijklmno") == 0) {
+    puts("test_name ok3");
+  }
+
+  if (prctl(0x7fffffff, 0, 0, 0, 0) < 0 && errno == EINVAL) {
+    puts("test_name ok4");
+  }
+}
+
+static volatile sig_atomic_t got_signal;
+
+static void on_signal(int sig) { got_signal = sig; }
+
+void test_pdeathsig() {
+  int sig = -1;
+  if (prctl(PR_SET_PDEATHSIG, 100) < 0 && errno == EINVAL &&
+      prctl(PR_SET_PDEATHSIG, SIGUSR1) == 0 &&
+      prctl(PR_GET_PDEATHSIG, &sig) == 0 && sig == SIGUSR1 &&
+      prctl(PR_SET_PDEATHSIG, 0) == 0 && prctl(PR_GET_PDEATHSIG, &sig) == 0 &&
+      sig == 0) {
+    puts("test_pdeathsig ok1");
+  }
+
+  // The grandchild asks for SIGUSR1 when its parent dies, then the parent
+  // is killed. The grandchild reports the signal it got through `result`.
+  int ready[2], result[2];
+  pipe(ready);
+  pipe(result);
+  pid_t parent = fork();
+  if (parent == 0) {
+    if (fork() == 0) {
+      signal(SIGUSR1, on_signal);
+      prctl(PR_SET_PDEATHSIG, SIGUSR1);
+      write(ready[1], "r", 1);
+      while (!got_signal) {
+        pause();
+      }
+      char c = got_signal == SIGUSR1 ? 'y' : 'n';
+      write(result[1], &c, 1);
+      _exit(0);
+    }
+    char c;
+    read(ready[0], &c, 1);
+    kill(getpid(), SIGKILL);
+  }
+  close(ready[1]);
+  close(result[1]);
+
+  int status;
+  waitpid(parent, &status, 0);
+  char c = 0;
+  if (WIFSIGNALED(status) && read(result[0], &c, 1) == 1 && c == 'y') {
+    puts("test_pdeathsig ok2");
+  }
+  close(ready[0]);
+  close(result[0]);
+}
+
+int main() {
+  test_name();
+  test_pdeathsig();
+  return 0;
+}
@@ -0,0 +1,56 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/uio.h>
+#include <unistd.h>
+
+void test_pread() {
+  char buf[16] = {0};
+  int fd = open("pread_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  write(fd, "0123456789", 10);
+  lseek(fd, 2, SEEK_SET);
+
+  // Neither moves the position.
+  if (pread(fd, buf, 3, 5) == 3 && strcmp(buf, "567") == 0 &&
+      pwrite(fd, "ab", 2, 8) == 2 && lseek(fd, 0, SEEK_CUR) == 2) {
+    puts("test_pread ok1");
+  }
+
+  // The last buffer is only partly filled at the end of the file.
+  char first[3], second[8] = {0};
+  struct iovec iov[2] = {{first, 3}, {second, 8}};
+  if (preadv(fd, iov, 2, 4) == 6 && memcmp(first, "456", 3) == 0 &&
+      strcmp(second, "7ab") == 0 && lseek(fd, 0, SEEK_CUR) == 2) {
+    puts("test_pread ok2");
+  }
+
+  struct iovec out[2] = {{"xy", 2}, {"z", 1}};
+  memset(buf, 0, sizeof(buf));
+  if (pwritev(fd, out, 2, 9) == 3 && lseek(fd, 0, SEEK_CUR) == 2 &&
+      pread(fd, buf, sizeof(buf), 0) == 12 &&
+      strcmp(buf, "01234567axyz") == 0) {
+    puts("test_pread ok3");
+  }
+
+  // More buffers than IOV_MAX are refused.
+  static struct iovec many[1025];
+  int fds[2];
+  pipe(fds);
+  if (pread(fd, buf, 1, -1) < 0 && errno == EINVAL &&
+      pread(fds[0], buf, 1, 0) < 0 && errno == ESPIPE &&
+      pwrite(fds[1], "a", 1, 0) < 0 && errno == ESPIPE &&
+      preadv(fd, many, 1025, 0) < 0 && errno == EINVAL) {
+    puts("test_pread ok4");
+  }
+  close(fds[0]);
+  close(fds[1]);
+  close(fd);
+  unlink("pread_file");
+}
+
+int main() {
+  test_pread();
+  return 0;
+}
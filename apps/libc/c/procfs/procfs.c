@@ -0,0 +1,156 @@
+#include <stdio.h>
+#include <fcntl.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/sysinfo.h>
+#include <unistd.h>
+
+// Finds the mapping containing `addr` in /proc/self/maps, copying its
+// permissions to `perms`. Returns 0 if there is none.
+int find_map(unsigned long addr, char *perms) {
+  FILE *f = fopen("/proc/self/maps", "r");
+  char line[512];
+  int found = 0;
+  while (fgets(line, sizeof(line), f)) {
+    unsigned long start, end;
+    char p[5];
+    if (sscanf(line, "%lx-%lx %4s", &start, &end, p) == 3 && start <= addr &&
+        addr < end) {
+      strcpy(perms, p);
+      found = 1;
+      break;
+    }
+  }
+  fclose(f);
+  return found;
+}
+
+int has_tag(const char *tag) {
+  FILE *f = fopen("/proc/self/maps", "r");
+  char line[512];
+  int found = 0;
+  while (fgets(line, sizeof(line), f)) {
+    if (strstr(line, tag)) {
+      found = 1;
+    }
+  }
+  fclose(f);
+  return found;
+}
+
+void test_maps() {
+  long page = sysconf(_SC_PAGESIZE);
+  char *p = mmap(NULL, 3 * page, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  char perms[5];
+
+  if (find_map((unsigned long)p, perms) && strcmp(perms, "rw-p") == 0) {
+    puts("test_maps ok1");
+  }
+
+  // Changing the middle page splits the mapping.
+  mprotect(p + page, page, PROT_READ);
+  int ok = find_map((unsigned long)p, perms) && strcmp(perms, "rw-p") == 0;
+  ok &= find_map((unsigned long)p + page, perms) && strcmp(perms, "r--p") == 0;
+  ok &= find_map((unsigned long)p + 2 * page, perms) &&
+        strcmp(perms, "rw-p") == 0;
+  if (ok) {
+    puts("test_maps ok2");
+  }
+
+  // Unmapped memory is no longer listed.
+  munmap(p, 3 * page);
+  if (!find_map((unsigned long)p + page, perms)) {
+    puts("test_maps ok3");
+  }
+
+  // The stack is tagged, and the code is mapped executable.
+  int local;
+  if (has_tag("[stack]") && find_map((unsigned long)&local, perms) &&
+      find_map((unsigned long)test_maps, perms) && perms[2] == 'x') {
+    puts("test_maps ok4");
+  }
+}
+
+// Returns the number after `key` in the proc file `fd`, read from the start.
+long read_field(int fd, const char *key) {
+  char buf[4096];
+  lseek(fd, 0, SEEK_SET);
+  ssize_t n = read(fd, buf, sizeof(buf) - 1);
+  if (n <= 0) {
+    return -1;
+  }
+  buf[n] = 0;
+  char *p = strstr(buf, key);
+  return p ? strtol(p + strlen(key), NULL, 10) : -1;
+}
+
+void test_meminfo() {
+  int fd = open("/proc/meminfo", O_RDONLY);
+  long total = read_field(fd, "MemTotal:");
+  long free = read_field(fd, "MemFree:");
+  if (total > 0 && free > 0 && free <= total) {
+    puts("test_meminfo ok1");
+  }
+  close(fd);
+}
+
+void test_sysinfo() {
+  struct sysinfo info;
+  int fd = open("/proc/meminfo", O_RDONLY);
+  long total = read_field(fd, "MemTotal:");
+  close(fd);
+  if (sysinfo(&info) == 0 && info.mem_unit == 1 &&
+      info.totalram / 1024 == total && info.freeram <= info.totalram &&
+      info.procs >= 1) {
+    puts("test_sysinfo ok1");
+  }
+}
+
+void test_cpuinfo() {
+  FILE *f = fopen("/proc/cpuinfo", "r");
+  char line[256];
+  long cpus = 0;
+  while (fgets(line, sizeof(line), f)) {
+    if (strncmp(line, "processor", 9) == 0) {
+      cpus++;
+    }
+  }
+  fclose(f);
+  if (cpus == sysconf(_SC_NPROCESSORS_ONLN)) {
+    puts("test_cpuinfo ok1");
+  }
+}
+
+void test_status() {
+  int fd = open("/proc/self/status", O_RDONLY);
+  if (read_field(fd, "\nPid:") == getpid() &&
+      read_field(fd, "PPid:") == getppid() &&
+      read_field(fd, "Threads:") == 1) {
+    puts("test_status ok1");
+  }
+
+  // The values are regenerated when read again: touching new memory grows
+  // the resident size.
+  long size = 4 << 20;
+  long rss = read_field(fd, "VmRSS:");
+  char *p = mmap(NULL, size, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  memset(p, 1, size);
+  if (rss > 0 && read_field(fd, "VmRSS:") >= rss + (size >> 10) / 2 &&
+      read_field(fd, "VmSize:") >= size >> 10) {
+    puts("test_status ok2");
+  }
+  munmap(p, size);
+  close(fd);
+}
+
+int main() {
+  test_maps();
+  test_meminfo();
+  test_sysinfo();
+  test_cpuinfo();
+  test_status();
+  return 0;
+}
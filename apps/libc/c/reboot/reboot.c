@@ -0,0 +1,31 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/reboot.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+// This powers the system off, so it has to be the last test to run. The
+// kernel prints "reboot: Power down" once every open file is written back.
+void test_reboot() {
+  errno = 0;
+  if (syscall(SYS_reboot, 0x12345678, 672274793, RB_POWER_OFF, NULL) < 0 &&
+      errno == EINVAL) {
+    puts("test_reboot ok1");
+  }
+  if (reboot(RB_DISABLE_CAD) == 0) {
+    puts("test_reboot ok2");
+  }
+
+  // Leave a written file open, for the shutdown to write back.
+  int fd = open("reboot_file", O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  write(fd, "data", 4);
+  fflush(stdout);
+  reboot(RB_POWER_OFF);
+  printf("test_reboot: still running after power off, fd %d\n", fd);
+}
+
+int main() {
+  test_reboot();
+  return 0;
+}
@@ -0,0 +1,203 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+
+#ifndef RENAME_NOREPLACE
+#define RENAME_NOREPLACE 1
+#endif
+#ifndef RENAME_EXCHANGE
+#define RENAME_EXCHANGE 2
+#endif
+
+static long renameat2_(const char *old, const char *new, unsigned flags) {
+  return syscall(SYS_renameat2, AT_FDCWD, old, AT_FDCWD, new, flags);
+}
+
+static void touch(const char *path) {
+  close(open(path, O_CREAT | O_WRONLY, 0644));
+}
+
+void test_rename() {
+  mkdir("rn_dir", 0755);
+  mkdir("rn_dir/sub", 0755);
+  touch("rn_file");
+  if (rename("rn_file", "rn_moved") == 0 && access("rn_file", F_OK) < 0 &&
+      access("rn_moved", F_OK) == 0) {
+    puts("test_rename ok1");
+  }
+
+  // A directory cannot be moved into itself.
+  if (rename("rn_dir", "rn_dir/sub/inner") < 0 && errno == EINVAL &&
+      rename("rn_dir", "rn_dir/inner") < 0 && errno == EINVAL) {
+    puts("test_rename ok2");
+  }
+
+  // Only an empty directory can be replaced.
+  mkdir("rn_full", 0755);
+  touch("rn_full/file");
+  mkdir("rn_empty", 0755);
+  if (rename("rn_dir", "rn_full") < 0 &&
+      (errno == ENOTEMPTY || errno == EEXIST) &&
+      rename("rn_dir", "rn_empty") == 0 &&
+      access("rn_empty/sub", F_OK) == 0) {
+    puts("test_rename ok3");
+  }
+
+  // A file and a directory cannot replace each other.
+  if (rename("rn_empty", "rn_moved") < 0 && errno == ENOTDIR &&
+      rename("rn_moved", "rn_full") < 0 && errno == EISDIR) {
+    puts("test_rename ok4");
+  }
+
+  touch("rn_other");
+  if (syscall(SYS_renameat2, AT_FDCWD, "rn_other", AT_FDCWD, "rn_moved",
+              RENAME_NOREPLACE) < 0 &&
+      errno == EEXIST && rename("rn_other", "rn_moved") == 0 &&
+      access("rn_other", F_OK) < 0) {
+    puts("test_rename ok5");
+  }
+
+  unlink("rn_moved");
+  unlink("rn_full/file");
+  rmdir("rn_full");
+  rmdir("rn_empty/sub");
+  rmdir("rn_empty");
+}
+
+static int same_mtime(struct stat *a, struct stat *b) {
+  return a->st_mtim.tv_sec == b->st_mtim.tv_sec &&
+         a->st_mtim.tv_nsec == b->st_mtim.tv_nsec;
+}
+
+// The timestamps move along with the file.
+void test_rename_times() {
+  struct timespec pause = {0, 20 * 1000000};
+  struct stat before, after;
+  int fd = open("rt_file", O_CREAT | O_WRONLY | O_TRUNC, 0644);
+  write(fd, "x", 1);
+  close(fd);
+  stat("rt_file", &before);
+  nanosleep(&pause, NULL);
+  if (rename("rt_file", "rt_moved") == 0 && stat("rt_moved", &after) == 0 &&
+      same_mtime(&before, &after)) {
+    puts("test_rename_times ok");
+  }
+  unlink("rt_moved");
+}
+
+// A write through a descriptor opened before the rename shows at the new name.
+void test_rename_times_open() {
+  struct timespec pause = {0, 20 * 1000000};
+  struct stat before, after;
+  int fd = open("rt_open", O_CREAT | O_WRONLY | O_TRUNC, 0644);
+  rename("rt_open", "rt_open_moved");
+  stat("rt_open_moved", &before);
+  nanosleep(&pause, NULL);
+  if (write(fd, "x", 1) == 1 && stat("rt_open_moved", &after) == 0 &&
+      !same_mtime(&before, &after)) {
+    puts("test_rename_times_open ok");
+  }
+  close(fd);
+  unlink("rt_open_moved");
+}
+
+void test_rename_exchange() {
+  struct stat st;
+  touch("ex_file");
+  mkdir("ex_dir", 0755);
+  touch("ex_dir/inside");
+
+  // The two swap places, even when of different types.
+  if (renameat2_("ex_file", "ex_dir", RENAME_EXCHANGE) == 0 &&
+      stat("ex_file", &st) == 0 && S_ISDIR(st.st_mode) &&
+      access("ex_file/inside", F_OK) == 0 && stat("ex_dir", &st) == 0 &&
+      S_ISREG(st.st_mode)) {
+    puts("test_rename_exchange ok1");
+  }
+
+  unsigned both = RENAME_EXCHANGE | RENAME_NOREPLACE;
+  if (renameat2_("ex_file", "ex_dir", both) < 0 && errno == EINVAL &&
+      renameat2_("ex_dir", "ex_none", RENAME_EXCHANGE) < 0 &&
+      errno == ENOENT &&
+      renameat2_("ex_file", "ex_file/inside", RENAME_EXCHANGE) < 0 &&
+      errno == EINVAL) {
+    puts("test_rename_exchange ok2");
+  }
+
+  // Nothing moves to another file system.
+  mkdir("ex_mnt", 0755);
+  mount("ex_mnt", "ex_mnt", NULL, MS_BIND, NULL);
+  if (rename("ex_dir", "ex_mnt/moved") < 0 && errno == EXDEV) {
+    puts("test_rename_exchange ok3");
+  }
+  umount("ex_mnt");
+  rmdir("ex_mnt");
+
+  // The timestamps are swapped too.
+  struct timespec pause = {0, 20 * 1000000};
+  struct stat file_before, dir_before, file_after, dir_after;
+  stat("ex_file", &dir_before);
+  stat("ex_dir", &file_before);
+  nanosleep(&pause, NULL);
+  if (renameat2_("ex_file", "ex_dir", RENAME_EXCHANGE) == 0 &&
+      stat("ex_file", &file_after) == 0 && stat("ex_dir", &dir_after) == 0 &&
+      same_mtime(&file_before, &file_after) &&
+      same_mtime(&dir_before, &dir_after)) {
+    puts("test_rename_exchange ok4");
+  }
+  renameat2_("ex_file", "ex_dir", RENAME_EXCHANGE);
+
+  unlink("ex_file/inside");
+  rmdir("ex_file");
+  unlink("ex_dir");
+}
+
+// What is open follows the rename.
+void test_rename_open() {
+  char cwd[256], old_cwd[256];
+  mkdir("ro_dir", 0755);
+  touch("ro_dir/file");
+  int dir = open("ro_dir", O_RDONLY | O_DIRECTORY);
+  getcwd(old_cwd, sizeof(old_cwd));
+  if (rename("ro_dir", "ro_moved") == 0 &&
+      faccessat(dir, "file", F_OK, 0) == 0 && fchdir(dir) == 0 &&
+      getcwd(cwd, sizeof(cwd)) &&
+      strcmp(strrchr(cwd, '/'), "/ro_moved") == 0) {
+    puts("test_rename_open ok");
+  }
+  chdir(old_cwd);
+  close(dir);
+  unlink("ro_moved/file");
+  rmdir("ro_moved");
+}
+
+// A replaced file stays usable through a descriptor open on it.
+void test_rename_replace_open() {
+  char buf[8] = {0};
+  int fd = open("rr_target", O_CREAT | O_RDWR | O_TRUNC, 0644);
+  write(fd, "old", 3);
+  touch("rr_file");
+  if (rename("rr_file", "rr_target") == 0 && pread(fd, buf, 3, 0) == 3 &&
+      strcmp(buf, "old") == 0 && access("rr_file", F_OK) < 0) {
+    puts("test_rename_replace_open ok");
+  }
+  close(fd);
+  unlink("rr_target");
+}
+
+int main() {
+  test_rename();
+  test_rename_times();
+  test_rename_exchange();
+  test_rename_open();
+  test_rename_times_open();
+  test_rename_replace_open();
+  return 0;
+}
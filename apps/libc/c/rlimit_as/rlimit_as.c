@@ -0,0 +1,61 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+
+// The size of the address space in bytes, from /proc/self/status.
+static long vm_size() {
+  FILE *f = fopen("/proc/self/status", "r");
+  char line[128];
+  long kb = -1;
+  while (f && fgets(line, sizeof(line), f)) {
+    if (sscanf(line, "VmSize: %ld kB", &kb) == 1) {
+      break;
+    }
+  }
+  if (f) {
+    fclose(f);
+  }
+  return kb * 1024;
+}
+
+static void *map(long size) {
+  return mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS,
+              -1, 0);
+}
+
+void test_rlimit_as() {
+  struct rlimit old, lim;
+  getrlimit(RLIMIT_AS, &old);
+
+  // With 8 MiB to spare, a 4 MiB mapping fits but the next 8 MiB does not.
+  long spare = 8 << 20;
+  lim.rlim_cur = vm_size() + spare;
+  lim.rlim_max = old.rlim_max;
+  setrlimit(RLIMIT_AS, &lim);
+  void *small = map(spare / 2);
+  void *large = map(spare);
+  if (small != MAP_FAILED && large == MAP_FAILED && errno == ENOMEM) {
+    puts("test_rlimit_as ok1");
+  }
+
+  // Growing a mapping past the limit fails too.
+  if (mremap(small, spare / 2, 2 * spare, MREMAP_MAYMOVE) == MAP_FAILED &&
+      errno == ENOMEM) {
+    puts("test_rlimit_as ok2");
+  }
+
+  // A limit below what is mapped is accepted, and only stops growth.
+  lim.rlim_cur = vm_size() / 2;
+  if (setrlimit(RLIMIT_AS, &lim) == 0 && map(4096) == MAP_FAILED &&
+      errno == ENOMEM && munmap(small, spare / 2) == 0) {
+    puts("test_rlimit_as ok3");
+  }
+  setrlimit(RLIMIT_AS, &old);
+}
+
+int main() {
+  test_rlimit_as();
+  return 0;
+}
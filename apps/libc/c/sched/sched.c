@@ -0,0 +1,122 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <pthread.h>
+#include <sched.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define NUM_YIELDERS 3
+#define ROUNDS 100
+
+static volatile int turn;
+static volatile int max_waits;
+
+// Every task takes a step on its turn only, yielding until then. As the
+// tasks share one CPU and a yield puts the caller behind its peers, the
+// task whose turn it is always runs before the yielding one comes back, so
+// one yield per turn is enough. One more is allowed for a preemption.
+static void *yielder(void *arg) {
+  long idx = (long)arg;
+  for (int i = 0; i < ROUNDS; i++) {
+    int waits = 0;
+    while (turn != idx) {
+      sched_yield();
+      waits++;
+    }
+    // The first turn also waits for the tasks to be created.
+    if (i > 0 && waits > max_waits) {
+      max_waits = waits;
+    }
+    turn = (idx + 1) % NUM_YIELDERS;
+  }
+  return NULL;
+}
+
+void test_sched_yield() {
+  cpu_set_t old, one;
+  sched_getaffinity(0, sizeof(old), &old);
+  CPU_ZERO(&one);
+  CPU_SET(0, &one);
+  if (sched_setaffinity(0, sizeof(one), &one) < 0) {
+    return;
+  }
+
+  pthread_t threads[NUM_YIELDERS];
+  for (long i = 0; i < NUM_YIELDERS; i++) {
+    pthread_create(&threads[i], NULL, yielder, (void *)i);
+  }
+  for (int i = 0; i < NUM_YIELDERS; i++) {
+    pthread_join(threads[i], NULL);
+  }
+  if (max_waits <= 2) {
+    puts("test_sched_yield ok");
+  } else {
+    printf("test_sched_yield failed: %d yields for a turn\n", max_waits);
+  }
+  sched_setaffinity(0, sizeof(old), &old);
+}
+
+void test_sched_affinity() {
+  cpu_set_t set;
+  CPU_ZERO(&set);
+  if (sched_getaffinity(0, sizeof(set), &set) == 0 && CPU_ISSET(0, &set)) {
+    puts("test_sched_affinity ok1");
+  }
+
+  // CPUs that do not exist are dropped from the mask.
+  CPU_ZERO(&set);
+  CPU_SET(0, &set);
+  CPU_SET(CPU_SETSIZE - 1, &set);
+  if (sched_setaffinity(0, sizeof(set), &set) == 0 &&
+      sched_getaffinity(0, sizeof(set), &set) == 0 && CPU_COUNT(&set) == 1 &&
+      CPU_ISSET(0, &set)) {
+    puts("test_sched_affinity ok2");
+  }
+
+  CPU_ZERO(&set);
+  if (sched_setaffinity(0, sizeof(set), &set) < 0 && errno == EINVAL) {
+    puts("test_sched_affinity ok3");
+  }
+
+  if (sched_getaffinity(999999, sizeof(set), &set) < 0 && errno == ESRCH) {
+    puts("test_sched_affinity ok4");
+  }
+}
+
+void test_priority() {
+  if (getpriority(PRIO_PROCESS, 0) == 0 &&
+      setpriority(PRIO_PROCESS, 0, 10) == 0 &&
+      getpriority(PRIO_PROCESS, 0) == 10) {
+    puts("test_priority ok1");
+  }
+
+  // Raising the priority needs privilege.
+  if (setpriority(PRIO_PROCESS, 0, -5) < 0 && errno == EACCES &&
+      getpriority(PRIO_PROCESS, 0) == 10) {
+    puts("test_priority ok2");
+  }
+
+  errno = 0;
+  if (nice(1) == 11 && errno == 0) {
+    puts("test_priority ok3");
+  }
+
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(getpriority(PRIO_PROCESS, 0) == 11 ? 0 : 1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_priority ok4");
+  }
+}
+
+int main() {
+  test_sched_yield();
+  test_sched_affinity();
+  test_priority();
+  return 0;
+}
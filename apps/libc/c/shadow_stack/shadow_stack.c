@@ -0,0 +1,45 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef SYS_map_shadow_stack
+#define SYS_map_shadow_stack 453
+#endif
+
+void test_shadow_stack() {
+  long page = sysconf(_SC_PAGESIZE);
+  long addr = syscall(SYS_map_shadow_stack, 0, page, 0);
+
+  // Without shadow stack support, that is all there is to it.
+  if (addr == -1) {
+    if (errno == ENOSYS || errno == EOPNOTSUPP) {
+      puts("test_shadow_stack ok1");
+      puts("test_shadow_stack ok2");
+    }
+    return;
+  }
+
+  // Otherwise it is readable, but ordinary writes fault.
+  volatile char *p = (char *)addr;
+  char c = p[0];
+  (void)c;
+  puts("test_shadow_stack ok1");
+  pid_t pid = fork();
+  if (pid == 0) {
+    p[0] = 1;
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV) {
+    puts("test_shadow_stack ok2");
+  }
+}
+
+int main() {
+  test_shadow_stack();
+  return 0;
+}
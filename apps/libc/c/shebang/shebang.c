@@ -0,0 +1,89 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static void write_script(const char *path, const char *interp,
+                         const char *arg, int mode) {
+  char line[256];
+  int len = snprintf(line, sizeof(line), "#!%s%s%s\necho unreachable\n",
+                     interp, arg ? " " : "", arg ? arg : "");
+  unlink(path);
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, mode);
+  write(fd, line, len);
+  close(fd);
+}
+
+// Run `path` with the argument "x", returning its exit status or -1.
+static int run(const char *path) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    char *argv[] = {(char *)path, "x", NULL};
+    char *envp[] = {NULL};
+    execve(path, argv, envp);
+    _exit(1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  return WIFEXITED(status) ? WEXITSTATUS(status) : -1;
+}
+
+// Fail to run `path`, returning the error number.
+static int run_error(const char *path) {
+  char *argv[] = {(char *)path, NULL};
+  char *envp[] = {NULL};
+  errno = 0;
+  execve(path, argv, envp);
+  return errno;
+}
+
+void test_shebang(const char *self) {
+  // This program is the interpreter, which checks its arguments.
+  write_script("shebang_script", self, "opt  arg", 0755);
+  if (run("shebang_script") == 4) {
+    puts("test_shebang ok1");
+  }
+
+  // The interpreter can be a script itself.
+  write_script("shebang_nested", "shebang_script", NULL, 0755);
+  if (run("shebang_nested") == 5) {
+    puts("test_shebang ok2");
+  }
+
+  write_script("shebang_noexec", self, NULL, 0644);
+  if (run_error("shebang_noexec") == EACCES) {
+    puts("test_shebang ok3");
+  }
+
+  write_script("shebang_missing", "/nonexistent/interpreter", NULL, 0755);
+  if (run_error("shebang_missing") == ENOENT) {
+    puts("test_shebang ok4");
+  }
+
+  write_script("shebang_loop", "shebang_loop", NULL, 0755);
+  if (run_error("shebang_loop") == ELOOP) {
+    puts("test_shebang ok5");
+  }
+
+  unlink("shebang_script");
+  unlink("shebang_nested");
+  unlink("shebang_noexec");
+  unlink("shebang_missing");
+  unlink("shebang_loop");
+}
+
+int main(int argc, char **argv) {
+  // Run as the interpreter: `[self, "opt  arg", "shebang_script", ...]`,
+  // ending with the argument "x".
+  if (argc >= 2 && strcmp(argv[1], "opt  arg") == 0) {
+    if (strcmp(argv[2], "shebang_script") != 0 ||
+        strcmp(argv[argc - 1], "x") != 0) {
+      return 1;
+    }
+    return argc;
+  }
+  test_shebang(argv[0]);
+  return 0;
+}
@@ -1,8 +1,11 @@
 #include <errno.h>
+#include <setjmp.h>
 #include <signal.h>
 #include <stddef.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <sys/syscall.h>
+#include <sys/time.h>
 #include <sys/wait.h>
 #include <unistd.h>
 
@@ -162,6 +165,305 @@ void test_sigsuspend() {
   kill(pid, SIGUSR1);
 }
 
+static char altstack[1 << 16];
+static volatile int on_altstack = 0;
+static volatile int altstack_eperm = 0;
+
+static void altstack_handler(int signum) {
+  char local;
+  on_altstack = &local >= altstack && &local < altstack + sizeof(altstack);
+
+  stack_t old, ss = {.ss_sp = altstack, .ss_size = sizeof(altstack)};
+  sigaltstack(NULL, &old);
+  altstack_eperm = (old.ss_flags & SS_ONSTACK) && sigaltstack(&ss, NULL) < 0 &&
+                   errno == EPERM;
+}
+
+void test_sigaltstack() {
+  stack_t ss = {.ss_sp = altstack, .ss_size = MINSIGSTKSZ - 1};
+  if (sigaltstack(&ss, NULL) < 0 && errno == ENOMEM) {
+    puts("test_sigaltstack ok1");
+  }
+
+  ss.ss_size = sizeof(altstack);
+  sigaltstack(&ss, NULL);
+
+  struct sigaction sa = {0};
+  sa.sa_handler = altstack_handler;
+  sa.sa_flags = SA_ONSTACK;
+  sigaction(SIGUSR2, &sa, NULL);
+  kill(getpid(), SIGUSR2);
+  if (on_altstack && altstack_eperm) {
+    puts("test_sigaltstack ok2");
+  }
+
+  stack_t old;
+  ss.ss_flags = SS_DISABLE;
+  sigaltstack(&ss, NULL);
+  sigaltstack(NULL, &old);
+  if (old.ss_flags == SS_DISABLE) {
+    puts("test_sigaltstack ok3");
+  }
+
+  sa.sa_handler = (void (*)(int))0;
+  sa.sa_flags = 0;
+  sigaction(SIGUSR2, &sa, NULL);
+}
+
+static sigjmp_buf segv_env;
+static volatile void *segv_addr;
+static volatile int segv_code;
+
+static void segv_handler(int signum, siginfo_t *info, void *ucontext) {
+  segv_addr = info->si_addr;
+  segv_code = info->si_code;
+  siglongjmp(segv_env, 1);
+}
+
+static volatile pid_t usr1_pid;
+static volatile int usr1_code;
+
+static void usr1_info_handler(int signum, siginfo_t *info, void *ucontext) {
+  usr1_pid = info->si_pid;
+  usr1_code = info->si_code;
+}
+
+void test_siginfo() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = usr1_info_handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGUSR1, &sa, NULL);
+  kill(getpid(), SIGUSR1);
+  if (usr1_pid == getpid() && usr1_code == SI_USER) {
+    puts("test_siginfo ok1");
+  }
+
+  sa.sa_sigaction = segv_handler;
+  sigaction(SIGSEGV, &sa, NULL);
+  volatile int *bad = (volatile int *)0x10;
+  if (sigsetjmp(segv_env, 1) == 0) {
+    *bad = 1;
+  }
+  if (segv_addr == (void *)bad && segv_code == SEGV_MAPERR) {
+    puts("test_siginfo ok2");
+  }
+
+  sa.sa_handler = (void (*)(int))0;
+  sa.sa_flags = 0;
+  sigaction(SIGUSR1, &sa, NULL);
+  sigaction(SIGSEGV, &sa, NULL);
+}
+
+static volatile int usr1_handled;
+
+static void usr1_flag_handler(int signum) { usr1_handled = 1; }
+static void usr2_handler(int signum) {}
+
+void test_sigtimedwait() {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGUSR1);
+
+  // A signal already pending is accepted right away.
+  siginfo_t info;
+  sigprocmask(SIG_BLOCK, &set, NULL);
+  kill(getpid(), SIGUSR1);
+  if (sigwaitinfo(&set, &info) == SIGUSR1 && info.si_signo == SIGUSR1 &&
+      info.si_pid == getpid()) {
+    puts("test_sigtimedwait ok1");
+  }
+  sigprocmask(SIG_UNBLOCK, &set, NULL);
+
+  // Waiting on an unblocked signal must not run its handler.
+  struct sigaction sa = {0};
+  sa.sa_handler = usr1_flag_handler;
+  sigaction(SIGUSR1, &sa, NULL);
+  int pid = fork();
+  if (pid == 0) {
+    sleep(1);
+    kill(getppid(), SIGUSR1);
+    exit(0);
+  }
+  struct timespec ts = {5, 0};
+  if (sigtimedwait(&set, &info, &ts) == SIGUSR1 && info.si_pid == pid &&
+      !usr1_handled) {
+    puts("test_sigtimedwait ok2");
+  }
+  wait(NULL);
+
+  ts.tv_nsec = 1000000000;
+  if (sigtimedwait(&set, NULL, &ts) < 0 && errno == EINVAL) {
+    puts("test_sigtimedwait ok3");
+  }
+
+  // Nothing arrives before the timeout.
+  ts.tv_sec = 0;
+  ts.tv_nsec = 10000000;
+  if (sigtimedwait(&set, NULL, &ts) < 0 && errno == EAGAIN) {
+    puts("test_sigtimedwait ok4");
+  }
+
+  // A handled signal outside the set interrupts the wait.
+  sa.sa_handler = usr2_handler;
+  sigaction(SIGUSR2, &sa, NULL);
+  pid = fork();
+  if (pid == 0) {
+    usleep(100000);
+    kill(getppid(), SIGUSR2);
+    exit(0);
+  }
+  ts.tv_sec = 5;
+  ts.tv_nsec = 0;
+  if (sigtimedwait(&set, NULL, &ts) < 0 && errno == EINTR) {
+    puts("test_sigtimedwait ok5");
+  }
+  wait(NULL);
+
+  sa.sa_handler = (void (*)(int))0;
+  sigaction(SIGUSR1, &sa, NULL);
+  sigaction(SIGUSR2, &sa, NULL);
+}
+
+static volatile int queued_value;
+
+static void queued_handler(int signum, siginfo_t *info, void *ucontext) {
+  queued_value = info->si_value.sival_int;
+}
+
+void test_sigqueue() {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGRTMIN);
+
+  // Real-time signals are queued in order, each with its own value.
+  siginfo_t info1, info2;
+  sigprocmask(SIG_BLOCK, &set, NULL);
+  sigqueue(getpid(), SIGRTMIN, (union sigval){.sival_int = 1});
+  sigqueue(getpid(), SIGRTMIN, (union sigval){.sival_int = 2});
+  if (sigwaitinfo(&set, &info1) == SIGRTMIN &&
+      sigwaitinfo(&set, &info2) == SIGRTMIN && info1.si_value.sival_int == 1 &&
+      info2.si_value.sival_int == 2 && info1.si_code == SI_QUEUE) {
+    puts("test_sigqueue ok1");
+  }
+  sigprocmask(SIG_UNBLOCK, &set, NULL);
+
+  struct sigaction sa = {0};
+  sa.sa_sigaction = queued_handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGUSR1, &sa, NULL);
+  sigqueue(getpid(), SIGUSR1, (union sigval){.sival_int = 42});
+  if (queued_value == 42) {
+    puts("test_sigqueue ok2");
+  }
+  sa.sa_handler = (void (*)(int))0;
+  sa.sa_flags = 0;
+  sigaction(SIGUSR1, &sa, NULL);
+
+  if (sigqueue(getpid(), 1000, (union sigval){0}) < 0 && errno == EINVAL) {
+    puts("test_sigqueue ok3");
+  }
+}
+
+void test_pidfd_send_signal() {
+  int pid = fork();
+  if (pid == 0) {
+    while (1)
+      sleep(1);
+  }
+  int pidfd = syscall(SYS_pidfd_open, pid, 0);
+  if (pidfd >= 0 && syscall(SYS_pidfd_send_signal, pidfd, SIGTERM, NULL, 0) == 0 &&
+      waitpid(pid, NULL, 0) == pid) {
+    puts("test_pidfd_send_signal ok1");
+  }
+  if (syscall(SYS_pidfd_send_signal, pidfd, SIGTERM, NULL, 0) < 0 &&
+      errno == ESRCH) {
+    puts("test_pidfd_send_signal ok2");
+  }
+  close(pidfd);
+
+  // The thread must belong to the given thread group.
+  if (syscall(SYS_tgkill, getpid(), syscall(SYS_gettid), 0) == 0 &&
+      syscall(SYS_tgkill, getppid(), syscall(SYS_gettid), 0) < 0 &&
+      errno == ESRCH) {
+    puts("test_pidfd_send_signal ok3");
+  }
+}
+
+static void on_alarm(int sig) {}
+
+void test_write_intr() {
+  struct sigaction sa = {0};
+  sa.sa_handler = on_alarm;
+  sigaction(SIGALRM, &sa, NULL);
+
+  // The reader takes a little and then stops reading, so the writer blocks
+  // on a full pipe.
+  int fds[2];
+  pipe(fds);
+  pid_t pid = fork();
+  if (pid == 0) {
+    char buf[100];
+    close(fds[1]);
+    read(fds[0], buf, sizeof(buf));
+    while (1) {
+      pause();
+    }
+  }
+  close(fds[0]);
+
+  // Some bytes made it before the signal, so the count is returned.
+  static char buf[65536];
+  struct itimerval val = {0};
+  val.it_value.tv_usec = 50000;
+  setitimer(ITIMER_REAL, &val, NULL);
+  ssize_t n = write(fds[1], buf, sizeof(buf));
+  if (n > 0 && n < sizeof(buf)) {
+    puts("test_write_intr ok1");
+  }
+
+  // Nothing made it, so the write fails.
+  setitimer(ITIMER_REAL, &val, NULL);
+  if (write(fds[1], buf, 1) < 0 && errno == EINTR) {
+    puts("test_write_intr ok2");
+  }
+
+  kill(pid, SIGKILL);
+  waitpid(pid, NULL, 0);
+  close(fds[1]);
+  signal(SIGALRM, SIG_DFL);
+}
+
+// A fault that is ignored or blocked would only happen again, so it kills
+// the process instead.
+static int fault_status(int block) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    signal(SIGSEGV, SIG_IGN);
+    if (block) {
+      sigset_t set;
+      sigemptyset(&set);
+      sigaddset(&set, SIGSEGV);
+      sigprocmask(SIG_BLOCK, &set, NULL);
+    }
+    *(volatile int *)NULL = 1;
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  return status;
+}
+
+void test_fault_ignored() {
+  int status = fault_status(0);
+  if (WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV) {
+    puts("test_fault_ignored ok1");
+  }
+  status = fault_status(1);
+  if (WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV) {
+    puts("test_fault_ignored ok2");
+  }
+}
+
 int main() {
   test_term();
   test_sigaction();
@@ -169,5 +471,12 @@ int main() {
   test_sigkill_stop();
   test_sigwait();
   test_sigsuspend();
+  test_sigaltstack();
+  test_siginfo();
+  test_sigtimedwait();
+  test_sigqueue();
+  test_pidfd_send_signal();
+  test_write_intr();
+  test_fault_ignored();
   return 0;
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/signalfd.h>
+#include <unistd.h>
+
+static volatile sig_atomic_t handled;
+
+void handler(int signo) { handled++; }
+
+void test_signalfd() {
+  sigset_t mask;
+  sigemptyset(&mask);
+  sigaddset(&mask, SIGUSR1);
+  sigaddset(&mask, SIGUSR2);
+  sigaddset(&mask, SIGRTMIN);
+  sigprocmask(SIG_BLOCK, &mask, NULL);
+  signal(SIGUSR1, handler);
+  signal(SIGUSR2, handler);
+  signal(SIGRTMIN, handler);
+
+  int fd = signalfd(-1, &mask, SFD_NONBLOCK);
+  raise(SIGUSR1);
+  raise(SIGUSR2);
+  raise(SIGRTMIN);
+
+  // One read drains all three signals.
+  struct signalfd_siginfo info[4];
+  ssize_t n = read(fd, info, sizeof(info));
+  if (n == 3 * sizeof(struct signalfd_siginfo)) {
+    puts("test_signalfd ok1");
+  }
+  int seen = 0;
+  for (int i = 0; i < n / (ssize_t)sizeof(struct signalfd_siginfo); i++) {
+    if (info[i].ssi_signo == SIGUSR1) {
+      seen |= 1;
+    } else if (info[i].ssi_signo == SIGUSR2) {
+      seen |= 2;
+    } else if (info[i].ssi_signo == SIGRTMIN) {
+      seen |= 4;
+    }
+  }
+  if (seen == 7) {
+    puts("test_signalfd ok2");
+  }
+
+  // The signals were consumed: nothing is pending and no handler runs once
+  // they are unblocked.
+  sigset_t pending;
+  sigpending(&pending);
+  sigprocmask(SIG_UNBLOCK, &mask, NULL);
+  if (!sigismember(&pending, SIGUSR1) && !sigismember(&pending, SIGUSR2) &&
+      !sigismember(&pending, SIGRTMIN) && handled == 0) {
+    puts("test_signalfd ok3");
+  }
+
+  // Nothing left to read.
+  if (read(fd, info, sizeof(info)) < 0 && errno == EAGAIN) {
+    puts("test_signalfd ok4");
+  }
+  close(fd);
+}
+
+void test_signalfd_mask() {
+  sigset_t mask;
+  sigemptyset(&mask);
+  sigaddset(&mask, SIGUSR1);
+  sigaddset(&mask, SIGUSR2);
+  sigprocmask(SIG_BLOCK, &mask, NULL);
+
+  sigset_t accepted;
+  sigemptyset(&accepted);
+  sigaddset(&accepted, SIGUSR1);
+  int fd = signalfd(-1, &accepted, SFD_NONBLOCK);
+  raise(SIGUSR2);
+
+  // SIGUSR2 is not accepted yet.
+  struct signalfd_siginfo info;
+  if (read(fd, &info, sizeof(info)) < 0 && errno == EAGAIN) {
+    puts("test_signalfd_mask ok1");
+  }
+
+  // Updating the mask of the same fd lets it through.
+  sigaddset(&accepted, SIGUSR2);
+  if (signalfd(fd, &accepted, 0) == fd &&
+      read(fd, &info, sizeof(info)) == sizeof(info) &&
+      info.ssi_signo == SIGUSR2) {
+    puts("test_signalfd_mask ok2");
+  }
+
+  // Buffers too small for a record are rejected.
+  raise(SIGUSR1);
+  if (read(fd, &info, sizeof(info) - 1) < 0 && errno == EINVAL) {
+    puts("test_signalfd_mask ok3");
+  }
+  read(fd, &info, sizeof(info));
+  close(fd);
+  sigprocmask(SIG_UNBLOCK, &mask, NULL);
+}
+
+int main() {
+  test_signalfd();
+  test_signalfd_mask();
+  return 0;
+}
@@ -0,0 +1,46 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+void test_sync() {
+  char buf[8] = {0};
+  int fd = open("sync_file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  if (write(fd, "data", 4) == 4 && fsync(fd) == 0 && fdatasync(fd) == 0) {
+    puts("test_sync ok1");
+  }
+
+  sync();
+  int other = open("sync_file", O_RDONLY);
+  if (syncfs(fd) == 0 && read(other, buf, sizeof(buf)) == 4 &&
+      strcmp(buf, "data") == 0) {
+    puts("test_sync ok2");
+  }
+
+  if (fsync(-1) < 0 && errno == EBADF && syncfs(100) < 0 && errno == EBADF) {
+    puts("test_sync ok3");
+  }
+
+  // Pipes and sockets have nothing to write back.
+  int fds[2];
+  pipe(fds);
+  int sock = socket(AF_INET, SOCK_DGRAM, 0);
+  if (fsync(fds[0]) < 0 && errno == EINVAL && fdatasync(fds[1]) < 0 &&
+      errno == EINVAL && fsync(sock) < 0 && errno == EINVAL) {
+    puts("test_sync ok4");
+  }
+  close(sock);
+  close(fds[0]);
+  close(fds[1]);
+  close(other);
+  close(fd);
+  unlink("sync_file");
+}
+
+int main() {
+  test_sync();
+  return 0;
+}
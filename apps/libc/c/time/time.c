@@ -0,0 +1,384 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/time.h>
+#include <sys/times.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+static long long ns_between(struct timespec *a, struct timespec *b) {
+  return (b->tv_sec - a->tv_sec) * 1000000000LL + (b->tv_nsec - a->tv_nsec);
+}
+
+static void on_signal(int sig) {}
+
+// Send `SIGUSR1` to the caller after `us` microseconds.
+static pid_t signal_later(int us) {
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  if (pid == 0) {
+    usleep(us);
+    kill(parent, SIGUSR1);
+    _exit(0);
+  }
+  return pid;
+}
+
+void test_clock_nanosleep() {
+  struct timespec start, end, req;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  req.tv_sec = 0;
+  req.tv_nsec = 10000000;
+  if (clock_nanosleep(CLOCK_MONOTONIC, 0, &req, NULL) == 0) {
+    clock_gettime(CLOCK_MONOTONIC, &end);
+    if (ns_between(&start, &end) >= 10000000) {
+      puts("test_clock_nanosleep ok1");
+    }
+  }
+
+  clock_gettime(CLOCK_MONOTONIC, &req);
+  req.tv_nsec += 20000000;
+  if (req.tv_nsec >= 1000000000) {
+    req.tv_sec++;
+    req.tv_nsec -= 1000000000;
+  }
+  if (clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME, &req, NULL) == 0) {
+    clock_gettime(CLOCK_MONOTONIC, &end);
+    if (ns_between(&req, &end) >= 0) {
+      puts("test_clock_nanosleep ok2");
+    }
+  }
+
+  // A deadline in the past returns right away.
+  req.tv_sec = 1;
+  req.tv_nsec = 0;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  if (clock_nanosleep(CLOCK_REALTIME, TIMER_ABSTIME, &req, NULL) == 0) {
+    clock_gettime(CLOCK_MONOTONIC, &end);
+    if (ns_between(&start, &end) < 1000000000) {
+      puts("test_clock_nanosleep ok3");
+    }
+  }
+
+  req.tv_nsec = 1000000000;
+  if (clock_nanosleep(CLOCK_MONOTONIC, 0, &req, NULL) == EINVAL &&
+      clock_nanosleep(12345, 0, &req, NULL) == EINVAL) {
+    puts("test_clock_nanosleep ok4");
+  }
+}
+
+void test_clock_nanosleep_intr() {
+  struct timespec req, rem;
+  signal(SIGUSR1, on_signal);
+
+  // Relative sleeps report the time left.
+  pid_t pid = signal_later(50000);
+  req.tv_sec = 5;
+  req.tv_nsec = 0;
+  if (clock_nanosleep(CLOCK_MONOTONIC, 0, &req, &rem) == EINTR &&
+      rem.tv_sec >= 3 && rem.tv_sec < 5) {
+    puts("test_clock_nanosleep_intr ok1");
+  }
+  waitpid(pid, NULL, 0);
+
+  // Absolute sleeps leave `rem` alone.
+  pid = signal_later(50000);
+  clock_gettime(CLOCK_REALTIME, &req);
+  req.tv_sec += 5;
+  rem.tv_sec = 42;
+  rem.tv_nsec = 0;
+  if (clock_nanosleep(CLOCK_REALTIME, TIMER_ABSTIME, &req, &rem) == EINTR &&
+      rem.tv_sec == 42) {
+    puts("test_clock_nanosleep_intr ok2");
+  }
+  waitpid(pid, NULL, 0);
+
+  // The SIGCHLD of an exiting child is ignored by default, and does not cut
+  // the sleep short.
+  pid = fork();
+  if (pid == 0) {
+    _exit(0);
+  }
+  req.tv_sec = 0;
+  req.tv_nsec = 50000000;
+  if (nanosleep(&req, &rem) == 0) {
+    puts("test_clock_nanosleep_intr ok3");
+  }
+  waitpid(pid, NULL, 0);
+  signal(SIGUSR1, SIG_DFL);
+}
+
+static volatile sig_atomic_t timer_hits;
+
+static void on_timer(int sig, siginfo_t *info, void *ctx) {
+  if (info->si_code == SI_TIMER && info->si_value.sival_int == 42) {
+    timer_hits++;
+  }
+}
+
+// Wait up to 200ms for the timer signal to arrive `n` times in total.
+static void wait_timer_hits(int n) {
+  for (int i = 0; i < 200 && timer_hits < n; i++) {
+    usleep(1000);
+  }
+}
+
+static void set_timer(timer_t timer, long value_ns, long interval_ns) {
+  struct itimerspec its = {0};
+  its.it_value.tv_nsec = value_ns;
+  its.it_interval.tv_nsec = interval_ns;
+  timer_settime(timer, 0, &its, NULL);
+}
+
+void test_posix_timer() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = on_timer;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGUSR2, &sa, NULL);
+
+  struct sigevent sev = {0};
+  sev.sigev_notify = SIGEV_SIGNAL;
+  sev.sigev_signo = SIGUSR2;
+  sev.sigev_value.sival_int = 42;
+  timer_t timer;
+  if (timer_create(CLOCK_MONOTONIC, &sev, &timer) != 0) {
+    return;
+  }
+
+  // A one-shot timer fires once and is disarmed afterwards.
+  struct itimerspec its;
+  set_timer(timer, 10000000, 0);
+  wait_timer_hits(1);
+  usleep(30000);
+  if (timer_hits == 1 && timer_gettime(timer, &its) == 0 &&
+      its.it_value.tv_sec == 0 && its.it_value.tv_nsec == 0) {
+    puts("test_posix_timer ok1");
+  }
+
+  // An interval timer keeps firing until it is disarmed.
+  set_timer(timer, 10000000, 10000000);
+  wait_timer_hits(4);
+  struct itimerspec old;
+  its.it_value.tv_sec = its.it_value.tv_nsec = 0;
+  its.it_interval = its.it_value;
+  if (timer_hits >= 4 && timer_settime(timer, 0, &its, &old) == 0 &&
+      old.it_interval.tv_nsec == 10000000) {
+    int hits = timer_hits;
+    usleep(30000);
+    if (timer_hits == hits) {
+      puts("test_posix_timer ok2");
+    }
+  }
+
+  // Expirations while the signal is pending count as overruns.
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGUSR2);
+  sigprocmask(SIG_BLOCK, &set, NULL);
+  int hits = timer_hits;
+  set_timer(timer, 5000000, 5000000);
+  usleep(50000);
+  set_timer(timer, 0, 0);
+  sigprocmask(SIG_UNBLOCK, &set, NULL);
+  if (timer_hits == hits + 1 && timer_getoverrun(timer) >= 3) {
+    puts("test_posix_timer ok3");
+  }
+
+  // Deleting a timer cancels its pending expiration.
+  hits = timer_hits;
+  set_timer(timer, 20000000, 0);
+  if (timer_delete(timer) == 0) {
+    usleep(50000);
+    if (timer_hits == hits) {
+      puts("test_posix_timer ok4");
+    }
+  }
+
+  if (timer_create(12345, &sev, &timer) < 0 && errno == EINVAL) {
+    puts("test_posix_timer ok5");
+  }
+  signal(SIGUSR2, SIG_DFL);
+
+  // The number of timers is limited, so a process cannot use up the kernel
+  // memory. This runs in a child, which frees its timers on exit.
+  pid_t pid = fork();
+  if (pid == 0) {
+    sev.sigev_notify = SIGEV_NONE;
+    int n = 0;
+    while (n < (1 << 20) && timer_create(CLOCK_MONOTONIC, &sev, &timer) == 0) {
+      n++;
+    }
+    if (n == 0 || errno != EAGAIN || timer_delete(timer) != 0 ||
+        timer_create(CLOCK_MONOTONIC, &sev, &timer) != 0) {
+      _exit(1);
+    }
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_posix_timer ok6");
+  }
+}
+
+static volatile sig_atomic_t alarm_hits;
+
+static void on_alarm(int sig) { alarm_hits++; }
+
+static int set_itimer(int which, long value_us, long interval_us,
+                      struct itimerval *old) {
+  struct itimerval val = {0};
+  val.it_value.tv_usec = value_us;
+  val.it_interval.tv_usec = interval_us;
+  return setitimer(which, &val, old);
+}
+
+void test_itimer() {
+  signal(SIGALRM, on_alarm);
+  signal(SIGPROF, on_alarm);
+
+  // A zero interval fires once.
+  struct itimerval val;
+  set_itimer(ITIMER_REAL, 10000, 0, NULL);
+  for (int i = 0; i < 200 && alarm_hits < 1; i++) {
+    usleep(1000);
+  }
+  usleep(30000);
+  if (alarm_hits == 1 && getitimer(ITIMER_REAL, &val) == 0 &&
+      val.it_value.tv_sec == 0 && val.it_value.tv_usec == 0) {
+    puts("test_itimer ok1");
+  }
+
+  // `getitimer` reports the time left, not the original value.
+  alarm_hits = 0;
+  set_itimer(ITIMER_REAL, 100000, 10000, NULL);
+  usleep(20000);
+  if (getitimer(ITIMER_REAL, &val) == 0 && val.it_value.tv_sec == 0 &&
+      val.it_value.tv_usec > 0 && val.it_value.tv_usec < 100000 &&
+      val.it_interval.tv_usec == 10000) {
+    puts("test_itimer ok2");
+  }
+
+  for (int i = 0; i < 300 && alarm_hits < 4; i++) {
+    usleep(1000);
+  }
+  struct itimerval old;
+  if (alarm_hits >= 4 && set_itimer(ITIMER_REAL, 0, 0, &old) == 0 &&
+      old.it_interval.tv_usec == 10000) {
+    int hits = alarm_hits;
+    usleep(30000);
+    if (alarm_hits == hits) {
+      puts("test_itimer ok3");
+    }
+  }
+
+  // The profiling timer counts CPU time.
+  alarm_hits = 0;
+  set_itimer(ITIMER_PROF, 10000, 0, NULL);
+  struct timespec start, now;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  do {
+    getppid();
+    clock_gettime(CLOCK_MONOTONIC, &now);
+  } while (!alarm_hits && ns_between(&start, &now) < 2000000000LL);
+  if (alarm_hits == 1) {
+    puts("test_itimer ok4");
+  }
+
+  if (set_itimer(12345, 10000, 0, NULL) < 0 && errno == EINVAL &&
+      set_itimer(ITIMER_REAL, 1000000, 0, NULL) < 0 && errno == EINVAL) {
+    puts("test_itimer ok5");
+  }
+  signal(SIGALRM, SIG_DFL);
+  signal(SIGPROF, SIG_DFL);
+}
+
+void test_clock_getres() {
+  clockid_t clocks[] = {CLOCK_REALTIME, CLOCK_MONOTONIC,
+                        CLOCK_PROCESS_CPUTIME_ID, CLOCK_THREAD_CPUTIME_ID};
+  int ok = 1;
+  for (int i = 0; i < 4; i++) {
+    struct timespec res;
+    if (clock_getres(clocks[i], &res) != 0 || res.tv_sec != 0 ||
+        res.tv_nsec <= 0 || res.tv_nsec > 1000000) {
+      ok = 0;
+    }
+  }
+  if (ok) {
+    puts("test_clock_getres ok1");
+  }
+
+  struct timespec ts;
+  if (clock_getres(CLOCK_MONOTONIC, NULL) == 0 &&
+      clock_getres(12345, &ts) < 0 && errno == EINVAL &&
+      clock_gettime(12345, &ts) < 0 && errno == EINVAL) {
+    puts("test_clock_getres ok2");
+  }
+
+  // Spin for 20ms of system calls, which is all CPU time.
+  struct timespec cpu_start, cpu_end, thread_end, start, now;
+  clock_gettime(CLOCK_PROCESS_CPUTIME_ID, &cpu_start);
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  do {
+    clock_gettime(CLOCK_MONOTONIC, &now);
+  } while (ns_between(&start, &now) < 20000000);
+  clock_gettime(CLOCK_PROCESS_CPUTIME_ID, &cpu_end);
+  clock_gettime(CLOCK_THREAD_CPUTIME_ID, &thread_end);
+  long long used = ns_between(&cpu_start, &cpu_end);
+  clock_gettime(CLOCK_MONOTONIC, &now);
+  if (used > 0 && used <= ns_between(&start, &now) + 1000000 &&
+      thread_end.tv_sec * 1000000000LL + thread_end.tv_nsec > 0) {
+    puts("test_clock_getres ok3");
+  }
+}
+
+// Spin for `ms` milliseconds of system calls, which is all CPU time.
+static void spin(int ms) {
+  struct timespec start, now;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  do {
+    clock_gettime(CLOCK_MONOTONIC, &now);
+  } while (ns_between(&start, &now) < ms * 1000000LL);
+}
+
+void test_times() {
+  struct tms before, after;
+  clock_t start = times(&before);
+  usleep(50000);
+  clock_t end = times(NULL);
+  long hz = sysconf(_SC_CLK_TCK);
+  if (start > 0 && end - start >= hz / 20 - 1 && end - start <= hz / 20 + 2) {
+    puts("test_times ok1");
+  }
+
+  spin(50);
+  times(&after);
+  if (after.tms_utime + after.tms_stime - before.tms_utime -
+          before.tms_stime >= 3) {
+    puts("test_times ok2");
+  }
+
+  // A reaped child's CPU time is added to the children's time.
+  pid_t pid = fork();
+  if (pid == 0) {
+    spin(50);
+    _exit(0);
+  }
+  waitpid(pid, NULL, 0);
+  times(&after);
+  if (after.tms_cutime + after.tms_cstime - before.tms_cutime -
+          before.tms_cstime >= 3) {
+    puts("test_times ok3");
+  }
+}
+
+int main() {
+  test_clock_nanosleep();
+  test_clock_nanosleep_intr();
+  test_posix_timer();
+  test_itimer();
+  test_clock_getres();
+  test_times();
+  return 0;
+}
@@ -0,0 +1,101 @@
+#include <errno.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <sys/timerfd.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+static void set_timer(int fd, int flags, struct timespec value,
+                      long interval_ns) {
+  struct itimerspec spec = {.it_value = value,
+                            .it_interval = {0, interval_ns}};
+  timerfd_settime(fd, flags, &spec, NULL);
+}
+
+// Sets CLOCK_REALTIME to what it reads, which still counts as a jump.
+static void step_clock() {
+  struct timespec now;
+  clock_gettime(CLOCK_REALTIME, &now);
+  clock_settime(CLOCK_REALTIME, &now);
+}
+
+void test_timerfd() {
+  uint64_t ticks;
+  int fd = timerfd_create(CLOCK_MONOTONIC, 0);
+
+  // A blocking read waits for the first expiration.
+  set_timer(fd, 0, (struct timespec){0, 50000000}, 0);
+  if (read(fd, &ticks, sizeof(ticks)) == sizeof(ticks) && ticks == 1) {
+    puts("test_timerfd ok1");
+  }
+
+  // A periodic timer counts every period since the last read.
+  set_timer(fd, 0, (struct timespec){0, 10000000}, 10000000);
+  usleep(55000);
+  struct itimerspec spec;
+  if (read(fd, &ticks, sizeof(ticks)) == sizeof(ticks) && ticks >= 4 &&
+      timerfd_gettime(fd, &spec) == 0 && spec.it_interval.tv_nsec == 10000000) {
+    puts("test_timerfd ok2");
+  }
+  close(fd);
+
+  // Nothing to read from a disarmed timer.
+  fd = timerfd_create(CLOCK_MONOTONIC, TFD_NONBLOCK);
+  set_timer(fd, 0, (struct timespec){0, 1000000}, 0);
+  set_timer(fd, 0, (struct timespec){0, 0}, 0);
+  usleep(5000);
+  if (read(fd, &ticks, sizeof(ticks)) < 0 && errno == EAGAIN) {
+    puts("test_timerfd ok3");
+  }
+  close(fd);
+}
+
+void test_timerfd_cancel() {
+  uint64_t ticks;
+  struct timespec later;
+  clock_gettime(CLOCK_REALTIME, &later);
+  later.tv_sec += 3600;
+
+  // Setting the clock cancels the timer, which is reported once.
+  int fd = timerfd_create(CLOCK_REALTIME, TFD_NONBLOCK);
+  set_timer(fd, TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET, later, 0);
+  step_clock();
+  if (read(fd, &ticks, sizeof(ticks)) < 0 && errno == ECANCELED &&
+      read(fd, &ticks, sizeof(ticks)) < 0 && errno == EAGAIN) {
+    puts("test_timerfd_cancel ok1");
+  }
+  close(fd);
+
+  // A blocked reader is woken up by the cancellation.
+  fd = timerfd_create(CLOCK_REALTIME, 0);
+  set_timer(fd, TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET, later, 0);
+  pid_t pid = fork();
+  if (pid == 0) {
+    int ok = read(fd, &ticks, sizeof(ticks)) < 0 && errno == ECANCELED;
+    _exit(ok ? 0 : 1);
+  }
+  usleep(50000);
+  step_clock();
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_timerfd_cancel ok2");
+  }
+  close(fd);
+
+  // Without TFD_TIMER_CANCEL_ON_SET, the timer just keeps waiting.
+  fd = timerfd_create(CLOCK_REALTIME, TFD_NONBLOCK);
+  set_timer(fd, TFD_TIMER_ABSTIME, later, 0);
+  step_clock();
+  if (read(fd, &ticks, sizeof(ticks)) < 0 && errno == EAGAIN) {
+    puts("test_timerfd_cancel ok3");
+  }
+  close(fd);
+}
+
+int main() {
+  test_timerfd();
+  test_timerfd_cancel();
+  return 0;
+}
@@ -0,0 +1,157 @@
+#include <pthread.h>
+#include <sched.h>
+#include <stdio.h>
+#include <unistd.h>
+#include <sys/syscall.h>
+
+#ifdef __x86_64__
+#include <asm/prctl.h>
+#include <errno.h>
+
+// libc must not be used while a foreign FS base is installed, since it keeps
+// its own thread data there. Issue the syscalls directly instead.
+static long raw_syscall2(long n, long a0, long a1) {
+  long ret;
+  __asm__ volatile("syscall"
+                   : "=a"(ret)
+                   : "a"(n), "D"(a0), "S"(a1)
+                   : "rcx", "r11", "memory");
+  return ret;
+}
+#endif
+
+void test_arch_prctl() {
+#ifdef __x86_64__
+  unsigned long old_fs = 0, fs = 0;
+  static unsigned long area[4];
+
+  syscall(SYS_arch_prctl, ARCH_GET_FS, &old_fs);
+  syscall(SYS_arch_prctl, ARCH_SET_FS, (unsigned long)area);
+  syscall(SYS_arch_prctl, ARCH_GET_FS, &fs);
+  // Restore the original TLS before calling into libc again
+  syscall(SYS_arch_prctl, ARCH_SET_FS, old_fs);
+  if (fs == (unsigned long)area) {
+    puts("test_arch_prctl ok1");
+  }
+
+  if (syscall(SYS_modify_ldt, 0, NULL, 0) < 0 && errno == ENOSYS) {
+    puts("test_arch_prctl ok2");
+  }
+#else
+  puts("test_arch_prctl ok1");
+  puts("test_arch_prctl ok2");
+#endif
+}
+
+#ifdef __x86_64__
+static unsigned long areas[2][4];
+
+// Install a private FS/GS base, yield to the other thread for a while, and
+// check that both bases are still ours afterwards.
+static void *switch_bases(void *arg) {
+  long idx = (long)arg;
+  unsigned long want = (unsigned long)areas[idx];
+  unsigned long old_fs = 0, fs = 0, gs = 0;
+
+  raw_syscall2(SYS_arch_prctl, ARCH_GET_FS, (long)&old_fs);
+  raw_syscall2(SYS_arch_prctl, ARCH_SET_FS, want);
+  raw_syscall2(SYS_arch_prctl, ARCH_SET_GS, want);
+  for (int i = 0; i < 16; i++) {
+    raw_syscall2(SYS_sched_yield, 0, 0);
+  }
+  raw_syscall2(SYS_arch_prctl, ARCH_GET_FS, (long)&fs);
+  raw_syscall2(SYS_arch_prctl, ARCH_GET_GS, (long)&gs);
+  raw_syscall2(SYS_arch_prctl, ARCH_SET_FS, old_fs);
+  raw_syscall2(SYS_arch_prctl, ARCH_SET_GS, 0);
+
+  return (void *)(long)(fs == want && gs == want);
+}
+#endif
+
+void test_tls_per_thread() {
+#ifdef __x86_64__
+  pthread_t thread;
+  void *ret0, *ret1;
+  pthread_create(&thread, NULL, switch_bases, (void *)1);
+  ret0 = switch_bases((void *)0);
+  pthread_join(thread, &ret1);
+  if (ret0 && ret1) {
+    puts("test_tls_per_thread ok");
+  }
+#else
+  puts("test_tls_per_thread ok");
+#endif
+}
+
+#ifdef __x86_64__
+#include <asm/ldt.h>
+#endif
+
+// 64-bit tasks keep their TLS pointer in the FS base, and have no TLS
+// segments.
+void test_thread_area() {
+#ifdef __x86_64__
+  struct user_desc desc = {0};
+  desc.entry_number = -1;
+  if (raw_syscall2(SYS_set_thread_area, (long)&desc, 0) == -ENOSYS) {
+    puts("test_thread_area ok1");
+  }
+  desc.entry_number = 12;
+  if (raw_syscall2(SYS_get_thread_area, (long)&desc, 0) == -ENOSYS) {
+    puts("test_thread_area ok2");
+  }
+#else
+  puts("test_thread_area ok1");
+  puts("test_thread_area ok2");
+#endif
+}
+
+static void *thread_pointer() {
+  void *tp;
+#if defined(__x86_64__)
+  __asm__ volatile("mov %%fs:0, %0" : "=r"(tp));
+#elif defined(__aarch64__)
+  __asm__ volatile("mrs %0, tpidr_el0" : "=r"(tp));
+#elif defined(__riscv)
+  __asm__ volatile("mv %0, tp" : "=r"(tp));
+#elif defined(__loongarch__)
+  __asm__ volatile("move %0, $tp" : "=r"(tp));
+#endif
+  return tp;
+}
+
+static __thread long tls_value;
+
+// Threads created with CLONE_SETTLS must each see their own thread pointer,
+// and keep it across scheduling.
+static void *check_thread_pointer(void *arg) {
+  void *tp = thread_pointer();
+  tls_value = (long)arg;
+  for (int i = 0; i < 16; i++) {
+    sched_yield();
+  }
+  return (void *)(long)(thread_pointer() == tp && tls_value == (long)arg);
+}
+
+void test_thread_pointer() {
+  pthread_t threads[2];
+  void *ret[2];
+  for (long i = 0; i < 2; i++) {
+    pthread_create(&threads[i], NULL, check_thread_pointer, (void *)(i + 1));
+  }
+  void *main_ret = check_thread_pointer((void *)0);
+  for (int i = 0; i < 2; i++) {
+    pthread_join(threads[i], &ret[i]);
+  }
+  if (main_ret && ret[0] && ret[1] && tls_value == 0) {
+    puts("test_thread_pointer ok");
+  }
+}
+
+int main() {
+  test_arch_prctl();
+  test_tls_per_thread();
+  test_thread_pointer();
+  test_thread_area();
+  return 0;
+}
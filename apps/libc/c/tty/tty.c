@@ -0,0 +1,200 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/ioctl.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void test_setsid() {
+  int pid = fork();
+  if (pid == 0) {
+    // The new session must not inherit the controlling terminal.
+    if (setsid() == getpid() && open("/dev/tty", O_RDWR) < 0 &&
+        errno == ENXIO) {
+      puts("test_setsid ok1");
+    }
+
+    // A session leader without a controlling terminal acquires the one it
+    // opens, with its own group in the foreground.
+    int fd = open("/dev/console", O_RDWR);
+    if (fd >= 0 && open("/dev/tty", O_RDWR) >= 0 && tcgetpgrp(fd) == getpid()) {
+      puts("test_setsid ok2");
+    }
+    exit(0);
+  }
+  wait(NULL);
+
+  // A process group leader cannot create a session.
+  if (fork() == 0) {
+    setsid();
+    if (setsid() < 0 && errno == EPERM) {
+      puts("test_setsid ok3");
+    }
+    exit(0);
+  }
+  wait(NULL);
+}
+
+void test_noctty() {
+  if (fork() == 0) {
+    setsid();
+    int fd = open("/dev/console", O_RDWR | O_NOCTTY);
+    if (fd >= 0 && tcgetpgrp(fd) < 0 && errno == ENOTTY &&
+        open("/dev/tty", O_RDWR) < 0 && errno == ENXIO) {
+      puts("test_noctty ok1");
+    }
+
+    fd = open("/dev/console", O_RDWR);
+    if (fd >= 0 && tcgetpgrp(fd) == getpid()) {
+      puts("test_noctty ok2");
+    }
+
+    // Requests the terminal does not know are refused like on other files.
+    if (ioctl(fd, 0x54ff) < 0 && errno == ENOTTY) {
+      puts("test_noctty ok3");
+    }
+    exit(0);
+  }
+  wait(NULL);
+}
+
+static volatile int hup_received;
+
+static void hup_handler(int signum) { hup_received = 1; }
+
+void test_vhangup() {
+  if (fork() == 0) {
+    setsid();
+    int fd = open("/dev/console", O_RDWR);
+    signal(SIGHUP, SIG_IGN);
+
+    int pid = fork();
+    if (pid == 0) {
+      // A member of the foreground group waiting for the hangup.
+      sigset_t set;
+      sigemptyset(&set);
+      signal(SIGHUP, hup_handler);
+      while (!hup_received) {
+        sigsuspend(&set);
+      }
+      exit(3);
+    }
+    sleep(1);
+    vhangup();
+
+    int status;
+    waitpid(pid, &status, 0);
+    if (WIFEXITED(status) && WEXITSTATUS(status) == 3) {
+      puts("test_vhangup ok1");
+    }
+    char c;
+    if (read(fd, &c, 1) < 0 && errno == EIO &&
+        open("/dev/tty", O_RDWR) < 0 && errno == ENXIO) {
+      puts("test_vhangup ok2");
+    }
+    exit(0);
+  }
+  wait(NULL);
+}
+
+void test_leader_exit() {
+  int fds[2];
+  pipe(fds);
+  if (fork() == 0) {
+    setsid();
+    open("/dev/console", O_RDWR);
+    if (fork() == 0) {
+      // The foreground group gets SIGHUP once the session leader is gone.
+      sigset_t set;
+      sigemptyset(&set);
+      signal(SIGHUP, hup_handler);
+      while (!hup_received) {
+        sigsuspend(&set);
+      }
+      write(fds[1], "h", 1);
+      exit(0);
+    }
+    sleep(1);
+    exit(0);
+  }
+  wait(NULL);
+
+  char c = 0;
+  if (read(fds[0], &c, 1) == 1 && c == 'h') {
+    puts("test_leader_exit ok");
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+static pid_t spawn_waiter(int fd) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    char c;
+    read(fd, &c, 1);
+    exit(0);
+  }
+  return pid;
+}
+
+void test_pgid() {
+  if (getpgrp() == getpgid(0) && getpgid(getpid()) == getpgid(0) &&
+      getsid(0) == getsid(getpid())) {
+    puts("test_pgid ok1");
+  }
+
+  int fds[2];
+  pipe(fds);
+  pid_t a = spawn_waiter(fds[0]);
+  pid_t b = spawn_waiter(fds[0]);
+
+  // Children start in the group and session of their parent.
+  if (getpgid(a) == getpgrp() && getsid(a) == getsid(0) &&
+      setpgid(a, 0) == 0 && getpgid(a) == a && getsid(a) == getsid(0)) {
+    puts("test_pgid ok2");
+  }
+
+  if (setpgid(b, a) == 0 && getpgid(b) == a) {
+    puts("test_pgid ok3");
+  }
+
+  if (setpgid(0x7fffff, 0) < 0 && errno == ESRCH && setpgid(0, -1) < 0 &&
+      errno == EINVAL && setpgid(b, 0x7fffff) < 0 && errno == EPERM) {
+    puts("test_pgid ok4");
+  }
+
+  write(fds[1], "xx", 2);
+  waitpid(a, NULL, 0);
+  waitpid(b, NULL, 0);
+
+  // A session leader cannot be moved into another group.
+  pid_t c = fork();
+  if (c == 0) {
+    setsid();
+    char ch;
+    read(fds[0], &ch, 1);
+    exit(0);
+  }
+  while (getsid(c) != c) {
+    sched_yield();
+  }
+  if (setpgid(c, getpgrp()) < 0 && errno == EPERM) {
+    puts("test_pgid ok5");
+  }
+  write(fds[1], "x", 1);
+  waitpid(c, NULL, 0);
+  close(fds[0]);
+  close(fds[1]);
+}
+
+int main() {
+  test_setsid();
+  test_noctty();
+  test_vhangup();
+  test_leader_exit();
+  test_pgid();
+  return 0;
+}
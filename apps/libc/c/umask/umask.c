@@ -0,0 +1,49 @@
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void test_umask() {
+  struct stat st;
+  // The default is 022, and setting it returns the previous one.
+  mode_t old = umask(022);
+  if (old == 022 && umask(077) == 022 && umask(022) == 077) {
+    puts("test_umask ok1");
+  }
+
+  int fd = open("umask_file", O_RDWR | O_CREAT | O_TRUNC, 0666);
+  if (fstat(fd, &st) == 0 && (st.st_mode & 0777) == 0644) {
+    puts("test_umask ok2");
+  }
+  close(fd);
+  unlink("umask_file");
+
+  if (mkdir("umask_dir", 0777) == 0 && stat("umask_dir", &st) == 0 &&
+      (st.st_mode & 0777) == 0755 &&
+      mknod("umask_dir/node", S_IFREG | 0666, 0) == 0 &&
+      stat("umask_dir/node", &st) == 0 && S_ISREG(st.st_mode) &&
+      (st.st_mode & 0777) == 0644) {
+    puts("test_umask ok3");
+  }
+  unlink("umask_dir/node");
+  rmdir("umask_dir");
+
+  // A child starts with the parent's umask, and changing it there does not
+  // affect the parent.
+  umask(027);
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(umask(0) == 027 ? 0 : 1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0 && umask(022) == 027) {
+    puts("test_umask ok4");
+  }
+}
+
+int main() {
+  test_umask();
+  return 0;
+}
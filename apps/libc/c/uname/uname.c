@@ -0,0 +1,35 @@
+#include <stdio.h>
+#include <string.h>
+#include <sys/utsname.h>
+
+#if defined(__x86_64__)
+#define MACHINE "x86_64"
+#elif defined(__riscv) && __riscv_xlen == 64
+#define MACHINE "riscv64"
+#elif defined(__aarch64__)
+#define MACHINE "aarch64"
+#elif defined(__loongarch64)
+#define MACHINE "loongarch64"
+#else
+#define MACHINE "unknown"
+#endif
+
+void test_uname() {
+  struct utsname name;
+  if (uname(&name) == 0 && strcmp(name.sysname, "Linux") == 0 &&
+      strcmp(name.machine, MACHINE) == 0) {
+    puts("test_uname ok1");
+  }
+
+  // The release looks like a Linux version, major.minor.patch.
+  int major, minor, patch;
+  if (sscanf(name.release, "%d.%d.%d", &major, &minor, &patch) == 3 &&
+      major >= 3 && name.nodename[0] != '\0' && name.version[0] != '\0') {
+    puts("test_uname ok2");
+  }
+}
+
+int main() {
+  test_uname();
+  return 0;
+}
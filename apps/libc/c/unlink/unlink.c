@@ -0,0 +1,64 @@
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+// Counts the entries of `path` other than "." and "..".
+static int count_entries(const char *path) {
+  DIR *dir = opendir(path);
+  struct dirent *ent;
+  int n = 0;
+  while ((ent = readdir(dir)) != NULL) {
+    if (strcmp(ent->d_name, ".") != 0 && strcmp(ent->d_name, "..") != 0) {
+      n++;
+    }
+  }
+  closedir(dir);
+  return n;
+}
+
+void test_unlink_open() {
+  struct stat st;
+  char buf[16] = {0};
+  mkdir("unlink_dir", 0755);
+  int fd = open("unlink_dir/file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  int other = open("unlink_dir/file", O_RDONLY);
+  write(fd, "hello", 5);
+
+  // The name is gone at once.
+  if (unlink("unlink_dir/file") == 0 && stat("unlink_dir/file", &st) < 0 &&
+      errno == ENOENT && count_entries("unlink_dir") == 0) {
+    puts("test_unlink_open ok1");
+  }
+
+  // The data is still there through every descriptor.
+  if (write(fd, " world", 6) == 6 && fstat(fd, &st) == 0 && st.st_size == 11 &&
+      pread(other, buf, sizeof(buf), 0) == 11 &&
+      strcmp(buf, "hello world") == 0) {
+    puts("test_unlink_open ok2");
+  }
+
+  // The name can be used for a new file meanwhile.
+  int fresh = open("unlink_dir/file", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  if (fresh >= 0 && fstat(fresh, &st) == 0 && st.st_size == 0 &&
+      fstat(fd, &st) == 0 && st.st_size == 11) {
+    puts("test_unlink_open ok3");
+  }
+  close(fresh);
+  unlink("unlink_dir/file");
+
+  // Once closed, nothing is left behind.
+  close(fd);
+  close(other);
+  if (rmdir("unlink_dir") == 0) {
+    puts("test_unlink_open ok4");
+  }
+}
+
+int main() {
+  test_unlink_open();
+  return 0;
+}
@@ -0,0 +1,162 @@
+#include <errno.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void test_wnohang() {
+  int fds[2];
+  pipe(fds);
+  pid_t pid = fork();
+  if (pid == 0) {
+    char c;
+    close(fds[1]);
+    read(fds[0], &c, 1);
+    _exit(3);
+  }
+  close(fds[0]);
+
+  int status;
+  if (waitpid(pid, &status, WNOHANG) == 0) {
+    puts("test_wnohang ok1");
+  }
+
+  write(fds[1], "x", 1);
+  close(fds[1]);
+  if (waitpid(pid, &status, 0) == pid && WIFEXITED(status) &&
+      WEXITSTATUS(status) == 3) {
+    puts("test_wnohang ok2");
+  }
+}
+
+void test_signaled() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    for (;;) {
+      sched_yield();
+    }
+  }
+  kill(pid, SIGTERM);
+  int status;
+  if (waitpid(pid, &status, 0) == pid && WIFSIGNALED(status) &&
+      WTERMSIG(status) == SIGTERM) {
+    puts("test_signaled ok");
+  }
+}
+
+void test_stop_continue() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    for (;;) {
+      sched_yield();
+    }
+  }
+
+  int status;
+  kill(pid, SIGSTOP);
+  if (waitpid(pid, &status, WUNTRACED) == pid && WIFSTOPPED(status) &&
+      WSTOPSIG(status) == SIGSTOP) {
+    puts("test_stop_continue ok1");
+  }
+
+  kill(pid, SIGCONT);
+  if (waitpid(pid, &status, WCONTINUED) == pid && WIFCONTINUED(status)) {
+    puts("test_stop_continue ok2");
+  }
+
+  // Stopped again, the child can still be killed.
+  kill(pid, SIGSTOP);
+  waitpid(pid, &status, WUNTRACED);
+  kill(pid, SIGKILL);
+  if (waitpid(pid, &status, 0) == pid && WIFSIGNALED(status) &&
+      WTERMSIG(status) == SIGKILL) {
+    puts("test_stop_continue ok3");
+  }
+}
+
+void test_rusage() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    volatile unsigned long sum = 0;
+    for (unsigned long i = 0; i < 50000000; i++) {
+      sum += i;
+    }
+    _exit(0);
+  }
+
+  int status;
+  struct rusage ru = {0};
+  if (wait4(pid, &status, 0, &ru) == pid && WIFEXITED(status) &&
+      (ru.ru_utime.tv_sec || ru.ru_utime.tv_usec || ru.ru_stime.tv_sec ||
+       ru.ru_stime.tv_usec)) {
+    puts("test_rusage ok");
+  }
+}
+
+void test_echild() {
+  int status;
+  if (waitpid(-1, &status, 0) < 0 && errno == ECHILD) {
+    puts("test_echild ok1");
+  }
+  if (waitpid(getpid(), &status, WNOHANG) < 0 && errno == ECHILD) {
+    puts("test_echild ok2");
+  }
+}
+
+void test_waitid() {
+  siginfo_t info;
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(5);
+  }
+
+  // WNOWAIT leaves the zombie to be waited for again.
+  if (waitid(P_PID, pid, &info, WEXITED | WNOWAIT) == 0 &&
+      info.si_pid == pid && info.si_signo == SIGCHLD &&
+      info.si_code == CLD_EXITED && info.si_status == 5) {
+    puts("test_waitid ok1");
+  }
+  info.si_pid = 0;
+  if (waitid(P_PID, pid, &info, WEXITED) == 0 && info.si_pid == pid &&
+      waitid(P_PID, pid, &info, WEXITED) < 0 && errno == ECHILD) {
+    puts("test_waitid ok2");
+  }
+
+  int fds[2];
+  pipe(fds);
+  pid = fork();
+  if (pid == 0) {
+    char c;
+    close(fds[1]);
+    read(fds[0], &c, 1);
+    _exit(0);
+  }
+  close(fds[0]);
+  info.si_pid = 123;
+  if (waitid(P_ALL, 0, &info, WEXITED | WNOHANG) == 0 && info.si_pid == 0) {
+    puts("test_waitid ok3");
+  }
+
+  kill(pid, SIGKILL);
+  if (waitid(P_PGID, 0, &info, WEXITED) == 0 && info.si_pid == pid &&
+      info.si_code == CLD_KILLED && info.si_status == SIGKILL) {
+    puts("test_waitid ok4");
+  }
+  close(fds[1]);
+
+  if (waitid(P_ALL, 0, &info, 0) < 0 && errno == EINVAL) {
+    puts("test_waitid ok5");
+  }
+}
+
+int main() {
+  test_wnohang();
+  test_signaled();
+  test_stop_continue();
+  test_rusage();
+  test_echild();
+  test_waitid();
+  return 0;
+}
@@ -0,0 +1,18 @@
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+// Crash the kernel with a file written and left open. The panic handler
+// writes it back first and prints "Emergency Sync complete".
+int main() {
+  int fd = open("panic_file", O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  write(fd, "data", 4);
+  int sysrq = open("/proc/sysrq-trigger", O_WRONLY);
+  if (sysrq >= 0) {
+    puts("test_panic ok1");
+  }
+  fflush(stdout);
+  write(sysrq, "c", 1);
+  printf("test_panic: still running after the crash, fd %d\n", fd);
+  return 0;
+}
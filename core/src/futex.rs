@@ -1,60 +1,207 @@
 //! Futex implementation.
 
-use core::ops::Deref;
+use core::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
 
-use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
 use axsync::Mutex;
 use axtask::{TaskExtRef, WaitQueue, current};
 
-/// A table mapping memory addresses to futex wait queues.
-pub struct FutexTable(Mutex<BTreeMap<usize, Arc<WaitQueue>>>);
+/// A task blocked on a futex.
+struct FutexWaiter {
+    /// The address of the futex the task is currently queued on. It changes
+    /// when the waiter is requeued.
+    key: AtomicUsize,
+    /// Only wakeups whose mask overlaps this one reach the waiter.
+    bitset: u32,
+    /// The TID of the task, to which a PI futex is handed over.
+    tid: u32,
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl FutexWaiter {
+    fn new(key: usize, bitset: u32) -> Self {
+        Self {
+            key: AtomicUsize::new(key),
+            bitset,
+            tid: axtask::current().id().as_u64() as u32,
+            woken: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    fn is_woken(&self) -> bool {
+        self.woken.load(Ordering::Acquire)
+    }
+
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+}
+
+type FutexQueues = BTreeMap<usize, VecDeque<Arc<FutexWaiter>>>;
+
+/// A table mapping memory addresses to the tasks waiting on them.
+///
+/// Every operation runs with the whole table locked, so checking the futex
+/// word, enqueueing, waking and requeueing are atomic with respect to each
+/// other.
+pub struct FutexTable(Mutex<FutexQueues>);
+
 impl FutexTable {
     /// Creates a new `FutexTable`.
     pub fn new() -> Self {
         Self(Mutex::new(BTreeMap::new()))
     }
 
-    /// Gets the wait queue associated with the given address.
-    pub fn get(&self, addr: usize) -> Option<WaitQueueGuard> {
-        let wq = self.0.lock().get(&addr).cloned()?;
-        Some(WaitQueueGuard {
-            key: addr,
-            inner: wq,
-        })
-    }
+    /// Blocks the current task on the futex at `addr`, waiting for a wakeup
+    /// whose mask overlaps `bitset`.
+    ///
+    /// `validate` runs with the table locked right before the task is
+    /// enqueued; if it fails the task does not block and the error is
+    /// returned. It should fail for the signals already pending, since only
+    /// those sent after it starts interrupt the wait, with `EINTR`.
+    ///
+    /// Returns `false` if the wait timed out.
+    pub fn wait(
+        &self,
+        addr: usize,
+        bitset: u32,
+        timeout: Option<Duration>,
+        validate: impl FnOnce() -> LinuxResult,
+    ) -> LinuxResult<bool> {
+        let curr = current();
+        let thr_data = curr.task_ext().thread_data();
+        let since = thr_data.interrupts();
+        let waiter = Arc::new(FutexWaiter::new(addr, bitset));
+        {
+            let mut table = self.0.lock();
+            validate()?;
+            table.entry(addr).or_default().push_back(waiter.clone());
+        }
+
+        thr_data.wait_interruptible(&waiter.wq, since, timeout, || waiter.is_woken());
 
-    /// Gets the wait queue associated with the given address, or inserts a a
-    /// new one if it doesn't exist.
-    pub fn get_or_insert(&self, addr: usize) -> WaitQueueGuard {
         let mut table = self.0.lock();
-        let wq = table
-            .entry(addr)
-            .or_insert_with(|| Arc::new(WaitQueue::new()));
-        WaitQueueGuard {
-            key: addr,
-            inner: wq.clone(),
+        if waiter.is_woken() {
+            return Ok(true);
+        }
+        let key = waiter.key.load(Ordering::Acquire);
+        if let Some(queue) = table.get_mut(&key) {
+            queue.retain(|it| !Arc::ptr_eq(it, &waiter));
+            if queue.is_empty() {
+                table.remove(&key);
+            }
+        }
+        if thr_data.interrupts() != since {
+            return Err(LinuxError::EINTR);
         }
+        Ok(false)
     }
-}
 
-#[doc(hidden)]
-pub struct WaitQueueGuard {
-    key: usize,
-    inner: Arc<WaitQueue>,
-}
-impl Deref for WaitQueueGuard {
-    type Target = Arc<WaitQueue>;
+    /// Wakes up at most `count` tasks waiting on the futex at `addr` whose
+    /// mask overlaps `bitset`.
+    ///
+    /// Returns the number of tasks woken up.
+    pub fn wake(&self, addr: usize, count: usize, bitset: u32) -> usize {
+        let mut table = self.0.lock();
+        wake_locked(&mut table, addr, count, bitset)
+    }
+
+    /// Returns the TIDs of the tasks waiting on the futex at `addr`, in the
+    /// order they queued.
+    pub fn waiters(&self, addr: usize) -> Vec<u32> {
+        let table = self.0.lock();
+        table
+            .get(&addr)
+            .map_or_else(Vec::new, |queue| queue.iter().map(|it| it.tid).collect())
+    }
+
+    /// Wakes up the first task waiting on the futex at `addr`, which a PI
+    /// futex is being handed over to.
+    ///
+    /// `update` runs with the table locked before anyone is woken up, and
+    /// receives the TIDs of the waiting tasks in the order they queued; if it
+    /// fails nobody is woken up and the error is returned.
+    pub fn wake_pi(&self, addr: usize, update: impl FnOnce(&[u32]) -> LinuxResult) -> LinuxResult {
+        let mut table = self.0.lock();
+        let tids = table
+            .get(&addr)
+            .map_or_else(Vec::new, |queue| queue.iter().map(|it| it.tid).collect());
+        update(&tids)?;
+        wake_locked(&mut table, addr, 1, u32::MAX);
+        Ok(())
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+    /// Wakes up at most `wake_count` tasks waiting on the futex at `addr`,
+    /// then moves at most `requeue_count` of the remaining waiters to the
+    /// futex at `addr2`.
+    ///
+    /// `validate` runs with the table locked before anything is touched.
+    ///
+    /// Returns the number of tasks woken up and requeued respectively.
+    pub fn requeue(
+        &self,
+        addr: usize,
+        wake_count: usize,
+        addr2: usize,
+        requeue_count: usize,
+        validate: impl FnOnce() -> LinuxResult,
+    ) -> LinuxResult<(usize, usize)> {
+        let mut table = self.0.lock();
+        validate()?;
+
+        let woken = wake_locked(&mut table, addr, wake_count, u32::MAX);
+        if addr == addr2 {
+            return Ok((woken, 0));
+        }
+
+        let Some(queue) = table.get_mut(&addr) else {
+            return Ok((woken, 0));
+        };
+        let moved = queue
+            .drain(..requeue_count.min(queue.len()))
+            .collect::<VecDeque<_>>();
+        if queue.is_empty() {
+            table.remove(&addr);
+        }
+
+        let requeued = moved.len();
+        if requeued > 0 {
+            for waiter in &moved {
+                waiter.key.store(addr2, Ordering::Release);
+            }
+            table.entry(addr2).or_default().extend(moved);
+        }
+        Ok((woken, requeued))
     }
 }
-impl Drop for WaitQueueGuard {
-    fn drop(&mut self) {
-        let curr = current();
-        let mut table = curr.task_ext().process_data().futex_table.0.lock();
-        if Arc::strong_count(&self.inner) == 1 && self.inner.is_empty() {
-            table.remove(&self.key);
+
+fn wake_locked(table: &mut FutexQueues, addr: usize, count: usize, bitset: u32) -> usize {
+    let Some(queue) = table.get_mut(&addr) else {
+        return 0;
+    };
+    let mut woken = 0;
+    queue.retain(|waiter| {
+        if woken < count && waiter.bitset & bitset != 0 {
+            waiter.wake();
+            woken += 1;
+            false
+        } else {
+            true
         }
+    });
+    if queue.is_empty() {
+        table.remove(&addr);
     }
+    woken
 }
@@ -10,5 +10,7 @@ extern crate alloc;
 
 pub mod futex;
 pub mod mm;
+pub mod resources;
 pub mod task;
 mod time;
+pub mod timer;
@@ -2,14 +2,234 @@
 
 use core::ffi::CStr;
 
-use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+use alloc::{
+    borrow::ToOwned, collections::btree_map::BTreeMap, format, string::String, vec, vec::Vec,
+};
 use axerrno::{AxError, AxResult};
 use axhal::{mem::virt_to_phys, paging::MappingFlags};
 use axmm::{AddrSpace, kernel_aspace};
 use kernel_elf_parser::{AuxvEntry, ELFParser, app_stack_region};
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, align_down, align_up_4k};
 use xmas_elf::{ElfFile, program::SegmentData};
 
+/// A mapping of a user address space, as listed in `/proc/<pid>/maps`.
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    /// The first address of the mapping.
+    pub start: usize,
+    /// The address after the mapping.
+    pub end: usize,
+    /// The access permissions.
+    pub flags: MappingFlags,
+    /// Whether changes are shared with other mappings of the same memory.
+    pub shared: bool,
+    /// The offset in the mapped file.
+    pub offset: usize,
+    /// The path of the mapped file, a tag like `[stack]`, or empty for
+    /// anonymous memory.
+    pub name: String,
+    /// Whether the mapping is sealed by `mseal`, so that it can no longer be
+    /// unmapped, moved or have its permissions changed.
+    pub sealed: bool,
+}
+
+impl MemoryMap {
+    /// Creates a private anonymous mapping of `[start, end)`.
+    pub fn anonymous(start: usize, end: usize, flags: MappingFlags) -> Self {
+        Self {
+            start,
+            end,
+            flags,
+            shared: false,
+            offset: 0,
+            name: String::new(),
+            sealed: false,
+        }
+    }
+
+    /// Sets the name of the mapping.
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The size of the mapping in bytes.
+    pub fn size(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// The mappings of a user address space.
+///
+/// [`AddrSpace`] does not tell what it maps, so the mappings are recorded
+/// here as they are made, split when changed in part, and shared along with
+/// the address space.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMaps {
+    maps: BTreeMap<usize, MemoryMap>,
+    /// The largest resident size in bytes recorded so far.
+    max_rss: usize,
+}
+
+impl MemoryMaps {
+    /// Splits the mapping across `addr`, if any, into two at `addr`.
+    fn split_at(&mut self, addr: usize) {
+        let Some((_, map)) = self.maps.range_mut(..addr).next_back() else {
+            return;
+        };
+        if map.end <= addr {
+            return;
+        }
+        let mut upper = map.clone();
+        map.end = addr;
+        upper.offset += addr - upper.start;
+        upper.start = addr;
+        self.maps.insert(addr, upper);
+    }
+
+    /// Records a new mapping, replacing whatever it overlaps.
+    pub fn insert(&mut self, map: MemoryMap) {
+        self.remove(map.start, map.end);
+        self.maps.insert(map.start, map);
+    }
+
+    /// Forgets the mappings in `[start, end)`.
+    pub fn remove(&mut self, start: usize, end: usize) {
+        self.split_at(start);
+        self.split_at(end);
+        self.maps.retain(|&addr, _| addr < start || addr >= end);
+    }
+
+    /// Extends the mapping that ends at `end` to `new_end`.
+    pub fn extend(&mut self, end: usize, new_end: usize) {
+        if let Some((_, map)) = self.maps.range_mut(..end).next_back() {
+            if map.end == end {
+                map.end = new_end;
+            }
+        }
+    }
+
+    /// Changes the permissions of the mappings in `[start, end)`.
+    pub fn protect(&mut self, start: usize, end: usize, flags: MappingFlags) {
+        self.split_at(start);
+        self.split_at(end);
+        for map in self.maps.range_mut(start..end).map(|(_, map)| map) {
+            map.flags = flags;
+        }
+    }
+
+    /// Seals the mappings in `[start, end)`.
+    pub fn seal(&mut self, start: usize, end: usize) {
+        self.split_at(start);
+        self.split_at(end);
+        for map in self.maps.range_mut(start..end).map(|(_, map)| map) {
+            map.sealed = true;
+        }
+    }
+
+    /// Returns whether any mapping overlapping `[start, end)` is sealed.
+    pub fn is_sealed(&self, start: usize, end: usize) -> bool {
+        self.overlapping(start, end).any(|map| map.sealed)
+    }
+
+    /// Returns whether every page of `[start, end)` is mapped.
+    pub fn is_mapped(&self, start: usize, end: usize) -> bool {
+        let mut addr = start;
+        for map in self.overlapping(start, end) {
+            if map.start > addr {
+                return false;
+            }
+            addr = map.end;
+        }
+        addr >= end
+    }
+
+    /// Iterates over the mappings overlapping `[start, end)` in address
+    /// order.
+    fn overlapping(&self, start: usize, end: usize) -> impl Iterator<Item = &MemoryMap> {
+        let first = self.find(start).map_or(start, |map| map.start);
+        self.maps
+            .range(first..end)
+            .map(|(_, map)| map)
+            .filter(move |map| map.end > start)
+    }
+
+    /// Forgets all the mappings.
+    ///
+    /// The peak resident size is kept, as it is across `execve`.
+    pub fn clear(&mut self) {
+        self.maps.clear();
+    }
+
+    /// Returns the mapping containing `addr`.
+    pub fn find(&self, addr: usize) -> Option<&MemoryMap> {
+        self.maps
+            .range(..=addr)
+            .next_back()
+            .map(|(_, map)| map)
+            .filter(|map| addr < map.end)
+    }
+
+    /// Iterates over the mappings in address order.
+    pub fn iter(&self) -> impl Iterator<Item = &MemoryMap> {
+        self.maps.values()
+    }
+
+    /// The size in bytes of the parts of the mappings within `[start, end)`.
+    pub fn mapped_size(&self, start: usize, end: usize) -> usize {
+        self.overlapping(start, end)
+            .map(|map| map.end.min(end) - map.start.max(start))
+            .sum()
+    }
+
+    /// The total size of the mappings in bytes.
+    pub fn total_size(&self) -> usize {
+        self.maps.values().map(MemoryMap::size).sum()
+    }
+
+    /// Records the resident size in `aspace` towards the peak.
+    ///
+    /// The resident size only grows between the times memory is released,
+    /// so calling this before releasing any keeps the peak exact.
+    pub fn record_rss(&mut self, aspace: &AddrSpace) {
+        self.max_rss = self.max_rss.max(self.resident_size(aspace));
+    }
+
+    /// Restarts the peak from the resident size in `aspace`, for the copy of
+    /// the mappings of a new process.
+    pub fn reset_max_rss(&mut self, aspace: &AddrSpace) {
+        self.max_rss = self.resident_size(aspace);
+    }
+
+    /// The largest resident size in bytes recorded by
+    /// [`MemoryMaps::record_rss`].
+    pub fn max_rss(&self) -> usize {
+        self.max_rss
+    }
+
+    /// The size in bytes of the pages of the mappings that are present in
+    /// the page table of `aspace`.
+    pub fn resident_size(&self, aspace: &AddrSpace) -> usize {
+        let mut size = 0;
+        for map in self.maps.values() {
+            let mut addr = map.start;
+            while addr < map.end {
+                let step = match aspace.page_table().query(addr.into()) {
+                    Ok((_, _, page_size)) => {
+                        let page_size = page_size as usize;
+                        let step = (align_down(addr, page_size) + page_size).min(map.end) - addr;
+                        size += step;
+                        step
+                    }
+                    Err(_) => PAGE_SIZE_4K,
+                };
+                addr += step;
+            }
+        }
+        size
+    }
+}
+
 /// Creates a new empty user address space.
 pub fn new_user_aspace_empty() -> AxResult<AddrSpace> {
     AddrSpace::new_empty(
@@ -31,14 +251,12 @@ pub fn copy_from_kernel(aspace: &mut AddrSpace) -> AxResult {
 }
 
 /// Map the signal trampoline to the user address space.
-pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
+pub fn map_trampoline(aspace: &mut AddrSpace, maps: &mut MemoryMaps) -> AxResult {
     let signal_trampoline_paddr = virt_to_phys(axsignal::arch::signal_trampoline_address().into());
-    aspace.map_linear(
-        axconfig::plat::SIGNAL_TRAMPOLINE.into(),
-        signal_trampoline_paddr,
-        PAGE_SIZE_4K,
-        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
-    )?;
+    let start = axconfig::plat::SIGNAL_TRAMPOLINE;
+    let flags = MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER;
+    aspace.map_linear(start.into(), signal_trampoline_paddr, PAGE_SIZE_4K, flags)?;
+    maps.insert(MemoryMap::anonymous(start, start + PAGE_SIZE_4K, flags).named("[sigpage]"));
     Ok(())
 }
 
@@ -46,11 +264,18 @@ pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
 ///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
+/// - `maps`: The mappings of `uspace`.
+/// - `path`: The path of the elf file.
 /// - `elf`: The elf file.
 ///
 /// # Returns
 /// - The entry point of the user app.
-fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEntry; 16])> {
+fn map_elf(
+    uspace: &mut AddrSpace,
+    maps: &mut MemoryMaps,
+    path: &str,
+    elf: &ElfFile,
+) -> AxResult<(VirtAddr, [AuxvEntry; 16])> {
     let uspace_base = uspace.base().as_usize();
     let elf_parser = ELFParser::new(
         elf,
@@ -72,12 +297,17 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
 
         let seg_align_size =
             (segement.memsz as usize + seg_pad + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        uspace.map_alloc(
-            segement.vaddr.align_down_4k(),
-            seg_align_size,
-            segement.flags,
-            true,
-        )?;
+        let seg_start = segement.vaddr.align_down_4k();
+        uspace.map_alloc(seg_start, seg_align_size, segement.flags, true)?;
+        maps.insert(MemoryMap {
+            offset: segement.offset - seg_pad,
+            ..MemoryMap::anonymous(
+                seg_start.as_usize(),
+                seg_start.as_usize() + seg_align_size,
+                segement.flags,
+            )
+            .named(path)
+        });
         let seg_data = elf
             .input
             .get(segement.offset..segement.offset + segement.filesz as usize)
@@ -92,105 +322,213 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
     ))
 }
 
+/// The smallest user stack, enough for the arguments and environment of
+/// most apps however low `RLIMIT_STACK` is.
+pub const MIN_USER_STACK_SIZE: usize = 4 * PAGE_SIZE_4K;
+
+/// The longest `#!` line of a script, as in Linux.
+const MAX_SHEBANG_LEN: usize = 256;
+
+/// The most scripts that may run one another as interpreters before the
+/// program is an ELF file, as in Linux.
+pub const MAX_SCRIPT_DEPTH: usize = 5;
+
+/// Parse the `#!` line at the start of `head`, the first bytes of a file.
+///
+/// Returns `None` if the file is not a script, or else the interpreter and
+/// its optional argument, which is the rest of the line. The interpreter is
+/// empty if the line does not name one.
+pub fn parse_shebang(head: &[u8]) -> Option<(String, Option<String>)> {
+    let line = head.strip_prefix(b"#!")?;
+    let line = &line[..line.len().min(MAX_SHEBANG_LEN - 2)];
+    let line = line.split(|&c| c == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_matches([' ', '\t', '\r']);
+    let (interp, arg) = line
+        .split_once([' ', '\t'])
+        .map_or((line, ""), |(interp, arg)| {
+            (interp, arg.trim_matches([' ', '\t']))
+        });
+    Some((interp.to_owned(), (!arg.is_empty()).then(|| arg.to_owned())))
+}
+
 /// Load the user app to the user address space.
 ///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
-/// - `args`: The arguments of the user app. The first argument is the path of the user app.
+/// - `maps`: The mappings of `uspace`, where the new ones are recorded.
+/// - `root`: The root directory of the process, without a trailing slash,
+///   which the interpreters of the user app are found in.
+/// - `path`: The path of the user app, from the root of the kernel. A script
+///   is run by its interpreter, with the arguments
+///   `[interp, optarg, path, args[1..]]`.
+/// - `args`: The arguments of the user app.
 /// - `envs`: The environment variables of the user app.
+/// - `stack_size`: The size of the user stack, as limited by `RLIMIT_STACK`.
+///   It is rounded up to whole pages, and kept between
+///   [`MIN_USER_STACK_SIZE`] and `USER_STACK_SIZE`. The stack does not
+///   grow, so going past it raises `SIGSEGV`.
 ///
 /// # Returns
 /// - The entry point of the user app.
 /// - The stack pointer of the user app.
 pub fn load_user_app(
     uspace: &mut AddrSpace,
+    maps: &mut MemoryMaps,
+    root: &str,
+    path: &str,
     args: &[String],
     envs: &[String],
+    stack_size: usize,
 ) -> AxResult<(VirtAddr, VirtAddr)> {
-    if args.is_empty() {
-        return Err(AxError::InvalidInput);
-    }
-    let file_data = axfs::api::read(args[0].as_str())?;
-    if file_data.starts_with(b"#!") {
-        let head = &file_data[2..file_data.len().min(256)];
-        let pos = head.iter().position(|c| *c == b'\n').unwrap_or(head.len());
-        let line = core::str::from_utf8(&head[..pos]).map_err(|_| AxError::InvalidData)?;
-
-        let new_args: Vec<String> = line
-            .splitn(2, |c: char| c.is_ascii_whitespace())
-            .map(|s| s.trim_ascii().to_owned())
-            .chain(args.iter().cloned())
-            .collect();
-        return load_user_app(uspace, &new_args, envs);
-    }
-    let elf = ElfFile::new(&file_data).map_err(|_| AxError::InvalidData)?;
-
-    if let Some(interp) = elf
-        .program_iter()
-        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
-    {
-        let interp = match interp.get_data(&elf) {
-            Ok(SegmentData::Undefined(data)) => data,
-            _ => panic!("Invalid data in Interp Elf Program Header"),
-        };
+    let mut loader = AppLoader {
+        uspace,
+        maps,
+        root,
+        envs,
+        stack_size,
+    };
+    loader.load(path, args, 0)
+}
+
+/// The state of [`load_user_app`] that stays the same while it follows
+/// interpreters.
+struct AppLoader<'a> {
+    uspace: &'a mut AddrSpace,
+    maps: &'a mut MemoryMaps,
+    root: &'a str,
+    envs: &'a [String],
+    stack_size: usize,
+}
+
+impl AppLoader<'_> {
+    /// `path` as the app sees it, from its root directory.
+    fn path_in_root<'p>(&self, path: &'p str) -> &'p str {
+        path.strip_prefix(self.root)
+            .filter(|rest| rest.starts_with('/'))
+            .unwrap_or(path)
+    }
+
+    /// `path` as the app sees it, from the root of the kernel.
+    fn path_from_root(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            format!("{}{}", self.root, path)
+        } else {
+            path.to_owned()
+        }
+    }
+
+    fn load(
+        &mut self,
+        path: &str,
+        args: &[String],
+        depth: usize,
+    ) -> AxResult<(VirtAddr, VirtAddr)> {
+        if args.is_empty() {
+            return Err(AxError::InvalidInput);
+        }
+        let file_data = axfs::api::read(path)?;
+        if let Some((interp, arg)) = parse_shebang(&file_data) {
+            if interp.is_empty() || depth >= MAX_SCRIPT_DEPTH {
+                return Err(AxError::InvalidData);
+            }
+            let new_args: Vec<String> = [interp.clone()]
+                .into_iter()
+                .chain(arg)
+                .chain([self.path_in_root(path).to_owned()])
+                .chain(args[1..].iter().cloned())
+                .collect();
+            return self.load(&self.path_from_root(&interp), &new_args, depth + 1);
+        }
+        let elf = ElfFile::new(&file_data).map_err(|_| AxError::InvalidData)?;
 
-        let mut interp_path = axfs::api::canonicalize(
-            CStr::from_bytes_with_nul(interp)
-                .map_err(|_| AxError::InvalidData)?
-                .to_str()
-                .map_err(|_| AxError::InvalidData)?,
-        )?;
-
-        if interp_path == "/lib/ld-linux-riscv64-lp64.so.1"
-            || interp_path == "/lib64/ld-linux-loongarch-lp64d.so.1"
-            || interp_path == "/lib64/ld-linux-x86-64.so.2"
-            || interp_path == "/lib/ld-linux-aarch64.so.1"
+        if let Some(interp) = elf
+            .program_iter()
+            .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
         {
-            // TODO: Use soft link
-            interp_path = String::from("/musl/lib/libc.so");
+            let interp = match interp.get_data(&elf) {
+                Ok(SegmentData::Undefined(data)) => data,
+                _ => panic!("Invalid data in Interp Elf Program Header"),
+            };
+
+            let mut interp_path = axfs::api::canonicalize(
+                CStr::from_bytes_with_nul(interp)
+                    .map_err(|_| AxError::InvalidData)?
+                    .to_str()
+                    .map_err(|_| AxError::InvalidData)?,
+            )?;
+
+            if interp_path == "/lib/ld-linux-riscv64-lp64.so.1"
+                || interp_path == "/lib64/ld-linux-loongarch-lp64d.so.1"
+                || interp_path == "/lib64/ld-linux-x86-64.so.2"
+                || interp_path == "/lib/ld-linux-aarch64.so.1"
+            {
+                // TODO: Use soft link
+                interp_path = String::from("/musl/lib/libc.so");
+            }
+
+            // The dynamic linker runs the user app given as its first
+            // argument, and looks it up from the root of the app.
+            let mut new_args = vec![interp_path.clone(), self.path_in_root(path).to_owned()];
+            new_args.extend_from_slice(&args[1..]);
+            return self.load(&self.path_from_root(&interp_path), &new_args, depth);
         }
 
-        // Set the first argument to the path of the user app.
-        let mut new_args = vec![interp_path];
-        new_args.extend_from_slice(args);
-        return load_user_app(uspace, &new_args, envs);
-    }
-
-    let (entry, mut auxv) = map_elf(uspace, &elf)?;
-    // The user stack is divided into two parts:
-    // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
-    // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
-    //  When the app starts running, the stack pointer points to `ustack_pointer`.
-    let ustack_end = VirtAddr::from_usize(axconfig::plat::USER_STACK_TOP);
-    let ustack_size = axconfig::plat::USER_STACK_SIZE;
-    let ustack_start = ustack_end - ustack_size;
-    debug!(
-        "Mapping user stack: {:#x?} -> {:#x?}",
-        ustack_start, ustack_end
-    );
-
-    let stack_data = app_stack_region(args, envs, &mut auxv, ustack_start, ustack_size);
-    uspace.map_alloc(
-        ustack_start,
-        ustack_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
-    )?;
-
-    let heap_start = VirtAddr::from_usize(axconfig::plat::USER_HEAP_BASE);
-    let heap_size = axconfig::plat::USER_HEAP_SIZE;
-    uspace.map_alloc(
-        heap_start,
-        heap_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
-    )?;
-
-    let user_sp = ustack_end - stack_data.len();
-
-    uspace.write(user_sp, stack_data.as_slice())?;
-
-    Ok((entry, user_sp))
+        self.map_app(path, args, &elf)
+    }
+
+    fn map_app(
+        &mut self,
+        path: &str,
+        args: &[String],
+        elf: &ElfFile,
+    ) -> AxResult<(VirtAddr, VirtAddr)> {
+        let (uspace, maps, envs, stack_size) = (
+            &mut *self.uspace,
+            &mut *self.maps,
+            self.envs,
+            self.stack_size,
+        );
+        let (entry, mut auxv) = map_elf(uspace, maps, path, elf)?;
+        // The user stack is divided into two parts:
+        // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
+        // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
+        //  When the app starts running, the stack pointer points to `ustack_pointer`.
+        let ustack_end = VirtAddr::from_usize(axconfig::plat::USER_STACK_TOP);
+        let ustack_size =
+            align_up_4k(stack_size.min(axconfig::plat::USER_STACK_SIZE)).max(MIN_USER_STACK_SIZE);
+        let ustack_start = ustack_end - ustack_size;
+        debug!(
+            "Mapping user stack: {:#x?} -> {:#x?}",
+            ustack_start, ustack_end
+        );
+
+        let stack_data = app_stack_region(args, envs, &mut auxv, ustack_start, ustack_size);
+        let data_flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+        uspace.map_alloc(ustack_start, ustack_size, data_flags, true)?;
+        maps.insert(
+            MemoryMap::anonymous(ustack_start.as_usize(), ustack_end.as_usize(), data_flags)
+                .named("[stack]"),
+        );
+
+        let heap_start = VirtAddr::from_usize(axconfig::plat::USER_HEAP_BASE);
+        let heap_size = axconfig::plat::USER_HEAP_SIZE;
+        uspace.map_alloc(heap_start, heap_size, data_flags, true)?;
+        maps.insert(
+            MemoryMap::anonymous(
+                heap_start.as_usize(),
+                heap_start.as_usize() + heap_size,
+                data_flags,
+            )
+            .named("[heap]"),
+        );
+
+        let user_sp = ustack_end - stack_data.len();
+
+        uspace.write(user_sp, stack_data.as_slice())?;
+
+        Ok((entry, user_sp))
+    }
 }
 
 #[percpu::def_percpu]
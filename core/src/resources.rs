@@ -0,0 +1,60 @@
+//! Resource limits.
+
+use core::ops::{Index, IndexMut};
+
+use linux_raw_sys::general::{RLIM_NLIMITS, RLIMIT_NOFILE, RLIMIT_STACK};
+
+/// The value of a resource limit meaning no limit.
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// The default limit on open files, the size of the file descriptor table.
+const NOFILE_LIMIT: u64 = 1024;
+
+/// The soft and hard limit of a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+    /// The soft limit, which is the one enforced.
+    pub current: u64,
+    /// The hard limit, the ceiling for the soft limit.
+    pub max: u64,
+}
+
+impl Rlimit {
+    /// Create a new [`Rlimit`] with the same soft and hard limit.
+    pub const fn new(limit: u64) -> Self {
+        Self {
+            current: limit,
+            max: limit,
+        }
+    }
+}
+
+/// The resource limits of a process, indexed by the `RLIMIT_*` constants.
+#[derive(Debug, Clone)]
+pub struct Rlimits([Rlimit; RLIM_NLIMITS as usize]);
+
+impl Default for Rlimits {
+    fn default() -> Self {
+        let mut limits = Self([Rlimit::new(RLIM_INFINITY); RLIM_NLIMITS as usize]);
+        limits[RLIMIT_NOFILE] = Rlimit::new(NOFILE_LIMIT);
+        limits[RLIMIT_STACK] = Rlimit {
+            current: axconfig::plat::USER_STACK_SIZE as _,
+            max: RLIM_INFINITY,
+        };
+        limits
+    }
+}
+
+impl Index<u32> for Rlimits {
+    type Output = Rlimit;
+
+    fn index(&self, resource: u32) -> &Rlimit {
+        &self.0[resource as usize]
+    }
+}
+
+impl IndexMut<u32> for Rlimits {
+    fn index_mut(&mut self, resource: u32) -> &mut Rlimit {
+        &mut self.0[resource as usize]
+    }
+}
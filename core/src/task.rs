@@ -3,11 +3,12 @@
 use core::{
     alloc::Layout,
     cell::RefCell,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering},
     time::Duration,
 };
 
 use alloc::{
+    collections::btree_map::BTreeMap,
     string::String,
     sync::{Arc, Weak},
     vec::Vec,
@@ -21,16 +22,23 @@ use axmm::{AddrSpace, kernel_aspace};
 use axns::{AxNamespace, AxNamespaceIf};
 use axprocess::{Pid, Process, ProcessGroup, Session, Thread};
 use axsignal::{
-    Signo,
+    SignalInfo, Signo,
     api::{ProcessSignalManager, SignalActions, ThreadSignalManager},
 };
 use axsync::{Mutex, RawMutex};
 use axtask::{TaskExtRef, TaskInner, WaitQueue, current};
+use linux_raw_sys::general::SI_KERNEL;
 use memory_addr::VirtAddrRange;
 use spin::{Once, RwLock};
 use weak_map::WeakMap;
 
-use crate::{futex::FutexTable, time::TimeStat};
+use crate::{
+    futex::FutexTable,
+    mm::MemoryMaps,
+    resources::Rlimits,
+    time::{CpuTime, TimeStat, TimerType},
+    timer::{IntervalTimer, TimerTable},
+};
 
 /// Create a new user task.
 pub fn new_user_task(
@@ -78,16 +86,65 @@ impl TaskExt {
 
     pub(crate) fn time_stat_from_kernel_to_user(&self, current_tick: usize) {
         self.time.borrow_mut().switch_into_user_mode(current_tick);
+        self.publish_cpu_time();
     }
 
     pub(crate) fn time_stat_from_user_to_kernel(&self, current_tick: usize) {
         self.time.borrow_mut().switch_into_kernel_mode(current_tick);
+        self.publish_cpu_time();
+    }
+
+    /// Copy the user and kernel time of the task to its [`ThreadData`], where
+    /// the other threads of the process can read it.
+    fn publish_cpu_time(&self) {
+        if let Some(data) = self.thread.data::<ThreadData>() {
+            let (utime_ns, stime_ns) = self.time.borrow().output();
+            data.cpu_time.set(utime_ns, stime_ns);
+        }
     }
 
-    pub(crate) fn time_stat_output(&self) -> (usize, usize) {
+    /// Get the user and kernel time of the task, in nanoseconds.
+    pub fn time_stat_output(&self) -> (usize, usize) {
         self.time.borrow().output()
     }
 
+    /// Get the interval and the time left of the `ITIMER_VIRTUAL` or
+    /// `ITIMER_PROF` timer of the task, in nanoseconds.
+    ///
+    /// Returns `None` for any other timer.
+    pub fn cpu_timer(&self, which: u32) -> Option<(usize, usize)> {
+        self.time.borrow().get_timer((which as usize).into())
+    }
+
+    /// Arm the `ITIMER_VIRTUAL` or `ITIMER_PROF` timer of the task, or
+    /// disarm it if `remained_ns` is zero.
+    ///
+    /// Returns the previous setting like [`TaskExt::cpu_timer`].
+    pub fn set_cpu_timer(
+        &self,
+        which: u32,
+        interval_ns: usize,
+        remained_ns: usize,
+    ) -> Option<(usize, usize)> {
+        self.time
+            .borrow_mut()
+            .set_timer((which as usize).into(), interval_ns, remained_ns)
+    }
+
+    /// Send the signals of the CPU time timers that expired.
+    fn send_cpu_timer_signals(&self) {
+        let expired: Vec<_> = self.time.borrow_mut().take_expired().collect();
+        for timer_type in expired {
+            let signo = match timer_type {
+                TimerType::VIRTUAL => Signo::SIGVTALRM,
+                _ => Signo::SIGPROF,
+            };
+            self.thread_data()
+                .signal
+                .send_signal(SignalInfo::new(signo, SI_KERNEL as _));
+        }
+    }
+
     /// Get the [`ThreadData`] associated with this task.
     pub fn thread_data(&self) -> &ThreadData {
         self.thread.data().unwrap()
@@ -107,6 +164,7 @@ pub fn time_stat_from_kernel_to_user() {
     curr_task
         .task_ext()
         .time_stat_from_kernel_to_user(monotonic_time_nanos() as usize);
+    curr_task.task_ext().send_cpu_timer_signals();
 }
 
 /// Update the time statistics to reflect a switch from user mode to kernel mode.
@@ -115,6 +173,7 @@ pub fn time_stat_from_user_to_kernel() {
     curr_task
         .task_ext()
         .time_stat_from_user_to_kernel(monotonic_time_nanos() as usize);
+    curr_task.task_ext().send_cpu_timer_signals();
 }
 
 /// Get the time statistics for the current task.
@@ -160,6 +219,44 @@ pub struct ThreadData {
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     pub clear_child_tid: AtomicUsize,
 
+    /// The robust futex list head registered by `set_robust_list`.
+    pub robust_list_head: AtomicUsize,
+
+    /// The user GS segment base, reloaded every time the thread returns to
+    /// user space since the MSR is not part of the saved context.
+    #[cfg(target_arch = "x86_64")]
+    pub gs_base: AtomicUsize,
+
+    /// The CPUs the thread is allowed to run on, one bit per CPU.
+    cpumask: AtomicUsize,
+    /// Whether `cpumask` changed since it was last passed to the scheduler.
+    cpumask_changed: AtomicBool,
+
+    /// The nice value, from -20 (highest priority) to 19 (lowest).
+    nice: AtomicI32,
+    /// The nice value lent by the threads waiting on the PI futexes the
+    /// thread owns, or `i32::MAX` if there is none.
+    pi_nice: AtomicI32,
+    /// The nice value lent for each PI futex the thread owns, by address.
+    /// `pi_nice` is the lowest of them.
+    pi_nices: spin::Mutex<BTreeMap<usize, i32>>,
+    /// Whether the nice value the thread runs with changed since it was last
+    /// passed to the scheduler.
+    nice_changed: AtomicBool,
+
+    /// The signal to deliver when the parent process dies, or 0 for none.
+    pdeath_signal: AtomicU32,
+
+    /// The user and kernel time of the thread as of its last switch between
+    /// user and kernel mode, for the other threads of the process to read.
+    pub cpu_time: CpuTime,
+
+    /// The address of the wait queue the thread sleeps on until it is
+    /// interrupted, if any.
+    sleeping_on: spin::Mutex<Option<usize>>,
+    /// Bumped whenever a signal is sent to the thread or its process.
+    interrupts: AtomicU64,
+
     /// The thread-level signal manager
     pub signal: ThreadSignalManager<RawMutex, WaitQueueWrapper>,
 }
@@ -170,6 +267,25 @@ impl ThreadData {
     pub fn new(proc: &ProcessData) -> Self {
         Self {
             clear_child_tid: AtomicUsize::new(0),
+            robust_list_head: AtomicUsize::new(0),
+
+            #[cfg(target_arch = "x86_64")]
+            gs_base: AtomicUsize::new(0),
+
+            cpumask: AtomicUsize::new(usize::MAX),
+            cpumask_changed: AtomicBool::new(false),
+
+            nice: AtomicI32::new(0),
+            pi_nice: AtomicI32::new(i32::MAX),
+            pi_nices: spin::Mutex::new(BTreeMap::new()),
+            nice_changed: AtomicBool::new(false),
+
+            pdeath_signal: AtomicU32::new(0),
+
+            cpu_time: CpuTime::default(),
+
+            sleeping_on: spin::Mutex::new(None),
+            interrupts: AtomicU64::new(0),
 
             signal: ThreadSignalManager::new(proc.signal.clone()),
         }
@@ -185,14 +301,186 @@ impl ThreadData {
         self.clear_child_tid
             .store(clear_child_tid, Ordering::Relaxed);
     }
+
+    /// Get the robust futex list head.
+    pub fn robust_list_head(&self) -> usize {
+        self.robust_list_head.load(Ordering::Relaxed)
+    }
+
+    /// Set the robust futex list head.
+    pub fn set_robust_list_head(&self, robust_list_head: usize) {
+        self.robust_list_head
+            .store(robust_list_head, Ordering::Relaxed);
+    }
+
+    /// Get the user GS segment base.
+    #[cfg(target_arch = "x86_64")]
+    pub fn gs_base(&self) -> usize {
+        self.gs_base.load(Ordering::Relaxed)
+    }
+
+    /// Set the user GS segment base.
+    #[cfg(target_arch = "x86_64")]
+    pub fn set_gs_base(&self, gs_base: usize) {
+        self.gs_base.store(gs_base, Ordering::Relaxed);
+    }
+
+    /// Get the CPU affinity mask.
+    pub fn cpumask(&self) -> usize {
+        self.cpumask.load(Ordering::Acquire)
+    }
+
+    /// Set the CPU affinity mask.
+    ///
+    /// The scheduler only learns of it through
+    /// [`ThreadData::take_cpumask_change`], since only the thread itself can
+    /// change its affinity there.
+    pub fn set_cpumask(&self, cpumask: usize) {
+        self.cpumask.store(cpumask, Ordering::Release);
+        self.cpumask_changed.store(true, Ordering::Release);
+    }
+
+    /// Returns the CPU affinity mask if it changed since the last call.
+    pub fn take_cpumask_change(&self) -> Option<usize> {
+        self.cpumask_changed
+            .swap(false, Ordering::AcqRel)
+            .then(|| self.cpumask())
+    }
+
+    /// Get the nice value.
+    pub fn nice(&self) -> i32 {
+        self.nice.load(Ordering::Acquire)
+    }
+
+    /// Set the nice value.
+    ///
+    /// Like the CPU affinity mask, the scheduler only learns of it through
+    /// [`ThreadData::take_nice_change`].
+    pub fn set_nice(&self, nice: i32) {
+        self.nice.store(nice, Ordering::Release);
+        self.nice_changed.store(true, Ordering::Release);
+    }
+
+    /// Get the nice value the thread runs with, which is that of the thread
+    /// with the highest priority among itself and the threads waiting on the
+    /// PI futexes it owns.
+    pub fn effective_nice(&self) -> i32 {
+        self.nice().min(self.pi_nice.load(Ordering::Acquire))
+    }
+
+    /// Run the thread with a nice value of at most `nice`, lent by a thread
+    /// waiting on the PI futex at `addr` it owns, until
+    /// [`ThreadData::unboost_nice`] for that futex.
+    pub fn boost_nice(&self, addr: usize, nice: i32) {
+        let mut pi_nices = self.pi_nices.lock();
+        let lent = pi_nices.entry(addr).or_insert(nice);
+        *lent = (*lent).min(nice);
+        self.update_pi_nice(&pi_nices);
+    }
+
+    /// Replace the nice value lent for the PI futex at `addr` with `nice`,
+    /// that of the threads still waiting on it, if any.
+    pub fn set_pi_nice(&self, addr: usize, nice: Option<i32>) {
+        let mut pi_nices = self.pi_nices.lock();
+        match nice {
+            Some(nice) => pi_nices.insert(addr, nice),
+            None => pi_nices.remove(&addr),
+        };
+        self.update_pi_nice(&pi_nices);
+    }
+
+    /// Give back the nice value lent for the PI futex at `addr`, which the
+    /// thread released. The thread keeps the one lent for the others.
+    pub fn unboost_nice(&self, addr: usize) {
+        self.set_pi_nice(addr, None);
+    }
+
+    fn update_pi_nice(&self, pi_nices: &BTreeMap<usize, i32>) {
+        let nice = pi_nices.values().copied().min().unwrap_or(i32::MAX);
+        if self.pi_nice.swap(nice, Ordering::AcqRel) != nice {
+            self.nice_changed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Returns the nice value the thread runs with if it changed since the
+    /// last call.
+    pub fn take_nice_change(&self) -> Option<i32> {
+        self.nice_changed
+            .swap(false, Ordering::AcqRel)
+            .then(|| self.effective_nice())
+    }
+
+    /// Get the signal to deliver when the parent process dies.
+    pub fn pdeath_signal(&self) -> Option<Signo> {
+        Signo::from_repr(self.pdeath_signal.load(Ordering::Acquire) as u8)
+    }
+
+    /// Set the signal to deliver when the parent process dies.
+    pub fn set_pdeath_signal(&self, signo: Option<Signo>) {
+        self.pdeath_signal
+            .store(signo.map_or(0, |it| it as u32), Ordering::Release);
+    }
+
+    /// Returns the number of times the thread was interrupted, to be read
+    /// before looking for pending signals and passed on to
+    /// [`ThreadData::wait_interruptible`].
+    pub fn interrupts(&self) -> u64 {
+        self.interrupts.load(Ordering::Acquire)
+    }
+
+    /// Blocks on `wq` until `condition` holds, `timeout` expires, or the
+    /// thread is interrupted after `since` was read.
+    pub fn wait_interruptible(
+        &self,
+        wq: &WaitQueue,
+        since: u64,
+        timeout: Option<Duration>,
+        condition: impl Fn() -> bool,
+    ) {
+        *self.sleeping_on.lock() = Some(wq as *const WaitQueue as usize);
+        let condition = || condition() || self.interrupts() != since;
+        match timeout {
+            Some(timeout) => {
+                wq.wait_timeout_until(timeout, condition);
+            }
+            None => wq.wait_until(condition),
+        }
+        *self.sleeping_on.lock() = None;
+    }
+
+    /// Wakes the thread up if it is blocked in
+    /// [`ThreadData::wait_interruptible`], for a signal sent to it.
+    pub fn interrupt(&self) {
+        self.interrupts.fetch_add(1, Ordering::AcqRel);
+        let sleeping_on = self.sleeping_on.lock();
+        if let Some(wq) = *sleeping_on {
+            // SAFETY: the thread clears `sleeping_on` under the same lock
+            // before it stops borrowing the wait queue.
+            unsafe { &*(wq as *const WaitQueue) }.notify_all(false);
+        }
+    }
+}
+
+/// A change of state of a process, reported to its parent by `wait`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobEvent {
+    /// The process was stopped by the signal.
+    Stopped(Signo),
+    /// The process was resumed by `SIGCONT`.
+    Continued,
 }
 
 /// Extended data for [`Process`].
 pub struct ProcessData {
     /// The executable path
     pub exe_path: RwLock<String>,
+    /// The root directory set by `chroot`, that absolute paths start from,
+    /// inherited by children.
+    pub root: RwLock<String>,
     /// The virtual memory address space.
     pub aspace: Arc<Mutex<AddrSpace>>,
+    /// The mappings of `aspace`, shared along with it.
+    pub maps: Arc<Mutex<MemoryMaps>>,
     /// The resource namespace
     pub ns: AxNamespace,
     /// The user heap bottom
@@ -205,11 +493,41 @@ pub struct ProcessData {
     /// The exit signal of the thread
     pub exit_signal: Option<Signo>,
 
+    /// Whether the process is stopped by a job control signal.
+    stopped: AtomicBool,
+    /// The last change of state not reported to the parent yet.
+    job_event: Mutex<Option<JobEvent>>,
+    /// The threads of the process wait here while it is stopped.
+    pub stop_wq: WaitQueue,
+
+    /// The CPU time used by the exited threads.
+    pub cpu_time: CpuTime,
+    /// The CPU time used by the children reaped by `wait`, and their own
+    /// reaped children.
+    pub children_cpu_time: CpuTime,
+    /// The largest peak resident size in bytes among the children reaped by
+    /// `wait`, and their own reaped children.
+    pub children_max_rss: AtomicUsize,
+
+    /// The resource limits, inherited by children.
+    pub rlimits: RwLock<Rlimits>,
+    /// The permission bits cleared from the mode of new files, inherited by
+    /// children.
+    umask: AtomicU32,
+
     /// The process signal manager
     pub signal: Arc<ProcessSignalManager<RawMutex, WaitQueueWrapper>>,
 
     /// The futex table.
     pub futex_table: FutexTable,
+
+    /// The timers created by `timer_create`.
+    pub timers: TimerTable,
+    /// The `ITIMER_REAL` timer, created on first use.
+    pub real_timer: Once<Arc<IntervalTimer>>,
+
+    /// The `membarrier` registration commands issued by the process.
+    membarrier_registered: AtomicU32,
 }
 
 impl ProcessData {
@@ -217,12 +535,15 @@ impl ProcessData {
     pub fn new(
         exe_path: String,
         aspace: Arc<Mutex<AddrSpace>>,
+        maps: Arc<Mutex<MemoryMaps>>,
         signal_actions: Arc<Mutex<SignalActions>>,
         exit_signal: Option<Signo>,
     ) -> Self {
         Self {
             exe_path: RwLock::new(exe_path),
+            root: RwLock::new("/".into()),
             aspace,
+            maps,
             ns: AxNamespace::new_thread_local(),
             heap_bottom: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
             heap_top: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
@@ -230,15 +551,41 @@ impl ProcessData {
             child_exit_wq: WaitQueue::new(),
             exit_signal,
 
+            stopped: AtomicBool::new(false),
+            job_event: Mutex::new(None),
+            stop_wq: WaitQueue::new(),
+
+            cpu_time: CpuTime::default(),
+            children_cpu_time: CpuTime::default(),
+            children_max_rss: AtomicUsize::new(0),
+
+            rlimits: RwLock::new(Rlimits::default()),
+            umask: AtomicU32::new(0o022),
+
             signal: Arc::new(ProcessSignalManager::new(
                 signal_actions,
                 axconfig::plat::SIGNAL_TRAMPOLINE,
             )),
 
             futex_table: FutexTable::new(),
+
+            timers: TimerTable::new(),
+            real_timer: Once::new(),
+
+            membarrier_registered: AtomicU32::new(0),
         }
     }
 
+    /// Get the file mode creation mask.
+    pub fn umask(&self) -> u32 {
+        self.umask.load(Ordering::Acquire)
+    }
+
+    /// Set the file mode creation mask, returning the previous one.
+    pub fn replace_umask(&self, umask: u32) -> u32 {
+        self.umask.swap(umask & 0o777, Ordering::AcqRel)
+    }
+
     /// Get the bottom address of the user heap.
     pub fn get_heap_bottom(&self) -> usize {
         self.heap_bottom.load(Ordering::Acquire)
@@ -259,6 +606,68 @@ impl ProcessData {
         self.heap_top.store(top, Ordering::Release)
     }
 
+    /// Record a `membarrier` registration command.
+    pub fn register_membarrier(&self, cmd: u32) {
+        self.membarrier_registered.fetch_or(cmd, Ordering::AcqRel);
+    }
+
+    /// Forget the `membarrier` registration commands, which do not survive
+    /// `execve`.
+    pub fn clear_membarrier(&self) {
+        self.membarrier_registered.store(0, Ordering::Release);
+    }
+
+    /// Whether the process has issued the given `membarrier` registration
+    /// command.
+    pub fn is_membarrier_registered(&self, cmd: u32) -> bool {
+        self.membarrier_registered.load(Ordering::Acquire) & cmd != 0
+    }
+
+    /// Whether the process is stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Stop the process on `signo`.
+    ///
+    /// Returns `false` if it was stopped already.
+    pub fn stop(&self, signo: Signo) -> bool {
+        let mut event = self.job_event.lock();
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        *event = Some(JobEvent::Stopped(signo));
+        true
+    }
+
+    /// Resume the process and wake up its threads.
+    ///
+    /// Returns `false` if it was not stopped.
+    pub fn resume(&self) -> bool {
+        let mut event = self.job_event.lock();
+        if !self.stopped.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        *event = Some(JobEvent::Continued);
+        drop(event);
+        self.stop_wq.notify_all(false);
+        true
+    }
+
+    /// Get the last change of state not reported to the parent yet.
+    pub fn job_event(&self) -> Option<JobEvent> {
+        *self.job_event.lock()
+    }
+
+    /// Mark `event` as reported, unless another change of state happened
+    /// since.
+    pub fn clear_job_event(&self, event: JobEvent) {
+        let mut current = self.job_event.lock();
+        if *current == Some(event) {
+            *current = None;
+        }
+    }
+
     /// Linux manual: A "clone" child is one which delivers no signal, or a
     /// signal other than SIGCHLD to its parent upon termination.
     pub fn is_clone_child(&self) -> bool {
@@ -319,12 +728,17 @@ pub fn add_thread_to_table(thread: &Arc<Thread>) {
     }
     process_table.insert(process.pid(), process);
 
+    add_process_group_to_table(&process.group());
+}
+
+/// Add the process group and possibly its session to the corresponding
+/// tables.
+pub fn add_process_group_to_table(process_group: &Arc<ProcessGroup>) {
     let mut process_group_table = PROCESS_GROUP_TABLE.write();
-    let process_group = process.group();
     if process_group_table.contains_key(&process_group.pgid()) {
         return;
     }
-    process_group_table.insert(process_group.pgid(), &process_group);
+    process_group_table.insert(process_group.pgid(), process_group);
 
     let mut session_table = SESSION_TABLE.write();
     let session = process_group.session();
@@ -339,6 +753,12 @@ pub fn processes() -> Vec<Arc<Process>> {
     PROCESS_TABLE.read().values().collect()
 }
 
+/// Lists all processes, unless the table is locked, for when waiting for it
+/// could deadlock.
+pub fn try_processes() -> Option<Vec<Arc<Process>>> {
+    Some(PROCESS_TABLE.try_read()?.values().collect())
+}
+
 /// Finds the thread with the given TID.
 pub fn get_thread(tid: Pid) -> LinuxResult<Arc<Thread>> {
     THREAD_TABLE.read().get(&tid).ok_or(LinuxError::ESRCH)
@@ -1,3 +1,5 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 numeric_enum_macro::numeric_enum! {
     #[repr(i32)]
     #[allow(non_camel_case_types)]
@@ -23,14 +25,39 @@ impl From<usize> for TimerType {
     }
 }
 
+/// A timer counting down the CPU time of a task.
+#[derive(Default, Clone, Copy)]
+struct CpuTimer {
+    interval_ns: usize,
+    remained_ns: usize,
+    /// Whether the timer expired since the last call to
+    /// [`TimeStat::take_expired`].
+    expired: bool,
+}
+
+impl CpuTimer {
+    fn update(&mut self, delta: usize) {
+        if self.remained_ns == 0 {
+            return;
+        }
+        if self.remained_ns > delta {
+            self.remained_ns -= delta;
+        } else {
+            self.remained_ns = self.interval_ns;
+            self.expired = true;
+        }
+    }
+}
+
 pub struct TimeStat {
     utime_ns: usize,
     stime_ns: usize,
     user_timestamp: usize,
     kernel_timestamp: usize,
-    timer_type: TimerType,
-    timer_interval_ns: usize,
-    timer_remained_ns: usize,
+    /// The `ITIMER_VIRTUAL` timer, counting user time.
+    virtual_timer: CpuTimer,
+    /// The `ITIMER_PROF` timer, counting user and kernel time.
+    prof_timer: CpuTimer,
 }
 
 impl Default for TimeStat {
@@ -46,9 +73,8 @@ impl TimeStat {
             stime_ns: 0,
             user_timestamp: 0,
             kernel_timestamp: 0,
-            timer_type: TimerType::NONE,
-            timer_interval_ns: 0,
-            timer_remained_ns: 0,
+            virtual_timer: CpuTimer::default(),
+            prof_timer: CpuTimer::default(),
         }
     }
 
@@ -68,9 +94,8 @@ impl TimeStat {
         let delta = now_time_ns - self.kernel_timestamp;
         self.utime_ns += delta;
         self.kernel_timestamp = now_time_ns;
-        if self.timer_type != TimerType::NONE {
-            self.update_timer(delta);
-        };
+        self.virtual_timer.update(delta);
+        self.prof_timer.update(delta);
     }
 
     pub fn switch_into_user_mode(&mut self, current_timestamp: usize) {
@@ -78,9 +103,7 @@ impl TimeStat {
         let delta = now_time_ns - self.kernel_timestamp;
         self.stime_ns += delta;
         self.user_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL || self.timer_type == TimerType::PROF {
-            self.update_timer(delta);
-        }
+        self.prof_timer.update(delta);
     }
 
     pub fn switch_from_old_task(&mut self, current_timestamp: usize) {
@@ -88,38 +111,97 @@ impl TimeStat {
         let delta = now_time_ns - self.kernel_timestamp;
         self.stime_ns += delta;
         self.kernel_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL || self.timer_type == TimerType::PROF {
-            self.update_timer(delta);
-        }
+        self.prof_timer.update(delta);
     }
 
     pub fn switch_to_new_task(&mut self, current_timestamp: usize) {
-        let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
-        self.kernel_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL {
-            self.update_timer(delta);
+        self.kernel_timestamp = current_timestamp;
+    }
+
+    fn timer_mut(&mut self, timer_type: TimerType) -> Option<&mut CpuTimer> {
+        match timer_type {
+            TimerType::VIRTUAL => Some(&mut self.virtual_timer),
+            TimerType::PROF => Some(&mut self.prof_timer),
+            _ => None,
         }
     }
 
+    /// Returns the interval and the time left of a CPU time timer, in
+    /// nanoseconds.
+    ///
+    /// Only `VIRTUAL` and `PROF` are counted here; `None` is returned for
+    /// other types.
+    pub fn get_timer(&self, timer_type: TimerType) -> Option<(usize, usize)> {
+        let timer = match timer_type {
+            TimerType::VIRTUAL => &self.virtual_timer,
+            TimerType::PROF => &self.prof_timer,
+            _ => return None,
+        };
+        Some((timer.interval_ns, timer.remained_ns))
+    }
+
+    /// Arms a CPU time timer, or disarms it if `timer_remained_ns` is zero.
+    ///
+    /// Returns the previous setting like [`TimeStat::get_timer`].
     pub fn set_timer(
         &mut self,
+        timer_type: TimerType,
         timer_interval_ns: usize,
         timer_remained_ns: usize,
-        timer_type: usize,
-    ) -> bool {
-        self.timer_type = timer_type.into();
-        self.timer_interval_ns = timer_interval_ns;
-        self.timer_remained_ns = timer_remained_ns;
-        self.timer_type != TimerType::NONE
+    ) -> Option<(usize, usize)> {
+        let timer = self.timer_mut(timer_type)?;
+        let old = (timer.interval_ns, timer.remained_ns);
+        *timer = CpuTimer {
+            interval_ns: timer_interval_ns,
+            remained_ns: timer_remained_ns,
+            expired: false,
+        };
+        Some(old)
     }
 
-    pub fn update_timer(&mut self, delta: usize) {
-        if self.timer_remained_ns == 0 {
-            return;
-        }
-        if self.timer_remained_ns > delta {
-            self.timer_remained_ns -= delta;
-        }
+    /// Returns the types of the timers that expired since the last call.
+    pub fn take_expired(&mut self) -> impl Iterator<Item = TimerType> {
+        let expired = [
+            (
+                TimerType::VIRTUAL,
+                core::mem::take(&mut self.virtual_timer.expired),
+            ),
+            (
+                TimerType::PROF,
+                core::mem::take(&mut self.prof_timer.expired),
+            ),
+        ];
+        expired
+            .into_iter()
+            .filter_map(|(timer_type, expired)| expired.then_some(timer_type))
+    }
+}
+
+/// CPU time accumulated in user and kernel mode, in nanoseconds.
+#[derive(Default)]
+pub struct CpuTime {
+    utime_ns: AtomicUsize,
+    stime_ns: AtomicUsize,
+}
+
+impl CpuTime {
+    /// Adds `utime_ns` of user time and `stime_ns` of kernel time.
+    pub fn add(&self, utime_ns: usize, stime_ns: usize) {
+        self.utime_ns.fetch_add(utime_ns, Ordering::Relaxed);
+        self.stime_ns.fetch_add(stime_ns, Ordering::Relaxed);
+    }
+
+    /// Replaces the user and kernel time with `utime_ns` and `stime_ns`.
+    pub fn set(&self, utime_ns: usize, stime_ns: usize) {
+        self.utime_ns.store(utime_ns, Ordering::Relaxed);
+        self.stime_ns.store(stime_ns, Ordering::Relaxed);
+    }
+
+    /// Returns the user and kernel time.
+    pub fn get(&self) -> (usize, usize) {
+        (
+            self.utime_ns.load(Ordering::Relaxed),
+            self.stime_ns.load(Ordering::Relaxed),
+        )
     }
 }
@@ -0,0 +1,248 @@
+//! POSIX interval timers.
+
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::TimeValue;
+use axsync::Mutex;
+use axtask::WaitQueue;
+use spin::Once;
+
+/// The most timers a process may create with `timer_create`.
+const MAX_TIMERS: i32 = 1024;
+
+/// The expiration settings of a timer.
+struct TimerState {
+    /// The next expiration, or `None` if the timer is disarmed.
+    deadline: Option<TimeValue>,
+    /// The period of the timer, or zero for a one-shot timer.
+    interval: TimeValue,
+}
+
+/// A timer created by `timer_create`.
+///
+/// The timer does not act on its own: once passed to [`drive_timer`], a
+/// single kernel task shared by all timers watches for its expirations.
+pub struct IntervalTimer {
+    /// Reads the clock the timer measures.
+    clock: fn() -> TimeValue,
+    state: Mutex<TimerState>,
+    deleted: AtomicBool,
+    /// The overrun count reported by `timer_getoverrun`.
+    overrun: AtomicI32,
+}
+
+impl IntervalTimer {
+    /// Creates a new disarmed timer measuring `clock`.
+    pub fn new(clock: fn() -> TimeValue) -> Self {
+        Self {
+            clock,
+            state: Mutex::new(TimerState {
+                deadline: None,
+                interval: TimeValue::ZERO,
+            }),
+            deleted: AtomicBool::new(false),
+            overrun: AtomicI32::new(0),
+        }
+    }
+
+    /// Reads the clock the timer measures.
+    pub fn now(&self) -> TimeValue {
+        (self.clock)()
+    }
+
+    /// Returns the time until the next expiration and the interval.
+    ///
+    /// The time is zero if the timer is disarmed.
+    pub fn get(&self) -> (TimeValue, TimeValue) {
+        self.setting(&self.state.lock())
+    }
+
+    fn setting(&self, state: &TimerState) -> (TimeValue, TimeValue) {
+        let left = state
+            .deadline
+            .map_or(TimeValue::ZERO, |it| it.saturating_sub(self.now()));
+        (left, state.interval)
+    }
+
+    /// Arms the timer to expire at `deadline` and then every `interval`, or
+    /// disarms it if `deadline` is `None`.
+    ///
+    /// Returns the previous setting like [`IntervalTimer::get`].
+    pub fn set(&self, deadline: Option<TimeValue>, interval: TimeValue) -> (TimeValue, TimeValue) {
+        let mut state = self.state.lock();
+        let old = self.setting(&state);
+        state.deadline = deadline;
+        state.interval = interval;
+        drop(state);
+        timers_changed();
+        old
+    }
+
+    /// Disarms the timer for good, so that it is no longer driven.
+    pub fn delete(&self) {
+        self.deleted.store(true, Ordering::Release);
+        self.set(None, TimeValue::ZERO);
+    }
+
+    /// Returns the time until the next expiration, or `None` if the timer is
+    /// disarmed.
+    fn time_left(&self) -> Option<TimeValue> {
+        let state = self.state.lock();
+        state.deadline.map(|it| it.saturating_sub(self.now()))
+    }
+
+    /// Checks whether the timer expired.
+    ///
+    /// A periodic timer is re-armed for the next period after the current
+    /// time. Returns the number of periods missed in between, or `None` if
+    /// the timer has not expired.
+    fn take_expiry(&self) -> Option<u64> {
+        let mut state = self.state.lock();
+        let deadline = state.deadline?;
+        let now = self.now();
+        if now < deadline {
+            return None;
+        }
+
+        let interval = state.interval;
+        if interval.is_zero() {
+            state.deadline = None;
+            return Some(0);
+        }
+        let missed = (now - deadline).as_nanos() / interval.as_nanos();
+        let next = interval.as_nanos() * (missed + 1);
+        state.deadline = Some(deadline + TimeValue::from_nanos(next as u64));
+        Some(missed as u64)
+    }
+
+    /// Returns the overrun count.
+    pub fn overrun(&self) -> i32 {
+        self.overrun.load(Ordering::Acquire)
+    }
+
+    /// Sets the overrun count, capping it at `i32::MAX`.
+    pub fn set_overrun(&self, overrun: u64) {
+        self.overrun
+            .store(overrun.min(i32::MAX as u64) as i32, Ordering::Release);
+    }
+
+    /// Adds `count` to the overrun count, capping it at `i32::MAX`.
+    pub fn add_overrun(&self, count: u64) {
+        self.set_overrun(self.overrun() as u64 + count);
+    }
+}
+
+/// The timers of a process, by ID.
+pub struct TimerTable(Mutex<BTreeMap<i32, Arc<IntervalTimer>>>);
+
+impl TimerTable {
+    /// Creates a new empty `TimerTable`.
+    pub fn new() -> Self {
+        Self(Mutex::new(BTreeMap::new()))
+    }
+
+    /// Adds `timer` to the table under the lowest free ID, which is
+    /// returned.
+    ///
+    /// Fails with `EAGAIN` once the process has [`MAX_TIMERS`] timers.
+    pub fn add(&self, timer: Arc<IntervalTimer>) -> LinuxResult<i32> {
+        let mut table = self.0.lock();
+        let id = (0..MAX_TIMERS)
+            .find(|id| !table.contains_key(id))
+            .ok_or(LinuxError::EAGAIN)?;
+        table.insert(id, timer);
+        Ok(id)
+    }
+
+    /// Finds the timer with the given ID.
+    pub fn get(&self, id: i32) -> LinuxResult<Arc<IntervalTimer>> {
+        self.0.lock().get(&id).cloned().ok_or(LinuxError::EINVAL)
+    }
+
+    /// Removes and deletes the timer with the given ID.
+    pub fn remove(&self, id: i32) -> LinuxResult {
+        let timer = self.0.lock().remove(&id).ok_or(LinuxError::EINVAL)?;
+        timer.delete();
+        Ok(())
+    }
+
+    /// Removes and deletes all the timers.
+    pub fn clear(&self) {
+        let table = core::mem::take(&mut *self.0.lock());
+        for timer in table.values() {
+            timer.delete();
+        }
+    }
+}
+
+/// Called on an expiration of a driven timer with the number of periods
+/// missed. Returning `false` deletes the timer.
+type ExpiryHandler = Arc<dyn Fn(&IntervalTimer, u64) -> bool + Send + Sync>;
+
+/// The timers watched by the timer task, with their handlers.
+static DRIVEN_TIMERS: Mutex<Vec<(Arc<IntervalTimer>, ExpiryHandler)>> = Mutex::new(Vec::new());
+
+/// Bumped whenever a timer is set, so that the timer task recomputes its
+/// next deadline.
+static TIMERS_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+static TIMERS_WQ: WaitQueue = WaitQueue::new();
+
+static TIMER_TASK: Once = Once::new();
+
+fn timers_changed() {
+    TIMERS_GENERATION.fetch_add(1, Ordering::AcqRel);
+    TIMERS_WQ.notify_all(false);
+}
+
+/// Calls `on_expiry` on every expiration of `timer`, until the timer is
+/// deleted or `on_expiry` returns `false`.
+pub fn drive_timer(
+    timer: Arc<IntervalTimer>,
+    on_expiry: impl Fn(&IntervalTimer, u64) -> bool + Send + Sync + 'static,
+) {
+    TIMER_TASK.call_once(|| {
+        axtask::spawn(run_timers);
+    });
+    DRIVEN_TIMERS.lock().push((timer, Arc::new(on_expiry)));
+    timers_changed();
+}
+
+/// The body of the timer task, which sleeps until the earliest deadline of
+/// all the driven timers or until one of them is set.
+fn run_timers() {
+    loop {
+        let generation = TIMERS_GENERATION.load(Ordering::Acquire);
+        let mut expired = Vec::new();
+        let mut timeout: Option<TimeValue> = None;
+        DRIVEN_TIMERS.lock().retain(|(timer, on_expiry)| {
+            if timer.deleted.load(Ordering::Acquire) {
+                return false;
+            }
+            if let Some(missed) = timer.take_expiry() {
+                expired.push((timer.clone(), on_expiry.clone(), missed));
+            }
+            if let Some(left) = timer.time_left() {
+                timeout = Some(timeout.map_or(left, |it| it.min(left)));
+            }
+            true
+        });
+
+        // The handlers send signals, so they run without the lock.
+        for (timer, on_expiry, missed) in expired {
+            if !on_expiry(&timer, missed) {
+                timer.delete();
+            }
+        }
+
+        let changed = || TIMERS_GENERATION.load(Ordering::Acquire) != generation;
+        match timeout {
+            Some(timeout) => {
+                TIMERS_WQ.wait_timeout_until(timeout, changed);
+            }
+            None => TIMERS_WQ.wait_until(changed),
+        }
+    }
+}
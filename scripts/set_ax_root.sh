@@ -10,4 +10,15 @@ AX_ROOT=$1
 mkdir -p .cargo
 sed -e "s|%AX_ROOT%|$AX_ROOT|g" scripts/config.toml.temp > .cargo/config.toml
 
+# Have the panic handler of axruntime call `starry_panic_hook` first, which
+# writes back the open files before the system halts.
+LANG_ITEMS=$AX_ROOT/modules/axruntime/src/lang_items.rs
+if [ -f "$LANG_ITEMS" ] && ! grep -q starry_panic_hook "$LANG_ITEMS"; then
+    sed -i '/^fn panic(/a\
+    unsafe extern "Rust" {\
+        fn starry_panic_hook();\
+    }\
+    unsafe { starry_panic_hook() };' "$LANG_ITEMS"
+fi
+
 echo "Set AX_ROOT (ArceOS directory) to $AX_ROOT"
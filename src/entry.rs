@@ -4,17 +4,20 @@ use axhal::arch::UspaceContext;
 use axprocess::{Pid, init_proc};
 use axsignal::Signo;
 use axsync::Mutex;
-use starry_api::file::FD_TABLE;
+use linux_raw_sys::general::RLIMIT_STACK;
+use starry_api::file::{FD_TABLE, console};
 use starry_core::{
-    mm::{copy_from_kernel, load_user_app, map_trampoline, new_user_aspace_empty},
+    mm::{MemoryMaps, copy_from_kernel, load_user_app, map_trampoline, new_user_aspace_empty},
+    resources::Rlimits,
     task::{ProcessData, TaskExt, ThreadData, add_thread_to_table, new_user_task},
 };
 
 pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
+    let mut maps = MemoryMaps::default();
     let mut uspace = new_user_aspace_empty()
         .and_then(|mut it| {
             copy_from_kernel(&mut it)?;
-            map_trampoline(&mut it)?;
+            map_trampoline(&mut it, &mut maps)?;
             Ok(it)
         })
         .expect("Failed to create user address space");
@@ -23,8 +26,11 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     let (dir, name) = exe_path.rsplit_once('/').unwrap_or(("", &exe_path));
     set_current_dir(dir).expect("Failed to set current dir");
 
-    let (entry_vaddr, ustack_top) = load_user_app(&mut uspace, args, envs)
-        .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
+    // The first process starts with the default limits.
+    let stack_size = Rlimits::default()[RLIMIT_STACK].current as usize;
+    let (entry_vaddr, ustack_top) =
+        load_user_app(&mut uspace, &mut maps, "", &args[0], args, envs, stack_size)
+            .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
 
     let uctx = UspaceContext::new(entry_vaddr.into(), ustack_top, 2333);
 
@@ -34,6 +40,7 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     let process_data = ProcessData::new(
         exe_path,
         Arc::new(Mutex::new(uspace)),
+        Arc::new(Mutex::new(maps)),
         Arc::default(),
         Some(Signo::SIGCHLD),
     );
@@ -56,6 +63,9 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
         .data(ThreadData::new(process.data().unwrap()))
         .build();
     add_thread_to_table(&thread);
+    // Like a login shell, user apps run with the console as their controlling
+    // terminal.
+    console().acquire(&init_proc());
 
     task.init_task_ext(TaskExt::new(thread));
 
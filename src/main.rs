@@ -15,6 +15,7 @@ mod syscall;
 fn main() {
     // Create a init process
     axprocess::Process::new_init(axtask::current().id().as_u64() as _).build();
+    starry_api::random::seed_random();
 
     let testcases = option_env!("AX_TESTCASES_LIST")
         .unwrap_or_else(|| "Please specify the testcases list by making user_apps")
@@ -33,4 +34,13 @@ fn main() {
         let exit_code = entry::run_user_app(&args, &[]);
         info!("User task {:?} exited with code: {:?}", args, exit_code);
     }
+
+    starry_api::shutdown(None);
+}
+
+/// Called first by the panic handler of axruntime, as patched by
+/// `scripts/set_ax_root.sh`.
+#[unsafe(no_mangle)]
+fn starry_panic_hook() {
+    starry_api::file::sync_on_panic();
 }
@@ -3,9 +3,11 @@ use axhal::{
     paging::MappingFlags,
     trap::{PAGE_FAULT, register_trap_handler},
 };
+use axsignal::Signo;
 use axtask::{TaskExtRef, current};
-use linux_raw_sys::general::SIGSEGV;
-use starry_api::do_exit;
+use linux_raw_sys::general::{SEGV_ACCERR, SEGV_MAPERR, SIGSEGV};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddrRange};
+use starry_api::{do_exit, signal::raise_fault_signal};
 use starry_core::mm::is_accessing_user_memory;
 
 #[register_trap_handler(PAGE_FAULT)]
@@ -19,13 +21,13 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
     }
 
     let curr = current();
-    if !curr
-        .task_ext()
-        .process_data()
-        .aspace
-        .lock()
-        .handle_page_fault(vaddr, access_flags)
-    {
+    let mut aspace = curr.task_ext().process_data().aspace.lock();
+    if aspace.handle_page_fault(vaddr, access_flags) {
+        return true;
+    }
+
+    if !is_user {
+        drop(aspace);
         warn!(
             "{} ({:?}): segmentation fault at {:#x}, exit!",
             curr.id_name(),
@@ -34,5 +36,23 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
         );
         do_exit(SIGSEGV as _, true);
     }
+
+    // Tell apart accesses to unmapped memory from permission violations.
+    let code = if aspace.check_region_access(
+        VirtAddrRange::from_start_size(vaddr.align_down_4k(), PAGE_SIZE_4K),
+        MappingFlags::empty(),
+    ) {
+        SEGV_ACCERR
+    } else {
+        SEGV_MAPERR
+    };
+    drop(aspace);
+    warn!(
+        "{} ({:?}): segmentation fault at {:#x}",
+        curr.id_name(),
+        curr.task_ext().thread,
+        vaddr
+    );
+    raise_fault_signal(Signo::SIGSEGV, code as _, vaddr.as_usize());
     true
 }
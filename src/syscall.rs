@@ -16,7 +16,20 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         // fs ctl
         Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
         Sysno::chdir => sys_chdir(tf.arg0().into()),
+        Sysno::fchdir => sys_fchdir(tf.arg0() as _),
+        Sysno::chroot => sys_chroot(tf.arg0().into()),
         Sysno::mkdirat => sys_mkdirat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::mknodat => sys_mknodat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::mkdir => sys_mkdir(tf.arg0().into(), tf.arg1() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::mknod => sys_mknod(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::umask => sys_umask(tf.arg0() as _),
         Sysno::getdents64 => sys_getdents64(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::linkat => sys_linkat(
             tf.arg0() as _,
@@ -30,6 +43,22 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::unlinkat => sys_unlinkat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::unlink => sys_unlink(tf.arg0().into()),
+        Sysno::renameat2 => sys_renameat2(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+            tf.arg4() as _,
+        ),
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        Sysno::renameat => sys_renameat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::rename => sys_rename(tf.arg0().into(), tf.arg1().into()),
         Sysno::getcwd => sys_getcwd(tf.arg0().into(), tf.arg1() as _),
 
         // fd ops
@@ -45,7 +74,7 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::dup => sys_dup(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::dup2 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
-        Sysno::dup3 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
+        Sysno::dup3 => sys_dup3(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::fcntl => sys_fcntl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
 
         // io
@@ -54,6 +83,48 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::write => sys_write(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::writev => sys_writev(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::lseek => sys_lseek(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::pread64 => sys_pread64(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::pwrite64 => sys_pwrite64(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::preadv => sys_preadv(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::pwritev => sys_pwritev(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::truncate => sys_truncate(tf.arg0().into(), tf.arg1() as _),
+        Sysno::ftruncate => sys_ftruncate(tf.arg0() as _, tf.arg1() as _),
+        Sysno::fallocate => sys_fallocate(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::cachestat => sys_cachestat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
+        Sysno::fsync => sys_fsync(tf.arg0() as _),
+        Sysno::fdatasync => sys_fdatasync(tf.arg0() as _),
+        Sysno::sync => sys_sync(),
+        Sysno::syncfs => sys_syncfs(tf.arg0() as _),
 
         // fs mount
         Sysno::mount => sys_mount(
@@ -69,6 +140,59 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::pipe2 => sys_pipe2(tf.arg0().into(), tf.arg1() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::pipe => sys_pipe2(tf.arg0().into(), 0),
+        Sysno::splice => sys_splice(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ),
+        Sysno::tee => sys_tee(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::ppoll => sys_ppoll(
+            tf,
+            tf.arg0().into(),
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+            tf.arg4() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::poll => sys_poll(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::epoll_create1 => sys_epoll_create1(tf.arg0() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::epoll_create => sys_epoll_create(tf.arg0() as _),
+        Sysno::epoll_ctl => sys_epoll_ctl(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::epoll_pwait => sys_epoll_pwait(
+            tf,
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4().into(),
+            tf.arg5() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::epoll_wait => sys_epoll_wait(
+            tf,
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::eventfd2 => sys_eventfd2(tf.arg0() as _, tf.arg1() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::eventfd => sys_eventfd2(tf.arg0() as _, 0),
 
         // fs stat
         #[cfg(target_arch = "x86_64")]
@@ -90,6 +214,17 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg2().into(),
             tf.arg3() as _,
         ),
+        Sysno::faccessat => sys_faccessat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::faccessat2 => sys_faccessat2(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::access => sys_access(tf.arg0().into(), tf.arg1() as _),
+        Sysno::statfs => sys_statfs(tf.arg0().into(), tf.arg1().into()),
+        Sysno::fstatfs => sys_fstatfs(tf.arg0() as _, tf.arg1().into()),
         Sysno::statx => sys_statx(
             tf.arg0() as _,
             tf.arg1().into(),
@@ -110,21 +245,111 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         ),
         Sysno::munmap => sys_munmap(tf.arg0(), tf.arg1() as _),
         Sysno::mprotect => sys_mprotect(tf.arg0(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::mremap => sys_mremap(
+            tf.arg0(),
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4(),
+        ),
+        Sysno::mseal => sys_mseal(tf.arg0(), tf.arg1() as _, tf.arg2() as _),
+        #[cfg(target_arch = "riscv64")]
+        Sysno::riscv_flush_icache => sys_riscv_flush_icache(tf.arg0(), tf.arg1(), tf.arg2() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::map_shadow_stack => sys_map_shadow_stack(tf.arg0(), tf.arg1() as _, tf.arg2() as _),
+
+        // ipc
+        Sysno::shmget => sys_shmget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::shmat => sys_shmat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::shmdt => sys_shmdt(tf.arg0() as _),
+        Sysno::shmctl => sys_shmctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
+        Sysno::mq_open => sys_mq_open(
+            tf.arg0().into(),
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::mq_unlink => sys_mq_unlink(tf.arg0().into()),
+        Sysno::mq_timedsend => sys_mq_timedsend(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::mq_timedreceive => sys_mq_timedreceive(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+            tf.arg4().into(),
+        ),
+        Sysno::mq_getsetattr => {
+            sys_mq_getsetattr(tf.arg0() as _, tf.arg1().into(), tf.arg2().into())
+        }
+        Sysno::mq_notify => sys_mq_notify(tf.arg0() as _, tf.arg1().into()),
+        Sysno::msgget => sys_msgget(tf.arg0() as _, tf.arg1() as _),
+        Sysno::msgsnd => sys_msgsnd(tf.arg0() as _, tf.arg1(), tf.arg2() as _, tf.arg3() as _),
+        Sysno::msgrcv => sys_msgrcv(
+            tf.arg0() as _,
+            tf.arg1(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        Sysno::msgctl => sys_msgctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
+        Sysno::semget => sys_semget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::semop => sys_semop(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::semctl => sys_semctl(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
 
         // task info
         Sysno::getpid => sys_getpid(),
         Sysno::getppid => sys_getppid(),
         Sysno::gettid => sys_gettid(),
+        Sysno::pidfd_open => sys_pidfd_open(tf.arg0() as _, tf.arg1() as _),
+        Sysno::setsid => sys_setsid(),
+        Sysno::getsid => sys_getsid(tf.arg0() as _),
+        Sysno::setpgid => sys_setpgid(tf.arg0() as _, tf.arg1() as _),
+        Sysno::getpgid => sys_getpgid(tf.arg0() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::getpgrp => sys_getpgrp(),
+        Sysno::vhangup => sys_vhangup(),
 
         // task sched
         Sysno::sched_yield => sys_sched_yield(),
+        Sysno::sched_setaffinity => {
+            sys_sched_setaffinity(tf.arg0() as _, tf.arg1() as _, tf.arg2().into())
+        }
+        Sysno::sched_getaffinity => {
+            sys_sched_getaffinity(tf.arg0() as _, tf.arg1() as _, tf.arg2().into())
+        }
+        Sysno::getpriority => sys_getpriority(tf.arg0() as _, tf.arg1() as _),
+        Sysno::setpriority => sys_setpriority(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::nanosleep => sys_nanosleep(tf.arg0().into(), tf.arg1().into()),
+        Sysno::clock_nanosleep => sys_clock_nanosleep(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
 
         // task ops
         Sysno::execve => sys_execve(tf, tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
         Sysno::set_tid_address => sys_set_tid_address(tf.arg0()),
+        Sysno::prctl => sys_prctl(tf.arg0() as _, tf.arg1(), tf.arg2(), tf.arg3(), tf.arg4()),
         #[cfg(target_arch = "x86_64")]
         Sysno::arch_prctl => sys_arch_prctl(tf, tf.arg0() as _, tf.arg1() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::set_thread_area => sys_set_thread_area(tf.arg0()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::get_thread_area => sys_get_thread_area(tf.arg0()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::modify_ldt => sys_modify_ldt(tf.arg0() as _, tf.arg1(), tf.arg2() as _),
 
         // task management
         Sysno::clone => sys_clone(
@@ -135,11 +360,24 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3(),
             tf.arg4(),
         ),
+        Sysno::clone3 => sys_clone3(tf, tf.arg0().into(), tf.arg1() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::fork => sys_fork(tf),
         Sysno::exit => sys_exit(tf.arg0() as _),
         Sysno::exit_group => sys_exit_group(tf.arg0() as _),
-        Sysno::wait4 => sys_waitpid(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::wait4 => sys_wait4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::waitid => sys_waitid(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
 
         // signal
         Sysno::rt_sigprocmask => sys_rt_sigprocmask(
@@ -163,23 +401,33 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3() as _,
         ),
         Sysno::rt_sigsuspend => sys_rt_sigsuspend(tf, tf.arg0().into(), tf.arg1() as _),
+        Sysno::signalfd4 => sys_signalfd4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::signalfd => sys_signalfd4(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _, 0),
         Sysno::kill => sys_kill(tf.arg0() as _, tf.arg1() as _),
         Sysno::tkill => sys_tkill(tf.arg0() as _, tf.arg1() as _),
         Sysno::tgkill => sys_tgkill(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
-        Sysno::rt_sigqueueinfo => sys_rt_sigqueueinfo(
+        Sysno::pidfd_send_signal => sys_pidfd_send_signal(
             tf.arg0() as _,
             tf.arg1() as _,
             tf.arg2().into(),
             tf.arg3() as _,
         ),
+        Sysno::rt_sigqueueinfo => {
+            sys_rt_sigqueueinfo(tf.arg0() as _, tf.arg1() as _, tf.arg2().into())
+        }
         Sysno::rt_tgsigqueueinfo => sys_rt_tgsigqueueinfo(
             tf.arg0() as _,
             tf.arg1() as _,
             tf.arg2() as _,
             tf.arg3().into(),
-            tf.arg4() as _,
         ),
-        Sysno::sigaltstack => sys_sigaltstack(tf.arg0().into(), tf.arg1().into()),
+        Sysno::sigaltstack => sys_sigaltstack(tf, tf.arg0().into(), tf.arg1().into()),
         Sysno::futex => sys_futex(
             tf.arg0().into(),
             tf.arg1() as _,
@@ -188,24 +436,73 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg4().into(),
             tf.arg5() as _,
         ),
+        Sysno::membarrier => sys_membarrier(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::set_robust_list => sys_set_robust_list(tf.arg0().into(), tf.arg1() as _),
+        Sysno::get_robust_list => {
+            sys_get_robust_list(tf.arg0() as _, tf.arg1().into(), tf.arg2().into())
+        }
 
         // sys
         Sysno::getuid => sys_getuid(),
         Sysno::geteuid => sys_geteuid(),
         Sysno::getgid => sys_getgid(),
         Sysno::getegid => sys_getegid(),
+        #[cfg(not(target_arch = "loongarch64"))]
+        Sysno::getrlimit => sys_getrlimit(tf.arg0() as _, tf.arg1().into()),
+        #[cfg(not(target_arch = "loongarch64"))]
+        Sysno::setrlimit => sys_setrlimit(tf.arg0() as _, tf.arg1().into()),
+        Sysno::prlimit64 => sys_prlimit64(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::getrusage => sys_getrusage(tf.arg0() as _, tf.arg1().into()),
         Sysno::uname => sys_uname(tf.arg0().into()),
+        Sysno::reboot => sys_reboot(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::getrandom => sys_getrandom(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::sysinfo => sys_sysinfo(tf.arg0().into()),
 
         // time
         Sysno::gettimeofday => sys_gettimeofday(tf.arg0().into()),
+        Sysno::settimeofday => sys_settimeofday(tf.arg0().into(), tf.arg1()),
         Sysno::times => sys_times(tf.arg0().into()),
         Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_settime => sys_clock_settime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_getres => sys_clock_getres(tf.arg0() as _, tf.arg1().into()),
+        Sysno::timer_create => sys_timer_create(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
+        Sysno::timer_settime => sys_timer_settime(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::timer_gettime => sys_timer_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::timer_getoverrun => sys_timer_getoverrun(tf.arg0() as _),
+        Sysno::timer_delete => sys_timer_delete(tf.arg0() as _),
+        Sysno::timerfd_create => sys_timerfd_create(tf.arg0() as _, tf.arg1() as _),
+        Sysno::timerfd_settime => sys_timerfd_settime(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::timerfd_gettime => sys_timerfd_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::getitimer => sys_getitimer(tf.arg0() as _, tf.arg1().into()),
+        Sysno::setitimer => sys_setitimer(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
 
         _ => {
             warn!("Unimplemented syscall: {}", sysno);
             Err(LinuxError::ENOSYS)
         }
     };
+    // Errors are only ever returned in the trap frame of the calling thread,
+    // as the negated error number. No syscall keeps it anywhere else.
     let ans = result.unwrap_or_else(|err| -err.code() as _);
     time_stat_from_kernel_to_user();
     info!("Syscall {:?} return {}", sysno, ans);